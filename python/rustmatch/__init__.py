@@ -27,12 +27,45 @@ Quick Start (No numpy needed!):
 
 Classes:
     MatchResult: Match result containing position and confidence
+    MatchDiagnostics: Search statistics returned with ``return_diagnostics=True``
+    Matcher: Thread-safe matcher holding one preprocessed template
+    TemplateLibrary: Named templates prepared once, e.g. loaded from a zip pack
+    Template: Template cut from a screenshot with Template.from_region
+    CompositeTemplate: Several template parts at fixed relative offsets
+    CompositeMatch: Match of a CompositeTemplate with its per-part results
+    MatcherStream: Reusable matcher for continuous frame streams
+    Tracker: Lightweight tracker that searches near the last position
+    TemplateBank: Template precomputed at several scales and rotations
+    MatchCluster: Group of nearby detections returned by find_clusters
+    RotScale: Rotation, scale and shift returned by register_rotscale
+    Alignment: Sub-pixel warp returned by refine_alignment
+    PerspectiveMatch: Projected corners returned by find_perspective
+
+Exceptions (all subclasses of RustMatchError, itself a ValueError):
+    DecodeError: Image data could not be decoded
+    TemplateTooLargeError: Template is larger than the source
+    InvalidDimensionsError: Sizes or coordinates are inconsistent
+    TemplateQualityError: Template has too little contrast (``strict=True``)
 
 Functions:
     find: Find single best match (file paths)
     find_all: Find all matches (file paths)
+    find_tiled: Find single best match in very large images (file paths)
+    find_all_tiled: Find all matches in very large images (file paths)
+    find_pages: Find best match on every page of a multi-page TIFF
     find_bytes: Find single match (image bytes)
     find_all_bytes: Find all matches (image bytes)
+    find_tensor: Find single match in a numpy array or CPU tensor, read in place
+    find_np: Find single match in contiguous numpy arrays
+    find_iter: Lazily iterate over matches, strongest first
+    find_next: Find the best match outside regions already handled
+    find_clusters: Merge nearby detections into clusters with statistics
+    find_repeats: Find regions that repeat within one image
+    find_rotated: Find a template at an unknown rotation
+    register_rotscale: Estimate the rotation and scale between two images
+    refine_alignment: Refine a match to a sub-pixel warp (ECC)
+    find_perspective: Find a template seen at an angle (homography)
+    assert_template_found: Test helper raising AssertionError with diagnostics
 """
 
 from __future__ import annotations
@@ -42,44 +75,222 @@ __author__ = "Junjie Duan"
 __all__ = [
     # Core classes
     "MatchResult",
+    "MatchDiagnostics",
+    "Matcher",
+    "TemplateLibrary",
+    "Template",
+    "CompositeTemplate",
+    "CompositeMatch",
+    "MatcherStream",
+    "Tracker",
+    "TemplateBank",
+    "MatchCluster",
+    "RotScale",
+    "Alignment",
+    "PerspectiveMatch",
+    # Exceptions
+    "RustMatchError",
+    "DecodeError",
+    "TemplateTooLargeError",
+    "InvalidDimensionsError",
+    "TemplateQualityError",
     # File path based (recommended!)
     "find",
     "find_all",
+    "MATCH_DTYPE",
+    "find_tiled",
+    "find_all_tiled",
+    "find_pages",
     # Bytes based
     "find_bytes",
     "find_all_bytes",
     # Raw pixel data
     "find_raw",
     "find_all_raw",
+    "find_yuv",
+    "find_all_yuv",
+    "find_tensor",
+    "find_all_tensor",
+    "find_np",
+    "find_all_np",
+    # Lazy iteration
+    "find_iter",
+    "find_next",
+    "find_clusters",
+    "find_repeats",
+    # Rotated search
+    "find_rotated",
+    "register_rotscale",
+    "refine_alignment",
+    "find_perspective",
+    "assert_template_found",
+    # Directories
+    "find_in_directory",
+    # Animated images and video (optional `video` feature)
+    "find_in_animation",
+    "find_in_video",
+    # Live camera (optional `capture` feature)
+    "watch_camera",
+    # HTTP sources (optional `http` feature)
+    "find_url",
     # Utilities
     "get_size",
     "get_size_bytes",
+    "compare_images",
+    "diff_images",
+    "compare_to_golden",
+    "phash",
+    "dhash",
+    "hamming_distance",
+    "autocrop",
+    "validate_template",
+    "calibrate_threshold",
+    "template_quality",
+    "suggest_template_region",
+    "score_sweep",
+    "score_grid",
+    "explain_match",
+    "estimate_scale",
+    "crop_image",
+    "resize_image",
+    "to_grayscale",
+    "get_image_info",
+    "benchmark",
+    "results_to_json",
+    "matches_to_svg",
+    "export_results",
+    "save_match_sheet",
+    "set_image_cache_size",
+    "clear_image_cache",
+    "image_cache_info",
+    "set_result_cache",
+    "clear_result_cache",
+    "result_cache_info",
     "set_threads",
+    "set_parallel_threshold",
     "version",
 ]
 
 # Import from Rust core
 from rustmatch._core import (
     MatchResult,
+    MatchDiagnostics,
+    Matcher,
+    TemplateLibrary,
+    Template,
+    CompositeTemplate,
+    CompositeMatch,
+    MatcherStream,
+    Tracker,
+    TemplateBank,
+    MatchCluster,
+    RotScale,
+    Alignment,
+    PerspectiveMatch,
+    RustMatchError,
+    DecodeError,
+    TemplateTooLargeError,
+    InvalidDimensionsError,
+    TemplateQualityError,
     find_template as _find_template,
     find_all_templates as _find_all_templates,
+    find_template_tiled as _find_template_tiled,
+    find_all_templates_tiled as _find_all_templates_tiled,
+    find_template_pages as _find_template_pages,
     find_template_bytes as _find_template_bytes,
     find_all_templates_bytes as _find_all_templates_bytes,
+    find_all_templates_packed as _find_all_templates_packed,
     find_template_raw as _find_template_raw,
     find_all_templates_raw as _find_all_templates_raw,
+    find_template_yuv as _find_template_yuv,
+    find_all_templates_yuv as _find_all_templates_yuv,
+    find_template_tensor as _find_template_tensor,
+    find_all_templates_tensor as _find_all_templates_tensor,
+    find_template_np as _find_template_np,
+    find_all_templates_np as _find_all_templates_np,
+    find_iter as _find_iter,
+    find_next as _find_next,
+    find_clusters as _find_clusters,
+    find_repeats as _find_repeats,
+    find_rotated as _find_rotated,
+    register_rotscale as _register_rotscale,
+    refine_alignment as _refine_alignment,
+    find_perspective as _find_perspective,
+    assert_template_found as _assert_template_found,
+    find_in_animation as _find_in_animation,
+    find_in_directory as _find_in_directory,
+    find_in_video as _find_in_video,
+    find_template_url as _find_template_url,
+    watch_camera as _watch_camera,
     get_image_size as _get_image_size,
     get_image_size_bytes as _get_image_size_bytes,
+    compare_images as _compare_images,
+    diff_images as _diff_images,
+    compare_to_golden as _compare_to_golden,
+    phash as _phash,
+    dhash as _dhash,
+    hamming_distance as _hamming_distance,
+    autocrop as _autocrop,
+    validate_template as _validate_template,
+    calibrate_threshold as _calibrate_threshold,
+    template_quality as _template_quality,
+    suggest_template_region as _suggest_template_region,
+    score_sweep as _score_sweep,
+    score_grid as _score_grid,
+    explain_match as _explain_match,
+    estimate_scale as _estimate_scale,
+    crop_image as _crop_image,
+    resize_image as _resize_image,
+    to_grayscale as _to_grayscale,
+    get_image_info as _get_image_info,
+    benchmark as _benchmark,
+    results_to_json as _results_to_json,
+    matches_to_svg as _matches_to_svg,
+    export_results as _export_results,
+    save_match_sheet as _save_match_sheet,
+    set_image_cache_size as _set_image_cache_size,
+    clear_image_cache as _clear_image_cache,
+    image_cache_info as _image_cache_info,
+    set_result_cache as _set_result_cache,
+    clear_result_cache as _clear_result_cache,
+    result_cache_info as _result_cache_info,
     set_num_threads,
+    set_parallel_threshold as _set_parallel_threshold,
     version as _version,
 )
 
-from typing import Optional, List, Union
+import contextlib
+import os
+import sys
+from typing import BinaryIO, Callable, Dict, Iterable, Iterator, Optional, List, Tuple, Union
+
+
+def _bank_options(options: dict) -> None:
+    """Reject matching options for a TemplateBank, whose variants are already prepared."""
+    if options:
+        raise TypeError(
+            f"matching options are not supported with a TemplateBank: {', '.join(sorted(options))}"
+        )
+
+
+# Record layout of ``as_array=True`` results, matching find_all_templates_packed
+MATCH_DTYPE = [("x", "<i4"), ("y", "<i4"), ("confidence", "<f8")]
+
+
+def _match_array(packed: bytes):
+    """View packed match records as a read-only numpy structured array."""
+    try:
+        import numpy as np
+    except ImportError:
+        raise ImportError("as_array=True requires numpy (pip install rustmatch[numpy])") from None
+    return np.frombuffer(packed, dtype=MATCH_DTYPE)
 
 
 def find(
     source: str,
-    template: str,
+    template: Union[str, TemplateBank],
     threshold: float = 0.8,
+    **options,
 ) -> Optional[MatchResult]:
     """
     Find single best match using file paths.
@@ -88,49 +299,161 @@ def find(
     
     Args:
         source: Path to source image file (PNG, JPEG, BMP, etc.)
-        template: Path to template image file
+        template: Path to template image file, or a TemplateBank
         threshold: Matching threshold (0.0-1.0), default 0.8
+        **options: Matching options such as ``autocrop=True`` (see docs/api.md);
+            not accepted with a TemplateBank
     
     Returns:
-        MatchResult if found, None otherwise
+        MatchResult if found, None otherwise; ``(result, MatchDiagnostics)``
+        with ``return_diagnostics=True``
     
     Example:
         >>> result = rustmatch.find("screen.png", "button.png")
         >>> if result:
         ...     print(f"Found at ({result.x}, {result.y}), confidence: {result.confidence:.2%}")
     """
-    return _find_template(source, template, threshold)
+    if isinstance(template, TemplateBank):
+        _bank_options(options)
+        return template.find(source, threshold)
+    return _find_template(source, template, threshold, **options)
 
 
 def find_all(
     source: str,
-    template: str,
+    template: Union[str, TemplateBank],
     threshold: float = 0.8,
     max_count: int = 10,
+    as_array: bool = False,
+    **options,
 ) -> List[MatchResult]:
     """
     Find all matches using file paths.
     
     Args:
         source: Path to source image file
-        template: Path to template image file
+        template: Path to template image file, or a TemplateBank
         threshold: Matching threshold (0.0-1.0), default 0.8
         max_count: Maximum number of matches to return, default 10
+        as_array: Return a numpy structured array with ``x``, ``y`` and
+            ``confidence`` fields (see ``MATCH_DTYPE``) instead of
+            MatchResult objects; much faster for thousands of matches
+        **options: Matching options such as ``autocrop=True`` (see docs/api.md);
+            not accepted with a TemplateBank
     
     Returns:
-        List of MatchResult objects, sorted by confidence (descending)
+        List of MatchResult objects, sorted by confidence (descending), or
+        a numpy structured array in the same order with ``as_array=True``;
+        ``(results, MatchDiagnostics)`` with ``return_diagnostics=True``
     
     Example:
         >>> results = rustmatch.find_all("screen.png", "star.png", max_count=5)
         >>> print(f"Found {len(results)} stars")
+        >>> dots = rustmatch.find_all("plate.png", "dot.png", max_count=100000, as_array=True)
+        >>> dots["x"].mean(), dots["confidence"].min()
+    """
+    if isinstance(template, TemplateBank):
+        _bank_options(options)
+        if as_array:
+            raise TypeError("as_array=True is not supported with a TemplateBank")
+        return template.find_all(source, threshold, max_count)
+    if as_array:
+        return _match_array(_find_all_templates_packed(source, template, threshold, max_count, **options))
+    return _find_all_templates(source, template, threshold, max_count, **options)
+
+
+def find_tiled(
+    source: str,
+    template: str,
+    threshold: float = 0.8,
+    tile_size: int = 2048,
+    **options,
+) -> Optional[MatchResult]:
+    """
+    Find single best match in a very large image, processing it tile by tile.
+    
+    PNG and TIFF sources are decoded to 8-bit grayscale one band of
+    ``tile_size`` rows at a time and searched in overlapping tiles, so peak
+    memory grows with ``tile_size`` times the source width rather than with
+    the source size. Use this for gigapixel scans that don't fit in memory
+    with :func:`find`.
+    
+    Args:
+        source: Path to source image file
+        template: Path to template image file
+        threshold: Matching threshold (0.0-1.0), default 0.8
+        tile_size: Tile edge length in pixels (must be >= template size), default 2048
+        **options: Matching options such as ``autocrop=True`` (see docs/api.md)
+    
+    Returns:
+        MatchResult if found, None otherwise
+    """
+    return _find_template_tiled(source, template, threshold, tile_size, **options)
+
+
+def find_all_tiled(
+    source: str,
+    template: str,
+    threshold: float = 0.8,
+    max_count: int = 10,
+    tile_size: int = 2048,
+    **options,
+) -> List[MatchResult]:
+    """
+    Find all matches in a very large image, processing it tile by tile.
+    
+    Args:
+        source: Path to source image file
+        template: Path to template image file
+        threshold: Matching threshold (0.0-1.0), default 0.8
+        max_count: Maximum number of matches to return, default 10
+        tile_size: Tile edge length in pixels (must be >= template size), default 2048
+        **options: Matching options such as ``autocrop=True`` (see docs/api.md)
+    
+    Returns:
+        List of MatchResult objects, sorted by confidence (descending)
     """
-    return _find_all_templates(source, template, threshold, max_count)
+    return _find_all_templates_tiled(source, template, threshold, max_count, tile_size, **options)
+
+
+def find_pages(
+    path: str,
+    template: Union[str, bytes],
+    threshold: float = 0.8,
+    **options,
+) -> List[Tuple[int, MatchResult]]:
+    """
+    Find the best match on every page of a multi-page TIFF.
+    
+    Pages are decoded one at a time; pages smaller than the template are
+    skipped. Files in other formats are searched as a single page 0.
+    
+    Args:
+        path: Path to TIFF file
+        template: Template as file path or encoded image bytes
+        threshold: Matching threshold (0.0-1.0), default 0.8
+        **options: Matching options such as ``autocrop=True`` (see docs/api.md)
+    
+    Returns:
+        List of ``(page_index, MatchResult)`` for pages with a match
+    
+    Example:
+        >>> for page, r in rustmatch.find_pages("scan.tiff", "stamp.png"):
+        ...     print(f"stamp on page {page + 1} at ({r.x}, {r.y})")
+    """
+    return _find_template_pages(path, template, threshold, **options)
+
+
+def _read_all(data: Union[bytes, BinaryIO]) -> bytes:
+    """Contents of a file-like object, for the calls that only take bytes."""
+    return data.read() if hasattr(data, "read") else data
 
 
 def find_bytes(
-    source: bytes,
-    template: bytes,
+    source: Union[bytes, BinaryIO],
+    template: Union[bytes, BinaryIO, TemplateBank],
     threshold: float = 0.8,
+    **options,
 ) -> Optional[MatchResult]:
     """
     Find single best match using image bytes.
@@ -138,12 +461,15 @@ def find_bytes(
     Useful when you have image data in memory (e.g., from screenshot capture).
     
     Args:
-        source: Source image as bytes (PNG, JPEG, etc. encoded)
-        template: Template image as bytes
+        source: Source image as bytes (PNG, JPEG, etc. encoded), or a binary
+            file-like object, which is read in chunks on the Rust side
+        template: Template image as bytes or a file-like object, or a TemplateBank
         threshold: Matching threshold (0.0-1.0), default 0.8
+        **options: Matching options such as ``autocrop=True`` (see docs/api.md)
     
     Returns:
-        MatchResult if found, None otherwise
+        MatchResult if found, None otherwise; ``(result, MatchDiagnostics)``
+        with ``return_diagnostics=True``
     
     Example:
         >>> with open("screen.png", "rb") as f:
@@ -151,39 +477,91 @@ def find_bytes(
         >>> with open("button.png", "rb") as f:
         ...     template = f.read()
         >>> result = rustmatch.find_bytes(source, template)
+        >>> # Or straight from a database BLOB
+        >>> with db.blobopen("shots", "png", row_id, readonly=True) as blob:
+        ...     result = rustmatch.find_bytes(blob, template)
     """
-    return _find_template_bytes(source, template, threshold)
+    if isinstance(template, TemplateBank):
+        _bank_options(options)
+        return template.find(_read_all(source), threshold)
+    return _find_template_bytes(source, template, threshold, **options)
 
 
 def find_all_bytes(
-    source: bytes,
-    template: bytes,
+    source: Union[bytes, BinaryIO],
+    template: Union[bytes, BinaryIO, TemplateBank],
     threshold: float = 0.8,
     max_count: int = 10,
+    as_array: bool = False,
+    **options,
 ) -> List[MatchResult]:
     """
     Find all matches using image bytes.
     
     Args:
-        source: Source image as bytes
-        template: Template image as bytes
+        source: Source image as bytes or a binary file-like object
+        template: Template image as bytes or a file-like object, or a TemplateBank
         threshold: Matching threshold (0.0-1.0), default 0.8
         max_count: Maximum number of matches, default 10
+        as_array: Return a numpy structured array instead (see ``find_all``)
+        **options: Matching options such as ``autocrop=True`` (see docs/api.md)
     
     Returns:
-        List of MatchResult objects
+        List of MatchResult objects, or a numpy array with ``as_array=True``;
+        ``(results, MatchDiagnostics)`` with ``return_diagnostics=True``
     """
-    return _find_all_templates_bytes(source, template, threshold, max_count)
+    if isinstance(template, TemplateBank):
+        _bank_options(options)
+        if as_array:
+            raise TypeError("as_array=True is not supported with a TemplateBank")
+        return template.find_all(_read_all(source), threshold, max_count)
+    if as_array:
+        packed = _find_all_templates_packed(_read_all(source), _read_all(template), threshold, max_count, **options)
+        return _match_array(packed)
+    return _find_all_templates_bytes(source, template, threshold, max_count, **options)
+
+
+# Raw pixels: a byte buffer (read in place), a list of ints, a SharedMemory
+# block or the name of one
+Pixels = Union[bytes, bytearray, memoryview, List[int], str]
+
+
+@contextlib.contextmanager
+def _pixel_view(pixels):
+    """Yield ``pixels`` as something the raw API reads without copying.
+
+    A ``multiprocessing.shared_memory.SharedMemory`` block is passed as its
+    ``buf``; a string names a block to attach to for the duration of the call.
+    """
+    if not isinstance(pixels, str):
+        yield getattr(pixels, "buf", pixels)
+        return
+    from multiprocessing import shared_memory
+    if sys.version_info >= (3, 13):
+        shm = shared_memory.SharedMemory(name=pixels, track=False)
+    else:
+        shm = shared_memory.SharedMemory(name=pixels)
+        if os.name != "nt":
+            # Attaching registers the block with this process's resource
+            # tracker, which would unlink it at exit; the producer owns it
+            from multiprocessing import resource_tracker
+            resource_tracker.unregister(shm._name, "shared_memory")
+    try:
+        yield shm.buf
+    finally:
+        shm.close()
 
 
 def find_raw(
-    source_pixels: Union[bytes, List[int]],
+    source_pixels: Pixels,
     source_width: int,
     source_height: int,
-    template_pixels: Union[bytes, List[int]],
+    template_pixels: Pixels,
     template_width: int,
     template_height: int,
     threshold: float = 0.8,
+    source_offset: Optional[int] = None,
+    **options,
 ) -> Optional[MatchResult]:
     """
     Find single match using raw grayscale pixel data.
@@ -191,74 +569,1442 @@ def find_raw(
     For advanced users who want to handle image loading themselves.
     
     Args:
-        source_pixels: Grayscale pixels as bytes or list (row-major, 0-255)
+        source_pixels: Grayscale pixels (row-major, 0-255) as a list or any
+            contiguous byte buffer (bytes, bytearray, memoryview, numpy uint8),
+            which is read in place; or a ``SharedMemory`` block or its name
         source_width: Source image width
         source_height: Source image height
-        template_pixels: Template grayscale pixels
+        template_pixels: Template grayscale pixels, in any of the same forms
         template_width: Template width
         template_height: Template height
         threshold: Matching threshold (0.0-1.0)
+        source_offset: Byte offset of the frame inside ``source_pixels``, e.g.
+            a slot of a ring buffer; the buffer may then be larger than one frame
+        **options: Matching options such as ``autocrop=True`` (see docs/api.md)
     
     Returns:
         MatchResult if found, None otherwise
+    
+    Example:
+        >>> # Worker process: match a frame the producer wrote to shared memory
+        >>> result = rustmatch.find_raw("frames", w, h, tpl, tw, th, source_offset=slot * w * h)
     """
-    src = list(source_pixels) if isinstance(source_pixels, bytes) else source_pixels
-    tpl = list(template_pixels) if isinstance(template_pixels, bytes) else template_pixels
-    return _find_template_raw(src, source_width, source_height, tpl, template_width, template_height, threshold)
+    with _pixel_view(source_pixels) as src, _pixel_view(template_pixels) as tpl:
+        return _find_template_raw(
+            src, source_width, source_height, tpl, template_width, template_height, threshold,
+            source_offset=source_offset, **options,
+        )
 
 
 def find_all_raw(
-    source_pixels: Union[bytes, List[int]],
+    source_pixels: Pixels,
     source_width: int,
     source_height: int,
-    template_pixels: Union[bytes, List[int]],
+    template_pixels: Pixels,
     template_width: int,
     template_height: int,
     threshold: float = 0.8,
     max_count: int = 10,
+    source_offset: Optional[int] = None,
+    **options,
 ) -> List[MatchResult]:
     """
     Find all matches using raw grayscale pixel data.
+    
+    Pixels are accepted in the same forms as ``find_raw``.
     """
-    src = list(source_pixels) if isinstance(source_pixels, bytes) else source_pixels
-    tpl = list(template_pixels) if isinstance(template_pixels, bytes) else template_pixels
-    return _find_all_templates_raw(src, source_width, source_height, tpl, template_width, template_height, threshold, max_count)
+    with _pixel_view(source_pixels) as src, _pixel_view(template_pixels) as tpl:
+        return _find_all_templates_raw(
+            src, source_width, source_height, tpl, template_width, template_height, threshold, max_count,
+            source_offset=source_offset, **options,
+        )
 
 
-def get_size(path: str) -> tuple:
+def find_yuv(
+    frame: bytes,
+    width: int,
+    height: int,
+    template: Union[str, bytes],
+    threshold: float = 0.8,
+    stride: Optional[int] = None,
+    **options,
+) -> Optional[MatchResult]:
     """
-    Get image dimensions from file.
+    Find single match in a planar YUV frame from a capture card or decoder.
+    
+    The Y plane (first in NV12, NV21, I420 and YV12 buffers) is used
+    directly as luma, with no colour conversion; chroma is ignored.
     
     Args:
-        path: Path to image file
+        frame: Frame buffer, or just its Y plane
+        width: Frame width in pixels
+        height: Frame height in pixels
+        template: Template as file path or encoded image bytes
+        threshold: Matching threshold (0.0-1.0), default 0.8
+        stride: Bytes per Y row including padding, default ``width``
+        **options: Matching options such as ``autocrop=True`` (see docs/api.md)
     
     Returns:
-        Tuple of (width, height)
+        MatchResult if found, None otherwise
     """
-    return _get_image_size(path)
+    return _find_template_yuv(frame, width, height, template, threshold, stride, **options)
 
 
-def get_size_bytes(data: bytes) -> tuple:
+def find_all_yuv(
+    frame: bytes,
+    width: int,
+    height: int,
+    template: Union[str, bytes],
+    threshold: float = 0.8,
+    max_count: int = 10,
+    stride: Optional[int] = None,
+    **options,
+) -> List[MatchResult]:
     """
-    Get image dimensions from bytes.
+    Find all matches in a planar YUV frame, using its Y plane as luma.
+    """
+    return _find_all_templates_yuv(frame, width, height, template, threshold, max_count, stride, **options)
+
+
+def find_tensor(
+    source,
+    template,
+    threshold: float = 0.8,
+    **options,
+) -> Optional[MatchResult]:
+    """
+    Find single match in a tensor, read in place without a bytes round-trip.
     
     Args:
-        data: Image data as bytes
+        source: Object exposing ``__array_interface__`` (numpy) or
+            ``__dlpack__`` (CPU torch tensor, ...), shaped (H, W) or
+            (H, W, 1|3|4), of dtype uint8, uint16, float32 or float64
+            (floats in 0-1; pass ``float_scale=1`` for 0-255); a file path
+            or image bytes also work
+        template: Template in any of the same forms
+        threshold: Matching threshold (0.0-1.0)
+        **options: Matching options such as ``autocrop=True`` (see docs/api.md)
     
     Returns:
-        Tuple of (width, height)
+        MatchResult if found, None otherwise
+    
+    Example:
+        >>> frame = model(batch)[0, 0].detach()  # (H, W) float32 on the CPU
+        >>> result = rustmatch.find_tensor(frame, "logo.png")
     """
-    return _get_image_size_bytes(data)
+    return _find_template_tensor(source, template, threshold, **options)
 
 
-def set_threads(num: int = 0) -> None:
+def find_all_tensor(
+    source,
+    template,
+    threshold: float = 0.8,
+    max_count: int = 10,
+    **options,
+) -> List[MatchResult]:
     """
-    Set number of threads for parallel processing.
+    Find all matches in a tensor, read in place.
+    """
+    return _find_all_templates_tensor(source, template, threshold, max_count, **options)
+
+
+def find_np(
+    source_array,
+    template_array,
+    threshold: float = 0.8,
+    **options,
+) -> Optional[MatchResult]:
+    """
+    Find single match in numpy arrays, without flattening them for find_raw.
     
     Args:
-        num: Number of threads (0 = auto-detect based on CPU cores)
+        source_array: C-contiguous numpy array shaped (H, W) or (H, W, 1|3|4),
+            of dtype uint8, uint16, float32 or float64 (floats in 0-1; pass
+            ``float_scale=1`` for 0-255)
+        template_array: Template array with the same requirements
+        threshold: Matching threshold (0.0-1.0)
+        **options: Matching options such as ``autocrop=True`` (see docs/api.md)
+    
+    Returns:
+        MatchResult if found, None otherwise
+    
+    Raises:
+        TypeError: If an argument isn't an array or has an unsupported dtype
+        ValueError: If an array isn't C-contiguous
+        InvalidDimensionsError: If an array isn't (H, W) or (H, W, 1|3|4)
+    
+    Example:
+        >>> frame = np.asarray(Image.open("screen.png"))  # (H, W, 3) uint8
+        >>> result = rustmatch.find_np(frame, frame[40:72, 100:132])
     """
-    set_num_threads(num)
+    return _find_template_np(source_array, template_array, threshold, **options)
+
+
+def find_all_np(
+    source_array,
+    template_array,
+    threshold: float = 0.8,
+    max_count: int = 10,
+    **options,
+) -> List[MatchResult]:
+    """
+    Find all matches in numpy arrays.
+    """
+    return _find_all_templates_np(source_array, template_array, threshold, max_count, **options)
+
+
+def find_iter(
+    source: Union[str, bytes],
+    template: Union[str, bytes],
+    threshold: float = 0.8,
+    **options,
+) -> Iterator[MatchResult]:
+    """
+    Iterate over matches in descending confidence, without a result cap.
+    
+    The source is scanned once when the iterator is created; overlapping
+    matches are suppressed one result at a time as you consume them, so you
+    can stop as soon as your own criteria are met.
+    
+    Args:
+        source: Source image as file path or encoded bytes
+        template: Template image as file path or encoded bytes
+        threshold: Matching threshold (0.0-1.0)
+        **options: Matching options such as ``autocrop=True`` (see docs/api.md)
+    
+    Returns:
+        Iterator of MatchResult, strongest first
+    
+    Example:
+        >>> for r in rustmatch.find_iter("screen.png", "row.png", threshold=0.7):
+        ...     if r.y > 600:
+        ...         break
+        ...     print(r)
+    """
+    return _find_iter(source, template, threshold, **options)
+
+
+def find_next(
+    source: Union[str, bytes],
+    template: Union[str, bytes],
+    found: Iterable[Union[MatchResult, Tuple[int, int, int, int]]],
+    threshold: float = 0.8,
+    **options,
+) -> Optional[MatchResult]:
+    """
+    Find the best match outside the regions you have already handled.
+    
+    Matches whose centre falls inside a found region are skipped. A previous
+    MatchResult covers the template's box at its position, the neighbourhood
+    ``find_iter`` suppresses around a match; boxes exclude any other region,
+    so there is no need to blank out the source.
+    
+    Args:
+        source: Source image as file path or encoded bytes
+        template: Template image as file path or encoded bytes
+        found: Previous MatchResult objects and ``(x, y, width, height)`` boxes
+        threshold: Matching threshold (0.0-1.0)
+        **options: Matching options such as ``autocrop=True`` (see docs/api.md)
+    
+    Returns:
+        MatchResult if found, None otherwise
+    
+    Example:
+        >>> handled = []
+        >>> while (r := rustmatch.find_next("screen.png", "row.png", handled)) is not None:
+        ...     process(r)
+        ...     handled.append(r)
+    """
+    return _find_next(source, template, list(found), threshold, **options)
+
+
+def find_clusters(
+    source: Union[str, bytes],
+    template: Union[str, bytes],
+    threshold: float = 0.8,
+    max_count: int = 10,
+    **options,
+) -> List[MatchCluster]:
+    """
+    Find matches, merging nearby detections into clusters.
+    
+    Instead of keeping only the strongest of overlapping detections, every
+    detection above the threshold is grouped with those it overlaps (closer
+    than half the template size in both axes, transitively). Each cluster
+    reports its confidence-weighted centroid, member count and score spread,
+    which is steadier than single peaks on noisy repeated patterns.
+    
+    Args:
+        source: Source image as file path or encoded bytes
+        template: Template image as file path or encoded bytes
+        threshold: Matching threshold (0.0-1.0)
+        max_count: Maximum number of clusters to return
+        **options: Matching options such as ``autocrop=True`` (see docs/api.md)
+    
+    Returns:
+        List of MatchCluster, strongest first
+    
+    Example:
+        >>> for c in rustmatch.find_clusters("board.png", "stone.png", threshold=0.7):
+        ...     print(f"({c.x:.1f}, {c.y:.1f}) from {c.count} detections")
+    """
+    return _find_clusters(source, template, threshold, max_count, **options)
+
+
+def find_repeats(
+    source: Union[str, bytes],
+    patch_size: int = 32,
+    threshold: float = 0.9,
+    stride: Optional[int] = None,
+) -> List[List[MatchResult]]:
+    """
+    Find regions that repeat within one image.
+    
+    Square patches are taken from the source every ``stride`` pixels and
+    matched against the source itself. To stay fast, a patch is only scored
+    against windows with a similar coarse layout (signs of its 4x4 block
+    means); flat patches are skipped. A repeat found from several seeds is
+    reported once.
+    
+    Args:
+        source: Source image as file path or encoded bytes
+        patch_size: Side of the square regions compared, at least 8
+        threshold: Matching threshold (0.0-1.0), default 0.9
+        stride: Spacing of the seed patches, default ``patch_size // 2``
+    
+    Returns:
+        List of groups, largest first. Each group lists the top-left corners
+        of one repeated region as MatchResult, the seed patch first.
+    
+    Example:
+        >>> for group in rustmatch.find_repeats("form.png", patch_size=24):
+        ...     print([(r.x, r.y) for r in group])
+    """
+    return _find_repeats(source, patch_size, threshold, stride)
+
+
+def find_rotated(
+    source: Union[str, bytes],
+    template: Union[str, bytes],
+    threshold: float = 0.8,
+    angles: Optional[Iterable[float]] = None,
+    candidates: int = 8,
+) -> Optional[MatchResult]:
+    """
+    Find a template at an unknown rotation.
+    
+    Every window is first compared to the template by its ring projection
+    (grey-level statistics of concentric rings, which rotation doesn't
+    change). Only the ``candidates`` best window centres are then searched
+    with each rotated template, so adding angles costs little.
+    
+    Args:
+        source: Source image as file path or encoded bytes
+        template: Template as file path or encoded image bytes (at least 9x9)
+        threshold: Matching threshold (0.0-1.0), default 0.8
+        angles: Rotations to try in degrees, clockwise; default every 10 degrees
+        candidates: Number of prescreened centres to verify, default 8
+    
+    Returns:
+        MatchResult with ``angle`` set, or None. ``x``/``y`` is the top-left
+        corner of the rotated template's bounding box.
+    
+    Example:
+        >>> r = rustmatch.find_rotated("table.png", "card.png", angles=range(0, 360, 5))
+        >>> if r:
+        ...     print(f"card at ({r.x}, {r.y}), rotated {r.angle} degrees")
+    """
+    angle_list = None if angles is None else [float(a) for a in angles]
+    return _find_rotated(source, template, threshold, angle_list, candidates)
+
+
+def register_rotscale(
+    img_a: Union[str, bytes],
+    img_b: Union[str, bytes],
+    template: Optional[Union[str, bytes]] = None,
+    threshold: float = 0.8,
+) -> RotScale:
+    """
+    Estimate the global rotation and scale between two images.
+    
+    Fourier-Mellin registration: rotating or zooming an image rotates or
+    scales its magnitude spectrum, which becomes a plain shift in log-polar
+    coordinates and is found by phase correlation. This covers camera roll
+    and zoom without sweeping angles and scales. Images are registered at
+    most 256 pixels across.
+    
+    Args:
+        img_a: Reference image as file path or encoded bytes
+        img_b: Rotated / zoomed image as file path or encoded bytes
+        template: Optional template cut from ``img_a``; it is scaled and
+            rotated by the estimate and searched in ``img_b``
+        threshold: Matching threshold for the template search (0.0-1.0)
+    
+    Returns:
+        RotScale mapping ``img_a`` onto ``img_b``; ``template_match`` holds
+        the template's MatchResult (or None)
+    
+    Example:
+        >>> reg = rustmatch.register_rotscale("before.png", "after.png", template="button.png")
+        >>> print(f"rotated {reg.angle:.1f} degrees, zoom {reg.scale:.2f}")
+        >>> if reg.template_match:
+        ...     print(reg.template_match.x, reg.template_match.y)
+    """
+    return _register_rotscale(img_a, img_b, template, threshold)
+
+
+def refine_alignment(
+    source: Union[str, bytes],
+    template: Union[str, bytes],
+    result: MatchResult,
+    motion: str = "translation",
+    max_iterations: int = 50,
+    epsilon: float = 1e-5,
+) -> Alignment:
+    """
+    Refine a match to a sub-pixel warp for measurement.
+    
+    Enhanced correlation coefficient (ECC) maximization, like OpenCV's
+    ``findTransformECC``: starting from ``result``, the warp is updated until
+    the NCC between the template and the warped source stops improving.
+    Rotated or scaled results (``TemplateBank``, ``find_rotated``) start
+    from their angle and scale.
+    
+    Args:
+        source: Source image as file path or encoded bytes
+        template: Template image as file path or encoded bytes
+        result: MatchResult to start from, within a pixel or two of the target
+        motion: ``"translation"``, ``"euclidean"`` (rotation and shift) or
+            ``"affine"``
+        max_iterations: Maximum number of warp updates
+        epsilon: Stop once the correlation changes less than this
+    
+    Returns:
+        Alignment with the 2x3 ``warp``, sub-pixel ``x``/``y`` and final
+        ``correlation``
+    
+    Example:
+        >>> r = rustmatch.find("board.png", "fiducial.png")
+        >>> a = rustmatch.refine_alignment("board.png", "fiducial.png", r)
+        >>> print(f"fiducial at ({a.x:.2f}, {a.y:.2f})")
+    """
+    return _refine_alignment(source, template, result, motion, max_iterations, epsilon)
+
+
+def find_perspective(
+    source: Union[str, bytes],
+    template: Union[str, bytes],
+    threshold: float = 0.7,
+    min_inliers: int = 8,
+    max_keypoints: int = 2000,
+) -> Optional[PerspectiveMatch]:
+    """
+    Find a template seen under perspective, e.g. in a photo of a screen.
+    
+    Translation search fails once the camera looks at the screen at an
+    angle. Here Harris corners of both images are matched by patch NCC,
+    RANSAC estimates the homography most matches agree with, and the
+    template is verified by NCC against the source sampled through it.
+    
+    Args:
+        source: Source image as file path or encoded bytes
+        template: Template image as file path or encoded bytes
+        threshold: Minimum verification NCC (0.0-1.0)
+        min_inliers: Minimum number of keypoint matches agreeing with the
+            homography (at least 4)
+        max_keypoints: Keypoints detected in the source, and in the
+            template across its scales
+    
+    Returns:
+        PerspectiveMatch with the projected ``corners``, the ``homography``,
+        an inlier-based ``confidence`` and the verification ``score``, or
+        None if no homography is supported and verified
+    
+    Example:
+        >>> m = rustmatch.find_perspective("photo.jpg", "dialog.png")
+        >>> if m:
+        ...     print(m.corners, m.confidence)
+    """
+    return _find_perspective(source, template, threshold, min_inliers, max_keypoints)
+
+
+def assert_template_found(
+    source: Union[str, bytes],
+    template: Union[str, bytes],
+    threshold: float = 0.8,
+    artifact_dir: Optional[Union[str, os.PathLike]] = None,
+    **options,
+) -> MatchResult:
+    """
+    Assert that a template is found, for pytest suites.
+    
+    On failure the AssertionError message gives the best score reached and
+    where, so a near miss (threshold too strict) is told apart from a
+    template that isn't on screen at all.
+    
+    Args:
+        source: Source image as file path or encoded bytes
+        template: Template image as file path or encoded bytes
+        threshold: Matching threshold (0.0-1.0)
+        artifact_dir: Directory to save a failure report PNG in: the source
+            with the best window outlined, the template above that window,
+            and a score heatmap. Its path is added to the message.
+        **options: Matching options such as ``autocrop=True`` (see docs/api.md)
+    
+    Returns:
+        The MatchResult, when found
+    
+    Raises:
+        AssertionError: If the best match is below ``threshold``
+    
+    Example:
+        >>> def test_login_button(tmp_path):
+        ...     rustmatch.assert_template_found("screen.png", "login.png", artifact_dir=tmp_path)
+    """
+    return _assert_template_found(source, template, threshold, artifact_dir, **options)
+
+
+def find_in_directory(
+    dir_path: str,
+    template: Union[str, bytes],
+    pattern: str = "*.png",
+    threshold: float = 0.8,
+    max_count: int = 1,
+    recursive: bool = False,
+    max_workers: Optional[int] = None,
+    return_timings: bool = False,
+    **options,
+) -> Union[Dict[str, List[MatchResult]], Tuple[Dict[str, List[MatchResult]], Dict[str, float]]]:
+    """
+    Find a template in every matching image of a directory.
+    
+    The directory is walked in Rust, the template is prepared once and the
+    images are decoded and matched in parallel without the GIL, which is much
+    faster than calling :func:`find` per file. Decoding and matching are
+    pipelined: decoder threads keep the matching stage fed while holding
+    only a bounded number of decoded images in memory.
+    
+    Args:
+        dir_path: Directory to search
+        template: Template as file path or encoded image bytes
+        pattern: Wildcard file names must match (``*`` and ``?``, case-sensitive)
+        threshold: Matching threshold (0.0-1.0), default 0.8
+        max_count: Maximum matches per image; 1 (default) keeps the best match only
+        recursive: Also search subdirectories
+        max_workers: Number of decoder threads and of matching threads in a
+            pool owned by this call (0 = one per CPU); None (default) matches
+            on the global pool with one decoder per global thread
+        return_timings: Also return the milliseconds spent decoding and
+            matching each file, e.g. for :func:`export_results`
+        **options: Matching options such as ``autocrop=True`` (see docs/api.md)
+    
+    Returns:
+        Dict of path relative to ``dir_path`` (``/`` separated) to its list of
+        MatchResult, strongest first; empty for images without a match. With
+        ``return_timings=True``, a tuple of that dict and a dict of path to
+        milliseconds
+    
+    Example:
+        >>> results = rustmatch.find_in_directory("screenshots", "logo.png", recursive=True)
+        >>> missing = [name for name, found in results.items() if not found]
+    """
+    return _find_in_directory(
+        dir_path, template, pattern, threshold, max_count, recursive, max_workers, return_timings, **options
+    )
+
+
+def find_in_animation(
+    image: Union[str, bytes],
+    template: Union[str, bytes],
+    threshold: float = 0.8,
+    **options,
+) -> List[Tuple[int, int, MatchResult]]:
+    """
+    Find a template in every frame of an animated GIF, WebP or PNG.
+    
+    Frames are composited as they would be displayed and matched one at a
+    time. Still images are treated as a single frame with delay 0.
+    
+    Args:
+        image: Animation as file path or encoded bytes
+        template: Template as file path or encoded image bytes
+        threshold: Matching threshold (0.0-1.0), default 0.8
+        **options: Matching options such as ``autocrop=True`` (see docs/api.md)
+    
+    Returns:
+        List of ``(frame_index, delay_ms, MatchResult)`` for frames with a match
+    
+    Example:
+        >>> for index, delay, r in rustmatch.find_in_animation("sticker.webp", "watermark.png"):
+        ...     print(f"watermark in frame {index} at ({r.x}, {r.y})")
+    """
+    return _find_in_animation(image, template, threshold, **options)
+
+
+def find_in_video(
+    video_path: str,
+    template: Union[str, bytes],
+    threshold: float = 0.8,
+    frame_step: int = 1,
+) -> List[Tuple[int, float, MatchResult]]:
+    """
+    Find a template in the frames of a video file.
+    
+    Decoding and matching run entirely in Rust. Requires rustmatch to be
+    built with the ``video`` feature (FFmpeg); otherwise RuntimeError is raised.
+    
+    Args:
+        video_path: Path to video file
+        template: Template as file path or encoded image bytes
+        threshold: Matching threshold (0.0-1.0), default 0.8
+        frame_step: Only match every N-th frame, default 1
+    
+    Returns:
+        List of (frame_index, timestamp_seconds, MatchResult) for frames with a match
+    
+    Example:
+        >>> for index, ts, r in rustmatch.find_in_video("capture.mp4", "logo.png", frame_step=5):
+        ...     print(f"frame {index} ({ts:.2f}s): ({r.x}, {r.y})")
+    """
+    return _find_in_video(video_path, template, threshold, frame_step)
+
+
+def find_url(
+    source_url: str,
+    template: Union[str, bytes],
+    threshold: float = 0.8,
+    timeout: float = 30.0,
+    **options,
+) -> Optional[MatchResult]:
+    """
+    Find single match in an image downloaded from an http(s) URL.
+    
+    The download happens in Rust with the GIL released. Requires rustmatch
+    to be built with the ``http`` feature; otherwise RuntimeError is raised.
+    
+    Args:
+        source_url: URL of the source image
+        template: Template as file path or encoded image bytes
+        threshold: Matching threshold (0.0-1.0), default 0.8
+        timeout: Seconds allowed for the whole request; TimeoutError past it
+        **options: Matching options such as ``autocrop=True`` (see docs/api.md)
+    
+    Returns:
+        MatchResult if found, None otherwise
+    
+    Example:
+        >>> result = rustmatch.find_url("http://shots.local/latest.png", "button.png", timeout=5)
+    """
+    return _find_template_url(source_url, template, threshold, timeout, **options)
+
+
+def watch_camera(
+    device_index: int,
+    template: Union[str, bytes],
+    callback: Callable[[int, MatchResult], Optional[bool]],
+    threshold: float = 0.8,
+    frame_step: int = 1,
+    max_frames: Optional[int] = None,
+) -> int:
+    """
+    Watch a live camera and report where the template appears.
+    
+    Frames are captured and matched on a Rust worker thread, so there is no
+    per-frame Python overhead. Requires rustmatch to be built with the
+    ``capture`` feature; otherwise RuntimeError is raised.
+    
+    Args:
+        device_index: Camera index (0 = default camera)
+        template: Template as file path or encoded image bytes
+        callback: Called as ``callback(frame_index, result)`` on every detection;
+            return True to stop watching
+        threshold: Matching threshold (0.0-1.0), default 0.8
+        frame_step: Only match every N-th frame, default 1
+        max_frames: Stop after this many frames, default None (until stopped or Ctrl+C)
+    
+    Returns:
+        Number of frames captured
+    
+    Example:
+        >>> def on_match(index, r):
+        ...     print(f"frame {index}: ({r.x}, {r.y})")
+        ...     return True  # stop after the first detection
+        >>> rustmatch.watch_camera(0, "badge.png", on_match)
+    """
+    return _watch_camera(device_index, template, callback, threshold, frame_step, max_frames)
+
+
+def get_size(path: str) -> tuple:
+    """
+    Get image dimensions from file.
+    
+    Args:
+        path: Path to image file
+    
+    Returns:
+        Tuple of (width, height)
+    """
+    return _get_image_size(path)
+
+
+def get_size_bytes(data: bytes) -> tuple:
+    """
+    Get image dimensions from bytes.
+    
+    Args:
+        data: Image data as bytes
+    
+    Returns:
+        Tuple of (width, height)
+    """
+    return _get_image_size_bytes(data)
+
+
+def compare_images(
+    a: Union[str, bytes],
+    b: Union[str, bytes],
+    method: str = "ssim",
+) -> float:
+    """
+    Compare two whole images of the same size.
+    
+    A cheap "are these basically the same screenshot?" check before
+    running a template search.
+    
+    Args:
+        a: First image as file path or encoded bytes
+        b: Second image as file path or encoded bytes
+        method: "ssim" (0-1, default), "psnr" (dB, inf if identical) or "ncc" (-1 to 1)
+    
+    Returns:
+        Similarity score, higher means more similar
+    
+    Example:
+        >>> if rustmatch.compare_images("before.png", "after.png") > 0.98:
+        ...     print("screen unchanged")
+    """
+    return _compare_images(a, b, method)
+
+
+def diff_images(
+    baseline: Union[str, bytes],
+    current: Union[str, bytes],
+    tolerance: float = 16,
+    min_area: int = 1,
+    merge_distance: int = 4,
+    return_image: bool = False,
+) -> Union[List[Tuple[int, int, int, int]], Tuple[List[Tuple[int, int, int, int]], bytes]]:
+    """
+    Find the regions that changed between two screenshots of the same size.
+    
+    Pixels whose grey levels differ by more than ``tolerance`` count as
+    changed; changes up to ``merge_distance`` pixels apart are grouped and
+    reported as the bounding box of their changed pixels.
+    
+    Args:
+        baseline: Reference image as file path or encoded bytes
+        current: New image as file path or encoded bytes
+        tolerance: Grey-level difference ignored per pixel (anti-aliasing, compression)
+        min_area: Smallest number of changed pixels a region must have
+        merge_distance: Changes up to this many pixels apart share one box
+        return_image: Also return a PNG of ``current`` with the changes
+            in red and the boxes outlined
+    
+    Returns:
+        List of ``(x, y, width, height)`` boxes, top to bottom, or
+        ``(boxes, png_bytes)`` with ``return_image=True``
+    
+    Example:
+        >>> boxes = rustmatch.diff_images("golden.png", "screenshot.png", min_area=20)
+        >>> assert not boxes, f"unexpected changes at {boxes}"
+    """
+    return _diff_images(baseline, current, tolerance, min_area, merge_distance, return_image)
+
+
+def compare_to_golden(
+    golden: Union[str, bytes],
+    current: Union[str, bytes],
+    tolerance: float = 16,
+    ignore_regions: Optional[List[Tuple[int, int, int, int]]] = None,
+    max_shift: int = 4,
+    min_area: int = 1,
+    merge_distance: int = 4,
+) -> dict:
+    """
+    Check a screenshot against its golden image for visual regressions.
+    
+    ``current`` is aligned to ``golden`` by the shift (up to ``max_shift``
+    pixels per axis) with the highest NCC, so a page rendered a pixel or
+    two off does not fail the whole comparison. The overlap is then diffed
+    like :func:`diff_images`, with ``ignore_regions`` masked out.
+    
+    Args:
+        golden: Reference image as file path or encoded bytes
+        current: New image of the same size as file path or encoded bytes
+        tolerance: Grey-level difference ignored per pixel (anti-aliasing, compression)
+        ignore_regions: ``(x, y, width, height)`` boxes in golden coordinates
+            that may change freely, e.g. clocks or ads
+        max_shift: Largest shift in pixels searched on each axis
+        min_area: Smallest number of changed pixels a region must have
+        merge_distance: Changes up to this many pixels apart share one region
+    
+    Returns:
+        Dict with ``passed``, ``shift`` (dx, dy of ``current`` relative to
+        ``golden``), ``alignment_score`` and ``regions`` (per region: ``x``,
+        ``y``, ``width``, ``height`` in golden coordinates, ``score`` (NCC
+        of the region), ``max_diff`` and ``changed_pixels``)
+    
+    Example:
+        >>> report = rustmatch.compare_to_golden("golden.png", "screenshot.png",
+        ...                                      ignore_regions=[(1200, 0, 80, 24)])
+        >>> assert report["passed"], report["regions"]
+    """
+    return _compare_to_golden(
+        golden, current, tolerance, ignore_regions or [], max_shift, min_area, merge_distance
+    )
+
+
+def phash(image: Union[str, bytes]) -> int:
+    """
+    Perceptual (DCT) hash of an image.
+    
+    Similar images have hashes with a small Hamming distance, which makes
+    this a microsecond-level prefilter for deduplicating screenshots.
+    
+    Args:
+        image: Image as file path or encoded bytes
+    
+    Returns:
+        64-bit hash as int
+    """
+    return _phash(image)
+
+
+def dhash(image: Union[str, bytes]) -> int:
+    """
+    Difference hash of an image (gradient directions of a 9x8 thumbnail).
+    
+    Args:
+        image: Image as file path or encoded bytes
+    
+    Returns:
+        64-bit hash as int
+    """
+    return _dhash(image)
+
+
+def hamming_distance(a: int, b: int) -> int:
+    """
+    Number of differing bits between two 64-bit hashes.
+    
+    Example:
+        >>> if rustmatch.hamming_distance(rustmatch.phash(a), rustmatch.phash(b)) <= 6:
+        ...     print("near-duplicate")
+    """
+    return _hamming_distance(a, b)
+
+
+def autocrop(
+    image: Union[str, bytes],
+    tolerance: float = 2.0,
+) -> Tuple[int, int, int, int]:
+    """
+    Locate the content of a template with flat margins around it.
+    
+    Border rows and columns whose grey levels vary by at most ``tolerance``
+    (and match the rest of the margin) are stripped. Passing
+    ``autocrop=True`` to any find function applies the same crop and
+    corrects the returned coordinates automatically.
+    
+    Args:
+        image: Template as file path or encoded bytes
+        tolerance: Maximum grey-level spread of a margin row/column, default 2.0
+    
+    Returns:
+        Tuple of (x, y, width, height); (x, y) is the offset of the trimmed
+        template within the original
+    
+    Example:
+        >>> x, y, w, h = rustmatch.autocrop("button_with_margin.png")
+    """
+    return _autocrop(image, tolerance)
+
+
+def validate_template(
+    template: Union[str, bytes],
+    min_std: float = 5.0,
+    min_entropy: float = 0.2,
+) -> dict:
+    """
+    Check whether a template has enough contrast to match reliably.
+    
+    Nearly uniform templates produce confidences dominated by noise. Pass
+    ``strict=True`` to the find functions to run this check (with the default
+    limits) before matching and raise TemplateQualityError instead.
+    
+    Args:
+        template: Template as file path or encoded bytes
+        min_std: Minimum grey-level standard deviation
+        min_entropy: Minimum grey-level histogram entropy in bits
+    
+    Returns:
+        Dict with ``std``, ``entropy``, ``valid`` and ``issues`` (list of
+        human-readable reasons, empty when valid)
+    
+    Example:
+        >>> report = rustmatch.validate_template("button.png")
+        >>> if not report["valid"]:
+        ...     print("; ".join(report["issues"]))
+    """
+    return _validate_template(template, min_std, min_entropy)
+
+
+def calibrate_threshold(
+    template: Union[str, bytes],
+    negative_sources: List[Union[str, bytes]],
+    false_positive_rate: float = 0.001,
+) -> dict:
+    """
+    Suggest a matching threshold from images known not to contain the template.
+    
+    Every window position of every negative image is scored with plain NCC;
+    the suggested threshold is the score reached by only
+    ``false_positive_rate`` of those windows (resolution 1e-4).
+    
+    Args:
+        template: Template as file path or encoded bytes
+        negative_sources: Images (paths or bytes) that don't contain the template
+        false_positive_rate: Tolerated fraction of negative windows at or above the threshold
+    
+    Returns:
+        Dict with ``threshold``, ``max_score`` (best score on any negative),
+        ``windows`` (number of scored positions) and ``false_positive_rate``
+        (rate actually achieved on the negatives)
+    
+    Example:
+        >>> cal = rustmatch.calibrate_threshold("button.png", ["empty1.png", "empty2.png"])
+        >>> result = rustmatch.find("screen.png", "button.png", threshold=max(cal["threshold"], 0.5))
+    """
+    return _calibrate_threshold(template, negative_sources, false_positive_rate)
+
+
+def template_quality(
+    template: Union[str, bytes],
+    background_sources: Optional[List[Union[str, bytes]]] = None,
+    threshold: float = 0.8,
+    margin: float = 0.05,
+    min_std: float = 5.0,
+    min_entropy: float = 0.2,
+) -> dict:
+    """
+    Report how distinctive a template is before shipping it.
+    
+    Runs the contrast checks of :func:`validate_template` and scores the
+    template with plain NCC at every window position of each background
+    image. The template's own occurrence (a window scoring at least 0.99,
+    e.g. in the screenshot it was cut from) is left out, so the best
+    remaining window is the strongest false positive it would produce.
+    
+    Args:
+        template: Template as file path or encoded bytes
+        background_sources: Images (paths or bytes) the template will be searched in
+        threshold: Matching threshold the template will be used with
+        margin: Flag spurious scores within this distance below ``threshold``
+        min_std: Minimum grey-level standard deviation
+        min_entropy: Minimum grey-level histogram entropy in bits
+    
+    Returns:
+        Dict with ``std``, ``entropy``, ``max_spurious_score`` (None without
+        backgrounds), ``backgrounds`` (per image: ``max_score``, ``x``, ``y``
+        and ``contains_template``), ``distinct`` and ``issues``
+    
+    Example:
+        >>> report = rustmatch.template_quality("ok.png", ["screen1.png", "screen2.png"])
+        >>> if not report["distinct"]:
+        ...     print("; ".join(report["issues"]))
+    """
+    return _template_quality(template, background_sources or [], threshold, margin, min_std, min_entropy)
+
+
+def suggest_template_region(
+    source: Union[str, bytes],
+    x: int,
+    y: int,
+    size_range: Tuple[int, int] = (16, 64),
+) -> Optional[dict]:
+    """
+    Suggest the most distinctive template region around a target point.
+    
+    Candidate rectangles containing the point, with sides from
+    ``size_range`` and aspect ratios up to 2, are checked for contrast like
+    :func:`validate_template` and scored by their best NCC with any other
+    non-overlapping window of the image. The candidate least similar to the
+    rest of the image wins.
+    
+    Args:
+        source: Screenshot as file path or encoded bytes
+        x: Target x coordinate, e.g. where the script should click
+        y: Target y coordinate
+        size_range: ``(min_side, max_side)`` of the candidate rectangles
+    
+    Returns:
+        Dict with ``x``, ``y``, ``width``, ``height``, ``self_similarity``,
+        ``std``, ``entropy`` and ``candidates``, or None if no candidate has
+        enough contrast
+    
+    Example:
+        >>> region = rustmatch.suggest_template_region("screen.png", 430, 236)
+        >>> box = (region["x"], region["y"], region["width"], region["height"])
+        >>> template = rustmatch.Template.from_region("screen.png", *box, save_path="ok.png")
+    """
+    return _suggest_template_region(source, x, y, size_range)
+
+
+def score_sweep(
+    source: Union[str, bytes],
+    template: Union[str, bytes],
+    thresholds: Optional[List[float]] = None,
+) -> List[Tuple[float, int]]:
+    """
+    Count how many non-overlapping matches each threshold would return.
+    
+    The source is scanned once and suppression runs once, instead of one
+    ``find_all`` call per threshold.
+    
+    Args:
+        source: Source image as file path or encoded bytes
+        template: Template image as file path or encoded bytes
+        thresholds: Thresholds to report, default [0.5, 0.55, ..., 0.95]
+    
+    Returns:
+        List of (threshold, match count) in the order given
+    
+    Example:
+        >>> for threshold, count in rustmatch.score_sweep("screen.png", "icon.png"):
+        ...     print(f"{threshold:.2f}: {count}")
+    """
+    return _score_sweep(source, template, thresholds)
+
+
+def score_grid(
+    source: Union[str, bytes],
+    template: Union[str, bytes],
+    cell: int = 32,
+) -> List[List[float]]:
+    """
+    Best NCC score per ``cell`` x ``cell`` block, for live overlays.
+    
+    Much smaller than a full score map: a 1920x1080 screen gives a 60x34
+    grid at the default cell size.
+    
+    Args:
+        source: Source image as file path or encoded bytes
+        template: Template image as file path or encoded bytes
+        cell: Cell size in pixels
+    
+    Returns:
+        Rows of per-cell maxima; ``grid[j][i]`` covers match positions
+        (top-left corners) ``x`` in ``[i * cell, (i + 1) * cell)`` and ``y``
+        in ``[j * cell, (j + 1) * cell)``
+    
+    Example:
+        >>> grid = rustmatch.score_grid("screen.png", "icon.png", cell=16)
+        >>> hot = [(i * 16, j * 16) for j, row in enumerate(grid) for i, s in enumerate(row) if s > 0.7]
+    """
+    return _score_grid(source, template, cell)
+
+
+def explain_match(
+    source: Union[str, bytes],
+    template: Union[str, bytes],
+    x: int,
+    y: int,
+    block: int = 1,
+    return_image: bool = False,
+) -> Union[List[List[float]], Tuple[List[List[float]], bytes]]:
+    """
+    Show which parts of the template agree with the source at a position.
+    
+    The NCC score of the window at ``(x, y)`` is a sum of one term per
+    template pixel; this returns those terms summed over ``block`` x
+    ``block`` cells. Positive cells raised the score, negative cells (e.g.
+    an occluded or changed part) lowered it.
+    
+    Args:
+        source: Source image as file path or encoded bytes
+        template: Template image as file path or encoded bytes
+        x: Left edge of the window, e.g. ``result.x``
+        y: Top edge of the window
+        block: Cell size in template pixels (1 = per pixel)
+        return_image: Also return a PNG of the window with agreeing cells
+            tinted green and disagreeing cells red
+    
+    Returns:
+        Rows of per-cell contributions, which sum to the NCC score, or
+        ``(rows, png_bytes)`` with ``return_image=True``
+    
+    Example:
+        >>> r = rustmatch.find("screen.png", "button.png", threshold=0.5)
+        >>> rows = rustmatch.explain_match("screen.png", "button.png", r.x, r.y, block=8)
+        >>> worst = min((c, i, j) for j, row in enumerate(rows) for i, c in enumerate(row))
+    """
+    return _explain_match(source, template, x, y, block, return_image)
+
+
+def estimate_scale(
+    source: Union[str, bytes],
+    template: Union[str, bytes],
+    scale_candidates: Optional[List[float]] = None,
+) -> Optional[Tuple[float, float]]:
+    """
+    Estimate how much larger or smaller the template appears in the source.
+    
+    Useful when templates were captured at 1x DPI but the source comes from a
+    scaled desktop. Each candidate factor is tried at a shared coarse pyramid
+    level. Pass ``auto_scale=True`` to the find functions to apply the best
+    factor automatically.
+    
+    Args:
+        source: Source image as file path or encoded bytes
+        template: Template image as file path or encoded bytes
+        scale_candidates: Factors to try, default [0.5, 0.75, 1.0, 1.25, 1.5, 1.75, 2.0, 2.5]
+    
+    Returns:
+        Tuple of (scale, coarse score), or None if no scaled template fits the source
+    
+    Example:
+        >>> scale, score = rustmatch.estimate_scale("screen_150.png", "button_100.png")
+    """
+    return _estimate_scale(source, template, scale_candidates)
+
+
+def crop_image(
+    image: Union[str, bytes],
+    x: int,
+    y: int,
+    width: int,
+    height: int,
+    out_path: Optional[str] = None,
+) -> Optional[bytes]:
+    """
+    Cut a rectangle out of an image, e.g. the box of a match result.
+    
+    Args:
+        image: Image as file path or encoded bytes
+        x, y: Top-left corner of the rectangle
+        width, height: Rectangle size; it must lie inside the image
+        out_path: Save the crop to this file instead (format from extension)
+    
+    Returns:
+        PNG bytes, or None when ``out_path`` is given
+    
+    Example:
+        >>> result = rustmatch.find("screen.png", "button.png")
+        >>> png = rustmatch.crop_image("screen.png", *result.bbox(80, 24))
+    """
+    return _crop_image(image, x, y, width, height, out_path)
+
+
+def resize_image(
+    image: Union[str, bytes],
+    width: int,
+    height: int,
+    filter: str = "lanczos",
+) -> bytes:
+    """
+    Resize an image to an exact size, e.g. to bring a template to the UI scale.
+    
+    Args:
+        image: Image as file path or encoded bytes
+        width: New width in pixels
+        height: New height in pixels (aspect ratio is not preserved)
+        filter: "nearest", "bilinear", "bicubic", "gaussian" or "lanczos" (default)
+    
+    Returns:
+        PNG bytes
+    
+    Example:
+        >>> w, h = rustmatch.get_size("button.png")
+        >>> scaled = rustmatch.resize_image("button.png", round(w * 1.5), round(h * 1.5))
+        >>> result = rustmatch.find_bytes(screen_bytes, scaled)
+    """
+    return _resize_image(image, width, height, filter)
+
+
+def to_grayscale(image: Union[str, bytes]) -> Tuple[bytes, int, int]:
+    """
+    Decode an image to raw 8-bit grayscale pixels.
+    
+    Uses exactly the conversion the matcher applies internally, so the
+    result can be cached and passed to ``find_raw`` / ``find_all_raw``.
+    
+    Args:
+        image: Image as file path or encoded bytes
+    
+    Returns:
+        Tuple of (pixels, width, height) with one row-major byte per pixel
+    
+    Example:
+        >>> pixels, w, h = rustmatch.to_grayscale("screen.png")
+        >>> tpl, tw, th = rustmatch.to_grayscale("button.png")
+        >>> result = rustmatch.find_raw(pixels, w, h, tpl, tw, th)
+    """
+    return _to_grayscale(image)
+
+
+def get_image_info(image: Union[str, bytes]) -> dict:
+    """
+    Describe an image so inputs can be validated before matching.
+    
+    Args:
+        image: Image as file path or encoded bytes
+    
+    Returns:
+        Dict with ``width``, ``height``, ``format`` (e.g. "png", "jpeg"),
+        ``color_type`` ("gray", "gray_alpha", "rgb" or "rgba"), ``channels``,
+        ``bit_depth`` (bits per channel), ``has_alpha`` and ``orientation``
+        (EXIF orientation 1-8, or None if the image is not tagged)
+    
+    Example:
+        >>> info = rustmatch.get_image_info("photo.jpg")
+        >>> if info["orientation"] not in (None, 1):
+        ...     print("image is stored rotated")
+    """
+    return _get_image_info(image)
+
+
+def benchmark(
+    source: Union[str, bytes],
+    template: Union[str, bytes],
+    repeats: int = 10,
+    threshold: float = 0.8,
+    **options,
+) -> dict:
+    """
+    Time the matching pipeline stage by stage.
+    
+    Stages are "decode", "grayscale", "preprocess" (matching options such as
+    ``denoise``), "integral", "coarse" and "refine" (a breakdown of the plain
+    NCC pyramid search), "match" (the full option-aware search) and "total"
+    (decode + grayscale + preprocess + match).
+    
+    Args:
+        source: Source image as file path or encoded bytes
+        template: Template image as file path or encoded bytes
+        repeats: Number of timed runs, default 10
+        threshold: Matching threshold (0.0-1.0)
+        **options: Matching options such as ``autocrop=True`` (see docs/api.md)
+    
+    Returns:
+        Dict with ``repeats``, ``result`` (MatchResult or None) and ``stages``
+        mapping each stage name to ``{"min_ms": ..., "median_ms": ...}``
+    
+    Example:
+        >>> report = rustmatch.benchmark("screen.png", "button.png", pyramid_filter="gaussian")
+        >>> for stage, t in report["stages"].items():
+        ...     print(f"{stage:>10}: {t['median_ms']:.2f} ms")
+    """
+    return _benchmark(source, template, repeats, threshold, **options)
+
+
+def results_to_json(results: List[MatchResult]) -> str:
+    """
+    Serialize match results as a JSON array.
+    
+    Each element is the object produced by ``MatchResult.to_json()``;
+    crops are not included.
+    
+    Args:
+        results: Results from find_all / find_iter (or any list of MatchResult)
+    
+    Returns:
+        JSON string such as ``[{"x":10,"y":20,"confidence":0.97}]``
+    """
+    return _results_to_json(results)
+
+
+def matches_to_svg(
+    matches: List[MatchResult],
+    template_size: Tuple[int, int],
+    image_size: Tuple[int, int],
+) -> str:
+    """
+    Render match results as an SVG layer to overlay on the source image.
+    
+    Each match becomes a ``<g class="match">`` with an outline ``<rect>``
+    (carrying ``data-confidence``) and a ``<text>`` confidence label, so a
+    web frontend can draw results over the screenshot without rasterizing.
+    
+    Args:
+        matches: Results to draw, e.g. from :func:`find_all`
+        template_size: ``(width, height)`` of the template; boxes are scaled
+            by each result's ``scale``
+        image_size: ``(width, height)`` of the source, used as the viewport
+    
+    Returns:
+        SVG document as a string
+    
+    Example:
+        >>> hits = rustmatch.find_all("screen.png", "icon.png")
+        >>> svg = rustmatch.matches_to_svg(hits, rustmatch.get_size("icon.png"), rustmatch.get_size("screen.png"))
+    """
+    return _matches_to_svg(matches, template_size, image_size)
+
+
+def export_results(
+    results: Union[Dict[str, Union[None, MatchResult, List[MatchResult]]], List[MatchResult]],
+    path: str,
+    format: str = "json",
+    template: Optional[Union[str, bytes]] = None,
+    timings: Optional[Dict[str, float]] = None,
+) -> int:
+    """
+    Write batch results to a JSON or CSV report with a fixed schema.
+    
+    Every row has the columns ``file``, ``template``, ``x``, ``y``,
+    ``confidence``, ``width``, ``height`` and ``time_ms``, in that order.
+    Files without a match get one row whose match columns are empty (CSV)
+    or null (JSON). JSON reports are ``{"version": 1, "results": [...]}``.
+    
+    Args:
+        results: Dict of file to results (as from :func:`find_in_directory`,
+            values may also be a single MatchResult or None) or a list of
+            MatchResult
+        path: Report file to write
+        format: "json" (default) or "csv"
+        template: Template the results came from, as file path or encoded
+            bytes; its path fills ``template`` and its size ``width`` and ``height``
+        timings: Dict of file to milliseconds filling ``time_ms``, as from
+            ``find_in_directory(..., return_timings=True)``
+    
+    Returns:
+        Number of rows written
+    
+    Example:
+        >>> results, timings = rustmatch.find_in_directory("shots", "logo.png", return_timings=True)
+        >>> rustmatch.export_results(results, "report.csv", format="csv", template="logo.png", timings=timings)
+    """
+    return _export_results(results, path, format, template, timings)
+
+
+def save_match_sheet(
+    source: Union[str, bytes],
+    matches: List[MatchResult],
+    template_size: Tuple[int, int],
+    out_path: str,
+    columns: int = 6,
+) -> None:
+    """
+    Save a contact sheet of matched regions for quick visual review.
+    
+    Each match is cropped from the source at ``template_size`` and placed in
+    a grid cell labelled with its ``x,y`` position and confidence. Matches
+    with a ``scale`` are cropped at the scaled size and resized back.
+    
+    Args:
+        source: Source image the matches were found in, as file path or bytes
+        matches: MatchResult list, e.g. from :func:`find_all`, laid out in order
+        template_size: ``(width, height)`` of the template, e.g. from :func:`get_size`
+        out_path: Image file to write (format from extension)
+        columns: Cells per row, default 6
+    
+    Example:
+        >>> hits = rustmatch.find_all("board.png", "pin.png", max_count=30)
+        >>> rustmatch.save_match_sheet("board.png", hits, rustmatch.get_size("pin.png"), "pins.png")
+    """
+    _save_match_sheet(source, matches, template_size, out_path, columns)
+
+
+def set_image_cache_size(size: int = 8) -> None:
+    """
+    Set how many decoded images loaded by file path are kept in memory.
+    
+    Repeated calls on the same file skip decoding while its modification
+    time and size are unchanged; the least recently used image is evicted first.
+    
+    Args:
+        size: Maximum number of cached images (0 disables the cache), default 8
+    """
+    _set_image_cache_size(size)
+
+
+def clear_image_cache() -> None:
+    """Drop every cached image and reset the hit/miss counters."""
+    _clear_image_cache()
+
+
+def image_cache_info() -> dict:
+    """
+    Decoded image cache statistics.
+    
+    Returns:
+        Dict with ``hits``, ``misses``, ``size`` (images cached) and ``capacity``
+    """
+    return _image_cache_info()
+
+
+def set_result_cache(size: int, ttl: Optional[float] = None) -> None:
+    """
+    Cache the results of ``find``, ``find_all``, ``find_bytes`` and ``find_all_bytes``.
+    
+    Calls with the same inputs, threshold, ``max_count`` and options return
+    the stored results without decoding or searching. Files are recognised by
+    path, modification time and size, bytes by their content. The cache is
+    disabled by default.
+    
+    Args:
+        size: Maximum number of cached calls (0 disables the cache); the
+            least recently used call is evicted first
+        ttl: Seconds a result stays valid, default None (until evicted);
+            changing it drops the cached results, and 0 caches nothing
+    """
+    _set_result_cache(size, ttl)
+
+
+def clear_result_cache() -> None:
+    """Drop every cached result and reset the hit/miss counters."""
+    _clear_result_cache()
+
+
+def result_cache_info() -> dict:
+    """
+    Result cache statistics.
+    
+    Returns:
+        Dict with ``hits``, ``misses``, ``size`` (calls cached), ``capacity``
+        and ``ttl``
+    """
+    return _result_cache_info()
+
+
+def set_threads(num: int = 0) -> None:
+    """
+    Set number of threads for parallel processing.
+    
+    Args:
+        num: Number of threads (0 = auto-detect based on CPU cores)
+    """
+    set_num_threads(num)
+
+
+def set_parallel_threshold(min_work: Optional[int] = None) -> None:
+    """
+    Set how much work a search needs before it is spread over the thread pool.
+    
+    Parallel loops are split into tasks of at least ``min_work`` pixel
+    comparisons (window positions times template pixels). Searches smaller
+    than one task, such as a 20x20 template in a 200x150 crop, run entirely
+    on the calling thread: they skip the fan-out cost and don't oversubscribe
+    the cores when many Python threads match small crops at once.
+    
+    Args:
+        min_work: Minimum comparisons per task; 0 parallelizes every search
+            and None restores the default (262144, about 0.2 ms of work)
+    
+    Example:
+        >>> rustmatch.set_parallel_threshold(0)  # always fan out
+    """
+    _set_parallel_threshold(min_work)
 
 
 def version() -> str:
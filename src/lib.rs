@@ -9,44 +9,327 @@
 
 use image::{DynamicImage, GrayImage, GenericImageView};
 use pyo3::prelude::*;
-use pyo3::exceptions::{PyValueError, PyIOError};
-use pyo3::types::PyBytes;
+use pyo3::buffer::PyBuffer;
+use pyo3::exceptions::{PyAssertionError, PyKeyError, PyValueError, PyIOError, PyRuntimeError, PyTypeError};
+use pyo3::pyclass::CompareOp;
+use pyo3::types::{PyBytes, PyCapsule, PyDict, PyIterator, PyType};
 use rayon::prelude::*;
-use std::io::Cursor;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+// ============================================================================
+// Exceptions
+// ============================================================================
+
+// All derive from ValueError, so existing `except ValueError` handlers keep working
+pyo3::create_exception!(_core, RustMatchError, PyValueError, "Base class of rustmatch errors");
+pyo3::create_exception!(_core, DecodeError, RustMatchError, "Image data could not be decoded");
+pyo3::create_exception!(_core, TemplateTooLargeError, RustMatchError, "Template is larger than the source");
+pyo3::create_exception!(_core, InvalidDimensionsError, RustMatchError, "Sizes or coordinates are inconsistent");
+pyo3::create_exception!(_core, TemplateQualityError, RustMatchError, "Template has too little contrast to match reliably");
+
+/// Missing/unreadable files stay `OSError`, undecodable contents become `DecodeError`
+fn load_error(path: &str, e: image::ImageError) -> PyErr {
+    let message = format!("Failed to load image '{}': {}", path, e);
+    match e {
+        image::ImageError::IoError(_) => PyIOError::new_err(message),
+        _ => DecodeError::new_err(message),
+    }
+}
 
 // ============================================================================
 // Data Structures
 // ============================================================================
 
 /// Match result containing position and confidence score
-#[pyclass]
+#[pyclass(module = "rustmatch._core")]
 #[derive(Clone)]
 pub struct MatchResult {
     #[pyo3(get)]
-    pub x: u32,
+    pub x: i32,
     #[pyo3(get)]
-    pub y: u32,
+    pub y: i32,
     #[pyo3(get)]
     pub confidence: f64,
+    /// Matched window as PNG bytes, filled in when `return_crop=True`
+    pub crop: Option<Vec<u8>>,
+    /// Scale factor of the `TemplateBank` variant that matched
+    #[pyo3(get)]
+    pub scale: Option<f64>,
+    /// Rotation in degrees of the `TemplateBank` variant that matched
+    #[pyo3(get)]
+    pub angle: Option<f64>,
+    /// Physical pixels per logical pixel (the `pixel_ratio` option)
+    #[pyo3(get)]
+    pub pixel_ratio: f64,
+    /// Mirroring of the template that matched with the `flip` option:
+    /// "horizontal", "vertical" or "both"
+    #[pyo3(get)]
+    pub flip: Option<String>,
+    /// True when the inverted template matched (the `invert` option)
+    #[pyo3(get)]
+    pub inverted: bool,
 }
 
 #[pymethods]
 impl MatchResult {
+    #[new]
+    #[pyo3(signature = (x, y, confidence, crop=None, scale=None, angle=None, pixel_ratio=1.0, flip=None, inverted=false))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        x: i32, y: i32, confidence: f64, crop: Option<&[u8]>, scale: Option<f64>, angle: Option<f64>, pixel_ratio: f64,
+        flip: Option<String>, inverted: bool,
+    ) -> Self {
+        Self { x, y, confidence, crop: crop.map(|data| data.to_vec()), scale, angle, pixel_ratio, flip, inverted }
+    }
+    
+    /// Rebuild through the constructor, so results survive pickle and copy
+    #[allow(clippy::type_complexity)]
+    fn __reduce__<'py>(
+        &self, py: Python<'py>,
+    ) -> (&'py PyType, (i32, i32, f64, Option<&'py PyBytes>, Option<f64>, Option<f64>, f64, Option<String>, bool)) {
+        let args = (
+            self.x, self.y, self.confidence, self.crop(py), self.scale, self.angle, self.pixel_ratio, self.flip.clone(),
+            self.inverted,
+        );
+        (py.get_type::<MatchResult>(), args)
+    }
+    
     fn __repr__(&self) -> String {
-        format!("MatchResult(x={}, y={}, confidence={:.4})", self.x, self.y, self.confidence)
+        let mut extra = match (self.scale, self.angle) {
+            (Some(scale), Some(angle)) => format!(", scale={}, angle={}", scale, angle),
+            _ => String::new(),
+        };
+        if self.pixel_ratio != 1.0 {
+            extra += &format!(", pixel_ratio={}", self.pixel_ratio);
+        }
+        if let Some(flip) = &self.flip {
+            extra += &format!(", flip='{}'", flip);
+        }
+        if self.inverted {
+            extra += ", inverted=True";
+        }
+        format!("MatchResult(x={}, y={}, confidence={:.4}{})", self.x, self.y, self.confidence, extra)
     }
     
     fn __str__(&self) -> String {
         self.__repr__()
     }
     
-    fn to_tuple(&self) -> (u32, u32, f64) {
+    /// `==` compares position and confidence (the crop is ignored); ordering compares confidence only
+    fn __richcmp__(&self, other: &PyAny, op: CompareOp, py: Python) -> PyObject {
+        let Ok(other) = other.extract::<PyRef<MatchResult>>() else { return py.NotImplemented() };
+        let same = self.x == other.x && self.y == other.y && self.confidence == other.confidence;
+        match op {
+            CompareOp::Eq => same.into_py(py),
+            CompareOp::Ne => (!same).into_py(py),
+            CompareOp::Lt => (self.confidence < other.confidence).into_py(py),
+            CompareOp::Le => (self.confidence <= other.confidence).into_py(py),
+            CompareOp::Gt => (self.confidence > other.confidence).into_py(py),
+            CompareOp::Ge => (self.confidence >= other.confidence).into_py(py),
+        }
+    }
+    
+    fn __hash__(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        // + 0.0 folds -0.0 into 0.0 so equal results always hash alike
+        (self.x, self.y, (self.confidence + 0.0).to_bits()).hash(&mut hasher);
+        hasher.finish()
+    }
+    
+    /// Check whether two results agree within tolerances
+    ///
+    /// Args:
+    ///     other: Result to compare against
+    ///     pixel_tolerance: Maximum allowed difference in x and in y
+    ///     confidence_tolerance: Maximum allowed confidence difference
+    ///
+    /// Returns:
+    ///     True if both coordinates and the confidence are within tolerance
+    #[pyo3(signature = (other, pixel_tolerance=1, confidence_tolerance=0.01))]
+    fn is_close(&self, other: PyRef<MatchResult>, pixel_tolerance: u32, confidence_tolerance: f64) -> bool {
+        self.x.abs_diff(other.x) <= pixel_tolerance
+            && self.y.abs_diff(other.y) <= pixel_tolerance
+            && (self.confidence - other.confidence).abs() <= confidence_tolerance
+    }
+    
+    fn to_tuple(&self) -> (i32, i32, f64) {
         (self.x, self.y, self.confidence)
     }
     
-    fn bbox(&self, width: u32, height: u32) -> (u32, u32, u32, u32) {
+    fn bbox(&self, width: u32, height: u32) -> (i32, i32, u32, u32) {
         (self.x, self.y, width, height)
     }
+    
+    /// X coordinate in logical (device-independent) pixels: `x / pixel_ratio`
+    #[getter]
+    fn logical_x(&self) -> f64 {
+        self.x as f64 / self.pixel_ratio
+    }
+    
+    /// Y coordinate in logical (device-independent) pixels: `y / pixel_ratio`
+    #[getter]
+    fn logical_y(&self) -> f64 {
+        self.y as f64 / self.pixel_ratio
+    }
+    
+    /// Bounding box in logical pixels, for a template `width` x `height` physical pixels
+    fn logical_bbox(&self, width: u32, height: u32) -> (f64, f64, f64, f64) {
+        let r = self.pixel_ratio;
+        (self.logical_x(), self.logical_y(), width as f64 / r, height as f64 / r)
+    }
+    
+    /// Matched window as PNG bytes (only set when matching with `return_crop=True`)
+    #[getter]
+    fn crop<'py>(&self, py: Python<'py>) -> Option<&'py PyBytes> {
+        self.crop.as_deref().map(|data| PyBytes::new(py, data))
+    }
+    
+    /// Fields as a dict (`crop`, `scale`, `angle`, `flip` and `inverted` are only
+    /// included when set, `pixel_ratio` and the logical coordinates when the ratio isn't 1)
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        let dict = PyDict::new(py);
+        dict.set_item("x", self.x)?;
+        dict.set_item("y", self.y)?;
+        dict.set_item("confidence", self.confidence)?;
+        if let Some(crop) = self.crop(py) {
+            dict.set_item("crop", crop)?;
+        }
+        if let Some(scale) = self.scale {
+            dict.set_item("scale", scale)?;
+        }
+        if let Some(angle) = self.angle {
+            dict.set_item("angle", angle)?;
+        }
+        if let Some(flip) = &self.flip {
+            dict.set_item("flip", flip)?;
+        }
+        if self.inverted {
+            dict.set_item("inverted", true)?;
+        }
+        if self.pixel_ratio != 1.0 {
+            dict.set_item("pixel_ratio", self.pixel_ratio)?;
+            dict.set_item("logical_x", self.logical_x())?;
+            dict.set_item("logical_y", self.logical_y())?;
+        }
+        Ok(dict)
+    }
+    
+    /// Fields as a compact JSON object (the binary crop is left out)
+    fn to_json(&self) -> String {
+        self.json()
+    }
+}
+
+impl MatchResult {
+    fn json(&self) -> String {
+        // JSON has no NaN/inf; emit null rather than an unparseable document
+        let number = |v: f64| if v.is_finite() { v.to_string() } else { "null".to_string() };
+        let mut json = format!("{{\"x\":{},\"y\":{},\"confidence\":{}", self.x, self.y, number(self.confidence));
+        if let Some(scale) = self.scale {
+            json += &format!(",\"scale\":{}", number(scale));
+        }
+        if let Some(angle) = self.angle {
+            json += &format!(",\"angle\":{}", number(angle));
+        }
+        if let Some(flip) = &self.flip {
+            json += &format!(",\"flip\":\"{}\"", flip);
+        }
+        if self.inverted {
+            json += ",\"inverted\":true";
+        }
+        if self.pixel_ratio != 1.0 {
+            json += &format!(
+                ",\"pixel_ratio\":{},\"logical_x\":{},\"logical_y\":{}",
+                number(self.pixel_ratio), number(self.logical_x()), number(self.logical_y())
+            );
+        }
+        json + "}"
+    }
+}
+
+/// What a search did, returned alongside the results with `return_diagnostics=True`
+#[pyclass(frozen)]
+pub struct MatchDiagnostics {
+    /// Downsampling factor of the coarse pyramid level, None when the
+    /// template was too small for the pyramid or the search was exhaustive
+    #[pyo3(get)]
+    pub pyramid_scale: Option<usize>,
+    /// Best score on the coarse level (before refinement)
+    #[pyo3(get)]
+    pub coarse_score: Option<f64>,
+    /// Full-resolution position of the best coarse score
+    #[pyo3(get)]
+    pub coarse_position: Option<(usize, usize)>,
+    /// Window positions scored, at every pyramid level
+    #[pyo3(get)]
+    pub windows_evaluated: usize,
+    /// Of those, windows given up before their full score was computed
+    #[pyo3(get)]
+    pub windows_pruned: usize,
+    /// Full-resolution windows `(x1, y1, x2, y2)` of top-left positions
+    /// searched after the coarse level
+    #[pyo3(get)]
+    pub refinement_windows: Vec<(usize, usize, usize, usize)>,
+    timings: Vec<(&'static str, f64)>,
+}
+
+impl MatchDiagnostics {
+    fn new(stats: &SearchStats) -> Self {
+        let detail = stats.detail();
+        Self {
+            pyramid_scale: detail.scale,
+            coarse_score: detail.coarse.map(|(score, _, _)| score),
+            coarse_position: detail.coarse.map(|(_, x, y)| (x, y)),
+            windows_evaluated: stats.evaluated.load(std::sync::atomic::Ordering::Relaxed),
+            windows_pruned: stats.pruned.load(std::sync::atomic::Ordering::Relaxed),
+            refinement_windows: detail.windows.clone(),
+            timings: detail.timings.clone(),
+        }
+    }
+}
+
+#[pymethods]
+impl MatchDiagnostics {
+    /// Milliseconds spent in each stage that ran
+    #[getter]
+    fn timings<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        let dict = PyDict::new(py);
+        for (stage, ms) in &self.timings {
+            dict.set_item(*stage, ms)?;
+        }
+        Ok(dict)
+    }
+
+    fn __repr__(&self) -> String {
+        let coarse = match (self.pyramid_scale, self.coarse_score) {
+            (Some(scale), Some(score)) => format!("pyramid_scale={}, coarse_score={:.4}, ", scale, score),
+            _ => String::new(),
+        };
+        format!(
+            "MatchDiagnostics({}windows_evaluated={}, windows_pruned={}, refinement_windows={})",
+            coarse, self.windows_evaluated, self.windows_pruned, self.refinement_windows.len()
+        )
+    }
+
+    /// Attributes as a dict, with `timings` as a nested dict
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        let dict = PyDict::new(py);
+        dict.set_item("pyramid_scale", self.pyramid_scale)?;
+        dict.set_item("coarse_score", self.coarse_score)?;
+        dict.set_item("coarse_position", self.coarse_position)?;
+        dict.set_item("windows_evaluated", self.windows_evaluated)?;
+        dict.set_item("windows_pruned", self.windows_pruned)?;
+        dict.set_item("refinement_windows", self.refinement_windows.clone())?;
+        dict.set_item("timings", self.timings(py)?)?;
+        Ok(dict)
+    }
 }
 
 /// Internal grayscale image wrapper
@@ -66,16 +349,38 @@ impl GrayImageData {
     fn from_dynamic(img: &DynamicImage) -> Self {
         Self::from_gray_image(&img.to_luma8())
     }
+
+    /// Luma with the grey levels in `(lo, hi)` stretched over 0-255 and the
+    /// rest clipped, without rounding to 8 bits. Levels are in the image's
+    /// own units: 0-65535 for 16-bit (and float) images, 0-255 otherwise.
+    fn from_dynamic_windowed(img: &DynamicImage, (lo, hi): (f64, f64)) -> Self {
+        let (w, h) = img.dimensions();
+        let map = |v: f64| ((v - lo) / (hi - lo) * 255.0).clamp(0.0, 255.0);
+        let color = img.color();
+        let data = if color.bytes_per_pixel() > color.channel_count() {
+            img.to_luma16().as_raw().iter().map(|&v| map(v as f64)).collect()
+        } else {
+            img.to_luma8().as_raw().iter().map(|&v| map(v as f64)).collect()
+        };
+        Self { data, width: w as usize, height: h as usize }
+    }
 }
 
 // ============================================================================
 // Integral Image Implementation
 // ============================================================================
 
+/// Summed-area tables of a plane and of its squares, in fixed-point integers
+/// so window sums are exact anywhere in the image. f64 tables lose the low
+/// bits of the totals once they pass 2^53, which skewed window variances
+/// towards the bottom-right of gigapixel or deep (16-bit) planes.
 struct IntegralImage {
-    sum: Vec<f64>,
-    sq_sum: Vec<f64>,
+    sum: Vec<i64>,
+    sq_sum: Vec<i64>,
     width: usize,
+    /// Value of one fixed-point unit: at most 1/256 level, coarser only when
+    /// the plane's total of squares would otherwise overflow i64
+    unit: f64,
 }
 
 impl IntegralImage {
@@ -83,23 +388,30 @@ impl IntegralImage {
         let width = w + 1;
         let size = width * (h + 1);
         
-        let mut sum = vec![0.0f64; size];
-        let mut sq_sum = vec![0.0f64; size];
+        let mut sum = vec![0i64; size];
+        let mut sq_sum = vec![0i64; size];
+
+        // Largest power-of-two scale, up to 256, at which the squares of
+        // every pixel add up to less than 2^62
+        let max = data.iter().map(|v| v.abs()).filter(|v| v.is_finite()).fold(0.0, f64::max);
+        let fit = (2f64.powi(62) / (w * h).max(1) as f64).sqrt() / max;
+        let scale = if fit >= 256.0 { 256.0 } else { 2f64.powi(fit.log2().floor() as i32) };
 
         for y in 0..h {
             let row_offset = y * w;
+            let (mut row, mut row_sq) = (0i64, 0i64);
             for x in 0..w {
-                let v = data[row_offset + x];
+                // Rounded half away from zero, without the libm call of `round`
+                let v = data[row_offset + x] * scale;
+                let q = (v + 0.5f64.copysign(v)) as i64;
+                row += q;
+                row_sq += q * q;
                 let idx = (y + 1) * width + (x + 1);
-                let idx_up = y * width + (x + 1);
-                let idx_left = (y + 1) * width + x;
-                let idx_diag = y * width + x;
-                
-                sum[idx] = v + sum[idx_up] + sum[idx_left] - sum[idx_diag];
-                sq_sum[idx] = v * v + sq_sum[idx_up] + sq_sum[idx_left] - sq_sum[idx_diag];
+                sum[idx] = sum[idx - width] + row;
+                sq_sum[idx] = sq_sum[idx - width] + row_sq;
             }
         }
-        Self { sum, sq_sum, width }
+        Self { sum, sq_sum, width, unit: 1.0 / scale }
     }
 
     #[inline(always)]
@@ -114,7 +426,7 @@ impl IntegralImage {
                   - *self.sum.get_unchecked(idx3) + *self.sum.get_unchecked(idx1);
             let sq = *self.sq_sum.get_unchecked(idx4) - *self.sq_sum.get_unchecked(idx2) 
                    - *self.sq_sum.get_unchecked(idx3) + *self.sq_sum.get_unchecked(idx1);
-            (s, sq)
+            (s as f64 * self.unit, sq as f64 * (self.unit * self.unit))
         }
     }
 }
@@ -128,6 +440,11 @@ struct Template {
     width: usize,
     height: usize,
     inv_std_n: f64,
+    /// Window variance below which a window scores 0 without the cross term
+    min_var: f64,
+    /// `tail_norm[r]`: L2 norm of the normalized template rows `r..`, for
+    /// bounding what those rows can add to the cross term
+    tail_norm: Vec<f64>,
 }
 
 impl Template {
@@ -139,14 +456,124 @@ impl Template {
         let var = (sq_sum / n) - mean * mean;
         let std = var.sqrt().max(1e-10);
         let normalized: Vec<f64> = data.iter().map(|&v| v - mean).collect();
-        Self { normalized, width: w, height: h, inv_std_n: 1.0 / (std * n) }
+        let mut tail_norm = vec![0.0; h + 1];
+        let mut energy = 0.0;
+        for (r, row) in normalized.chunks_exact(w).enumerate().rev() {
+            energy += row.iter().map(|v| v * v).sum::<f64>();
+            tail_norm[r] = energy.sqrt();
+        }
+        Self { normalized, width: w, height: h, inv_std_n: 1.0 / (std * n), min_var: FLAT_WINDOW_VARIANCE, tail_norm }
+    }
+
+    /// Also skip windows whose variance is below `ratio` times the template's
+    fn with_variance_ratio(mut self, ratio: f64) -> Self {
+        let std = 1.0 / (self.inv_std_n * (self.width * self.height) as f64);
+        self.min_var = self.min_var.max(ratio * std * std);
+        self
+    }
+}
+
+/// Template preprocessed at full resolution and, when the pyramid applies,
+/// at the coarse level too, so it can be reused across many sources
+struct PreparedTemplate {
+    full: Template,
+    /// Coarse level, its scale and its `coarse_calibration`
+    coarse: Option<(Template, usize, f64)>,
+    /// Filter used to build the coarse level of both template and source
+    filter: DownsampleFilter,
+    /// Number of coarse hits refined at full resolution
+    coarse_candidates: usize,
+}
+
+impl PreparedTemplate {
+    /// `Template::with_variance_ratio` for both levels
+    fn with_variance_ratio(mut self, ratio: f64) -> Self {
+        self.full = self.full.with_variance_ratio(ratio);
+        self.coarse = self.coarse.map(|(small, scale, calibration)| (small.with_variance_ratio(ratio), scale, calibration));
+        self
+    }
+
+    fn new(data: &[f64], w: usize, h: usize) -> Self {
+        Self::with_filter(data, w, h, DownsampleFilter::Area)
+    }
+
+    fn with_filter(data: &[f64], w: usize, h: usize, filter: DownsampleFilter) -> Self {
+        let scale = pyramid_scale(w, h);
+        let coarse = if scale >= 4 {
+            let (small, stw, sth) = downsample_filtered(data, w, h, scale, filter);
+            let small = Template::new(&small, stw, sth);
+            let calibration = coarse_calibration(data, w, h, &small, scale, filter);
+            Some((small, scale, calibration))
+        } else { None };
+        Self { full: Template::new(data, w, h), coarse, filter, coarse_candidates: 1 }
+    }
+}
+
+/// Bounding box `(x, y, w, h)` left after stripping margin rows and columns.
+/// A border line is margin when its values span at most `tolerance` and agree
+/// with the margin already stripped, so all four sides share one margin value.
+/// If what remains would be flat, the full box is returned unchanged.
+fn autocrop_box(data: &[f64], w: usize, h: usize, tolerance: f64) -> (usize, usize, usize, usize) {
+    // Value of a line if it is flat and continues the margin seen so far
+    let margin = |values: &mut dyn Iterator<Item = f64>, seen: Option<f64>| -> Option<f64> {
+        let (lo, hi) = values.fold((f64::MAX, f64::MIN), |(lo, hi), v| (lo.min(v), hi.max(v)));
+        let flat = hi - lo <= tolerance && seen.map_or(true, |m| (lo - m).abs() <= tolerance && (hi - m).abs() <= tolerance);
+        if flat { Some(seen.unwrap_or(lo)) } else { None }
+    };
+    let row = |y: usize, seen| margin(&mut data[y * w..(y + 1) * w].iter().copied(), seen);
+    let col = |x: usize, y1: usize, y2: usize, seen| margin(&mut (y1..y2).map(|y| data[y * w + x]), seen);
+
+    let (mut y1, mut y2, mut x1, mut x2) = (0usize, h, 0usize, w);
+    let mut seen = None;
+    while y2 - y1 > 1 { match row(y1, seen) { Some(m) => { seen = Some(m); y1 += 1; } None => break } }
+    while y2 - y1 > 1 { match row(y2 - 1, seen) { Some(m) => { seen = Some(m); y2 -= 1; } None => break } }
+    while x2 - x1 > 1 { match col(x1, y1, y2, seen) { Some(m) => { seen = Some(m); x1 += 1; } None => break } }
+    while x2 - x1 > 1 { match col(x2 - 1, y1, y2, seen) { Some(m) => { seen = Some(m); x2 -= 1; } None => break } }
+
+    // Nothing but margin (e.g. a lone line or dot) would leave no variance to correlate
+    let mut rest = (y1..y2).flat_map(|y| data[y * w + x1..y * w + x2].iter().copied());
+    if margin(&mut rest, None).is_some() { return (0, 0, w, h); }
+    (x1, y1, x2 - x1, y2 - y1)
+}
+
+/// Default `validate_template` limits: grey-level standard deviation and
+/// histogram entropy (bits) below which NCC scores are dominated by noise
+const MIN_TEMPLATE_STD: f64 = 5.0;
+const MIN_TEMPLATE_ENTROPY: f64 = 0.2;
+
+/// Standard deviation and Shannon entropy (bits over 256 grey-level bins) of a template
+fn template_stats(data: &[f64]) -> (f64, f64) {
+    let n = data.len() as f64;
+    let mean = data.iter().sum::<f64>() / n;
+    let var = data.iter().map(|&v| (v - mean) * (v - mean)).sum::<f64>() / n;
+    let mut hist = [0usize; 256];
+    for &v in data { hist[v.round().clamp(0.0, 255.0) as usize] += 1; }
+    let entropy = hist.iter().filter(|&&c| c > 0).map(|&c| {
+        let p = c as f64 / n;
+        -p * p.log2()
+    }).sum::<f64>();
+    (var.sqrt(), entropy)
+}
+
+/// Reasons a template is unreliable to match; empty if it passes both limits
+fn template_issues(std: f64, entropy: f64, min_std: f64, min_entropy: f64) -> Vec<String> {
+    let mut issues = Vec::new();
+    if std < min_std {
+        issues.push(format!("grey-level standard deviation {:.2} is below {}", std, min_std));
     }
+    if entropy < min_entropy {
+        issues.push(format!("histogram entropy {:.3} bits is below {}", entropy, min_entropy));
+    }
+    issues
 }
 
 // ============================================================================
 // NCC Core Computation
 // ============================================================================
 
+/// Window variance below which a window counts as flat and scores 0
+const FLAT_WINDOW_VARIANCE: f64 = 1.0;
+
 #[inline(always)]
 fn compute_ncc(
     src: &[f64], src_width: usize, integral: &IntegralImage, tpl: &Template, x: usize, y: usize,
@@ -159,8 +586,56 @@ fn compute_ncc(
     let s_mean = s_sum / n;
     let s_var = (s_sq_sum / n) - s_mean * s_mean;
     
-    if s_var < 1.0 { return 0.0; }
+    if s_var < tpl.min_var { return 0.0; }
+    let s_std = s_var.sqrt();
+
+    let mut cross = 0.0f64;
+    let mut tpl_idx = 0;
+    
+    for ty in 0..th {
+        let src_row = (y + ty) * src_width + x;
+        for tx in 0..tw {
+            let sv = unsafe { *src.get_unchecked(src_row + tx) } - s_mean;
+            let tv = unsafe { *tpl.normalized.get_unchecked(tpl_idx) };
+            cross += sv * tv;
+            tpl_idx += 1;
+        }
+    }
+    cross * tpl.inv_std_n / s_std
+}
+
+/// Margin kept below `floor` before a bounded window is given up, so integral
+/// image rounding can never prune the true best window
+const PRUNE_MARGIN: f64 = 1e-6;
+
+/// `compute_ncc` that gives up on a window as soon as it can't reach `floor`.
+/// Every few template rows, Cauchy-Schwarz bounds what the remaining rows can
+/// add to the cross term by the product of their source and template norms.
+/// Scores that can reach `floor` are exact; otherwise the returned bound is
+/// below `floor`, and `pruned` is incremented for windows given up early.
+#[inline(always)]
+#[allow(clippy::too_many_arguments)]
+fn compute_ncc_bounded(
+    src: &[f64], src_width: usize, integral: &IntegralImage, tpl: &Template, x: usize, y: usize, floor: f64,
+    pruned: &mut usize,
+) -> f64 {
+    let tw = tpl.width;
+    let th = tpl.height;
+    let n = (tw * th) as f64;
+
+    let (s_sum, s_sq_sum) = integral.get_stats(x, y, tw, th);
+    let s_mean = s_sum / n;
+    let s_var = (s_sq_sum / n) - s_mean * s_mean;
+    
+    if s_var < tpl.min_var {
+        *pruned += 1;
+        return 0.0;
+    }
     let s_std = s_var.sqrt();
+    let scale = tpl.inv_std_n / s_std;
+    let floor = floor - PRUNE_MARGIN;
+    // Check about every 64 pixels, so the bound stays cheap for narrow templates
+    let check_every = (64 / tw).max(1);
 
     let mut cross = 0.0f64;
     let mut tpl_idx = 0;
@@ -173,59 +648,306 @@ fn compute_ncc(
             cross += sv * tv;
             tpl_idx += 1;
         }
+        let done = ty + 1;
+        if done < th && done % check_every == 0 {
+            let rows = th - done;
+            let (rest_sum, rest_sq_sum) = integral.get_stats(x, y + done, tw, rows);
+            let rest_energy = rest_sq_sum - 2.0 * s_mean * rest_sum + (tw * rows) as f64 * s_mean * s_mean;
+            let bound = (cross + rest_energy.max(0.0).sqrt() * tpl.tail_norm[done]) * scale;
+            if bound < floor {
+                *pruned += 1;
+                return bound;
+            }
+        }
     }
+    // Same rounding as `compute_ncc`, so both report identical scores
     cross * tpl.inv_std_n / s_std
 }
 
+/// Best score found so far by a parallel scan, shared so every row can prune
+/// against it. Only positive scores are stored: their bit patterns order like
+/// the values, so `fetch_max` keeps the highest.
+struct SharedBest(std::sync::atomic::AtomicU64);
+
+impl SharedBest {
+    fn new() -> Self {
+        Self(std::sync::atomic::AtomicU64::new(0))
+    }
+
+    #[inline(always)]
+    fn get(&self) -> f64 {
+        match self.0.load(std::sync::atomic::Ordering::Relaxed) {
+            0 => -1.0,
+            bits => f64::from_bits(bits),
+        }
+    }
+
+    fn raise(&self, score: f64) {
+        if score > 0.0 { self.0.fetch_max(score.to_bits(), std::sync::atomic::Ordering::Relaxed); }
+    }
+}
+
 // ============================================================================
 // Search Strategies
 // ============================================================================
 
+/// Default of `set_parallel_threshold`: pixel comparisons (window positions
+/// times template pixels) one rayon task must cover, about 0.2 ms of work
+const DEFAULT_PARALLEL_MIN_WORK: usize = 1 << 18;
+
+/// Current `set_parallel_threshold` value
+static PARALLEL_MIN_WORK: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(DEFAULT_PARALLEL_MIN_WORK);
+
+/// Minimum items per rayon task for a parallel loop doing `item_work` pixel
+/// comparisons per item (`with_min_len`). Loops with less total work than
+/// `PARALLEL_MIN_WORK` form a single task and run on the calling thread, so
+/// small searches skip the fan-out and don't compete for the pool.
+fn min_items(item_work: usize) -> usize {
+    let min_work = PARALLEL_MIN_WORK.load(std::sync::atomic::Ordering::Relaxed);
+    let item_work = item_work.max(1);
+    ((min_work + item_work - 1) / item_work).max(1)
+}
+
+thread_local! {
+    /// Deadline of the `timeout_ms` search running on this thread
+    static SEARCH_DEADLINE: std::cell::Cell<Option<Instant>> = const { std::cell::Cell::new(None) };
+}
+
+/// Deadline of the search running on the calling thread. Parallel loops
+/// capture it before fanning out and skip their remaining items once it has
+/// passed, so a timed-out search stops with what it has found so far.
+#[derive(Clone, Copy)]
+struct Deadline(Option<Instant>);
+
+impl Deadline {
+    fn current() -> Self {
+        Self(SEARCH_DEADLINE.with(|d| d.get()))
+    }
+
+    #[inline]
+    fn passed(self) -> bool {
+        self.0.is_some_and(|d| Instant::now() >= d)
+    }
+}
+
+thread_local! {
+    /// Statistics of the `return_diagnostics` search running on this thread
+    static SEARCH_STATS: std::cell::RefCell<Option<Arc<SearchStats>>> = const { std::cell::RefCell::new(None) };
+}
+
+/// What one search did, collected for `return_diagnostics`
+#[derive(Default)]
+struct SearchStats {
+    /// Window positions scored, at every pyramid level
+    evaluated: std::sync::atomic::AtomicUsize,
+    /// Of those, windows given up before their full score (flat or bounded)
+    pruned: std::sync::atomic::AtomicUsize,
+    detail: Mutex<StatsDetail>,
+}
+
+#[derive(Default)]
+struct StatsDetail {
+    /// Downsampling factor of the coarse level, if the pyramid ran
+    scale: Option<usize>,
+    /// Best coarse score and its full-resolution position
+    coarse: Option<(f64, usize, usize)>,
+    /// Full-resolution windows `(x1, y1, x2, y2)` refined after the coarse level
+    windows: Vec<(usize, usize, usize, usize)>,
+    /// Milliseconds per stage, in the order stages first ran
+    timings: Vec<(&'static str, f64)>,
+}
+
+impl SearchStats {
+    /// Run `search` with these statistics set for the calling thread
+    fn collect<T>(stats: &Arc<Self>, search: impl FnOnce() -> T) -> T {
+        let outer = SEARCH_STATS.with(|s| s.replace(Some(stats.clone())));
+        let found = search();
+        SEARCH_STATS.with(|s| *s.borrow_mut() = outer);
+        found
+    }
+
+    fn detail(&self) -> std::sync::MutexGuard<'_, StatsDetail> {
+        self.detail.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Statistics of the search running on the calling thread, captured like
+/// `Deadline` before parallel loops fan out. Every method is a no-op unless
+/// the search was started with `return_diagnostics`.
+#[derive(Clone)]
+struct Stats(Option<Arc<SearchStats>>);
+
+impl Stats {
+    fn current() -> Self {
+        Self(SEARCH_STATS.with(|s| s.borrow().clone()))
+    }
+
+    fn count(&self, evaluated: usize, pruned: usize) {
+        let Some(stats) = &self.0 else { return };
+        stats.evaluated.fetch_add(evaluated, std::sync::atomic::Ordering::Relaxed);
+        stats.pruned.fetch_add(pruned, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// Record a coarse level result; the best score over all searches is kept
+    fn coarse(&self, scale: usize, score: f64, x: usize, y: usize) {
+        let Some(stats) = &self.0 else { return };
+        let mut detail = stats.detail();
+        detail.scale = Some(scale);
+        if detail.coarse.map_or(true, |(best, _, _)| score > best) {
+            detail.coarse = Some((score, x * scale, y * scale));
+        }
+    }
+
+    fn window(&self, window: (usize, usize, usize, usize)) {
+        if let Some(stats) = &self.0 { stats.detail().windows.push(window); }
+    }
+
+    /// Run `stage`, adding its duration to the stage's total
+    fn time<T>(&self, stage: &'static str, f: impl FnOnce() -> T) -> T {
+        let Some(stats) = &self.0 else { return f() };
+        let start = Instant::now();
+        let out = f();
+        let ms = start.elapsed().as_secs_f64() * 1000.0;
+        let mut detail = stats.detail();
+        match detail.timings.iter_mut().find(|(name, _)| *name == stage) {
+            Some((_, total)) => *total += ms,
+            None => detail.timings.push((stage, ms)),
+        }
+        out
+    }
+}
+
 fn search_best(src: &[f64], sw: usize, sh: usize, tpl: &Template, threshold: f64) -> Option<MatchResult> {
+    if tpl.width > sw || tpl.height > sh { return None; }
+    search_best_with(src, sw, sh, &IntegralImage::new(src, sw, sh), tpl, threshold)
+}
+
+/// `search_best` over a source whose integral image is already built
+fn search_best_with(
+    src: &[f64], sw: usize, sh: usize, integral: &IntegralImage, tpl: &Template, threshold: f64,
+) -> Option<MatchResult> {
     let tw = tpl.width;
     let th = tpl.height;
-    if tw > sw || th > sh { return None; }
-
-    let integral = IntegralImage::new(src, sw, sh);
     let end_x = sw - tw;
     let end_y = sh - th;
+    let shared = SharedBest::new();
+    let deadline = Deadline::current();
+    let stats = Stats::current();
 
-    let best = (0..=end_y)
+    let best = (0..end_y + 1)
         .into_par_iter()
+        .with_min_len(min_items((end_x + 1) * tw * th))
         .map(|y| {
             let mut row_best = (0usize, y, -1.0f64);
+            if deadline.passed() { return row_best; }
+            let mut pruned = 0;
             for x in 0..=end_x {
-                let score = compute_ncc(src, sw, &integral, tpl, x, y);
+                let floor = row_best.2.max(shared.get()).max(threshold);
+                let score = compute_ncc_bounded(src, sw, integral, tpl, x, y, floor, &mut pruned);
                 if score > row_best.2 { row_best = (x, y, score); }
             }
+            stats.count(end_x + 1, pruned);
+            shared.raise(row_best.2);
             row_best
         })
         .reduce(|| (0, 0, -1.0f64), |a, b| if a.2 > b.2 { a } else { b });
 
     if best.2 >= threshold {
-        Some(MatchResult { x: best.0 as u32, y: best.1 as u32, confidence: best.2 })
+        Some(MatchResult { x: best.0 as i32, y: best.1 as i32, confidence: best.2, crop: None, scale: None, angle: None, pixel_ratio: 1.0, flip: None, inverted: false })
     } else { None }
 }
 
+/// Best match among window positions `(x1, y1)..=(x2, y2)`. The windows are
+/// counted into `stats`, captured by the caller before any parallel loop.
 fn search_region(
-    src: &[f64], sw: usize, sh: usize, tpl: &Template,
-    x1: usize, y1: usize, x2: usize, y2: usize, threshold: f64,
+    src: &[f64], sw: usize, integral: &IntegralImage, tpl: &Template,
+    window: (usize, usize, usize, usize), threshold: f64, stats: &Stats,
 ) -> Option<MatchResult> {
-    let integral = IntegralImage::new(src, sw, sh);
+    let (x1, y1, x2, y2) = window;
     let mut best = (0usize, 0usize, -1.0f64);
+    let mut pruned = 0;
     
     for y in y1..=y2 {
         for x in x1..=x2 {
-            let score = compute_ncc(src, sw, &integral, tpl, x, y);
+            let score = compute_ncc_bounded(src, sw, integral, tpl, x, y, best.2.max(threshold), &mut pruned);
             if score > best.2 { best = (x, y, score); }
         }
     }
+    stats.count((x2 - x1 + 1) * (y2 - y1 + 1), pruned);
 
     if best.2 >= threshold {
-        Some(MatchResult { x: best.0 as u32, y: best.1 as u32, confidence: best.2 })
+        Some(MatchResult { x: best.0 as i32, y: best.1 as i32, confidence: best.2, crop: None, scale: None, angle: None, pixel_ratio: 1.0, flip: None, inverted: false })
     } else { None }
 }
 
+/// Copy a rectangular region out of a row-major f64 image
+fn crop_region(src: &[f64], sw: usize, x: usize, y: usize, w: usize, h: usize) -> Vec<f64> {
+    let mut data = Vec::with_capacity(w * h);
+    for row in y..y + h {
+        let start = row * sw + x;
+        data.extend_from_slice(&src[start..start + w]);
+    }
+    data
+}
+
+/// Score of every window position, row-major over
+/// `(sw - tw + 1) x (sh - th + 1)`. The template must fit the source.
+fn score_map<S: WindowScore>(src: &[f64], sw: usize, sh: usize, tpl: &S) -> Vec<f64> {
+    let integral = IntegralImage::new(src, sw, sh);
+    let (tw, th) = tpl.size();
+    let cols = sw - tw + 1;
+    let mut scores = vec![0.0; cols * (sh - th + 1)];
+    let deadline = Deadline::current();
+    let stats = Stats::current();
+    scores.par_chunks_mut(cols).enumerate().with_min_len(min_items(cols * tw * th)).for_each(|(y, row)| {
+        if deadline.passed() { return; }
+        for (x, out) in row.iter_mut().enumerate() {
+            *out = tpl.score(src, sw, &integral, x, y);
+        }
+        stats.count(cols, 0);
+    });
+    scores
+}
+
+/// Exhaustive counterpart of `match_candidates`: every window position is
+/// scored and each local maximum reaching `threshold` is a candidate
+fn exact_candidates<S: WindowScore>(src: &[f64], sw: usize, sh: usize, tpl: &S, threshold: f64) -> Vec<MatchResult> {
+    let (tw, th) = tpl.size();
+    if tw > sw || th > sh { return vec![]; }
+    let peaks = score_peaks(&score_map(src, sw, sh, tpl), sw - tw + 1, sh - th + 1, threshold);
+    log::debug!("{} local maxima above {:.3} in the exhaustive search", peaks.len(), threshold);
+    peaks
+}
+
+/// Best match among window positions `(x1, y1)..=(x2, y2)`. Only the covered
+/// region is cropped and integrated, so cost scales with the window, not the source.
+fn search_window(
+    src: &[f64], sw: usize, tpl: &Template, window: (usize, usize, usize, usize), threshold: f64,
+) -> Option<MatchResult> {
+    let (x1, y1, x2, y2) = window;
+    let cw = x2 - x1 + tpl.width;
+    let ch = y2 - y1 + tpl.height;
+    let region = crop_region(src, sw, x1, y1, cw, ch);
+    search_best(&region, cw, ch, tpl, threshold).map(|mut r| {
+        r.x += x1 as i32;
+        r.y += y1 as i32;
+        r
+    })
+}
+
+/// Windows of top-left positions around `centre`, from `margin` pixels on
+/// each side doubling until the last one covers every position up to `end`
+fn expanding_windows(end: (usize, usize), centre: (usize, usize), margin: usize) -> impl Iterator<Item = (usize, usize, usize, usize)> {
+    let (cx, cy) = (centre.0.min(end.0), centre.1.min(end.1));
+    std::iter::successors(Some(margin.max(1)), |&m| Some(m.saturating_mul(2)))
+        .map(move |m| (cx.saturating_sub(m), cy.saturating_sub(m), cx.saturating_add(m).min(end.0), cy.saturating_add(m).min(end.1)))
+        .scan(false, move |done, window| {
+            if *done { return None; }
+            *done = window == (0, 0, end.0, end.1);
+            Some(window)
+        })
+}
+
 fn downsample(src: &[f64], sw: usize, sh: usize, scale: usize) -> (Vec<f64>, usize, usize) {
     let nw = sw / scale;
     let nh = sh / scale;
@@ -246,336 +968,10073 @@ fn downsample(src: &[f64], sw: usize, sh: usize, scale: usize) -> (Vec<f64>, usi
     (result, nw, nh)
 }
 
-fn pyramid_match(
-    src: &[f64], sw: usize, sh: usize, tpl_data: &[f64], tw: usize, th: usize, threshold: f64,
-) -> Option<MatchResult> {
-    if tw > sw || th > sh { return None; }
-
-    let min_tpl_size = 16usize;
-    let max_scale = tw.min(th) / min_tpl_size;
-    let scale = max_scale.min(8).next_power_of_two().max(1);
+/// Filter for building the coarse pyramid level
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum DownsampleFilter {
+    /// Plain box average over each `scale x scale` cell
+    Area,
+    /// Bilinear interpolation at each cell centre (sharpest, aliases most)
+    Bilinear,
+    /// Gaussian blur (sigma = scale / 2) before the box average, suppressing
+    /// frequencies the coarse level can't represent
+    Gaussian,
+}
 
-    if scale >= 4 {
-        let (small_src, ssw, ssh) = downsample(src, sw, sh, scale);
-        let (small_tpl, stw, sth) = downsample(tpl_data, tw, th, scale);
-        let small_template = Template::new(&small_tpl, stw, sth);
-        
-        if let Some(coarse) = search_best(&small_src, ssw, ssh, &small_template, threshold * 0.5) {
-            let margin = scale * 4;
-            let cx = coarse.x as usize * scale;
-            let cy = coarse.y as usize * scale;
-            
-            let x1 = cx.saturating_sub(margin);
-            let y1 = cy.saturating_sub(margin);
-            let x2 = (cx + margin).min(sw.saturating_sub(tw));
-            let y2 = (cy + margin).min(sh.saturating_sub(th));
-            
-            let tpl = Template::new(tpl_data, tw, th);
-            return search_region(src, sw, sh, &tpl, x1, y1, x2, y2, threshold);
+fn downsample_filtered(
+    src: &[f64], sw: usize, sh: usize, scale: usize, filter: DownsampleFilter,
+) -> (Vec<f64>, usize, usize) {
+    match filter {
+        DownsampleFilter::Area => downsample(src, sw, sh, scale),
+        DownsampleFilter::Bilinear => {
+            let (nw, nh) = (sw / scale, sh / scale);
+            let at = |x: usize, y: usize| src[y.min(sh - 1) * sw + x.min(sw - 1)];
+            let half = (scale as f64 - 1.0) / 2.0;
+            let mut result = Vec::with_capacity(nw * nh);
+            for y in 0..nh {
+                let fy = (y * scale) as f64 + half;
+                let (y0, ty) = (fy.floor() as usize, fy.fract());
+                for x in 0..nw {
+                    let fx = (x * scale) as f64 + half;
+                    let (x0, tx) = (fx.floor() as usize, fx.fract());
+                    let top = at(x0, y0) * (1.0 - tx) + at(x0 + 1, y0) * tx;
+                    let bottom = at(x0, y0 + 1) * (1.0 - tx) + at(x0 + 1, y0 + 1) * tx;
+                    result.push(top * (1.0 - ty) + bottom * ty);
+                }
+            }
+            (result, nw, nh)
+        }
+        DownsampleFilter::Gaussian => {
+            let blurred = gaussian_blur(src, sw, sh, scale as f64 / 2.0);
+            downsample(&blurred, sw, sh, scale)
         }
-        None
-    } else {
-        let tpl = Template::new(tpl_data, tw, th);
-        search_best(src, sw, sh, &tpl, threshold)
     }
 }
 
-fn match_multi(
-    src: &[f64], sw: usize, sh: usize, tpl_data: &[f64], tw: usize, th: usize,
-    threshold: f64, max_count: usize,
-) -> Vec<MatchResult> {
-    if tw > sw || th > sh { return vec![]; }
+/// Separable Gaussian blur with edge replication
+fn gaussian_blur(src: &[f64], w: usize, h: usize, sigma: f64) -> Vec<f64> {
+    let radius = (2.0 * sigma).ceil() as usize;
+    let kernel: Vec<f64> = (0..=2 * radius)
+        .map(|i| (-((i as f64 - radius as f64).powi(2)) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let norm: f64 = kernel.iter().sum();
+    let kernel: Vec<f64> = kernel.iter().map(|k| k / norm).collect();
 
-    let integral = IntegralImage::new(src, sw, sh);
-    let tpl = Template::new(tpl_data, tw, th);
-    let end_x = sw - tw;
-    let end_y = sh - th;
-    let step = 2usize;
-    
-    let candidates: Vec<_> = (0..=end_y / step)
-        .into_par_iter()
-        .flat_map(|yi| {
-            let y = yi * step;
-            let mut row_candidates = Vec::new();
-            for xi in 0..=end_x / step {
-                let x = xi * step;
-                let score = compute_ncc(src, sw, &integral, &tpl, x, y);
-                if score >= threshold * 0.9 { row_candidates.push((x, y, score)); }
+    let mut rows = vec![0.0; w * h];
+    rows.par_chunks_mut(w).enumerate().with_min_len(min_items(w * kernel.len())).for_each(|(y, row)| {
+        for (x, out) in row.iter_mut().enumerate() {
+            *out = kernel.iter().enumerate()
+                .map(|(i, k)| k * src[y * w + (x + i).saturating_sub(radius).min(w - 1)])
+                .sum();
+        }
+    });
+    let mut result = vec![0.0; w * h];
+    result.par_chunks_mut(w).enumerate().with_min_len(min_items(w * kernel.len())).for_each(|(y, row)| {
+        for (x, out) in row.iter_mut().enumerate() {
+            *out = kernel.iter().enumerate()
+                .map(|(i, k)| k * rows[(y + i).saturating_sub(radius).min(h - 1) * w + x])
+                .sum();
+        }
+    });
+    result
+}
+
+/// Downsampling factor used for the coarse pyramid level (1 = no pyramid)
+fn pyramid_scale(tw: usize, th: usize) -> usize {
+    let min_tpl_size = 16usize;
+    let max_scale = tw.min(th) / min_tpl_size;
+    max_scale.min(8).next_power_of_two().max(1)
+}
+
+/// Coarse score of a perfect match at the least favourable offset from the
+/// coarse grid, which decides how far coarse scores fall below full-resolution
+/// ones. The template is placed half a cell off the grid (in x, y and both)
+/// among scrambled copies of its pixels, downsampled with `filter` and scored
+/// by `small`, its own coarse level. The coarse cutoff is the threshold scaled by this, so
+/// a threshold keeps its meaning at every pyramid scale: fine textures, which
+/// lose most to the offset, get a lower cutoff than smooth templates.
+fn coarse_calibration<S: WindowScore>(
+    data: &[f64], w: usize, h: usize, small: &S, scale: usize, filter: DownsampleFilter,
+) -> f64 {
+    let (cw, ch) = (w + 2 * scale, h + 2 * scale);
+    // Unrelated surroundings of the template's contrast: its own pixels, scrambled
+    let surround: Vec<f64> = (0..cw * ch)
+        .map(|i| data[(i as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15).rotate_left(29) as usize % data.len()])
+        .collect();
+    let (stw, sth) = small.size();
+    let half = scale / 2;
+    [(half, 0), (0, half), (half, half)].into_iter()
+        .map(|(dx, dy)| {
+            let mut canvas = surround.clone();
+            for (y, row) in data.chunks_exact(w).enumerate() {
+                let start = (scale + dy + y) * cw + scale + dx;
+                canvas[start..start + w].copy_from_slice(row);
             }
-            row_candidates
+            let (coarse, nw, nh) = downsample_filtered(&canvas, cw, ch, scale, filter);
+            let integral = IntegralImage::new(&coarse, nw, nh);
+            (0..=nh - sth)
+                .flat_map(|y| (0..=nw - stw).map(move |x| (x, y)))
+                .map(|(x, y)| small.score(&coarse, nw, &integral, x, y))
+                .fold(-1.0, f64::max)
         })
-        .collect();
+        .fold(1.0, f64::min)
+        .clamp(0.0, 1.0)
+}
 
-    let mut results: Vec<MatchResult> = candidates
-        .iter()
-        .filter_map(|&(cx, cy, _)| {
-            let mut best = (cx, cy, -1.0f64);
-            for dy in 0..step {
-                for dx in 0..step {
-                    let x = (cx + dx).min(end_x);
-                    let y = (cy + dy).min(end_y);
-                    let score = compute_ncc(src, sw, &integral, &tpl, x, y);
+/// Coarse cutoff for a search at `threshold`: the threshold scaled by the
+/// template's `coarse_calibration`, less `1 / sqrt(n)`, the spread of an
+/// NCC over the `n` pixels of a `stw x sth` coarse template, by which a real
+/// match's coarse score varies with its surroundings
+fn coarse_cutoff(threshold: f64, calibration: f64, (stw, sth): (usize, usize)) -> f64 {
+    threshold * calibration - 1.0 / ((stw * sth) as f64).sqrt()
+}
+
+/// Windows of a `tw x th` template with variance below `min_var`, which the
+/// NCC scan skips wherever it scores them
+fn count_low_variance_windows(src: &[f64], sw: usize, sh: usize, tw: usize, th: usize, min_var: f64) -> usize {
+    if tw > sw || th > sh { return 0; }
+    let integral = IntegralImage::new(src, sw, sh);
+    let n = (tw * th) as f64;
+    (0..sh - th + 1).into_par_iter()
+        .with_min_len(min_items(sw - tw + 1))
+        .map(|y| (0..=sw - tw).filter(|&x| {
+            let (sum, sq_sum) = integral.get_stats(x, y, tw, th);
+            let mean = sum / n;
+            sq_sum / n - mean * mean < min_var
+        }).count())
+        .sum()
+}
+
+fn pyramid_match_prepared(
+    src: &[f64], sw: usize, sh: usize, tpl: &PreparedTemplate, threshold: f64,
+) -> Option<MatchResult> {
+    PreparedSource::new(src, sw, sh, std::slice::from_ref(tpl)).find(tpl, threshold)
+}
+
+/// Downsampled source for one coarse pyramid level, with its integral image
+struct SourceLevel {
+    data: Vec<f64>,
+    width: usize,
+    height: usize,
+    integral: IntegralImage,
+}
+
+/// Source-side work of the pyramid search, done once per source however many
+/// templates are matched against it: the coarse levels the templates need
+/// (one per scale and filter) and the full-resolution integral image
+struct PreparedSource<'a> {
+    data: &'a [f64],
+    width: usize,
+    height: usize,
+    levels: Vec<(usize, DownsampleFilter, SourceLevel)>,
+    /// Built on first use, as coarse misses never reach full resolution
+    integral: std::sync::OnceLock<IntegralImage>,
+}
+
+impl<'a> PreparedSource<'a> {
+    fn new(data: &'a [f64], width: usize, height: usize, templates: &[PreparedTemplate]) -> Self {
+        let mut levels: Vec<(usize, DownsampleFilter, SourceLevel)> = Vec::new();
+        let stats = Stats::current();
+        for tpl in templates {
+            let Some((_, scale, _)) = &tpl.coarse else { continue };
+            if levels.iter().any(|(s, f, _)| s == scale && *f == tpl.filter) { continue; }
+            let level = stats.time("pyramid", || {
+                let (small, ssw, ssh) = downsample_filtered(data, width, height, *scale, tpl.filter);
+                let integral = IntegralImage::new(&small, ssw, ssh);
+                SourceLevel { data: small, width: ssw, height: ssh, integral }
+            });
+            levels.push((*scale, tpl.filter, level));
+        }
+        Self { data, width, height, levels, integral: std::sync::OnceLock::new() }
+    }
+
+    fn level(&self, scale: usize, filter: DownsampleFilter) -> Option<&SourceLevel> {
+        self.levels.iter().find(|(s, f, _)| *s == scale && *f == filter).map(|(_, _, level)| level)
+    }
+
+    fn integral(&self) -> &IntegralImage {
+        self.integral.get_or_init(|| IntegralImage::new(self.data, self.width, self.height))
+    }
+
+    /// `pyramid_match_prepared` against this source; `tpl` must be one of
+    /// the templates it was prepared for
+    fn find(&self, tpl: &PreparedTemplate, threshold: f64) -> Option<MatchResult> {
+        let (sw, sh) = (self.width, self.height);
+        if tpl.full.width > sw || tpl.full.height > sh {
+            log::debug!("template {}x{} is larger than source {}x{}", tpl.full.width, tpl.full.height, sw, sh);
+            return None;
+        }
+        let stats = Stats::current();
+        let windows = stats.time("coarse", || coarse_windows_in(self, tpl, threshold));
+        let best = stats.time("refine", || {
+            windows.iter()
+                .filter_map(|&window| {
+                    stats.window(window);
+                    refine_window(self.data, sw, sh, self.integral(), tpl, window, -1.0)
+                })
+                .max_by(|a, b| a.confidence.partial_cmp(&b.confidence).unwrap())
+        })?;
+        log_final_score(&best, threshold);
+        (best.confidence >= threshold).then_some(best)
+    }
+}
+
+fn log_final_score(best: &MatchResult, threshold: f64) {
+    log::debug!("best score {:.4} at ({}, {}), threshold {:.3}", best.confidence, best.x, best.y, threshold);
+}
+
+/// Log the coarse pyramid result; false if it misses the coarse cutoff
+fn log_coarse_score(scale: usize, x: usize, y: usize, score: f64, cutoff: f64) -> bool {
+    if score < cutoff {
+        log::debug!("coarse miss at scale {}: best score {:.4} at ({}, {}) below cutoff {:.3}", scale, score, x, y, cutoff);
+        return false;
+    }
+    log::trace!("coarse level at scale {}: best score {:.4} at ({}, {})", scale, score, x, y);
+    true
+}
+
+/// Coarse pyramid stage: the windows `(x1, y1, x2, y2)` of full-resolution
+/// positions left to refine around the best `coarse_candidates` coarse
+/// matches (non-overlapping, passing the calibrated coarse cutoff), or every position
+/// when the template has no coarse level. Empty if the coarse search failed.
+fn coarse_windows(
+    src: &[f64], sw: usize, sh: usize, tpl: &PreparedTemplate, threshold: f64,
+) -> Vec<(usize, usize, usize, usize)> {
+    coarse_windows_in(&PreparedSource::new(src, sw, sh, std::slice::from_ref(tpl)), tpl, threshold)
+}
+
+/// `coarse_windows` on a source whose coarse levels are already built
+fn coarse_windows_in(
+    source: &PreparedSource, tpl: &PreparedTemplate, threshold: f64,
+) -> Vec<(usize, usize, usize, usize)> {
+    let end_x = source.width - tpl.full.width;
+    let end_y = source.height - tpl.full.height;
+    let Some((small_template, scale, calibration)) = &tpl.coarse else {
+        log::trace!("no pyramid for {}x{} template, searching all positions", tpl.full.width, tpl.full.height);
+        return vec![(0, 0, end_x, end_y)];
+    };
+    let scale = *scale;
+    let Some(level) = source.level(scale, tpl.filter) else { return vec![]; };
+    let (small_src, ssw, ssh) = (level.data.as_slice(), level.width, level.height);
+    let (stw, sth) = (small_template.width, small_template.height);
+    if stw > ssw || sth > ssh { return vec![]; }
+    
+    let coarse = if tpl.coarse_candidates <= 1 {
+        search_best_with(small_src, ssw, ssh, &level.integral, small_template, -1.0).into_iter().collect()
+    } else {
+        let scores = score_map(small_src, ssw, ssh, small_template);
+        let peaks = score_peaks(&scores, ssw - stw + 1, ssh - sth + 1, -1.0);
+        non_max_suppression(peaks, stw, sth, tpl.coarse_candidates)
+    };
+    let cutoff = coarse_cutoff(threshold, *calibration, small_template.size());
+    let Some(best) = coarse.first() else { return vec![]; };
+    Stats::current().coarse(scale, best.confidence, best.x as usize, best.y as usize);
+    if !log_coarse_score(scale, best.x as usize, best.y as usize, best.confidence, cutoff) { return vec![]; }
+    coarse.iter()
+        .filter(|c| c.confidence >= cutoff)
+        .map(|c| {
+            let window = coarse_to_window(c.x as usize, c.y as usize, scale, scale * 4, end_x, end_y);
+            log::trace!(
+                "refinement window ({}, {})-({}, {}) for coarse score {:.4}",
+                window.0, window.1, window.2, window.3, c.confidence
+            );
+            window
+        })
+        .collect()
+}
+
+/// Full-resolution positions within `margin` of coarse position `(cx, cy)`,
+/// clamped to the last valid position `(end_x, end_y)`
+fn coarse_to_window(
+    cx: usize, cy: usize, scale: usize, margin: usize, end_x: usize, end_y: usize,
+) -> (usize, usize, usize, usize) {
+    let (x, y) = (cx * scale, cy * scale);
+    (x.saturating_sub(margin), y.saturating_sub(margin), (x + margin).min(end_x), (y + margin).min(end_y))
+}
+
+/// Full-resolution stage over a window from `coarse_windows`
+fn refine_window(
+    src: &[f64], sw: usize, sh: usize, integral: &IntegralImage, tpl: &PreparedTemplate,
+    window: (usize, usize, usize, usize), threshold: f64,
+) -> Option<MatchResult> {
+    if tpl.coarse.is_some() {
+        search_region(src, sw, integral, &tpl.full, window, threshold, &Stats::current())
+    } else {
+        search_best_with(src, sw, sh, integral, &tpl.full, threshold)
+    }
+}
+
+/// All refined matches above `threshold`, before non-maximum suppression.
+/// Templates with a coarse level are located on the pyramid first; smaller
+/// ones fall back to a dense scan at full resolution.
+fn match_candidates(src: &[f64], sw: usize, sh: usize, tpl: &PreparedTemplate, threshold: f64) -> Vec<MatchResult> {
+    if tpl.full.width > sw || tpl.full.height > sh { return vec![]; }
+
+    let integral = IntegralImage::new(src, sw, sh);
+    match &tpl.coarse {
+        Some((small, scale, calibration)) if small.width <= sw / scale && small.height <= sh / scale => {
+            let cutoff = coarse_cutoff(threshold, *calibration, small.size());
+            pyramid_candidates(src, sw, sh, &integral, tpl, small, *scale, cutoff, threshold)
+        }
+        _ => dense_candidates(src, sw, sh, &integral, &tpl.full, threshold),
+    }
+}
+
+/// Multi-match counterpart of `coarse_windows` + `refine_window`: every local
+/// maximum of the coarse score map that passes the coarse cutoff is refined
+/// in its own full-resolution window. Nearby coarse peaks often refine to the
+/// same position, so exact duplicates are dropped here.
+#[allow(clippy::too_many_arguments)]
+fn pyramid_candidates(
+    src: &[f64], sw: usize, sh: usize, integral: &IntegralImage, tpl: &PreparedTemplate,
+    small: &Template, scale: usize, cutoff: f64, threshold: f64,
+) -> Vec<MatchResult> {
+    let stats = Stats::current();
+    let (small_src, ssw, ssh) = stats.time("pyramid", || downsample_filtered(src, sw, sh, scale, tpl.filter));
+    let cols = ssw - small.width + 1;
+    let peaks = stats.time("coarse", || {
+        let scores = score_map(&small_src, ssw, ssh, small);
+        if let Some((i, &score)) = scores.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1)) {
+            stats.coarse(scale, score, i % cols, i / cols);
+        }
+        score_peaks(&scores, cols, ssh - small.height + 1, cutoff)
+    });
+    let (end_x, end_y) = (sw - tpl.full.width, sh - tpl.full.height);
+    
+    // Tighter than the single-match margin: there is one window per peak
+    let deadline = Deadline::current();
+    let mut results: Vec<MatchResult> = stats.time("refine", || {
+        peaks
+            .par_iter()
+            .with_min_len(min_items((4 * scale + 1).pow(2) * tpl.full.width * tpl.full.height))
+            .filter_map(|p| {
+                if deadline.passed() { return None; }
+                let window = coarse_to_window(p.x as usize, p.y as usize, scale, scale * 2, end_x, end_y);
+                stats.window(window);
+                search_region(src, sw, integral, &tpl.full, window, threshold, &stats)
+            })
+            .collect()
+    });
+    results.sort_by_key(|r| (r.y, r.x));
+    results.dedup_by_key(|r| (r.x, r.y));
+
+    log::debug!(
+        "{} coarse peaks above {:.3} at scale {}, {} above threshold after refinement",
+        peaks.len(), cutoff, scale, results.len()
+    );
+    results
+}
+
+/// Stride-2 scan of every window position, refining each hit in its 2x2 cell
+fn dense_candidates(
+    src: &[f64], sw: usize, sh: usize, integral: &IntegralImage, tpl: &Template, threshold: f64,
+) -> Vec<MatchResult> {
+    let (tw, th) = (tpl.width, tpl.height);
+    let end_x = sw - tw;
+    let end_y = sh - th;
+    let step = 2usize;
+    
+    let deadline = Deadline::current();
+    let stats = Stats::current();
+    let candidates: Vec<_> = (0..end_y / step + 1)
+        .into_par_iter()
+        .with_min_len(min_items((end_x / step + 1) * tw * th))
+        .flat_map(|yi| {
+            let y = yi * step;
+            let mut row_candidates = Vec::new();
+            if deadline.passed() { return row_candidates; }
+            for xi in 0..=end_x / step {
+                let x = xi * step;
+                let score = compute_ncc(src, sw, integral, tpl, x, y);
+                if score >= threshold * 0.9 { row_candidates.push((x, y, score)); }
+            }
+            stats.count(end_x / step + 1, 0);
+            row_candidates
+        })
+        .collect();
+    stats.count(candidates.len() * step * step, 0);
+
+    // Dense scenes produce thousands of candidates, so refinement is parallel too
+    let results: Vec<MatchResult> = candidates
+        .par_iter()
+        .with_min_len(min_items(step * step * tw * th))
+        .filter_map(|&(cx, cy, _)| {
+            if deadline.passed() { return None; }
+            let mut best = (cx, cy, -1.0f64);
+            for dy in 0..step {
+                for dx in 0..step {
+                    let x = (cx + dx).min(end_x);
+                    let y = (cy + dy).min(end_y);
+                    let score = compute_ncc(src, sw, integral, tpl, x, y);
                     if score > best.2 { best = (x, y, score); }
                 }
             }
             if best.2 >= threshold {
-                Some(MatchResult { x: best.0 as u32, y: best.1 as u32, confidence: best.2 })
+                Some(MatchResult { x: best.0 as i32, y: best.1 as i32, confidence: best.2, crop: None, scale: None, angle: None, pixel_ratio: 1.0, flip: None, inverted: false })
             } else { None }
         })
-        .collect();
+        .collect();
+
+    log::debug!(
+        "{} candidates above {:.3} on the stride-{} grid, {} above threshold after refinement",
+        candidates.len(), threshold * 0.9, step, results.len()
+    );
+    results
+}
+
+/// Whether two results are closer than half the template size in both axes
+fn overlaps(a: &MatchResult, b: &MatchResult, tw: usize, th: usize) -> bool {
+    a.x.abs_diff(b.x) < tw as u32 / 2 && a.y.abs_diff(b.y) < th as u32 / 2
+}
+
+/// Kept results bucketed on a grid of half-template cells. A result can only
+/// overlap kept results in its own or the 8 neighbouring cells, so each test
+/// is O(1) on average instead of a scan over everything kept.
+struct OverlapGrid {
+    cells: HashMap<(i32, i32), Vec<MatchResult>>,
+    tw: usize,
+    th: usize,
+}
+
+impl OverlapGrid {
+    fn new(tw: usize, th: usize) -> Self {
+        Self { cells: HashMap::new(), tw, th }
+    }
+
+    fn cell(&self, r: &MatchResult) -> (i32, i32) {
+        (r.x.div_euclid((self.tw / 2).max(1) as i32), r.y.div_euclid((self.th / 2).max(1) as i32))
+    }
+
+    fn overlaps(&self, r: &MatchResult) -> bool {
+        let (cx, cy) = self.cell(r);
+        (cy - 1..=cy + 1).any(|y| (cx - 1..=cx + 1).any(|x| {
+            self.cells.get(&(x, y)).is_some_and(|kept| kept.iter().any(|k| overlaps(r, k, self.tw, self.th)))
+        }))
+    }
+
+    fn insert(&mut self, r: MatchResult) {
+        self.cells.entry(self.cell(&r)).or_default().push(r);
+    }
+}
+
+fn sort_by_confidence(results: &mut [MatchResult]) {
+    // Stable, so equal scores keep their scan order whatever the thread count
+    results.par_sort_by(|a, b| b.confidence.partial_cmp(&a.confidence).unwrap());
+}
+
+/// Sort by confidence and drop results whose offset to a stronger result is
+/// less than half the template size in both axes
+fn non_max_suppression(mut results: Vec<MatchResult>, tw: usize, th: usize, max_count: usize) -> Vec<MatchResult> {
+    sort_by_confidence(&mut results);
+    
+    let mut kept = OverlapGrid::new(tw, th);
+    let mut filtered = Vec::new();
+    for r in results {
+        if !kept.overlaps(&r) {
+            kept.insert(r.clone());
+            filtered.push(r);
+            if filtered.len() >= max_count { break; }
+        }
+    }
+    filtered
+}
+
+/// Single-linkage clusters of results, linking any two that overlap (closer
+/// than half the template size in both axes). Returns member indices per cluster.
+fn cluster_matches(results: &[MatchResult], tw: usize, th: usize) -> Vec<Vec<usize>> {
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+    let (cw, ch) = ((tw / 2).max(1) as i32, (th / 2).max(1) as i32);
+    let mut parent: Vec<usize> = (0..results.len()).collect();
+    let mut grid: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+    for (i, r) in results.iter().enumerate() {
+        let (cx, cy) = (r.x.div_euclid(cw), r.y.div_euclid(ch));
+        for y in cy - 1..=cy + 1 {
+            for x in cx - 1..=cx + 1 {
+                for &j in grid.get(&(x, y)).into_iter().flatten() {
+                    if overlaps(r, &results[j], tw, th) {
+                        let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                        parent[a] = b;
+                    }
+                }
+            }
+        }
+        grid.entry((cx, cy)).or_default().push(i);
+    }
+    let mut clusters: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..results.len() {
+        clusters.entry(root(&mut parent, i)).or_default().push(i);
+    }
+    clusters.into_values().collect()
+}
+
+// ============================================================================
+// Tiled Matching (bounded memory for very large sources)
+// ============================================================================
+
+/// Tile origins along one axis. Consecutive tiles overlap by `tpl - 1` pixels,
+/// so every window position is evaluated in exactly one tile.
+fn tile_origins(len: usize, tile: usize, tpl: usize) -> Vec<usize> {
+    let step = tile - tpl + 1;
+    let mut origins = vec![0usize];
+    let mut o = 0usize;
+    while o + tile < len {
+        o += step;
+        origins.push(o);
+    }
+    origins
+}
+
+/// 8-bit grayscale rows of a tiled source, read top to bottom. PNG and TIFF
+/// files are decoded as the rows are read; other formats, EXIF-rotated files
+/// and sources that need the whole image for preprocessing are decoded whole.
+struct GrayRows {
+    path: String,
+    width: usize,
+    height: usize,
+    source: RowSource,
+}
+
+impl GrayRows {
+    fn open(path: &str, opts: &MatchOptions) -> PyResult<Self> {
+        let whole = opts.denoise.is_some() || opts.adjusts_tone() || opts.gradient;
+        let rotated = file_orientation(path, opts.exif_orientation).is_some_and(|o| o > 1);
+        if let Some((width, height, source)) = (!whole && !rotated).then(|| stream_rows(path)).flatten() {
+            return Ok(Self { path: path.to_string(), width, height, source });
+        }
+        let gray = opts.prepare_gray(load_gray_from_path(path, opts.exif_orientation)?);
+        let (width, height) = (gray.width() as usize, gray.height() as usize);
+        Ok(Self { path: path.to_string(), width, height, source: RowSource::Decoded { gray, next: 0 } })
+    }
+
+    fn read_row(&mut self, out: &mut [u8]) -> PyResult<()> {
+        self.source.read_row(self.width, out).ok_or_else(|| {
+            DecodeError::new_err(format!("Failed to load image '{}': corrupt or truncated pixel data", self.path))
+        })
+    }
+}
+
+/// Copy one tile of a band of 8-bit grayscale rows into f64 samples
+fn extract_tile(band: &[u8], stride: usize, x0: usize, w: usize, h: usize) -> Vec<f64> {
+    let mut data = Vec::with_capacity(w * h);
+    for row in band.chunks_exact(stride).take(h) {
+        data.extend(row[x0..x0 + w].iter().map(|&v| v as f64));
+    }
+    data
+}
+
+/// Run `f` on each overlapping tile. Only one band of tile rows and one tile
+/// (plus its integral images, built inside `f`) are alive at a time, keeping
+/// peak memory proportional to `tile_size` times the source width instead of
+/// the source size.
+fn for_each_tile<F>(rows: &mut GrayRows, tw: usize, th: usize, tile_size: usize, mut f: F) -> PyResult<()>
+where
+    F: FnMut(&[f64], usize, usize, usize, usize),
+{
+    let (sw, sh) = (rows.width, rows.height);
+    if tw > sw || th > sh { return Ok(()); }
+    let tile_w = tile_size.max(tw).min(sw);
+    let tile_h = tile_size.max(th).min(sh);
+
+    // Rows `band_y..band_y + band.len() / sw` of the source
+    let mut band = Vec::with_capacity(tile_h * sw);
+    let mut band_y = 0;
+    for y0 in tile_origins(sh, tile_h, th) {
+        let h = tile_h.min(sh - y0);
+        // Tiles overlap vertically, so only the rows above this one are dropped
+        band.drain(..(y0 - band_y) * sw);
+        band_y = y0;
+        while band.len() < h * sw {
+            let start = band.len();
+            band.resize(start + sw, 0);
+            rows.read_row(&mut band[start..])?;
+        }
+        for x0 in tile_origins(sw, tile_w, tw) {
+            let w = tile_w.min(sw - x0);
+            let tile = extract_tile(&band, sw, x0, w, h);
+            f(&tile, x0, y0, w, h);
+        }
+    }
+    Ok(())
+}
+
+fn tiled_match(
+    rows: &mut GrayRows, tpl: &GrayImageData, threshold: f64, tile_size: usize, opts: &MatchOptions,
+) -> PyResult<Option<MatchResult>> {
+    let mut best: Option<MatchResult> = None;
+    for_each_tile(rows, tpl.width, tpl.height, tile_size, |tile, x0, y0, w, h| {
+        if let Some(mut r) = opts.find_best(tile, w, h, tpl, threshold) {
+            r.x += x0 as i32;
+            r.y += y0 as i32;
+            if best.as_ref().map_or(true, |b| r.confidence > b.confidence) { best = Some(r); }
+        }
+    })?;
+    Ok(best)
+}
+
+fn tiled_match_multi(
+    rows: &mut GrayRows, tpl: &GrayImageData, threshold: f64, max_count: usize, tile_size: usize,
+    opts: &MatchOptions,
+) -> PyResult<Vec<MatchResult>> {
+    let mut results = Vec::new();
+    for_each_tile(rows, tpl.width, tpl.height, tile_size, |tile, x0, y0, w, h| {
+        for mut r in opts.find_all(tile, w, h, tpl, threshold, max_count) {
+            r.x += x0 as i32;
+            r.y += y0 as i32;
+            results.push(r);
+        }
+    })?;
+    // Neighbouring tiles can report the same target from either side of the seam
+    Ok(non_max_suppression(results, tpl.width, tpl.height, max_count))
+}
+
+/// Attach `return_crop` crops to finished tiled results, reading the source a
+/// second time and keeping only the crops in memory
+fn attach_tiled_crops(path: &str, opts: &MatchOptions, tpl: &SearchTemplate, results: &mut [MatchResult]) -> PyResult<()> {
+    if !opts.return_crop || results.is_empty() { return Ok(()); }
+    let mut rows = GrayRows::open(path, opts)?;
+    let size = (rows.width as u32, rows.height as u32);
+    let boxes: Vec<_> = results.iter().map(|r| visible_box(r, tpl.footprint, size)).collect();
+    let mut crops: Vec<GrayImage> = boxes.iter().map(|&(_, _, w, h)| GrayImage::new(w, h)).collect();
+    let bottom = boxes.iter().map(|&(_, y, _, h)| y + h).max().unwrap_or(0);
+    let mut row = vec![0u8; rows.width];
+    for y in 0..bottom {
+        rows.read_row(&mut row)?;
+        for (crop, &(x0, y0, w, h)) in crops.iter_mut().zip(&boxes) {
+            if (y0..y0 + h).contains(&y) {
+                let (x0, w) = (x0 as usize, w as usize);
+                crop.as_mut()[(y - y0) as usize * w..][..w].copy_from_slice(&row[x0..x0 + w]);
+            }
+        }
+    }
+    for (r, crop) in results.iter_mut().zip(crops) {
+        r.crop = Some(encode_png(&DynamicImage::ImageLuma8(crop))?);
+    }
+    Ok(())
+}
+
+// ============================================================================
+// Alternative Window Scores (same search strategy, different score)
+// ============================================================================
+
+/// Window score used in place of plain NCC by the robust and weighted modes
+trait WindowScore: Sync {
+    fn size(&self) -> (usize, usize);
+    fn score(&self, src: &[f64], sw: usize, integral: &IntegralImage, x: usize, y: usize) -> f64;
+
+    /// `score` for a search that only needs scores reaching `floor`: below it,
+    /// any value under `floor` may be returned
+    #[inline]
+    fn score_above(&self, src: &[f64], sw: usize, integral: &IntegralImage, x: usize, y: usize, _floor: f64) -> f64 {
+        self.score(src, sw, integral, x, y)
+    }
+}
+
+impl WindowScore for Template {
+    fn size(&self) -> (usize, usize) { (self.width, self.height) }
+
+    #[inline]
+    fn score(&self, src: &[f64], sw: usize, integral: &IntegralImage, x: usize, y: usize) -> f64 {
+        compute_ncc(src, sw, integral, self, x, y)
+    }
+
+    #[inline]
+    fn score_above(&self, src: &[f64], sw: usize, integral: &IntegralImage, x: usize, y: usize, floor: f64) -> f64 {
+        compute_ncc_bounded(src, sw, integral, self, x, y, floor, &mut 0)
+    }
+}
+
+/// Best score among window positions `(x1, y1)..=(x2, y2)`
+fn scored_search<S: WindowScore>(
+    src: &[f64], sw: usize, sh: usize, tpl: &S, window: (usize, usize, usize, usize),
+) -> (usize, usize, f64) {
+    let (x1, y1, x2, y2) = window;
+    let (tw, th) = tpl.size();
+    let integral = IntegralImage::new(src, sw, sh);
+    let shared = SharedBest::new();
+    let deadline = Deadline::current();
+    let stats = Stats::current();
+    (y1..y2 + 1)
+        .into_par_iter()
+        .with_min_len(min_items((x2 - x1 + 1) * tw * th))
+        .map(|y| {
+            let mut row_best = (x1, y, -1.0f64);
+            if deadline.passed() { return row_best; }
+            for x in x1..=x2 {
+                let score = tpl.score_above(src, sw, &integral, x, y, row_best.2.max(shared.get()));
+                if score > row_best.2 { row_best = (x, y, score); }
+            }
+            stats.count(x2 - x1 + 1, 0);
+            shared.raise(row_best.2);
+            row_best
+        })
+        .reduce(|| (0, 0, -1.0f64), |a, b| if a.2 > b.2 { a } else { b })
+}
+
+/// Coarse-to-fine search mirroring `pyramid_match_prepared`. `coarse` is the
+/// template prepared at the downsampled level (with `filter`), its scale and
+/// its `coarse_calibration`, if that level is usable.
+fn scored_match<S: WindowScore>(
+    src: &[f64], sw: usize, sh: usize, full: &S, coarse: Option<(&S, usize, f64)>, filter: DownsampleFilter,
+    threshold: f64,
+) -> Option<MatchResult> {
+    let (tw, th) = full.size();
+    if tw > sw || th > sh {
+        log::debug!("template {}x{} is larger than source {}x{}", tw, th, sw, sh);
+        return None;
+    }
+    let (end_x, end_y) = (sw - tw, sh - th);
+
+    let window = match coarse {
+        Some((small_tpl, scale, calibration)) => {
+            let (small_src, ssw, ssh) = downsample_filtered(src, sw, sh, scale, filter);
+            let (stw, sth) = small_tpl.size();
+            if stw > ssw || sth > ssh { return None; }
+            let (cx, cy, score) = scored_search(&small_src, ssw, ssh, small_tpl, (0, 0, ssw - stw, ssh - sth));
+            Stats::current().coarse(scale, score, cx, cy);
+            if !log_coarse_score(scale, cx, cy, score, coarse_cutoff(threshold, calibration, (stw, sth))) { return None; }
+            let margin = scale * 4;
+            let (cx, cy) = (cx * scale, cy * scale);
+            let window = (cx.saturating_sub(margin), cy.saturating_sub(margin), (cx + margin).min(end_x), (cy + margin).min(end_y));
+            log::trace!("refinement window ({}, {})-({}, {})", window.0, window.1, window.2, window.3);
+            Stats::current().window(window);
+            window
+        }
+        None => (0, 0, end_x, end_y),
+    };
+
+    let (x, y, score) = scored_search(src, sw, sh, full, window);
+    let best = MatchResult { x: x as i32, y: y as i32, confidence: score, crop: None, scale: None, angle: None, pixel_ratio: 1.0, flip: None, inverted: false };
+    log_final_score(&best, threshold);
+    (score >= threshold).then_some(best)
+}
+
+/// Counterpart of `match_candidates`: stride-2 scan and local refinement
+fn scored_candidates<S: WindowScore>(src: &[f64], sw: usize, sh: usize, tpl: &S, threshold: f64) -> Vec<MatchResult> {
+    let (tw, th) = tpl.size();
+    if tw > sw || th > sh { return vec![]; }
+
+    let integral = IntegralImage::new(src, sw, sh);
+    let (end_x, end_y) = (sw - tw, sh - th);
+    let step = 2usize;
+
+    let deadline = Deadline::current();
+    (0..end_y / step + 1)
+        .into_par_iter()
+        .with_min_len(min_items((end_x / step + 1) * tw * th))
+        .flat_map(|yi| {
+            let y = yi * step;
+            let mut row_results = Vec::new();
+            if deadline.passed() { return row_results; }
+            for xi in 0..=end_x / step {
+                let x = xi * step;
+                if tpl.score(src, sw, &integral, x, y) < threshold * 0.9 { continue; }
+                let mut best = (x, y, -1.0f64);
+                for dy in 0..step {
+                    for dx in 0..step {
+                        let (bx, by) = ((x + dx).min(end_x), (y + dy).min(end_y));
+                        let score = tpl.score(src, sw, &integral, bx, by);
+                        if score > best.2 { best = (bx, by, score); }
+                    }
+                }
+                if best.2 >= threshold {
+                    row_results.push(MatchResult { x: best.0 as i32, y: best.1 as i32, confidence: best.2, crop: None, scale: None, angle: None, pixel_ratio: 1.0, flip: None, inverted: false });
+                }
+            }
+            row_results
+        })
+        .collect()
+}
+
+// ============================================================================
+// Occlusion-Robust Block Scoring
+// ============================================================================
+
+const MAX_ROBUST_GRID: usize = 8;
+
+/// Template split into a grid of independently normalized blocks. A window's
+/// score is the mean NCC of its best-scoring `keep` blocks, so blocks hidden
+/// behind a tooltip or cursor are simply left out.
+struct BlockTemplate {
+    blocks: Vec<(usize, usize, Template)>,
+    width: usize,
+    height: usize,
+    keep: usize,
+}
+
+impl BlockTemplate {
+    fn new(data: &[f64], w: usize, h: usize, grid: usize, keep_fraction: f64) -> Self {
+        let gx = grid.min(w);
+        let gy = grid.min(h);
+        let mut blocks = Vec::with_capacity(gx * gy);
+        for j in 0..gy {
+            let (y1, y2) = (j * h / gy, (j + 1) * h / gy);
+            for i in 0..gx {
+                let (x1, x2) = (i * w / gx, (i + 1) * w / gx);
+                let block = crop_region(data, w, x1, y1, x2 - x1, y2 - y1);
+                let tpl = Template::new(&block, x2 - x1, y2 - y1);
+                // Flat blocks (std < 1) carry no correlation signal; leave them out
+                if tpl.inv_std_n * (block.len() as f64) <= 1.0 {
+                    blocks.push((x1, y1, tpl));
+                }
+            }
+        }
+        if blocks.is_empty() { blocks.push((0, 0, Template::new(data, w, h))); }
+        let keep = ((blocks.len() as f64 * keep_fraction).ceil() as usize).clamp(1, blocks.len());
+        Self { blocks, width: w, height: h, keep }
+    }
+}
+
+impl WindowScore for BlockTemplate {
+    fn size(&self) -> (usize, usize) { (self.width, self.height) }
+
+    #[inline]
+    fn score(&self, src: &[f64], sw: usize, integral: &IntegralImage, x: usize, y: usize) -> f64 {
+        let mut scores = [0.0f64; MAX_ROBUST_GRID * MAX_ROBUST_GRID];
+        let n = self.blocks.len();
+        for (s, (bx, by, tpl)) in scores.iter_mut().zip(&self.blocks) {
+            *s = compute_ncc(src, sw, integral, tpl, x + bx, y + by);
+        }
+        let scores = &mut scores[..n];
+        if self.keep < n {
+            scores.select_nth_unstable_by(self.keep - 1, |a, b| b.partial_cmp(a).unwrap());
+        }
+        scores[..self.keep].iter().sum::<f64>() / self.keep as f64
+    }
+}
+
+/// Robust template at full resolution plus its coarse level. The coarse level
+/// is skipped when downsampled blocks would be too small to score.
+fn robust_levels(
+    tpl: &GrayImageData, (grid, keep): (usize, f64), filter: DownsampleFilter,
+) -> (BlockTemplate, Option<(BlockTemplate, usize, f64)>) {
+    let (tw, th) = (tpl.width, tpl.height);
+    let scale = pyramid_scale(tw, th);
+    let coarse = if scale >= 4 && tw.min(th) / scale >= grid * 3 {
+        let (small, stw, sth) = downsample_filtered(&tpl.data, tw, th, scale, filter);
+        let small = BlockTemplate::new(&small, stw, sth, grid, keep);
+        let calibration = coarse_calibration(&tpl.data, tw, th, &small, scale, filter);
+        Some((small, scale, calibration))
+    } else { None };
+    (BlockTemplate::new(&tpl.data, tw, th, grid, keep), coarse)
+}
+
+// ============================================================================
+// Per-Pixel Weighted NCC
+// ============================================================================
+
+/// Template with a per-pixel weight map. The score is the weighted Pearson
+/// correlation, so high-weight pixels dominate and zero-weight pixels are ignored.
+/// Source statistics depend on the weights, so they are accumulated per window
+/// instead of read from the integral image.
+struct WeightedTemplate {
+    weights: Vec<f64>,
+    /// w * (t - weighted mean of t)
+    centered: Vec<f64>,
+    sum_w: f64,
+    /// sqrt(sum w * (t - mean)^2)
+    t_norm: f64,
+    width: usize,
+    height: usize,
+}
+
+impl WeightedTemplate {
+    fn new(data: &[f64], weights: &[f64], w: usize, h: usize) -> Self {
+        let sum_w: f64 = weights.iter().sum::<f64>().max(1e-10);
+        let mean = data.iter().zip(weights).map(|(&t, &wt)| t * wt).sum::<f64>() / sum_w;
+        let centered: Vec<f64> = data.iter().zip(weights).map(|(&t, &wt)| wt * (t - mean)).collect();
+        let t_var: f64 = data.iter().zip(weights).map(|(&t, &wt)| wt * (t - mean) * (t - mean)).sum();
+        Self { weights: weights.to_vec(), centered, sum_w, t_norm: t_var.sqrt().max(1e-10), width: w, height: h }
+    }
+}
+
+impl WindowScore for WeightedTemplate {
+    fn size(&self) -> (usize, usize) { (self.width, self.height) }
+
+    #[inline]
+    fn score(&self, src: &[f64], sw: usize, _integral: &IntegralImage, x: usize, y: usize) -> f64 {
+        let (mut cross, mut s_sum, mut s_sq_sum) = (0.0f64, 0.0f64, 0.0f64);
+        for ty in 0..self.height {
+            let row = (y + ty) * sw + x;
+            let idx = ty * self.width;
+            for tx in 0..self.width {
+                let sv = src[row + tx];
+                let wt = self.weights[idx + tx];
+                cross += sv * self.centered[idx + tx];
+                s_sum += wt * sv;
+                s_sq_sum += wt * sv * sv;
+            }
+        }
+        // Weighted variance of the source window, scaled by sum_w like t_norm^2
+        let s_var = s_sq_sum - s_sum * s_sum / self.sum_w;
+        if s_var < self.sum_w { return 0.0; }
+        cross / (s_var.sqrt() * self.t_norm)
+    }
+}
+
+fn weighted_levels(
+    tpl: &GrayImageData, weights: &[f64], filter: DownsampleFilter,
+) -> (WeightedTemplate, Option<(WeightedTemplate, usize, f64)>) {
+    let (tw, th) = (tpl.width, tpl.height);
+    let scale = pyramid_scale(tw, th);
+    let coarse = if scale >= 4 {
+        let (small, stw, sth) = downsample_filtered(&tpl.data, tw, th, scale, filter);
+        let (small_w, _, _) = downsample(weights, tw, th, scale);
+        let small = WeightedTemplate::new(&small, &small_w, stw, sth);
+        let calibration = coarse_calibration(&tpl.data, tw, th, &small, scale, filter);
+        Some((small, scale, calibration))
+    } else { None };
+    (WeightedTemplate::new(&tpl.data, weights, tw, th), coarse)
+}
+
+// ============================================================================
+// Sampled NCC (approximate scan for very large templates)
+// ============================================================================
+
+/// How far below the threshold a sampled score may fall and still be
+/// re-scored exactly, to absorb the sampling error
+const SAMPLED_SCORE_SLACK: f64 = 0.05;
+
+/// Fewest template pixels a sampled score is taken over
+const MIN_SAMPLED_PIXELS: usize = 64;
+
+/// Template reduced to a fixed pseudo-random subset of its pixels; the score
+/// is the Pearson correlation over that subset only. The subset depends only
+/// on the template size and fraction, so results are reproducible.
+struct SampledTemplate {
+    /// `(x, y)` of every sampled pixel, in row-major order
+    offsets: Vec<(usize, usize)>,
+    /// Sampled template values minus their mean
+    centered: Vec<f64>,
+    t_norm: f64,
+    width: usize,
+    height: usize,
+}
+
+impl SampledTemplate {
+    fn new(data: &[f64], w: usize, h: usize, fraction: f64) -> Self {
+        let n = w * h;
+        let k = ((n as f64 * fraction).ceil() as usize).max(MIN_SAMPLED_PIXELS).min(n);
+        // Partial Fisher-Yates shuffle driven by xorshift64
+        let mut indices: Vec<usize> = (0..n).collect();
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        for i in 0..k {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let j = i + (state % (n - i) as u64) as usize;
+            indices.swap(i, j);
+        }
+        let mut picked = indices[..k].to_vec();
+        picked.sort_unstable();
+        let mean = picked.iter().map(|&i| data[i]).sum::<f64>() / k.max(1) as f64;
+        let centered: Vec<f64> = picked.iter().map(|&i| data[i] - mean).collect();
+        let t_norm = centered.iter().map(|v| v * v).sum::<f64>().sqrt().max(1e-10);
+        let offsets = picked.into_iter().map(|i| (i % w, i / w)).collect();
+        Self { offsets, centered, t_norm, width: w, height: h }
+    }
+}
+
+impl WindowScore for SampledTemplate {
+    fn size(&self) -> (usize, usize) { (self.width, self.height) }
+
+    #[inline]
+    fn score(&self, src: &[f64], sw: usize, _integral: &IntegralImage, x: usize, y: usize) -> f64 {
+        let (mut cross, mut s_sum, mut s_sq_sum) = (0.0f64, 0.0f64, 0.0f64);
+        for (&(tx, ty), &t) in self.offsets.iter().zip(&self.centered) {
+            let sv = src[(y + ty) * sw + x + tx];
+            cross += sv * t;
+            s_sum += sv;
+            s_sq_sum += sv * sv;
+        }
+        let k = self.offsets.len() as f64;
+        // Same flat-window cutoff as the weighted score: variance below 1 grey level
+        let s_var = s_sq_sum - s_sum * s_sum / k;
+        if s_var < k { return 0.0; }
+        cross / (s_var.sqrt() * self.t_norm)
+    }
+}
+
+/// Sampled template and its coarse pyramid level. The coarse level is small
+/// and its scores are the least reliable, so it keeps every pixel.
+fn sampled_levels(
+    tpl: &GrayImageData, fraction: f64, filter: DownsampleFilter,
+) -> (SampledTemplate, Option<(SampledTemplate, usize, f64)>) {
+    let (tw, th) = (tpl.width, tpl.height);
+    let scale = pyramid_scale(tw, th);
+    let coarse = if scale >= 4 {
+        let (small, stw, sth) = downsample_filtered(&tpl.data, tw, th, scale, filter);
+        let small = SampledTemplate::new(&small, stw, sth, 1.0);
+        let calibration = coarse_calibration(&tpl.data, tw, th, &small, scale, filter);
+        Some((small, scale, calibration))
+    } else { None };
+    (SampledTemplate::new(&tpl.data, tw, th, fraction), coarse)
+}
+
+/// Exact NCC of the best window within one pixel of a sampled hit
+fn rescore_exact(src: &[f64], sw: usize, sh: usize, tpl: &Template, hit: &MatchResult, threshold: f64) -> Option<MatchResult> {
+    let (x, y) = (hit.x as usize, hit.y as usize);
+    let (end_x, end_y) = (sw - tpl.width, sh - tpl.height);
+    let window = (x.saturating_sub(1), y.saturating_sub(1), (x + 1).min(end_x), (y + 1).min(end_y));
+    search_window(src, sw, tpl, window, threshold)
+}
+
+/// Single best match scanned with the sampled score (through the coarse
+/// pyramid unless `exact`), then confirmed by exact NCC
+#[allow(clippy::too_many_arguments)]
+fn sampled_match(
+    src: &[f64], sw: usize, sh: usize, tpl: &GrayImageData, fraction: f64, exact: bool, filter: DownsampleFilter,
+    threshold: f64,
+) -> Option<MatchResult> {
+    let (full, coarse) = sampled_levels(tpl, fraction, filter);
+    let coarse = coarse.as_ref().filter(|_| !exact).map(|(small, scale, calibration)| (small, *scale, *calibration));
+    let hit = scored_match(src, sw, sh, &full, coarse, filter, threshold - SAMPLED_SCORE_SLACK)?;
+    rescore_exact(src, sw, sh, &Template::new(&tpl.data, tpl.width, tpl.height), &hit, threshold)
+}
+
+/// All matches found with the sampled score, each confirmed by exact NCC.
+/// Mirrors `pyramid_candidates`: coarse peaks are refined in their own
+/// full-resolution windows, now with the sampled score.
+#[allow(clippy::too_many_arguments)]
+fn sampled_candidates(
+    src: &[f64], sw: usize, sh: usize, tpl: &GrayImageData, fraction: f64, exact: bool, filter: DownsampleFilter,
+    threshold: f64,
+) -> Vec<MatchResult> {
+    let (full, coarse) = sampled_levels(tpl, fraction, filter);
+    let loose = threshold - SAMPLED_SCORE_SLACK;
+    let (end_x, end_y) = (sw - tpl.width, sh - tpl.height);
+    let hits = match coarse.filter(|_| !exact) {
+        Some((small, scale, calibration)) if small.width <= sw / scale && small.height <= sh / scale => {
+            let stats = Stats::current();
+            let (small_src, ssw, ssh) = downsample_filtered(src, sw, sh, scale, filter);
+            let scores = score_map(&small_src, ssw, ssh, &small);
+            let cols = ssw - small.width + 1;
+            if let Some((i, &score)) = scores.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1)) {
+                stats.coarse(scale, score, i % cols, i / cols);
+            }
+            let cutoff = coarse_cutoff(threshold, calibration, small.size());
+            let peaks = score_peaks(&scores, cols, ssh - small.height + 1, cutoff);
+            let integral = IntegralImage::new(src, sw, sh);
+            let deadline = Deadline::current();
+            peaks.par_iter()
+                .filter_map(|p| {
+                    if deadline.passed() { return None; }
+                    let window = coarse_to_window(p.x as usize, p.y as usize, scale, scale * 2, end_x, end_y);
+                    stats.window(window);
+                    let (x1, y1, x2, y2) = window;
+                    let (x, y, score) = (y1..=y2)
+                        .flat_map(|y| (x1..=x2).map(move |x| (x, y)))
+                        .map(|(x, y)| (x, y, full.score(src, sw, &integral, x, y)))
+                        .fold((0, 0, -1.0f64), |a, b| if b.2 > a.2 { b } else { a });
+                    stats.count((x2 - x1 + 1) * (y2 - y1 + 1), 0);
+                    (score >= loose).then_some(MatchResult {
+                        x: x as i32, y: y as i32, confidence: score, crop: None, scale: None, angle: None, pixel_ratio: 1.0, flip: None, inverted: false,
+                    })
+                })
+                .collect()
+        }
+        _ if exact => exact_candidates(src, sw, sh, &full, loose),
+        _ => scored_candidates(src, sw, sh, &full, loose),
+    };
+    log::debug!("{} sampled hits to re-score exactly", hits.len());
+    let exact_tpl = Template::new(&tpl.data, tpl.width, tpl.height);
+    let deadline = Deadline::current();
+    let mut results: Vec<MatchResult> = hits.par_iter()
+        .filter_map(|hit| {
+            if deadline.passed() { return None; }
+            rescore_exact(src, sw, sh, &exact_tpl, hit, threshold)
+        })
+        .collect();
+    results.sort_by_key(|r| (r.y, r.x));
+    results.dedup_by_key(|r| (r.x, r.y));
+    results
+}
+
+// ============================================================================
+// Binary Mask Matching (intersection over union)
+// ============================================================================
+
+/// 1.0 for pixels at or above `cutoff`, else 0.0
+fn binarize(data: &[f64], cutoff: f64) -> Vec<f64> {
+    data.iter().map(|&v| if v >= cutoff { 1.0 } else { 0.0 }).collect()
+}
+
+/// Binary template scored by intersection over union against a binarized
+/// source. The window's foreground count comes from the source integral
+/// image, so each position only visits the template's foreground pixels.
+struct BinaryTemplate {
+    /// `(x, y)` of every foreground pixel
+    ones: Vec<(usize, usize)>,
+    width: usize,
+    height: usize,
+}
+
+impl BinaryTemplate {
+    fn new(data: &[f64], w: usize, h: usize, cutoff: f64) -> Self {
+        let ones = (0..h).flat_map(|y| (0..w).map(move |x| (x, y))).filter(|&(x, y)| data[y * w + x] >= cutoff).collect();
+        Self { ones, width: w, height: h }
+    }
+}
+
+impl WindowScore for BinaryTemplate {
+    fn size(&self) -> (usize, usize) { (self.width, self.height) }
+
+    #[inline]
+    fn score(&self, src: &[f64], sw: usize, integral: &IntegralImage, x: usize, y: usize) -> f64 {
+        let (window_ones, _) = integral.get_stats(x, y, self.width, self.height);
+        let intersection: f64 = self.ones.iter().map(|&(tx, ty)| src[(y + ty) * sw + x + tx]).sum();
+        let union = window_ones + self.ones.len() as f64 - intersection;
+        if union < 0.5 { 0.0 } else { intersection / union }
+    }
+}
+
+// ============================================================================
+// Census Transform Matching (rank order, invariant to monotonic intensity changes)
+// ============================================================================
+
+/// `(column, row)` indices into the 3x3 neighbourhood, centre excluded
+const CENSUS_NEIGHBOURS: [(usize, usize); 8] = [(0, 0), (1, 0), (2, 0), (0, 1), (2, 1), (0, 2), (1, 2), (2, 2)];
+
+/// 3x3 census transform: bit `i` of each code is set when neighbour `i` is
+/// darker than the centre pixel. Edge pixels are replicated. Codes are kept
+/// as `f64` so the transformed source goes through the usual search.
+fn census_transform(data: &[f64], w: usize, h: usize) -> Vec<f64> {
+    let mut out = vec![0.0; data.len()];
+    if w == 0 || h == 0 { return out; }
+    out.par_chunks_mut(w).enumerate().for_each(|(y, row)| {
+        let rows = [y.saturating_sub(1), y, (y + 1).min(h - 1)];
+        for (x, out_px) in row.iter_mut().enumerate() {
+            let cols = [x.saturating_sub(1), x, (x + 1).min(w - 1)];
+            let centre = data[y * w + x];
+            let mut code = 0u8;
+            for (bit, &(i, j)) in CENSUS_NEIGHBOURS.iter().enumerate() {
+                if data[rows[j] * w + cols[i]] < centre { code |= 1 << bit; }
+            }
+            *out_px = code as f64;
+        }
+    });
+    out
+}
+
+/// Census codes of the template's interior pixels, scored by Hamming
+/// similarity (fraction of equal bits) against a census-transformed source.
+/// Border pixels are skipped: their neighbourhood extends past the template,
+/// so their codes depend on what surrounds the target.
+struct CensusTemplate {
+    /// `(x, y, code)` of every interior pixel
+    codes: Vec<(usize, usize, u8)>,
+    width: usize,
+    height: usize,
+}
+
+impl CensusTemplate {
+    fn new(data: &[f64], w: usize, h: usize) -> Self {
+        let census = census_transform(data, w, h);
+        let codes = (1..h.saturating_sub(1))
+            .flat_map(|y| (1..w.saturating_sub(1)).map(move |x| (x, y)))
+            .map(|(x, y)| (x, y, census[y * w + x] as u8))
+            .collect();
+        Self { codes, width: w, height: h }
+    }
+}
+
+impl WindowScore for CensusTemplate {
+    fn size(&self) -> (usize, usize) { (self.width, self.height) }
+
+    #[inline]
+    fn score(&self, src: &[f64], sw: usize, _integral: &IntegralImage, x: usize, y: usize) -> f64 {
+        if self.codes.is_empty() { return 0.0; }
+        let differing: u32 = self.codes.iter().map(|&(tx, ty, code)| (src[(y + ty) * sw + x + tx] as u8 ^ code).count_ones()).sum();
+        1.0 - differing as f64 / (8 * self.codes.len()) as f64
+    }
+}
+
+// ============================================================================
+// Mutual Information (multi-modal matching)
+// ============================================================================
+
+/// Grey-level bins per image in the joint histogram
+const MI_BINS: usize = 16;
+
+#[inline]
+fn mi_bin(v: f64) -> usize {
+    ((v.max(0.0) as usize) * MI_BINS / 256).min(MI_BINS - 1)
+}
+
+/// `sum c * ln(c)` over histogram counts
+fn count_entropy_term(counts: impl Iterator<Item = u32>) -> f64 {
+    counts.filter(|&c| c > 0).map(|c| { let c = c as f64; c * c.ln() }).sum()
+}
+
+/// Template scored by normalized mutual information with each window:
+/// `2 * I(T; W) / (H(T) + H(W))` over a joint histogram of binned grey
+/// levels, 1.0 when one image's levels determine the other's through any
+/// one-to-one mapping, however nonlinear.
+struct MutualInfoTemplate {
+    bins: Vec<u8>,
+    /// `sum c * ln(c)` of the template's own histogram
+    template_term: f64,
+    width: usize,
+    height: usize,
+}
+
+impl MutualInfoTemplate {
+    fn new(data: &[f64], w: usize, h: usize) -> Self {
+        let bins: Vec<u8> = data.iter().map(|&v| mi_bin(v) as u8).collect();
+        let mut hist = [0u32; MI_BINS];
+        for &b in &bins { hist[b as usize] += 1; }
+        Self { bins, template_term: count_entropy_term(hist.into_iter()), width: w, height: h }
+    }
+}
+
+impl WindowScore for MutualInfoTemplate {
+    fn size(&self) -> (usize, usize) { (self.width, self.height) }
+
+    fn score(&self, src: &[f64], sw: usize, _integral: &IntegralImage, x: usize, y: usize) -> f64 {
+        let mut joint = [0u32; MI_BINS * MI_BINS];
+        let mut window = [0u32; MI_BINS];
+        for (ty, row) in self.bins.chunks_exact(self.width).enumerate() {
+            let src_row = &src[(y + ty) * sw + x..][..self.width];
+            for (&tb, &v) in row.iter().zip(src_row) {
+                let sb = mi_bin(v);
+                joint[tb as usize * MI_BINS + sb] += 1;
+                window[sb] += 1;
+            }
+        }
+        let n = self.bins.len() as f64;
+        // H = ln(n) - term / n for each histogram
+        let (ht, hw) = (n.ln() - self.template_term / n, n.ln() - count_entropy_term(window.into_iter()) / n);
+        let hj = n.ln() - count_entropy_term(joint.into_iter()) / n;
+        if ht + hw < 1e-9 { return 0.0; }
+        (2.0 * (ht + hw - hj) / (ht + hw)).clamp(0.0, 1.0)
+    }
+}
+
+/// Full-resolution template and, for templates big enough to keep a useful
+/// histogram when downsampled, its coarse pyramid level
+fn mutual_info_levels(
+    tpl: &GrayImageData, filter: DownsampleFilter,
+) -> (MutualInfoTemplate, Option<(MutualInfoTemplate, usize, f64)>) {
+    let (tw, th) = (tpl.width, tpl.height);
+    let scale = pyramid_scale(tw, th);
+    let coarse = if scale >= 4 && tw.min(th) / scale >= 8 {
+        let (small, stw, sth) = downsample_filtered(&tpl.data, tw, th, scale, filter);
+        let small = MutualInfoTemplate::new(&small, stw, sth);
+        let calibration = coarse_calibration(&tpl.data, tw, th, &small, scale, filter);
+        Some((small, scale, calibration))
+    } else { None };
+    (MutualInfoTemplate::new(&tpl.data, tw, th), coarse)
+}
+
+// ============================================================================
+// Partial-Border Matching (targets cut off by the image edge)
+// ============================================================================
+
+/// Fraction of the template width and of its height that must lie inside the
+/// source for a window overhanging the border to be scored
+const MIN_PARTIAL_VISIBLE: f64 = 0.5;
+
+/// Template with its own integral image, so the statistics of whichever part
+/// of it overlaps the source are O(1) at every border position
+struct PartialTemplate {
+    data: Vec<f64>,
+    integral: IntegralImage,
+    width: usize,
+    height: usize,
+}
+
+impl PartialTemplate {
+    fn new(data: &[f64], w: usize, h: usize) -> Self {
+        Self { data: data.to_vec(), integral: IntegralImage::new(data, w, h), width: w, height: h }
+    }
+
+    /// How far a window may extend past each edge, in x and y
+    fn overhang(&self) -> (usize, usize) {
+        let visible = |len: usize| ((len as f64 * MIN_PARTIAL_VISIBLE).ceil() as usize).clamp(1, len);
+        (self.width - visible(self.width), self.height - visible(self.height))
+    }
+
+    /// NCC between the source and the template over their overlap only, for
+    /// a window whose top-left corner `(x, y)` may lie outside the source
+    fn score(&self, src: &[f64], sw: usize, sh: usize, integral: &IntegralImage, x: isize, y: isize) -> f64 {
+        let (tx0, ty0) = ((-x).max(0) as usize, (-y).max(0) as usize);
+        let tx1 = (sw as isize - x).min(self.width as isize) as usize;
+        let ty1 = (sh as isize - y).min(self.height as isize) as usize;
+        let (ow, oh) = (tx1 - tx0, ty1 - ty0);
+        let (sx, sy) = ((x + tx0 as isize) as usize, (y + ty0 as isize) as usize);
+        let n = (ow * oh) as f64;
+
+        let (s_sum, s_sq_sum) = integral.get_stats(sx, sy, ow, oh);
+        let (t_sum, t_sq_sum) = self.integral.get_stats(tx0, ty0, ow, oh);
+        let (s_mean, t_mean) = (s_sum / n, t_sum / n);
+        let s_var = s_sq_sum / n - s_mean * s_mean;
+        let t_var = t_sq_sum / n - t_mean * t_mean;
+        if s_var < 1.0 || t_var < 1.0 { return 0.0; }
+
+        let mut cross = 0.0f64;
+        for row in 0..oh {
+            let s_row = &src[(sy + row) * sw + sx..][..ow];
+            let t_row = &self.data[(ty0 + row) * self.width + tx0..][..ow];
+            cross += s_row.iter().zip(t_row).map(|(s, t)| s * t).sum::<f64>();
+        }
+        (cross / n - s_mean * t_mean) / (s_var.sqrt() * t_var.sqrt())
+    }
+}
+
+/// Scores of the window positions that overhang the border, over the grid
+/// `x in -ox..=sw - tw + ox`, `y in -oy..=sh - th + oy` (row-major, with
+/// `(ox, oy)` the overhang). Positions fully inside the source are left at
+/// -inf; they belong to the regular search. Returns `(scores, cols, rows, ox, oy)`.
+fn border_score_map(src: &[f64], sw: usize, sh: usize, tpl: &PartialTemplate) -> (Vec<f64>, usize, usize, usize, usize) {
+    let integral = IntegralImage::new(src, sw, sh);
+    let (ox, oy) = tpl.overhang();
+    let (end_x, end_y) = ((sw - tpl.width) as isize, (sh - tpl.height) as isize);
+    let cols = sw - tpl.width + 1 + 2 * ox;
+    let rows = sh - tpl.height + 1 + 2 * oy;
+    let mut scores = vec![f64::NEG_INFINITY; cols * rows];
+    let deadline = Deadline::current();
+    scores.par_chunks_mut(cols).enumerate().with_min_len(min_items(cols * tpl.width * tpl.height)).for_each(|(yi, row)| {
+        if deadline.passed() { return; }
+        let y = yi as isize - oy as isize;
+        for (xi, out) in row.iter_mut().enumerate() {
+            let x = xi as isize - ox as isize;
+            if x < 0 || y < 0 || x > end_x || y > end_y {
+                *out = tpl.score(src, sw, sh, &integral, x, y);
+            }
+        }
+    });
+    (scores, cols, rows, ox, oy)
+}
+
+/// Local maxima of `border_score_map` reaching `threshold`, in source coordinates
+fn border_candidates(src: &[f64], sw: usize, sh: usize, tpl: &PartialTemplate, threshold: f64) -> Vec<MatchResult> {
+    if tpl.width > sw || tpl.height > sh { return vec![]; }
+    let (scores, cols, rows, ox, oy) = border_score_map(src, sw, sh, tpl);
+    let mut peaks = score_peaks(&scores, cols, rows, threshold);
+    for p in &mut peaks {
+        p.x -= ox as i32;
+        p.y -= oy as i32;
+    }
+    log::debug!("{} border windows above {:.3}", peaks.len(), threshold);
+    peaks
+}
+
+/// Best border-overhanging window, if it reaches `threshold`
+fn border_best(src: &[f64], sw: usize, sh: usize, tpl: &PartialTemplate, threshold: f64) -> Option<MatchResult> {
+    if tpl.width > sw || tpl.height > sh { return None; }
+    let (scores, cols, _, ox, oy) = border_score_map(src, sw, sh, tpl);
+    let (i, &score) = scores.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1))?;
+    let x = (i % cols) as i32 - ox as i32;
+    let y = (i / cols) as i32 - oy as i32;
+    log::debug!("best border window {:.4} at ({}, {})", score, x, y);
+    (score >= threshold).then_some(MatchResult { x, y, confidence: score, crop: None, scale: None, angle: None, pixel_ratio: 1.0, flip: None, inverted: false })
+}
+
+// ============================================================================
+// Wrap-Around Matching (tileable sources)
+// ============================================================================
+
+/// Source extended by `tw - 1` columns and `th - 1` rows copied from the
+/// opposite edges, so each toroidal window position `(x, y)` with `x < sw`
+/// and `y < sh` is a plain window of the padded image, at the same coordinates
+fn wrap_pad(src: &[f64], sw: usize, sh: usize, tw: usize, th: usize) -> (Vec<f64>, usize, usize) {
+    let (pw, ph) = (sw + tw - 1, sh + th - 1);
+    let mut data = Vec::with_capacity(pw * ph);
+    for y in 0..ph {
+        let row = &src[(y % sh) * sw..][..sw];
+        data.extend((0..pw).map(|x| row[x % sw]));
+    }
+    log::trace!("wrap-around search over {}x{} padded source", pw, ph);
+    (data, pw, ph)
+}
+
+/// `w x h` crop at `(x, y)` of the image tiled infinitely, for matches that
+/// cross the seam. `w` and `h` must not exceed the image size.
+fn wrapped_crop(img: &DynamicImage, x: u32, y: u32, w: u32, h: u32) -> DynamicImage {
+    let (iw, ih) = img.dimensions();
+    let mut out = img.crop_imm(0, 0, w, h);
+    let (w1, h1) = (w.min(iw - x), h.min(ih - y));
+    for (px, pw, dx) in [(x, w1, 0), (0, w - w1, w1)] {
+        for (py, ph, dy) in [(y, h1, 0), (0, h - h1, h1)] {
+            if pw > 0 && ph > 0 {
+                image::imageops::replace(&mut out, &img.crop_imm(px, py, pw, ph), dx as i64, dy as i64);
+            }
+        }
+    }
+    out
+}
+
+// ============================================================================
+// Path-Constrained Search (matches along a known polyline)
+// ============================================================================
+
+/// `x` range of the points on row `y` within `tolerance` of segment `a`-`b`.
+/// That set is convex (a capsule), so its row is spanned by where the row
+/// crosses the two end caps and the band swept between them.
+fn capsule_row(a: (f64, f64), b: (f64, f64), tolerance: f64, y: f64) -> Option<(f64, f64)> {
+    let mut range: Option<(f64, f64)> = None;
+    let mut include = |x: f64| range = Some(range.map_or((x, x), |(lo, hi)| (lo.min(x), hi.max(x))));
+    for (px, py) in [a, b] {
+        let dy = y - py;
+        if dy.abs() <= tolerance {
+            let half = (tolerance * tolerance - dy * dy).sqrt();
+            include(px - half);
+            include(px + half);
+        }
+    }
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let len = dx.hypot(dy);
+    if len > 0.0 {
+        let (nx, ny) = (-dy / len * tolerance, dx / len * tolerance);
+        let corners = [(a.0 + nx, a.1 + ny), (b.0 + nx, b.1 + ny), (b.0 - nx, b.1 - ny), (a.0 - nx, a.1 - ny)];
+        for (p, q) in corners.iter().zip(corners.iter().cycle().skip(1)) {
+            if (p.1 - y) * (q.1 - y) > 0.0 { continue; }
+            if p.1 == q.1 {
+                include(p.0);
+                include(q.0);
+            } else {
+                include(p.0 + (y - p.1) / (q.1 - p.1) * (q.0 - p.0));
+            }
+        }
+    }
+    range
+}
+
+/// Window positions `(x, y)` up to `end` whose centre, `(x, y) + centre`,
+/// lies within `tolerance` of the polyline `path`, as row spans `(y, x1, x2)`
+/// sorted by row and then column
+fn corridor_spans(path: &[(f64, f64)], tolerance: f64, centre: (f64, f64), end: (usize, usize)) -> Vec<(usize, usize, usize)> {
+    let segments: Vec<_> = if path.len() == 1 { vec![(path[0], path[0])] } else { path.windows(2).map(|s| (s[0], s[1])).collect() };
+    let mut spans = Vec::new();
+    for y in 0..=end.1 {
+        let mut ranges: Vec<(f64, f64)> = segments.iter()
+            .filter_map(|&(a, b)| capsule_row(a, b, tolerance, y as f64 + centre.1))
+            .map(|(lo, hi)| ((lo - centre.0).ceil().max(0.0), (hi - centre.0).floor().min(end.0 as f64)))
+            .filter(|(lo, hi)| lo <= hi)
+            .collect();
+        ranges.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut row: Vec<(usize, usize, usize)> = Vec::new();
+        for (lo, hi) in ranges {
+            let (x1, x2) = (lo as usize, hi as usize);
+            match row.last_mut() {
+                Some(last) if x1 <= last.2 + 1 => last.2 = last.2.max(x2),
+                _ => row.push((y, x1, x2)),
+            }
+        }
+        spans.extend(row);
+    }
+    spans
+}
+
+/// Score of every window position in `spans`, one row of scores per span
+fn span_scores<S: WindowScore>(src: &[f64], sw: usize, sh: usize, tpl: &S, spans: &[(usize, usize, usize)]) -> Vec<Vec<f64>> {
+    let integral = IntegralImage::new(src, sw, sh);
+    let (tw, th) = tpl.size();
+    let deadline = Deadline::current();
+    let stats = Stats::current();
+    spans.par_iter()
+        .with_min_len(min_items(tw * th))
+        .map(|&(y, x1, x2)| {
+            if deadline.passed() { return vec![-1.0; x2 - x1 + 1]; }
+            stats.count(x2 - x1 + 1, 0);
+            (x1..=x2).map(|x| tpl.score(src, sw, &integral, x, y)).collect()
+        })
+        .collect()
+}
+
+/// Best match among the window positions in `spans`
+fn spans_best<S: WindowScore>(
+    src: &[f64], sw: usize, sh: usize, tpl: &S, spans: &[(usize, usize, usize)], threshold: f64,
+) -> Option<MatchResult> {
+    let (x, y, score) = spans.iter().zip(span_scores(src, sw, sh, tpl, spans))
+        .flat_map(|(&(y, x1, _), row)| row.into_iter().enumerate().map(move |(i, s)| (x1 + i, y, s)))
+        .fold((0, 0, -1.0f64), |a, b| if b.2 > a.2 { b } else { a });
+    let best = MatchResult { x: x as i32, y: y as i32, confidence: score, crop: None, scale: None, angle: None, pixel_ratio: 1.0, flip: None, inverted: false };
+    log_final_score(&best, threshold);
+    (score >= threshold).then_some(best)
+}
+
+/// Local maxima among the window positions in `spans` that reach `threshold`,
+/// compared only with neighbours inside the spans
+fn spans_candidates<S: WindowScore>(
+    src: &[f64], sw: usize, sh: usize, tpl: &S, spans: &[(usize, usize, usize)], threshold: f64,
+) -> Vec<MatchResult> {
+    let scores = span_scores(src, sw, sh, tpl, spans);
+    let at = |x: usize, y: usize| {
+        let first = spans.partition_point(|s| s.0 < y);
+        spans[first..].iter().zip(&scores[first..])
+            .take_while(|(s, _)| s.0 == y)
+            .find(|(s, _)| (s.1..=s.2).contains(&x))
+            .map_or(f64::NEG_INFINITY, |(s, row)| row[x - s.1])
+    };
+    let mut peaks = Vec::new();
+    for (&(y, x1, _), row) in spans.iter().zip(&scores) {
+        for (i, &s) in row.iter().enumerate() {
+            let x = x1 + i;
+            if s < threshold { continue; }
+            let is_peak = (y.saturating_sub(1)..=y + 1)
+                .all(|ny| (x.saturating_sub(1)..=x + 1).all(|nx| at(nx, ny) <= s));
+            if is_peak {
+                peaks.push(MatchResult { x: x as i32, y: y as i32, confidence: s, crop: None, scale: None, angle: None, pixel_ratio: 1.0, flip: None, inverted: false });
+            }
+        }
+    }
+    log::debug!("{} local maxima above {:.3} along the path", peaks.len(), threshold);
+    peaks
+}
+
+// ============================================================================
+// Scale Estimation (templates captured at a different DPI)
+// ============================================================================
+
+const DEFAULT_SCALE_CANDIDATES: [f64; 8] = [0.5, 0.75, 1.0, 1.25, 1.5, 1.75, 2.0, 2.5];
+
+/// Bilinear resample to an arbitrary size (used for enlarging)
+fn resize_bilinear(src: &[f64], sw: usize, sh: usize, nw: usize, nh: usize) -> Vec<f64> {
+    let at = |x: usize, y: usize| src[y.min(sh - 1) * sw + x.min(sw - 1)];
+    let mut result = Vec::with_capacity(nw * nh);
+    for y in 0..nh {
+        let fy = ((y as f64 + 0.5) * sh as f64 / nh as f64 - 0.5).max(0.0);
+        let (y0, ty) = (fy.floor() as usize, fy.fract());
+        for x in 0..nw {
+            let fx = ((x as f64 + 0.5) * sw as f64 / nw as f64 - 0.5).max(0.0);
+            let (x0, tx) = (fx.floor() as usize, fx.fract());
+            let top = at(x0, y0) * (1.0 - tx) + at(x0 + 1, y0) * tx;
+            let bottom = at(x0, y0 + 1) * (1.0 - tx) + at(x0 + 1, y0 + 1) * tx;
+            result.push(top * (1.0 - ty) + bottom * ty);
+        }
+    }
+    result
+}
+
+/// Resize by `factor`: box average when shrinking, bilinear when enlarging.
+/// None if the result would be smaller than 4x4.
+fn scale_image(img: &GrayImageData, factor: f64) -> Option<GrayImageData> {
+    let nw = (img.width as f64 * factor).round() as usize;
+    let nh = (img.height as f64 * factor).round() as usize;
+    if nw < 4 || nh < 4 { return None; }
+    let data = if factor < 1.0 {
+        downsample_to(&img.data, img.width, img.height, nw, nh)
+    } else {
+        resize_bilinear(&img.data, img.width, img.height, nw, nh)
+    };
+    Some(GrayImageData { data, width: nw, height: nh })
+}
+
+/// Pick the template scale factor that correlates best with the source.
+/// Every candidate is compared at one shared coarse level (that of the
+/// smallest scaled template), so the source is downsampled only once.
+/// Returns `(factor, coarse score)`.
+fn estimate_template_scale(
+    src: &[f64], sw: usize, sh: usize, tpl: &GrayImageData, candidates: &[f64],
+) -> Option<(f64, f64)> {
+    let scaled: Vec<(f64, GrayImageData)> = candidates.iter()
+        .filter_map(|&f| scale_image(tpl, f).map(|t| (f, t)))
+        .filter(|(_, t)| t.width <= sw && t.height <= sh)
+        .collect();
+    let level = scaled.iter().map(|(_, t)| pyramid_scale(t.width, t.height)).min()?;
+    let (small_src, ssw, ssh) = downsample(src, sw, sh, level);
+
+    scaled.iter()
+        .filter_map(|(f, t)| {
+            let (small, stw, sth) = downsample(&t.data, t.width, t.height, level);
+            search_best(&small_src, ssw, ssh, &Template::new(&small, stw, sth), -1.0).map(|r| (*f, r.confidence))
+        })
+        .fold(None, |best: Option<(f64, f64)>, c| match best {
+            Some(b) if b.1 >= c.1 => Some(b),
+            _ => Some(c),
+        })
+}
+
+// ============================================================================
+// Template Rotation
+// ============================================================================
+
+/// Template rotated by `angle` degrees about its centre (clockwise on screen,
+/// as the y axis points down), on the bounding box of the rotated rectangle.
+/// Also returns a 0/1 mask of the pixels that fall inside the template, so
+/// the corners can be left out of the score.
+fn rotate_image(img: &GrayImageData, angle: f64) -> (GrayImageData, Vec<f64>) {
+    let (w, h) = (img.width as f64, img.height as f64);
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let nw = (w * cos.abs() + h * sin.abs()).round().max(1.0) as usize;
+    let nh = (w * sin.abs() + h * cos.abs()).round().max(1.0) as usize;
+    let at = |x: usize, y: usize| img.data[y.min(img.height - 1) * img.width + x.min(img.width - 1)];
+    let mut data = Vec::with_capacity(nw * nh);
+    let mut mask = Vec::with_capacity(nw * nh);
+    for y in 0..nh {
+        let dy = y as f64 + 0.5 - nh as f64 / 2.0;
+        for x in 0..nw {
+            let dx = x as f64 + 0.5 - nw as f64 / 2.0;
+            // Inverse rotation back into template coordinates (pixel centres at +0.5)
+            let sx = cos * dx + sin * dy + w / 2.0 - 0.5;
+            let sy = -sin * dx + cos * dy + h / 2.0 - 0.5;
+            if sx < -0.5 || sy < -0.5 || sx > w - 0.5 || sy > h - 0.5 {
+                data.push(0.0);
+                mask.push(0.0);
+                continue;
+            }
+            let (fx, fy) = (sx.max(0.0), sy.max(0.0));
+            let (x0, tx, y0, ty) = (fx.floor() as usize, fx.fract(), fy.floor() as usize, fy.fract());
+            let top = at(x0, y0) * (1.0 - tx) + at(x0 + 1, y0) * tx;
+            let bottom = at(x0, y0 + 1) * (1.0 - tx) + at(x0 + 1, y0 + 1) * tx;
+            data.push(top * (1.0 - ty) + bottom * ty);
+            mask.push(1.0);
+        }
+    }
+    (GrayImageData { data, width: nw, height: nh }, mask)
+}
+
+// ============================================================================
+// Rotation-Invariant Prescreen (ring projection)
+// ============================================================================
+
+/// Default spacing of the angles tried by `find_rotated`, in degrees
+const DEFAULT_ROTATION_STEP: f64 = 10.0;
+
+/// Ring radius the coarse level must keep for the prescreen to discriminate;
+/// smaller templates are prescreened at full resolution
+const MIN_RING_RADIUS: usize = 8;
+
+/// Smallest template side `find_rotated` accepts
+const MIN_ROTATED_TEMPLATE: usize = 9;
+
+/// Disk offsets grouped by rounded distance from the centre. Ring statistics
+/// (the ring projection) don't change when the disk is rotated, so one
+/// signature comparison stands in for every angle.
+struct RingProjection {
+    rings: Vec<Vec<(isize, isize)>>,
+    radius: usize,
+}
+
+impl RingProjection {
+    fn new(radius: usize) -> Self {
+        let r = radius as isize;
+        let mut rings = vec![Vec::new(); radius + 1];
+        for dy in -r..=r {
+            for dx in -r..=r {
+                let d = ((dx * dx + dy * dy) as f64).sqrt().round() as usize;
+                if d <= radius { rings[d].push((dx, dy)); }
+            }
+        }
+        Self { rings, radius }
+    }
+
+    /// Mean and standard deviation of every ring, with `at` giving the value
+    /// at an offset from the centre. The spread tells apart windows whose
+    /// rings average out to the same grey.
+    fn signature(&self, at: impl Fn(isize, isize) -> f64) -> (Vec<f64>, Vec<f64>) {
+        self.rings.iter()
+            .map(|ring| {
+                let (sum, sq_sum) = ring.iter().fold((0.0, 0.0), |(s, sq), &(dx, dy)| {
+                    let v = at(dx, dy);
+                    (s + v, sq + v * v)
+                });
+                let n = ring.len() as f64;
+                let mean = sum / n;
+                (mean, (sq_sum / n - mean * mean).max(0.0).sqrt())
+            })
+            .unzip()
+    }
+
+    /// Signature of a window centred on pixel `(cx, cy)`, which must be
+    /// `radius` away from every edge
+    fn window_signature(&self, data: &[f64], w: usize, cx: usize, cy: usize) -> (Vec<f64>, Vec<f64>) {
+        self.signature(|dx, dy| data[cy.wrapping_add_signed(dy) * w + cx.wrapping_add_signed(dx)])
+    }
+
+    /// Mean of the ring-size weighted correlations of the means and of the spreads
+    fn similarity(&self, a: &(Vec<f64>, Vec<f64>), b: &(Vec<f64>, Vec<f64>)) -> f64 {
+        let weighted = |x: &[f64], y: &[f64]| {
+            let weights: Vec<f64> = self.rings.iter().map(|r| r.len() as f64).collect();
+            let total: f64 = weights.iter().sum();
+            let mx = x.iter().zip(&weights).map(|(v, w)| v * w).sum::<f64>() / total;
+            let my = y.iter().zip(&weights).map(|(v, w)| v * w).sum::<f64>() / total;
+            let (mut cross, mut vx, mut vy) = (0.0f64, 0.0f64, 0.0f64);
+            for ((&xv, &yv), &w) in x.iter().zip(y).zip(&weights) {
+                cross += w * (xv - mx) * (yv - my);
+                vx += w * (xv - mx) * (xv - mx);
+                vy += w * (yv - my) * (yv - my);
+            }
+            if vx < 1e-10 || vy < 1e-10 { 0.0 } else { cross / (vx * vy).sqrt() }
+        };
+        (weighted(&a.0, &b.0) + weighted(&a.1, &b.1)) / 2.0
+    }
+}
+
+/// Bilinear sample at continuous pixel coordinates, clamped to the image
+fn bilinear_at(data: &[f64], w: usize, h: usize, x: f64, y: f64) -> f64 {
+    let (x, y) = (x.clamp(0.0, (w - 1) as f64), y.clamp(0.0, (h - 1) as f64));
+    let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+    let (x1, y1) = ((x0 + 1).min(w - 1), (y0 + 1).min(h - 1));
+    let (tx, ty) = (x - x0 as f64, y - y0 as f64);
+    let top = data[y0 * w + x0] * (1.0 - tx) + data[y0 * w + x1] * tx;
+    let bottom = data[y1 * w + x0] * (1.0 - tx) + data[y1 * w + x1] * tx;
+    top * (1.0 - ty) + bottom * ty
+}
+
+/// Centres of the `count` windows whose ring projection best matches the
+/// template's, found on the coarse pyramid level and mapped back to full
+/// resolution. Also returns the pyramid factor, which bounds the position error.
+fn ring_candidates(src: &[f64], sw: usize, sh: usize, tpl: &GrayImageData, count: usize) -> (Vec<(usize, usize)>, usize) {
+    let mut scale = pyramid_scale(tpl.width, tpl.height);
+    while scale > 1 && (tpl.width.min(tpl.height) / scale).saturating_sub(1) / 2 < MIN_RING_RADIUS { scale /= 2; }
+    let (small_tpl, stw, sth) = downsample_filtered(&tpl.data, tpl.width, tpl.height, scale, DownsampleFilter::Area);
+    let (small_src, ssw, ssh) = downsample_filtered(src, sw, sh, scale, DownsampleFilter::Area);
+    let rings = RingProjection::new((stw.min(sth).saturating_sub(1) / 2).max(1));
+    let r = rings.radius;
+    if ssw <= 2 * r || ssh <= 2 * r { return (vec![], scale); }
+    // Sampled at the exact centre, which falls between pixels for even sizes
+    let (tcx, tcy) = ((stw as f64 - 1.0) / 2.0, (sth as f64 - 1.0) / 2.0);
+    let wanted = rings.signature(|dx, dy| bilinear_at(&small_tpl, stw, sth, tcx + dx as f64, tcy + dy as f64));
+    let (cols, rows) = (ssw - 2 * r, ssh - 2 * r);
+    let scores: Vec<f64> = (0..rows)
+        .into_par_iter()
+        .flat_map_iter(|j| {
+            let (rings, wanted, small_src) = (&rings, &wanted, &small_src);
+            (0..cols).map(move |i| rings.similarity(&rings.window_signature(small_src, ssw, i + r, j + r), wanted))
+        })
+        .collect();
+    let mut peaks = score_peaks(&scores, cols, rows, -1.0);
+    peaks.sort_by(|a, b| b.confidence.partial_cmp(&a.confidence).unwrap());
+    let centres = peaks.into_iter()
+        .take(count)
+        .map(|p| ((p.x as usize + r) * scale + scale / 2, (p.y as usize + r) * scale + scale / 2))
+        .collect();
+    (centres, scale)
+}
+
+/// Best rotated match: the ring projection prescreen proposes `candidates`
+/// centres, and only there is every rotated template scored by masked NCC,
+/// within the prescreen's position error
+fn find_rotated_impl(
+    src: &[f64], sw: usize, sh: usize, tpl: &GrayImageData, angles: &[f64], candidates: usize, threshold: f64,
+) -> Option<MatchResult> {
+    let (centres, scale) = ring_candidates(src, sw, sh, tpl, candidates);
+    let variants: Vec<(f64, WeightedTemplate)> = angles.par_iter()
+        .map(|&angle| {
+            let (rotated, mask) = rotate_image(tpl, angle);
+            (angle, WeightedTemplate::new(&rotated.data, &mask, rotated.width, rotated.height))
+        })
+        .collect();
+    let integral = IntegralImage::new(src, sw, sh);
+    let margin = scale + 1;
+    let pairs: Vec<_> = centres.iter().flat_map(|&c| variants.iter().map(move |v| (c, v))).collect();
+    let best = pairs.into_par_iter()
+        .filter_map(|((cx, cy), (angle, variant))| {
+            let (vw, vh) = variant.size();
+            if vw > sw || vh > sh { return None; }
+            let (x0, y0) = (cx.saturating_sub(vw / 2), cy.saturating_sub(vh / 2));
+            let mut best = (0, 0, -1.0f64);
+            for y in y0.saturating_sub(margin)..=(y0 + margin).min(sh - vh) {
+                for x in x0.saturating_sub(margin)..=(x0 + margin).min(sw - vw) {
+                    let score = variant.score(src, sw, &integral, x, y);
+                    if score > best.2 { best = (x, y, score); }
+                }
+            }
+            (best.2 >= 0.0).then_some((best, *angle))
+        })
+        .max_by(|a, b| a.0.2.partial_cmp(&b.0.2).unwrap())?;
+    let ((x, y, score), angle) = best;
+    log::debug!("find_rotated best score {:.4} at ({}, {}), angle {} ({} candidates)", score, x, y, angle, centres.len());
+    (score >= threshold).then_some(MatchResult {
+        x: x as i32, y: y as i32, confidence: score, crop: None, scale: None, angle: Some(angle), pixel_ratio: 1.0, flip: None, inverted: false,
+    })
+}
+
+// ============================================================================
+// Fourier-Mellin Registration (global rotation and scale)
+// ============================================================================
+
+/// Largest side of the square canvas registration runs on; bigger images are shrunk to fit
+const REGISTER_MAX_SIZE: usize = 256;
+
+/// Smallest image side `register_rotscale` accepts
+const MIN_REGISTER_SIZE: usize = 16;
+
+/// In-place radix-2 FFT; the length must be a power of two. The inverse is
+/// scaled by 1/n, so a round trip returns the input.
+fn fft(re: &mut [f64], im: &mut [f64], inverse: bool) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        let (ws, wc) = (sign * 2.0 * std::f64::consts::PI / len as f64).sin_cos();
+        for start in (0..n).step_by(len) {
+            let (mut cr, mut ci) = (1.0, 0.0);
+            for k in start..start + len / 2 {
+                let m = k + len / 2;
+                let (tr, ti) = (re[m] * cr - im[m] * ci, re[m] * ci + im[m] * cr);
+                re[m] = re[k] - tr;
+                im[m] = im[k] - ti;
+                re[k] += tr;
+                im[k] += ti;
+                (cr, ci) = (cr * wc - ci * ws, cr * ws + ci * wc);
+            }
+        }
+        len <<= 1;
+    }
+    if inverse {
+        let s = 1.0 / n as f64;
+        re.iter_mut().chain(im.iter_mut()).for_each(|v| *v *= s);
+    }
+}
+
+fn transpose(v: &mut [f64], n: usize) {
+    for y in 0..n {
+        for x in y + 1..n {
+            v.swap(y * n + x, x * n + y);
+        }
+    }
+}
+
+/// 2-D FFT of an n x n complex image: rows, then (transposed) columns
+fn fft2(re: &mut [f64], im: &mut [f64], n: usize, inverse: bool) {
+    for _ in 0..2 {
+        re.par_chunks_mut(n).zip(im.par_chunks_mut(n)).for_each(|(r, i)| fft(r, i, inverse));
+        transpose(re, n);
+        transpose(im, n);
+    }
+}
+
+/// Shift `(dx, dy)` of `b` relative to `a` (b(p) ~ a(p - shift)), to sub-pixel
+/// precision, and the height of the correlation peak (1.0 for a pure shift).
+/// Both are n x n and treated as periodic.
+fn phase_correlation(a: &[f64], b: &[f64], n: usize) -> (f64, f64, f64) {
+    let (mut ar, mut ai) = (a.to_vec(), vec![0.0; n * n]);
+    let (mut br, mut bi) = (b.to_vec(), vec![0.0; n * n]);
+    fft2(&mut ar, &mut ai, n, false);
+    fft2(&mut br, &mut bi, n, false);
+    // Normalized cross-power spectrum B * conj(A): only the phase difference is kept
+    for i in 0..n * n {
+        let (r, im) = (br[i] * ar[i] + bi[i] * ai[i], bi[i] * ar[i] - br[i] * ai[i]);
+        let mag = r.hypot(im).max(1e-12);
+        ar[i] = r / mag;
+        ai[i] = im / mag;
+    }
+    fft2(&mut ar, &mut ai, n, true);
+    let (peak_at, &peak) = ar.iter().enumerate().max_by(|a, b| a.1.partial_cmp(b.1).unwrap()).unwrap();
+    let (px, py) = (peak_at % n, peak_at / n);
+    let at = |x: usize, y: usize| ar[(y % n) * n + x % n];
+    // Parabola through the peak and its neighbours on each axis
+    let offset = |l: f64, r: f64| {
+        let d = l - 2.0 * peak + r;
+        if d < -1e-12 { (0.5 * (l - r) / d).clamp(-0.5, 0.5) } else { 0.0 }
+    };
+    let ox = offset(at(px + n - 1, py), at(px + 1, py));
+    let oy = offset(at(px, py + n - 1), at(px, py + 1));
+    let unwrap = |p: usize, o: f64| {
+        let v = p as f64 + o;
+        if v >= n as f64 / 2.0 { v - n as f64 } else { v }
+    };
+    (unwrap(px, ox), unwrap(py, oy), peak)
+}
+
+/// An image shrunk by a shared factor and centred on the registration canvas
+struct RegisterCanvas {
+    /// Mean-removed pixels, zero outside the image
+    plain: Vec<f64>,
+    /// `plain` under a Hann window, so the image border adds no spectrum edges
+    windowed: Vec<f64>,
+    /// Image extent on the canvas: left, top, width, height
+    extent: (usize, usize, usize, usize),
+}
+
+impl RegisterCanvas {
+    fn new(img: &GrayImageData, factor: f64, n: usize) -> Self {
+        let shrunk = if factor < 1.0 { scale_image(img, factor) } else { None };
+        let scaled = shrunk.as_ref().unwrap_or(img);
+        let (w, h) = (scaled.width.min(n), scaled.height.min(n));
+        let (x0, y0) = ((n - w) / 2, (n - h) / 2);
+        let mean = scaled.data.iter().sum::<f64>() / scaled.data.len() as f64;
+        let hann = |i: usize, len: usize| 0.5 - 0.5 * (2.0 * std::f64::consts::PI * (i as f64 + 0.5) / len as f64).cos();
+        let mut plain = vec![0.0; n * n];
+        let mut windowed = vec![0.0; n * n];
+        for y in 0..h {
+            for x in 0..w {
+                let v = scaled.data[y * scaled.width + x] - mean;
+                plain[(y0 + y) * n + x0 + x] = v;
+                windowed[(y0 + y) * n + x0 + x] = v * hann(x, w) * hann(y, h);
+            }
+        }
+        Self { plain, windowed, extent: (x0, y0, w, h) }
+    }
+
+    /// Centre of the image on the canvas
+    fn centre(&self) -> (f64, f64) {
+        let (x0, y0, w, h) = self.extent;
+        (x0 as f64 + w as f64 / 2.0, y0 as f64 + h as f64 / 2.0)
+    }
+
+    fn contains(&self, x: f64, y: f64) -> bool {
+        let (x0, y0, w, h) = self.extent;
+        x >= x0 as f64 - 0.5 && y >= y0 as f64 - 0.5 && x <= (x0 + w) as f64 - 0.5 && y <= (y0 + h) as f64 - 0.5
+    }
+}
+
+/// Bilinear sample of an n x n canvas, zero outside
+fn canvas_at(v: &[f64], n: usize, x: f64, y: f64) -> f64 {
+    if x < 0.0 || y < 0.0 || x > (n - 1) as f64 || y > (n - 1) as f64 { return 0.0; }
+    let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+    let (x1, y1) = ((x0 + 1).min(n - 1), (y0 + 1).min(n - 1));
+    let (tx, ty) = (x - x0 as f64, y - y0 as f64);
+    let top = v[y0 * n + x0] * (1.0 - tx) + v[y0 * n + x1] * tx;
+    let bottom = v[y1 * n + x0] * (1.0 - tx) + v[y1 * n + x1] * tx;
+    top * (1.0 - ty) + bottom * ty
+}
+
+/// Log-polar resampling of the high-passed log magnitude spectrum: row k is
+/// the angle k*pi/n (the spectrum of a real image is symmetric, so half a
+/// turn covers it), column j the radius exp(j * log_base). Rotating the image
+/// shifts this along the rows; scaling it shifts it along the columns.
+fn log_polar_spectrum(canvas: &[f64], n: usize, log_base: f64) -> Vec<f64> {
+    let (mut re, mut im) = (canvas.to_vec(), vec![0.0; n * n]);
+    fft2(&mut re, &mut im, n, false);
+    let freq = |i: usize| if i >= n / 2 { i as f64 - n as f64 } else { i as f64 } / n as f64;
+    // Reddy-Chatterji high-pass: damps the low frequencies that dominate
+    // natural images but say little about orientation
+    let mag: Vec<f64> = (0..n * n)
+        .map(|i| {
+            let c = (std::f64::consts::PI * freq(i % n)).cos() * (std::f64::consts::PI * freq(i / n)).cos();
+            re[i].hypot(im[i]).ln_1p() * (1.0 - c) * (2.0 - c)
+        })
+        .collect();
+    let at = |fx: f64, fy: f64| {
+        // Wrap frequency coordinates into the unshifted spectrum
+        let (x0, y0) = (fx.floor(), fy.floor());
+        let (tx, ty) = (fx - x0, fy - y0);
+        let idx = |x: f64, y: f64| (y.rem_euclid(n as f64) as usize) * n + x.rem_euclid(n as f64) as usize;
+        let top = mag[idx(x0, y0)] * (1.0 - tx) + mag[idx(x0 + 1.0, y0)] * tx;
+        let bottom = mag[idx(x0, y0 + 1.0)] * (1.0 - tx) + mag[idx(x0 + 1.0, y0 + 1.0)] * tx;
+        top * (1.0 - ty) + bottom * ty
+    };
+    (0..n * n)
+        .map(|i| {
+            let (theta, r) = (std::f64::consts::PI * (i / n) as f64 / n as f64, ((i % n) as f64 * log_base).exp());
+            at(r * theta.cos(), r * theta.sin())
+        })
+        .collect()
+}
+
+
+/// Estimate the rotation, scale and shift mapping `a` onto `b` by
+/// Fourier-Mellin registration: the log-polar magnitude spectra are phase
+/// correlated for rotation and scale, then `b` is warped back and phase
+/// correlated with `a` for the shift. The spectrum can't tell `angle` from
+/// `angle + 180`, so both are tried and the better shift peak wins.
+fn register_rotscale_impl(a: &GrayImageData, b: &GrayImageData) -> RotScale {
+    let side = a.width.max(a.height).max(b.width).max(b.height);
+    let n = side.next_power_of_two().min(REGISTER_MAX_SIZE);
+    let factor = (n as f64 / side as f64).min(1.0);
+    let (ca, cb) = (RegisterCanvas::new(a, factor, n), RegisterCanvas::new(b, factor, n));
+    let log_base = (n as f64 / 2.0).ln() / n as f64;
+
+    let (lpa, lpb) = rayon::join(|| log_polar_spectrum(&ca.windowed, n, log_base), || log_polar_spectrum(&cb.windowed, n, log_base));
+    let (shift_r, shift_t, peak) = phase_correlation(&lpa, &lpb, n);
+    let scale = (-shift_r * log_base).exp();
+    let base_angle = shift_t * 180.0 / n as f64;
+    log::debug!("log-polar peak {:.4}: scale {:.4}, angle {:.2} (mod 180)", peak, scale, base_angle);
+
+    // Canvas centre: the pivot both warps use
+    let c = n as f64 / 2.0;
+    let best = [base_angle, base_angle + 180.0]
+        .into_par_iter()
+        .map(|angle| {
+            let (sin, cos) = angle.to_radians().sin_cos();
+            // b sampled at c + s*R*(p - c) is a shifted by R^-1 * t / s
+            let warped: Vec<f64> = (0..n * n)
+                .map(|i| {
+                    let (px, py) = ((i % n) as f64 - c, (i / n) as f64 - c);
+                    canvas_at(&cb.windowed, n, c + scale * (cos * px - sin * py), c + scale * (sin * px + cos * py))
+                })
+                .collect();
+            let (ux, uy, peak) = phase_correlation(&ca.windowed, &warped, n);
+            // Shift of b relative to a, back in b's frame: t = s*R*u
+            let (tx, ty) = (scale * (cos * ux - sin * uy), scale * (sin * ux + cos * uy));
+            (angle, tx, ty, peak)
+        })
+        .max_by(|a, b| a.3.partial_cmp(&b.3).unwrap())
+        .unwrap();
+    let (angle, tx, ty, shift_peak) = best;
+    log::debug!("shift peak {:.4} at angle {:.2}", shift_peak, angle);
+
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let map = |x: f64, y: f64| (c + scale * (cos * (x - c) - sin * (y - c)) + tx, c + scale * (sin * (x - c) + cos * (y - c)) + ty);
+
+    // Overlap NCC: every a pixel whose mapped position falls inside b
+    let (x0, y0, w, h) = ca.extent;
+    let (mut n_px, mut sa, mut sb, mut saa, mut sbb, mut sab) = (0.0f64, 0.0, 0.0, 0.0, 0.0, 0.0);
+    for y in y0..y0 + h {
+        for x in x0..x0 + w {
+            let (qx, qy) = map(x as f64, y as f64);
+            if !cb.contains(qx, qy) { continue; }
+            let (va, vb) = (ca.plain[y * n + x], canvas_at(&cb.plain, n, qx, qy));
+            n_px += 1.0;
+            sa += va;
+            sb += vb;
+            saa += va * va;
+            sbb += vb * vb;
+            sab += va * vb;
+        }
+    }
+    let var_a = saa - sa * sa / n_px.max(1.0);
+    let var_b = sbb - sb * sb / n_px.max(1.0);
+    // Less than a quarter of `a` overlapping is treated as no registration
+    let confidence = if n_px < (w * h) as f64 / 4.0 || var_a <= 1e-9 || var_b <= 1e-9 {
+        0.0
+    } else {
+        (sab - sa * sb / n_px) / (var_a * var_b).sqrt()
+    };
+
+    let (ax, ay) = ca.centre();
+    let (mx, my) = map(ax, ay);
+    let (bx, by) = cb.centre();
+    RotScale {
+        angle: (angle + 180.0).rem_euclid(360.0) - 180.0,
+        scale,
+        dx: (mx - bx) / factor,
+        dy: (my - by) / factor,
+        confidence,
+        template_match: None,
+    }
+}
+
+// ============================================================================
+// ECC Alignment Refinement (sub-pixel translation, euclidean or affine warps)
+// ============================================================================
+
+/// Warp models refined by `refine_alignment`, as in OpenCV's findTransformECC
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum MotionModel {
+    /// Shift only (2 parameters)
+    Translation,
+    /// Rotation and shift (3 parameters); the initial scale is kept
+    Euclidean,
+    /// Full 2x3 affine warp (6 parameters)
+    Affine,
+}
+
+impl MotionModel {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "translation" => Some(Self::Translation),
+            "euclidean" => Some(Self::Euclidean),
+            "affine" => Some(Self::Affine),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Translation => "translation",
+            Self::Euclidean => "euclidean",
+            Self::Affine => "affine",
+        }
+    }
+
+    fn params(self) -> usize {
+        match self {
+            Self::Translation => 2,
+            Self::Euclidean => 3,
+            Self::Affine => 6,
+        }
+    }
+}
+
+/// 2x3 warp taking template pixel `(u, v)` to source position `warp * (u, v, 1)`
+type Warp = [[f64; 3]; 2];
+
+fn apply_warp(warp: &Warp, u: f64, v: f64) -> (f64, f64) {
+    (warp[0][0] * u + warp[0][1] * v + warp[0][2], warp[1][0] * u + warp[1][1] * v + warp[1][2])
+}
+
+/// Solve `a x = b` by Gaussian elimination with partial pivoting; None if singular
+fn solve_linear(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    let tiny = a.iter().flatten().fold(0.0f64, |m, v| m.max(v.abs())) * 1e-12;
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().partial_cmp(&a[j][col].abs()).unwrap())?;
+        if a[pivot][col].abs() <= tiny { return None; }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        let (done, rest) = a.split_at_mut(col + 1);
+        let (pivot_row, pivot_rhs) = (&done[col], b[col]);
+        for (row, rhs) in rest.iter_mut().zip(b[col + 1..].iter_mut()) {
+            let f = row[col] / pivot_row[col];
+            row.iter_mut().zip(pivot_row).skip(col).for_each(|(r, p)| *r -= f * p);
+            *rhs -= f * pivot_rhs;
+        }
+    }
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let s: f64 = (row + 1..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - s) / a[row][row];
+    }
+    Some(x)
+}
+
+/// Result of `ecc_refine`: the best warp seen and its correlation
+struct EccAlignment {
+    warp: Warp,
+    correlation: f64,
+    /// Warp updates applied
+    iterations: usize,
+    /// Whether the correlation settled within epsilon before the iteration limit
+    converged: bool,
+}
+
+/// Enhanced correlation coefficient alignment (Evangelidis & Psarakis, 2008),
+/// starting from `warp`: each step linearizes the warped source and moves to
+/// the parameters that maximize its NCC with the template. Template pixels
+/// warped outside the source are left out.
+#[allow(clippy::too_many_arguments)]
+fn ecc_refine(
+    src: &[f64], sw: usize, sh: usize, tpl: &GrayImageData, motion: MotionModel, mut warp: Warp,
+    max_iterations: usize, epsilon: f64,
+) -> EccAlignment {
+    let (tw, th) = (tpl.width, tpl.height);
+    let k = motion.params();
+    // Euclidean steps move the angle; the initial scale stays fixed
+    let scale = (warp[0][0] * warp[1][1] - warp[0][1] * warp[1][0]).abs().sqrt();
+    let mut theta = warp[1][0].atan2(warp[0][0]);
+    let mut best = EccAlignment { warp, correlation: -1.0, iterations: 0, converged: false };
+    let mut last = f64::NEG_INFINITY;
+
+    for iteration in 0..=max_iterations {
+        // Template value, warped source, its gradient and the template coordinates
+        let samples: Vec<[f64; 6]> = (0..tw * th)
+            .filter_map(|i| {
+                let (u, v) = ((i % tw) as f64, (i / tw) as f64);
+                let (x, y) = apply_warp(&warp, u, v);
+                if x < 0.0 || y < 0.0 || x > (sw - 1) as f64 || y > (sh - 1) as f64 { return None; }
+                let at = |x: f64, y: f64| bilinear_at(src, sw, sh, x, y);
+                let (gx, gy) = ((at(x + 1.0, y) - at(x - 1.0, y)) / 2.0, (at(x, y + 1.0) - at(x, y - 1.0)) / 2.0);
+                Some([tpl.data[i], at(x, y), gx, gy, u, v])
+            })
+            .collect();
+        if samples.len() < (tw * th / 2).max(k + 1) {
+            log::debug!("ECC warp left the source after {} iterations", iteration);
+            break;
+        }
+        let n = samples.len() as f64;
+        let t_mean = samples.iter().map(|s| s[0]).sum::<f64>() / n;
+        let i_mean = samples.iter().map(|s| s[1]).sum::<f64>() / n;
+        let (sin, cos) = theta.sin_cos();
+        let jacobian = |s: &[f64; 6]| -> [f64; 6] {
+            let [_, _, gx, gy, u, v] = *s;
+            match motion {
+                MotionModel::Translation => [gx, gy, 0.0, 0.0, 0.0, 0.0],
+                MotionModel::Euclidean => [scale * (gx * (-sin * u - cos * v) + gy * (cos * u - sin * v)), gx, gy, 0.0, 0.0, 0.0],
+                MotionModel::Affine => [gx * u, gx * v, gx, gy * u, gy * v, gy],
+            }
+        };
+        let mut hessian = vec![vec![0.0; k]; k];
+        let (mut jt, mut ji) = (vec![0.0; k], vec![0.0; k]);
+        let (mut tt, mut ii, mut ti) = (0.0, 0.0, 0.0);
+        for s in &samples {
+            let (t, i) = (s[0] - t_mean, s[1] - i_mean);
+            let j = jacobian(s);
+            for a in 0..k {
+                jt[a] += j[a] * t;
+                ji[a] += j[a] * i;
+                for b in 0..k { hessian[a][b] += j[a] * j[b]; }
+            }
+            tt += t * t;
+            ii += i * i;
+            ti += t * i;
+        }
+        if tt < 1e-9 || ii < 1e-9 { break; }
+        let rho = ti / (tt * ii).sqrt();
+        if rho > best.correlation {
+            best = EccAlignment { warp, correlation: rho, iterations: iteration, converged: false };
+        }
+        if (rho - last).abs() < epsilon {
+            best.converged = true;
+            break;
+        }
+        if iteration == max_iterations { break; }
+        last = rho;
+
+        let Some(hi) = solve_linear(hessian.clone(), ji.clone()) else { break };
+        let dot = |x: &[f64], y: &[f64]| x.iter().zip(y).map(|(a, b)| a * b).sum::<f64>();
+        // Scale of the template that the warped source is closest to after the step
+        let (lambda_n, lambda_d) = (ii - dot(&ji, &hi), ti - dot(&jt, &hi));
+        if lambda_d <= 0.0 { break; }
+        let lambda = lambda_n / lambda_d;
+        let error: Vec<f64> = jt.iter().zip(&ji).map(|(t, i)| lambda * t - i).collect();
+        let Some(delta) = solve_linear(hessian, error) else { break };
+        match motion {
+            MotionModel::Translation => {
+                warp[0][2] += delta[0];
+                warp[1][2] += delta[1];
+            }
+            MotionModel::Euclidean => {
+                theta += delta[0];
+                let (sin, cos) = theta.sin_cos();
+                warp = [[scale * cos, -scale * sin, warp[0][2] + delta[1]], [scale * sin, scale * cos, warp[1][2] + delta[2]]];
+            }
+            MotionModel::Affine => {
+                for (p, d) in warp.iter_mut().flatten().zip(&delta) { *p += d; }
+            }
+        }
+    }
+    log::debug!(
+        "ECC {}: correlation {:.5} after {} iterations{}",
+        motion.name(), best.correlation, best.iterations, if best.converged { "" } else { " (not converged)" }
+    );
+    best
+}
+
+// ============================================================================
+// Homography Estimation (keypoints, RANSAC and NCC verification)
+// ============================================================================
+
+/// Half-size of the square patch compared between keypoints
+const KEYPOINT_PATCH_RADIUS: usize = 7;
+/// Harris detector sensitivity
+const HARRIS_K: f64 = 0.04;
+/// Template scales keypoints are detected at, so the template may appear
+/// zoomed in the photo by about 0.4x to 2.5x
+const KEYPOINT_TEMPLATE_SCALES: [f64; 5] = [0.5, 0.71, 1.0, 1.41, 2.0];
+/// Patch NCC a tentative match must reach
+const KEYPOINT_MIN_NCC: f64 = 0.7;
+/// Distance ratio test: `1 - best` must be below this fraction of `1 - runner-up`
+const KEYPOINT_RATIO: f64 = 0.8;
+/// Reprojection error in source pixels within which a match is an inlier
+const RANSAC_INLIER_DISTANCE: f64 = 3.0;
+/// Upper bound on RANSAC hypotheses; fewer are drawn once 99.9% confident
+const RANSAC_MAX_ITERATIONS: usize = 2000;
+
+/// 3x3 homography taking template point `(u, v)` to source `(x, y)`, scaled so `h[2][2] = 1`
+type Homography = [[f64; 3]; 3];
+
+/// Template point and the source point it was matched to
+type Correspondence = ((f64, f64), (f64, f64));
+
+fn apply_homography(h: &Homography, u: f64, v: f64) -> Option<(f64, f64)> {
+    let w = h[2][0] * u + h[2][1] * v + h[2][2];
+    (w.abs() > 1e-12).then(|| ((h[0][0] * u + h[0][1] * v + h[0][2]) / w, (h[1][0] * u + h[1][1] * v + h[1][2]) / w))
+}
+
+fn multiply_homography(a: &Homography, b: &Homography) -> Homography {
+    let mut out = [[0.0; 3]; 3];
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            *v = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    out
+}
+
+/// Corner with the mean-free, unit-norm patch around it
+struct Keypoint {
+    x: f64,
+    y: f64,
+    patch: Vec<f64>,
+}
+
+/// Harris corners of `img`, strongest first, at most `max`: maxima of the
+/// corner response over their 7x7 neighbourhood that reach 1% of the
+/// strongest response. Corners on nearly flat patches are dropped.
+fn detect_keypoints(img: &GrayImageData, max: usize) -> Vec<Keypoint> {
+    let (w, h, r) = (img.width, img.height, KEYPOINT_PATCH_RADIUS);
+    if w < 2 * r + 3 || h < 2 * r + 3 { return Vec::new(); }
+    let smooth = gaussian_blur(&img.data, w, h, 1.0);
+    let mut products = [vec![0.0; w * h], vec![0.0; w * h], vec![0.0; w * h]];
+    for y in 1..h - 1 {
+        for x in 1..w - 1 {
+            let i = y * w + x;
+            let (gx, gy) = ((smooth[i + 1] - smooth[i - 1]) / 2.0, (smooth[i + w] - smooth[i - w]) / 2.0);
+            products[0][i] = gx * gx;
+            products[1][i] = gy * gy;
+            products[2][i] = gx * gy;
+        }
+    }
+    let [xx, yy, xy] = products.map(|p| gaussian_blur(&p, w, h, 1.5));
+    let response: Vec<f64> = (0..w * h).map(|i| xx[i] * yy[i] - xy[i] * xy[i] - HARRIS_K * (xx[i] + yy[i]).powi(2)).collect();
+    let floor = response.iter().fold(0.0f64, |m, &v| m.max(v)) * 0.01;
+    let mut corners: Vec<(f64, usize, usize)> = (r..h - r)
+        .flat_map(|y| (r..w - r).map(move |x| (x, y)))
+        .filter_map(|(x, y)| {
+            let (i, v) = (y * w + x, response[y * w + x]);
+            if v <= floor { return None; }
+            // Plateaus keep their first pixel
+            let local_max = (y - 3.min(y)..(y + 4).min(h)).all(|ny| {
+                (x - 3.min(x)..(x + 4).min(w)).all(|nx| {
+                    let (j, o) = (ny * w + nx, response[ny * w + nx]);
+                    o < v || (o == v && j >= i)
+                })
+            });
+            local_max.then_some((v, x, y))
+        })
+        .collect();
+    corners.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let side = 2 * r + 1;
+    corners.into_iter()
+        .filter_map(|(_, x, y)| {
+            let mut patch = crop_region(&smooth, w, x - r, y - r, side, side);
+            let mean = patch.iter().sum::<f64>() / patch.len() as f64;
+            patch.iter_mut().for_each(|v| *v -= mean);
+            let norm = patch.iter().map(|v| v * v).sum::<f64>().sqrt();
+            // Below one grey level of standard deviation the patch is noise
+            if norm < (patch.len() as f64).sqrt() { return None; }
+            patch.iter_mut().for_each(|v| *v /= norm);
+            Some(Keypoint { x: x as f64, y: y as f64, patch })
+        })
+        .take(max)
+        .collect()
+}
+
+/// Keypoints of the template at each of `KEYPOINT_TEMPLATE_SCALES`, with
+/// positions mapped back to template pixels
+fn template_keypoints(tpl: &GrayImageData, max_per_scale: usize) -> Vec<Keypoint> {
+    KEYPOINT_TEMPLATE_SCALES.iter()
+        .filter_map(|&s| {
+            let scaled = if s == 1.0 { GrayImageData { data: tpl.data.clone(), ..*tpl } } else { scale_image(tpl, s)? };
+            let (fx, fy) = (tpl.width as f64 / scaled.width as f64, tpl.height as f64 / scaled.height as f64);
+            let mut kps = detect_keypoints(&scaled, max_per_scale);
+            for kp in &mut kps {
+                kp.x = (kp.x + 0.5) * fx - 0.5;
+                kp.y = (kp.y + 0.5) * fy - 0.5;
+            }
+            Some(kps)
+        })
+        .flatten()
+        .collect()
+}
+
+/// Tentative correspondences: each template keypoint is paired with the
+/// source keypoint whose patch correlates best, when that passes
+/// `KEYPOINT_MIN_NCC` and the ratio test; each source keypoint keeps only
+/// its best pairing
+fn match_keypoints(tpl: &[Keypoint], src: &[Keypoint]) -> Vec<Correspondence> {
+    let found: Vec<(usize, usize, f64)> = tpl.par_iter()
+        .enumerate()
+        .filter_map(|(ti, t)| {
+            let (mut best, mut second) = ((-1.0, 0), -1.0);
+            for (si, s) in src.iter().enumerate() {
+                let ncc: f64 = t.patch.iter().zip(&s.patch).map(|(a, b)| a * b).sum();
+                if ncc > best.0 {
+                    second = best.0;
+                    best = (ncc, si);
+                } else if ncc > second {
+                    second = ncc;
+                }
+            }
+            (best.0 >= KEYPOINT_MIN_NCC && 1.0 - best.0 < KEYPOINT_RATIO * (1.0 - second)).then_some((ti, best.1, best.0))
+        })
+        .collect();
+    let mut by_source: HashMap<usize, (usize, f64)> = HashMap::new();
+    for (ti, si, ncc) in found {
+        let entry = by_source.entry(si).or_insert((ti, ncc));
+        if ncc > entry.1 { *entry = (ti, ncc); }
+    }
+    let mut pairs: Vec<(usize, usize)> = by_source.into_iter().map(|(si, (ti, _))| (ti, si)).collect();
+    pairs.sort_unstable();
+    pairs.into_iter().map(|(ti, si)| ((tpl[ti].x, tpl[ti].y), (src[si].x, src[si].y))).collect()
+}
+
+/// Similarity moving the points' centroid to the origin and their mean
+/// distance from it to sqrt(2), which keeps the DLT well conditioned
+fn normalizing_transform(points: impl Iterator<Item = (f64, f64)> + Clone) -> Homography {
+    let n = points.clone().count() as f64;
+    let (cx, cy) = points.clone().fold((0.0, 0.0), |(sx, sy), (x, y)| (sx + x / n, sy + y / n));
+    let spread = points.map(|(x, y)| ((x - cx).powi(2) + (y - cy).powi(2)).sqrt()).sum::<f64>() / n;
+    let s = if spread > 1e-12 { std::f64::consts::SQRT_2 / spread } else { 1.0 };
+    [[s, 0.0, -s * cx], [0.0, s, -s * cy], [0.0, 0.0, 1.0]]
+}
+
+/// Least-squares homography through at least 4 correspondences (direct
+/// linear transform with `h[2][2] = 1` on normalized points); None if degenerate
+fn fit_homography(pairs: &[Correspondence]) -> Option<Homography> {
+    if pairs.len() < 4 { return None; }
+    let t = normalizing_transform(pairs.iter().map(|p| p.0));
+    let s = normalizing_transform(pairs.iter().map(|p| p.1));
+    let mut ata = vec![vec![0.0; 8]; 8];
+    let mut atb = vec![0.0; 8];
+    for &((u, v), (x, y)) in pairs {
+        let (u, v) = (t[0][0] * u + t[0][2], t[1][1] * v + t[1][2]);
+        let (x, y) = (s[0][0] * x + s[0][2], s[1][1] * y + s[1][2]);
+        for (row, rhs) in [([u, v, 1.0, 0.0, 0.0, 0.0, -u * x, -v * x], x), ([0.0, 0.0, 0.0, u, v, 1.0, -u * y, -v * y], y)] {
+            for a in 0..8 {
+                atb[a] += row[a] * rhs;
+                for b in 0..8 { ata[a][b] += row[a] * row[b]; }
+            }
+        }
+    }
+    let p = solve_linear(ata, atb)?;
+    let normalized = [[p[0], p[1], p[2]], [p[3], p[4], p[5]], [p[6], p[7], 1.0]];
+    let s_inv = [[1.0 / s[0][0], 0.0, -s[0][2] / s[0][0]], [0.0, 1.0 / s[1][1], -s[1][2] / s[1][1]], [0.0, 0.0, 1.0]];
+    let h = multiply_homography(&multiply_homography(&s_inv, &normalized), &t);
+    if h[2][2].abs() < 1e-12 { return None; }
+    Some(h.map(|row| row.map(|v| v / h[2][2])))
+}
+
+/// Whether the template rectangle maps to a convex quadrilateral with the
+/// same orientation; mirrored or folded hypotheses aren't a view of a screen
+fn plausible_homography(h: &Homography, tw: usize, th: usize) -> bool {
+    let (w, hh) = (tw as f64, th as f64);
+    let Some(q) = [(0.0, 0.0), (w, 0.0), (w, hh), (0.0, hh)].iter().map(|&(u, v)| apply_homography(h, u, v)).collect::<Option<Vec<_>>>()
+    else { return false };
+    (0..4).all(|i| {
+        let (a, b, c) = (q[i], q[(i + 1) % 4], q[(i + 2) % 4]);
+        (b.0 - a.0) * (c.1 - b.1) - (b.1 - a.1) * (c.0 - b.0) > 0.0
+    })
+}
+
+fn homography_inliers(h: &Homography, pairs: &[Correspondence]) -> Vec<bool> {
+    pairs.iter()
+        .map(|&((u, v), (x, y))| {
+            apply_homography(h, u, v).is_some_and(|(px, py)| (px - x).hypot(py - y) <= RANSAC_INLIER_DISTANCE)
+        })
+        .collect()
+}
+
+/// RANSAC over minimal 4-point samples, then least-squares refits on the
+/// inliers while that gains inliers. Sampling is seeded, so results are
+/// reproducible. Returns the homography and its inlier count.
+fn ransac_homography(pairs: &[Correspondence], tw: usize, th: usize) -> Option<(Homography, usize)> {
+    let n = pairs.len();
+    if n < 4 { return None; }
+    let mut state = 0x9E37_79B9_7F4A_7C15u64;
+    let mut next = |bound: usize| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state % bound as u64) as usize
+    };
+    let mut best: Option<(Homography, usize)> = None;
+    let mut iterations = RANSAC_MAX_ITERATIONS;
+    let mut i = 0;
+    while i < iterations {
+        i += 1;
+        let mut sample = [0usize; 4];
+        for k in 0..4 {
+            sample[k] = loop {
+                let j = next(n);
+                if !sample[..k].contains(&j) { break j; }
+            };
+        }
+        let Some(h) = fit_homography(&sample.map(|j| pairs[j])) else { continue };
+        if !plausible_homography(&h, tw, th) { continue; }
+        let count = homography_inliers(&h, pairs).iter().filter(|&&inlier| inlier).count();
+        if best.as_ref().map_or(true, |b| count > b.1) {
+            best = Some((h, count));
+            // Hypotheses needed to draw an all-inlier sample with 99.9% confidence
+            let all_inliers = (count as f64 / n as f64).powi(4);
+            if all_inliers >= 1.0 { break; }
+            let needed = (1e-3f64).ln() / (1.0 - all_inliers).ln();
+            iterations = iterations.min(needed.ceil() as usize);
+        }
+    }
+    let (mut h, mut count) = best?;
+    loop {
+        let inliers: Vec<Correspondence> = pairs.iter().zip(homography_inliers(&h, pairs)).filter(|p| p.1).map(|p| *p.0).collect();
+        let Some(refit) = fit_homography(&inliers).filter(|r| plausible_homography(r, tw, th)) else { break };
+        let refit_count = homography_inliers(&refit, pairs).iter().filter(|&&inlier| inlier).count();
+        if refit_count < count { break; }
+        let gained = refit_count > count;
+        (h, count) = (refit, refit_count);
+        if !gained { break; }
+    }
+    Some((h, count))
+}
+
+/// NCC between the template and the source sampled (bilinearly) where `h`
+/// maps each template pixel; None if less than half of them land inside
+fn homography_ncc(src: &GrayImageData, tpl: &GrayImageData, h: &Homography) -> Option<f64> {
+    let (sw, sh) = (src.width as f64, src.height as f64);
+    let samples: Vec<(f64, f64)> = (0..tpl.width * tpl.height)
+        .filter_map(|i| {
+            let (x, y) = apply_homography(h, (i % tpl.width) as f64, (i / tpl.width) as f64)?;
+            (x >= 0.0 && y >= 0.0 && x <= sw - 1.0 && y <= sh - 1.0)
+                .then(|| (tpl.data[i], bilinear_at(&src.data, src.width, src.height, x, y)))
+        })
+        .collect();
+    if samples.len() * 2 < tpl.data.len() { return None; }
+    let n = samples.len() as f64;
+    let (tm, sm) = samples.iter().fold((0.0, 0.0), |(a, b), (t, s)| (a + t / n, b + s / n));
+    let (mut ts, mut tt, mut ss) = (0.0, 0.0, 0.0);
+    for (t, s) in &samples {
+        ts += (t - tm) * (s - sm);
+        tt += (t - tm).powi(2);
+        ss += (s - sm).powi(2);
+    }
+    Some(if tt < 1e-9 || ss < 1e-9 { 0.0 } else { ts / (tt * ss).sqrt() })
+}
+
+/// Homography found by `estimate_homography`, before it is verified
+struct HomographyEstimate {
+    homography: Homography,
+    inliers: usize,
+    matches: usize,
+}
+
+/// Keypoint matching and RANSAC: where the template appears in the source
+/// under perspective, if at least `min_inliers` correspondences agree
+fn estimate_homography(src: &GrayImageData, tpl: &GrayImageData, max_keypoints: usize, min_inliers: usize) -> Option<HomographyEstimate> {
+    let tpl_kps = template_keypoints(tpl, max_keypoints / KEYPOINT_TEMPLATE_SCALES.len());
+    let src_kps = detect_keypoints(src, max_keypoints);
+    let pairs = match_keypoints(&tpl_kps, &src_kps);
+    let result = ransac_homography(&pairs, tpl.width, tpl.height);
+    log::debug!(
+        "perspective: {} template and {} source keypoints, {} tentative matches, {} inliers",
+        tpl_kps.len(), src_kps.len(), pairs.len(), result.map_or(0, |r| r.1)
+    );
+    let (homography, inliers) = result.filter(|r| r.1 >= min_inliers)?;
+    Some(HomographyEstimate { homography, inliers, matches: pairs.len() })
+}
+
+// ============================================================================
+// Repeated Region Detection (self-matching)
+// ============================================================================
+
+/// Blocks per side of the descriptor that buckets candidate windows
+const REPEAT_GRID: usize = 4;
+
+/// Seed descriptor components smaller than this fraction of their RMS may
+/// flip sign in a repeat, so both buckets are probed
+const REPEAT_AMBIGUOUS: f64 = 0.25;
+
+/// Most ambiguous components probed both ways (2^n buckets per seed)
+const REPEAT_MAX_PROBE_BITS: usize = 3;
+
+/// Block means of the `size`-square window at `(x, y)` on a 4x4 grid, minus
+/// their mean, so the descriptor ignores brightness like NCC does
+fn block_descriptor(integral: &IntegralImage, x: usize, y: usize, size: usize) -> [f64; REPEAT_GRID * REPEAT_GRID] {
+    let b = size / REPEAT_GRID;
+    let mut d = [0.0; REPEAT_GRID * REPEAT_GRID];
+    for (i, v) in d.iter_mut().enumerate() {
+        *v = integral.get_stats(x + (i % REPEAT_GRID) * b, y + (i / REPEAT_GRID) * b, b, b).0;
+    }
+    let mean = d.iter().sum::<f64>() / d.len() as f64;
+    d.iter_mut().for_each(|v| *v -= mean);
+    d
+}
+
+/// Sign pattern of a descriptor: windows that match well share it, up to
+/// components near zero
+fn descriptor_code(d: &[f64; REPEAT_GRID * REPEAT_GRID]) -> u16 {
+    d.iter().enumerate().fold(0u16, |code, (i, &v)| if v > 0.0 { code | 1 << i } else { code })
+}
+
+/// Codes to look up for a seed: its own, with every combination of its most
+/// ambiguous bits flipped
+fn probe_codes(d: &[f64; REPEAT_GRID * REPEAT_GRID]) -> Vec<u16> {
+    let rms = (d.iter().map(|v| v * v).sum::<f64>() / d.len() as f64).sqrt();
+    let mut weak: Vec<usize> = (0..d.len()).filter(|&i| d[i].abs() < rms * REPEAT_AMBIGUOUS).collect();
+    weak.sort_by(|&a, &b| d[a].abs().partial_cmp(&d[b].abs()).unwrap());
+    weak.truncate(REPEAT_MAX_PROBE_BITS);
+    let code = descriptor_code(d);
+    (0..1u16 << weak.len())
+        .map(|mask| weak.iter().enumerate().fold(code, |c, (bit, &i)| if mask >> bit & 1 == 1 { c ^ 1 << i } else { c }))
+        .collect()
+}
+
+/// Groups of `size`-square regions that repeat within the source. Seeds are
+/// taken every `stride` pixels (flat ones skipped); each is verified by exact
+/// NCC only against windows whose descriptor code it shares, and groups whose
+/// seed intersects a region already reported are dropped.
+fn find_repeated_regions(src: &[f64], sw: usize, sh: usize, size: usize, stride: usize, threshold: f64) -> Vec<Vec<MatchResult>> {
+    if size > sw || size > sh { return vec![]; }
+    let integral = IntegralImage::new(src, sw, sh);
+    let (cols, rows) = (sw - size + 1, sh - size + 1);
+    let codes: Vec<u16> = (0..rows)
+        .into_par_iter()
+        .flat_map_iter(|y| {
+            let integral = &integral;
+            (0..cols).map(move |x| descriptor_code(&block_descriptor(integral, x, y, size)))
+        })
+        .collect();
+    let mut buckets: HashMap<u16, Vec<usize>> = HashMap::new();
+    for (i, &code) in codes.iter().enumerate() {
+        buckets.entry(code).or_default().push(i);
+    }
+
+    let seeds: Vec<(usize, usize)> = (0..rows).step_by(stride).flat_map(|y| (0..cols).step_by(stride).map(move |x| (x, y))).collect();
+    let groups: Vec<((usize, usize), Vec<MatchResult>)> = seeds
+        .par_iter()
+        .filter_map(|&(sx, sy)| {
+            let patch = crop_region(src, sw, sx, sy, size, size);
+            if template_stats(&patch).0 < MIN_TEMPLATE_STD { return None; }
+            let tpl = Template::new(&patch, size, size);
+            let hits: Vec<MatchResult> = probe_codes(&block_descriptor(&integral, sx, sy, size))
+                .into_iter()
+                .flat_map(|code| buckets.get(&code).into_iter().flatten())
+                .filter_map(|&i| {
+                    let (x, y) = (i % cols, i / cols);
+                    let score = compute_ncc(src, sw, &integral, &tpl, x, y);
+                    (score >= threshold).then_some(MatchResult {
+                        x: x as i32, y: y as i32, confidence: score, crop: None, scale: None, angle: None, pixel_ratio: 1.0, flip: None, inverted: false,
+                    })
+                })
+                .collect();
+            // The seed matches itself with score 1, so it leads its own group
+            let group = non_max_suppression(hits, size, size, usize::MAX);
+            (group.len() >= 2).then_some(((sx, sy), group))
+        })
+        .collect();
+    log::debug!("{} of {} seeds repeat", groups.len(), seeds.len());
+
+    let mut groups = groups;
+    groups.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then((a.0.1, a.0.0).cmp(&(b.0.1, b.0.0))));
+    let mut kept: Vec<Vec<MatchResult>> = Vec::new();
+    for ((sx, sy), group) in groups {
+        let covered = kept.iter().flatten().any(|r| r.x.abs_diff(sx as i32) < size as u32 && r.y.abs_diff(sy as i32) < size as u32);
+        if !covered { kept.push(group); }
+    }
+    kept
+}
+
+// ============================================================================
+// Threshold Calibration and Sweeps
+// ============================================================================
+
+/// Default thresholds of `score_sweep`: 0.50, 0.55, ..., 0.95
+const DEFAULT_SWEEP_THRESHOLDS: [f64; 10] = [0.5, 0.55, 0.6, 0.65, 0.7, 0.75, 0.8, 0.85, 0.9, 0.95];
+
+/// Local maxima (at least equal to all 8 neighbours) of a score map that reach `min_score`
+fn score_peaks(scores: &[f64], cols: usize, rows: usize, min_score: f64) -> Vec<MatchResult> {
+    let mut peaks = Vec::new();
+    for y in 0..rows {
+        for x in 0..cols {
+            let s = scores[y * cols + x];
+            if s < min_score { continue; }
+            let is_peak = (y.saturating_sub(1)..(y + 2).min(rows))
+                .all(|ny| (x.saturating_sub(1)..(x + 2).min(cols)).all(|nx| scores[ny * cols + nx] <= s));
+            if is_peak {
+                peaks.push(MatchResult { x: x as i32, y: y as i32, confidence: s, crop: None, scale: None, angle: None, pixel_ratio: 1.0, flip: None, inverted: false });
+            }
+        }
+    }
+    peaks
+}
+
+/// Maximum score per `cell x cell` block of window positions, row-major over
+/// `ceil(cols / cell) x ceil(rows / cell)`. Each band of `cell` rows is
+/// scored and pooled on its own, so the full score map is never held.
+fn pooled_score_map<S: WindowScore>(src: &[f64], sw: usize, sh: usize, tpl: &S, cell: usize) -> (Vec<f64>, usize, usize) {
+    let integral = IntegralImage::new(src, sw, sh);
+    let (tw, th) = tpl.size();
+    let (cols, rows) = (sw - tw + 1, sh - th + 1);
+    let (gw, gh) = ((cols + cell - 1) / cell, (rows + cell - 1) / cell);
+    let mut grid = vec![f64::NEG_INFINITY; gw * gh];
+    grid.par_chunks_mut(gw).enumerate().for_each(|(gy, out)| {
+        for y in gy * cell..((gy + 1) * cell).min(rows) {
+            for x in 0..cols {
+                let best = &mut out[x / cell];
+                *best = best.max(tpl.score(src, sw, &integral, x, y));
+            }
+        }
+    });
+    (grid, gw, gh)
+}
+
+/// Resolution of `ScoreHistogram`: bins of width 1e-4 over [-1, 1]
+const SCORE_BINS: usize = 20_000;
+
+/// Distribution of window scores, binned so any number of windows fits in fixed memory
+struct ScoreHistogram {
+    counts: Vec<u64>,
+    total: u64,
+    max: f64,
+}
+
+impl ScoreHistogram {
+    fn new() -> Self {
+        Self { counts: vec![0; SCORE_BINS], total: 0, max: -1.0 }
+    }
+
+    fn add(&mut self, scores: &[f64]) {
+        for &s in scores {
+            let bin = ((s + 1.0) / 2.0 * SCORE_BINS as f64).max(0.0) as usize;
+            self.counts[bin.min(SCORE_BINS - 1)] += 1;
+            self.max = self.max.max(s);
+        }
+        self.total += scores.len() as u64;
+    }
+
+    /// Lowest bin edge such that at most `rate` of the windows score at or above it
+    fn threshold_at(&self, rate: f64) -> f64 {
+        let allowed = (rate * self.total as f64).floor() as u64;
+        let edge = |bin: usize| bin as f64 / SCORE_BINS as f64 * 2.0 - 1.0;
+        let mut above = 0u64;
+        for bin in (0..SCORE_BINS).rev() {
+            if above + self.counts[bin] > allowed { return edge(bin + 1); }
+            above += self.counts[bin];
+        }
+        -1.0
+    }
+
+    /// Fraction of windows scoring at or above `threshold`
+    fn rate_above(&self, threshold: f64) -> f64 {
+        if self.total == 0 { return 0.0; }
+        let first = ((threshold + 1.0) / 2.0 * SCORE_BINS as f64).round().max(0.0) as usize;
+        let above: u64 = self.counts[first.min(SCORE_BINS)..].iter().sum();
+        above as f64 / self.total as f64
+    }
+}
+
+// ============================================================================
+// Whole-Image Similarity
+// ============================================================================
+
+const SSIM_WINDOW: usize = 7;
+
+/// Pearson correlation of two equally sized images. Two flat images count as
+/// identical only if their values are equal.
+fn global_ncc(a: &[f64], b: &[f64]) -> f64 {
+    let n = a.len() as f64;
+    let ma = a.iter().sum::<f64>() / n;
+    let mb = b.iter().sum::<f64>() / n;
+    let (mut cross, mut va, mut vb) = (0.0f64, 0.0f64, 0.0f64);
+    for (&x, &y) in a.iter().zip(b) {
+        cross += (x - ma) * (y - mb);
+        va += (x - ma) * (x - ma);
+        vb += (y - mb) * (y - mb);
+    }
+    if va < 1e-10 || vb < 1e-10 {
+        return if a == b { 1.0 } else { 0.0 };
+    }
+    cross / (va * vb).sqrt()
+}
+
+/// Peak signal-to-noise ratio in dB for 8-bit data (infinite for identical images)
+fn psnr(a: &[f64], b: &[f64]) -> f64 {
+    let mse = a.iter().zip(b).map(|(&x, &y)| (x - y) * (x - y)).sum::<f64>() / a.len() as f64;
+    if mse == 0.0 { return f64::INFINITY; }
+    10.0 * (255.0 * 255.0 / mse).log10()
+}
+
+/// Mean SSIM over all 7x7 windows, with window statistics taken from integral images
+fn ssim(a: &[f64], b: &[f64], w: usize, h: usize) -> f64 {
+    let c1 = (0.01 * 255.0f64).powi(2);
+    let c2 = (0.03 * 255.0f64).powi(2);
+    let win_w = SSIM_WINDOW.min(w);
+    let win_h = SSIM_WINDOW.min(h);
+    let n = (win_w * win_h) as f64;
+
+    let ia = IntegralImage::new(a, w, h);
+    let ib = IntegralImage::new(b, w, h);
+    let ab: Vec<f64> = a.iter().zip(b).map(|(&x, &y)| x * y).collect();
+    let iab = IntegralImage::new(&ab, w, h);
+
+    let end_x = w - win_w;
+    let end_y = h - win_h;
+    let total: f64 = (0..=end_y)
+        .into_par_iter()
+        .map(|y| {
+            let mut row = 0.0f64;
+            for x in 0..=end_x {
+                let (sa, sqa) = ia.get_stats(x, y, win_w, win_h);
+                let (sb, sqb) = ib.get_stats(x, y, win_w, win_h);
+                let (sab, _) = iab.get_stats(x, y, win_w, win_h);
+                let (ma, mb) = (sa / n, sb / n);
+                let va = sqa / n - ma * ma;
+                let vb = sqb / n - mb * mb;
+                let cov = sab / n - ma * mb;
+                row += ((2.0 * ma * mb + c1) * (2.0 * cov + c2))
+                    / ((ma * ma + mb * mb + c1) * (va + vb + c2));
+            }
+            row
+        })
+        .sum();
+    total / ((end_x + 1) * (end_y + 1)) as f64
+}
+
+// ============================================================================
+// Visual Diff (changed regions between two screenshots)
+// ============================================================================
+
+/// Changed region found by `diff_regions`: bounding box of its changed pixels
+struct DiffRegion {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+/// Regions where `a` and `b` differ by more than `tolerance` grey levels.
+/// Changed pixels up to `merge_distance` apart end up in one region: the
+/// change mask is dilated (window counts on its integral image) before
+/// labelling, while each box still bounds only the changed pixels. Regions
+/// with fewer than `min_area` changed pixels are dropped.
+fn diff_regions(a: &[f64], b: &[f64], w: usize, h: usize, tolerance: f64, merge_distance: usize, min_area: usize) -> (Vec<bool>, Vec<DiffRegion>) {
+    let changed: Vec<bool> = a.iter().zip(b).map(|(x, y)| (x - y).abs() > tolerance).collect();
+    let mask: Vec<f64> = changed.iter().map(|&c| if c { 1.0 } else { 0.0 }).collect();
+    let integral = IntegralImage::new(&mask, w, h);
+    let r = (merge_distance + 1) / 2;
+    let grown: Vec<bool> = (0..w * h)
+        .into_par_iter()
+        .map(|i| {
+            let (x, y) = (i % w, i / w);
+            let (x0, y0) = (x.saturating_sub(r), y.saturating_sub(r));
+            let (x1, y1) = ((x + r + 1).min(w), (y + r + 1).min(h));
+            integral.get_stats(x0, y0, x1 - x0, y1 - y0).0 > 0.5
+        })
+        .collect();
+
+    let mut seen = vec![false; w * h];
+    let mut regions = Vec::new();
+    let mut stack = Vec::new();
+    for start in 0..w * h {
+        if !grown[start] || seen[start] { continue; }
+        seen[start] = true;
+        stack.push(start);
+        let (mut x0, mut y0, mut x1, mut y1, mut count) = (usize::MAX, usize::MAX, 0, 0, 0);
+        while let Some(i) = stack.pop() {
+            let (x, y) = (i % w, i / w);
+            if changed[i] {
+                (x0, y0, x1, y1) = (x0.min(x), y0.min(y), x1.max(x), y1.max(y));
+                count += 1;
+            }
+            let neighbours = [
+                (x > 0).then(|| i - 1), (x + 1 < w).then(|| i + 1),
+                (y > 0).then(|| i - w), (y + 1 < h).then(|| i + w),
+            ];
+            for n in neighbours.into_iter().flatten() {
+                if grown[n] && !seen[n] {
+                    seen[n] = true;
+                    stack.push(n);
+                }
+            }
+        }
+        if count > 0 && count >= min_area {
+            regions.push(DiffRegion { x: x0, y: y0, width: x1 - x0 + 1, height: y1 - y0 + 1 });
+        }
+    }
+    regions.sort_by_key(|r| (r.y, r.x));
+    log::debug!("{} changed pixels in {} regions", changed.iter().filter(|&&c| c).count(), regions.len());
+    (changed, regions)
+}
+
+/// Diff visualization: `current` faded to grey, changed pixels in red and
+/// region boxes outlined in magenta
+fn render_diff(current: &[f64], w: usize, h: usize, changed: &[bool], regions: &[DiffRegion]) -> image::RgbImage {
+    let mut img = image::RgbImage::from_fn(w as u32, h as u32, |x, y| {
+        let i = y as usize * w + x as usize;
+        if changed[i] { return image::Rgb([255, 0, 0]); }
+        let v = (160.0 + (current[i] - 128.0) / 4.0).round().clamp(0.0, 255.0) as u8;
+        image::Rgb([v, v, v])
+    });
+    for r in regions {
+        let (x1, y1) = (r.x + r.width - 1, r.y + r.height - 1);
+        for x in r.x..=x1 {
+            img.put_pixel(x as u32, r.y as u32, image::Rgb([255, 0, 255]));
+            img.put_pixel(x as u32, y1 as u32, image::Rgb([255, 0, 255]));
+        }
+        for y in r.y..=y1 {
+            img.put_pixel(r.x as u32, y as u32, image::Rgb([255, 0, 255]));
+            img.put_pixel(x1 as u32, y as u32, image::Rgb([255, 0, 255]));
+        }
+    }
+    img
+}
+
+/// Shift of `current` relative to `golden`, within `max_shift` pixels on each
+/// axis, that maximizes the NCC of their overlap: `current[y + dy][x + dx]` is
+/// compared with `golden[y][x]`, skipping golden pixels set in `ignore`. Ties
+/// go to the smallest shift. Returns `((dx, dy), ncc)`
+fn golden_shift(golden: &[f64], current: &[f64], w: usize, h: usize, ignore: &[bool], max_shift: usize) -> ((isize, isize), f64) {
+    let m = max_shift as isize;
+    let mut shifts: Vec<(isize, isize)> = (-m..=m).flat_map(|dy| (-m..=m).map(move |dx| (dx, dy))).collect();
+    shifts.sort_by_key(|&(dx, dy)| (dx.abs() + dy.abs(), dy, dx));
+    let scores: Vec<f64> = shifts
+        .par_iter()
+        .map(|&(dx, dy)| {
+            let (x0, x1) = ((-dx).max(0) as usize, (w as isize).min(w as isize - dx) as usize);
+            let (y0, y1) = ((-dy).max(0) as usize, (h as isize).min(h as isize - dy) as usize);
+            let (mut n, mut sa, mut sb, mut saa, mut sbb, mut sab) = (0.0f64, 0.0f64, 0.0f64, 0.0f64, 0.0f64, 0.0f64);
+            for y in y0..y1 {
+                let cy = (y as isize + dy) as usize;
+                for x in x0..x1 {
+                    if ignore[y * w + x] { continue; }
+                    let a = golden[y * w + x];
+                    let b = current[cy * w + (x as isize + dx) as usize];
+                    n += 1.0;
+                    (sa, sb, saa, sbb, sab) = (sa + a, sb + b, saa + a * a, sbb + b * b, sab + a * b);
+                }
+            }
+            if n < 1.0 { return -1.0; }
+            let (va, vb) = (saa - sa * sa / n, sbb - sb * sb / n);
+            if va < 1e-10 || vb < 1e-10 {
+                // Flat overlap: aligned only if both sides hold the same value
+                return if va < 1e-10 && vb < 1e-10 && (sa - sb).abs() < 1e-6 * n { 1.0 } else { 0.0 };
+            }
+            (sab - sa * sb / n) / (va * vb).sqrt()
+        })
+        .collect();
+    let mut best = 0;
+    for (i, &s) in scores.iter().enumerate() {
+        if s > scores[best] + 1e-9 { best = i; }
+    }
+    log::debug!("golden alignment shift {:?} ncc {:.4}", shifts[best], scores[best]);
+    (shifts[best], scores[best])
+}
+
+/// Offending region of a golden comparison, in golden-image coordinates
+struct GoldenRegion {
+    region: DiffRegion,
+    score: f64,
+    max_diff: f64,
+    changed_pixels: usize,
+}
+
+/// Compare `current` against `golden` after aligning it by `golden_shift`.
+/// Golden pixels inside `ignore_regions` (clamped to the image) are treated
+/// as unchanged; the remaining overlap is diffed with `diff_regions` and each
+/// region is scored by the NCC of its golden and current pixels.
+/// Returns `((dx, dy), alignment ncc, regions)`
+#[allow(clippy::too_many_arguments)]
+fn compare_golden(
+    golden: &[f64], current: &[f64], w: usize, h: usize, ignore_regions: &[(usize, usize, usize, usize)], max_shift: usize,
+    tolerance: f64, merge_distance: usize, min_area: usize,
+) -> ((isize, isize), f64, Vec<GoldenRegion>) {
+    let mut ignore = vec![false; w * h];
+    for &(rx, ry, rw, rh) in ignore_regions {
+        for y in ry.min(h)..ry.saturating_add(rh).min(h) {
+            ignore[y * w + rx.min(w)..y * w + rx.saturating_add(rw).min(w)].fill(true);
+        }
+    }
+    let ((dx, dy), alignment) = golden_shift(golden, current, w, h, &ignore, max_shift);
+    let (x0, y0) = ((-dx).max(0) as usize, (-dy).max(0) as usize);
+    let (ow, oh) = (w - dx.unsigned_abs(), h - dy.unsigned_abs());
+    let mut g = Vec::with_capacity(ow * oh);
+    let mut c = Vec::with_capacity(ow * oh);
+    for y in y0..y0 + oh {
+        let cy = (y as isize + dy) as usize;
+        for x in x0..x0 + ow {
+            let gv = golden[y * w + x];
+            g.push(gv);
+            c.push(if ignore[y * w + x] { gv } else { current[cy * w + (x as isize + dx) as usize] });
+        }
+    }
+    let (changed, regions) = diff_regions(&g, &c, ow, oh, tolerance, merge_distance, min_area);
+    let regions = regions
+        .into_iter()
+        .map(|r| {
+            let (mut a, mut b, mut max_diff, mut changed_pixels) = (Vec::new(), Vec::new(), 0.0f64, 0);
+            for y in r.y..r.y + r.height {
+                for x in r.x..r.x + r.width {
+                    let i = y * ow + x;
+                    a.push(g[i]);
+                    b.push(c[i]);
+                    max_diff = max_diff.max((g[i] - c[i]).abs());
+                    changed_pixels += changed[i] as usize;
+                }
+            }
+            let region = DiffRegion { x: r.x + x0, y: r.y + y0, ..r };
+            GoldenRegion { region, score: global_ncc(&a, &b), max_diff, changed_pixels }
+        })
+        .collect();
+    ((dx, dy), alignment, regions)
+}
+
+// ============================================================================
+// Match Explanation (per-block NCC contributions)
+// ============================================================================
+
+/// Each template pixel's term of the NCC between `tpl` and the window at
+/// `(x, y)`: `(s - mean_s) * (t - mean_t) / sqrt(var_s * var_t)`, summed
+/// over `block x block` cells. The cells add up to the window's NCC score;
+/// all are 0 when the window or the template is flat.
+fn ncc_contributions(src: &[f64], sw: usize, tpl: &GrayImageData, x: usize, y: usize, block: usize) -> (Vec<f64>, usize, usize) {
+    let (tw, th) = (tpl.width, tpl.height);
+    let window = crop_region(src, sw, x, y, tw, th);
+    let n = (tw * th) as f64;
+    let (ms, mt) = (window.iter().sum::<f64>() / n, tpl.data.iter().sum::<f64>() / n);
+    let var_s: f64 = window.iter().map(|s| (s - ms) * (s - ms)).sum();
+    let var_t: f64 = tpl.data.iter().map(|t| (t - mt) * (t - mt)).sum();
+    let denom = (var_s * var_t).sqrt();
+    let (gw, gh) = ((tw + block - 1) / block, (th + block - 1) / block);
+    let mut cells = vec![0.0; gw * gh];
+    if denom > 1e-10 {
+        for (i, (s, t)) in window.iter().zip(&tpl.data).enumerate() {
+            cells[(i / tw / block) * gw + i % tw / block] += (s - ms) * (t - mt) / denom;
+        }
+    }
+    (cells, gw, gh)
+}
+
+/// Contribution heatmap: the source window in grey, each cell tinted green
+/// where it raised the score and red where it lowered it, in proportion to
+/// its share of the largest contribution
+fn render_contributions(window: &[f64], tw: usize, th: usize, cells: &[f64], gw: usize, block: usize) -> image::RgbImage {
+    let peak = cells.iter().fold(0.0f64, |m, c| m.max(c.abs()));
+    image::RgbImage::from_fn(tw as u32, th as u32, |x, y| {
+        let (x, y) = (x as usize, y as usize);
+        let c = cells[(y / block) * gw + x / block];
+        let alpha = if peak > 0.0 { 0.7 * c.abs() / peak } else { 0.0 };
+        let tint = if c >= 0.0 { [0.0, 200.0, 0.0] } else { [230.0, 0.0, 0.0] };
+        let grey = window[y * tw + x];
+        image::Rgb(tint.map(|t| (grey * (1.0 - alpha) + t * alpha).round().clamp(0.0, 255.0) as u8))
+    })
+}
+
+// ============================================================================
+// Perceptual Hashing
+// ============================================================================
+
+/// Box-average resample to an arbitrary size: each output pixel averages the
+/// block of source pixels it covers (the non-integer counterpart of `downsample`)
+fn downsample_to(src: &[f64], sw: usize, sh: usize, nw: usize, nh: usize) -> Vec<f64> {
+    let mut result = vec![0.0; nw * nh];
+    for y in 0..nh {
+        let y0 = y * sh / nh;
+        let y1 = ((y + 1) * sh / nh).max(y0 + 1).min(sh);
+        for x in 0..nw {
+            let x0 = x * sw / nw;
+            let x1 = ((x + 1) * sw / nw).max(x0 + 1).min(sw);
+            let mut sum = 0.0;
+            for sy in y0..y1 {
+                sum += src[sy * sw + x0..sy * sw + x1].iter().sum::<f64>();
+            }
+            result[y * nw + x] = sum / ((y1 - y0) * (x1 - x0)) as f64;
+        }
+    }
+    result
+}
+
+/// Difference hash: 9x8 thumbnail, one bit per horizontally adjacent pair
+fn dhash_bits(src: &[f64], sw: usize, sh: usize) -> u64 {
+    let small = downsample_to(src, sw, sh, 9, 8);
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small[y * 9 + x] < small[y * 9 + x + 1] { hash |= 1; }
+        }
+    }
+    hash
+}
+
+/// DCT hash: 32x32 thumbnail, 2D DCT-II, one bit per low-frequency 8x8
+/// coefficient above the median (the DC term is excluded from the median)
+fn phash_bits(src: &[f64], sw: usize, sh: usize) -> u64 {
+    const N: usize = 32;
+    const K: usize = 8;
+    let small = downsample_to(src, sw, sh, N, N);
+    let cos: Vec<f64> = (0..K * N)
+        .map(|i| {
+            let (k, n) = (i / N, i % N);
+            (std::f64::consts::PI / N as f64 * (n as f64 + 0.5) * k as f64).cos()
+        })
+        .collect();
+
+    // Rows first, keeping only the K lowest frequencies, then columns
+    let mut rows = vec![0.0f64; N * K];
+    for y in 0..N {
+        for k in 0..K {
+            rows[y * K + k] = (0..N).map(|x| small[y * N + x] * cos[k * N + x]).sum();
+        }
+    }
+    let mut coeffs = [0.0f64; K * K];
+    for ky in 0..K {
+        for kx in 0..K {
+            coeffs[ky * K + kx] = (0..N).map(|y| rows[y * K + kx] * cos[ky * N + y]).sum();
+        }
+    }
+
+    let mut sorted: Vec<f64> = coeffs[1..].to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let median = sorted[sorted.len() / 2];
+    coeffs.iter().fold(0u64, |hash, &c| (hash << 1) | (c > median) as u64)
+}
+
+// ============================================================================
+// Denoising
+// ============================================================================
+
+/// Median filter over a `(2 * radius + 1)`-square window, replicating edge
+/// pixels. Generic so 8-bit tiling sources are filtered without widening.
+fn median_filter<T>(data: &[T], w: usize, h: usize, radius: usize) -> Vec<T>
+where
+    T: Copy + PartialOrd + Send + Sync,
+{
+    let mut out = data.to_vec();
+    out.par_chunks_mut(w.max(1)).enumerate().for_each(|(y, row)| {
+        let mut window = Vec::with_capacity((2 * radius + 1) * (2 * radius + 1));
+        for (x, out_px) in row.iter_mut().enumerate() {
+            window.clear();
+            for wy in y.saturating_sub(radius)..=(y + radius).min(h - 1) {
+                for wx in x.saturating_sub(radius)..=(x + radius).min(w - 1) {
+                    window.push(data[wy * w + wx]);
+                }
+            }
+            let mid = window.len() / 2;
+            *out_px = *window.select_nth_unstable_by(mid, |a, b| a.partial_cmp(b).unwrap()).1;
+        }
+    });
+    out
+}
+
+// ============================================================================
+// Gradient Magnitude (structure-only matching)
+// ============================================================================
+
+/// Largest Sobel magnitude on 0-255 input, used to keep gradient maps in 0-255
+const MAX_SOBEL_MAGNITUDE: f64 = 1020.0 * std::f64::consts::SQRT_2;
+
+/// Sobel gradient magnitude, replicating edge pixels and scaled back to 0-255
+/// so later options (`binary_threshold`, 8-bit tiling) see the usual range
+fn sobel_magnitude(data: &[f64], w: usize, h: usize) -> Vec<f64> {
+    let mut out = vec![0.0; data.len()];
+    if w == 0 || h == 0 { return out; }
+    out.par_chunks_mut(w).enumerate().for_each(|(y, row)| {
+        let (up, down) = (y.saturating_sub(1) * w, (y + 1).min(h - 1) * w);
+        let mid = y * w;
+        for (x, out_px) in row.iter_mut().enumerate() {
+            let (l, r) = (x.saturating_sub(1), (x + 1).min(w - 1));
+            let gx = (data[up + r] + 2.0 * data[mid + r] + data[down + r]) - (data[up + l] + 2.0 * data[mid + l] + data[down + l]);
+            let gy = (data[down + l] + 2.0 * data[down + x] + data[down + r]) - (data[up + l] + 2.0 * data[up + x] + data[up + r]);
+            *out_px = (gx * gx + gy * gy).sqrt() * (255.0 / MAX_SOBEL_MAGNITUDE);
+        }
+    });
+    out
+}
+
+// ============================================================================
+// Tone Preprocessing (applied per image to source and template alike)
+// ============================================================================
+
+#[derive(Clone, Copy)]
+enum Gamma {
+    Fixed(f64),
+    /// Gamma that maps the image's mean brightness to mid-grey
+    Auto,
+}
+
+/// Pointwise tone curve fitted to one image: optional min-max stretch to the
+/// full 0-255 range, then gamma correction
+struct ToneCurve {
+    offset: f64,
+    scale: f64,
+    gamma: f64,
+}
+
+impl ToneCurve {
+    fn from_stats((lo, hi, mean): (f64, f64, f64), stretch: bool, gamma: Option<Gamma>) -> Self {
+        let (offset, scale) = if stretch && hi > lo { (lo, 255.0 / (hi - lo)) } else { (0.0, 1.0) };
+        let gamma = match gamma {
+            Some(Gamma::Fixed(g)) => g,
+            Some(Gamma::Auto) => {
+                let m = ((mean - offset) * scale / 255.0).clamp(0.01, 0.99);
+                (0.5f64.ln() / m.ln()).clamp(0.2, 5.0)
+            }
+            None => 1.0,
+        };
+        Self { offset, scale, gamma }
+    }
+
+    fn fit(data: &[f64], stretch: bool, gamma: Option<Gamma>) -> Self {
+        let (lo, hi) = data.iter().fold((f64::MAX, f64::MIN), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+        let mean = data.iter().sum::<f64>() / data.len().max(1) as f64;
+        Self::from_stats((lo, hi, mean), stretch, gamma)
+    }
+
+    fn fit_gray(gray: &GrayImage, stretch: bool, gamma: Option<Gamma>) -> Self {
+        let mut hist = [0u64; 256];
+        for &v in gray.as_raw() { hist[v as usize] += 1; }
+        let lo = hist.iter().position(|&c| c > 0).unwrap_or(0) as f64;
+        let hi = hist.iter().rposition(|&c| c > 0).unwrap_or(0) as f64;
+        let n = gray.as_raw().len().max(1) as f64;
+        let mean = hist.iter().enumerate().map(|(i, &c)| i as f64 * c as f64).sum::<f64>() / n;
+        Self::from_stats((lo, hi, mean), stretch, gamma)
+    }
+
+    #[inline]
+    fn apply(&self, v: f64) -> f64 {
+        let v = ((v - self.offset) * self.scale).clamp(0.0, 255.0);
+        if self.gamma == 1.0 { v } else { 255.0 * (v / 255.0).powf(self.gamma) }
+    }
+}
+
+// ============================================================================
+// Decoded Image Cache
+// ============================================================================
+
+const DEFAULT_IMAGE_CACHE_SIZE: usize = 8;
+
+/// Identity of a file on disk: a rewrite changes the mtime or the length.
+/// `upright` tells apart decodes with and without the EXIF orientation.
+#[derive(PartialEq)]
+struct CacheKey {
+    path: String,
+    modified: SystemTime,
+    len: u64,
+    upright: bool,
+}
+
+/// Least-recently-used cache of decoded 8-bit grayscale images, most recent last
+struct ImageCache {
+    capacity: usize,
+    entries: VecDeque<(CacheKey, Arc<GrayImage>)>,
+    hits: u64,
+    misses: u64,
+}
+
+impl ImageCache {
+    fn get(&mut self, key: &CacheKey) -> Option<Arc<GrayImage>> {
+        let Some(i) = self.entries.iter().position(|(k, _)| k == key) else {
+            self.misses += 1;
+            return None;
+        };
+        self.hits += 1;
+        let entry = self.entries.remove(i)?;
+        let gray = entry.1.clone();
+        self.entries.push_back(entry);
+        Some(gray)
+    }
+
+    fn insert(&mut self, key: CacheKey, gray: Arc<GrayImage>) {
+        // Drop stale versions of the same file along with the overflow
+        self.entries.retain(|(k, _)| k.path != key.path || k.upright != key.upright);
+        if self.capacity == 0 { return; }
+        self.entries.push_back((key, gray));
+        while self.entries.len() > self.capacity { self.entries.pop_front(); }
+    }
+}
+
+static IMAGE_CACHE: Mutex<ImageCache> = Mutex::new(ImageCache {
+    capacity: DEFAULT_IMAGE_CACHE_SIZE, entries: VecDeque::new(), hits: 0, misses: 0,
+});
+
+fn lock_cache() -> std::sync::MutexGuard<'static, ImageCache> {
+    // The cache holds no invariants a panicking holder could break
+    IMAGE_CACHE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// `None` when the file can't be stat'ed; decoding then reports the error
+fn cache_key(path: &str, upright: bool) -> Option<CacheKey> {
+    let meta = std::fs::metadata(path).ok()?;
+    Some(CacheKey { path: path.to_string(), modified: meta.modified().ok()?, len: meta.len(), upright })
+}
+
+// ============================================================================
+// Result Cache (opt-in, keyed on input hashes)
+// ============================================================================
+
+/// Least-recently-used cache of search results, most recent last. Disabled
+/// (capacity 0) until `set_result_cache` is called.
+struct ResultCache {
+    capacity: usize,
+    /// Entries older than this are dropped instead of returned
+    ttl: Option<Duration>,
+    entries: VecDeque<(u64, Instant, Vec<MatchResult>)>,
+    hits: u64,
+    misses: u64,
+}
+
+impl ResultCache {
+    fn get(&mut self, key: u64) -> Option<Vec<MatchResult>> {
+        let now = Instant::now();
+        if let Some(ttl) = self.ttl {
+            self.entries.retain(|(_, stored, _)| now.duration_since(*stored) <= ttl);
+        }
+        let Some(i) = self.entries.iter().position(|(k, _, _)| *k == key) else {
+            self.misses += 1;
+            return None;
+        };
+        self.hits += 1;
+        let entry = self.entries.remove(i)?;
+        let results = entry.2.clone();
+        self.entries.push_back(entry);
+        Some(results)
+    }
+
+    fn insert(&mut self, key: u64, results: Vec<MatchResult>) {
+        if self.capacity == 0 || self.ttl == Some(Duration::ZERO) { return; }
+        self.entries.retain(|(k, _, _)| *k != key);
+        self.entries.push_back((key, Instant::now(), results));
+        while self.entries.len() > self.capacity { self.entries.pop_front(); }
+    }
+}
+
+static RESULT_CACHE: Mutex<ResultCache> = Mutex::new(ResultCache {
+    capacity: 0, ttl: None, entries: VecDeque::new(), hits: 0, misses: 0,
+});
+
+fn lock_result_cache() -> std::sync::MutexGuard<'static, ResultCache> {
+    RESULT_CACHE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Hash of a search call: the function, its inputs, its numeric arguments
+/// and the `repr()` of every option. Files are identified like in the image
+/// cache (path, mtime and length), bytes by their content. `None` when the
+/// cache is disabled, a file can't be stat'ed, an option's repr is
+/// abbreviated (large numpy arrays) or `timeout_ms` or `debug_dir` is set,
+/// so nothing is cached.
+fn result_key(call: &str, inputs: &[ImageInput], args: &[f64], options: Option<&PyDict>) -> PyResult<Option<u64>> {
+    if lock_result_cache().capacity == 0 { return Ok(None); }
+    let mut hasher = DefaultHasher::new();
+    call.hash(&mut hasher);
+    for input in inputs {
+        match input {
+            ImageInput::Path(path) => {
+                let Some(key) = cache_key(path, true) else { return Ok(None) };
+                (0u8, key.path, key.modified, key.len).hash(&mut hasher);
+            }
+            ImageInput::Bytes(data) => (1u8, data).hash(&mut hasher),
+        }
+    }
+    for arg in args { arg.to_bits().hash(&mut hasher); }
+    let mut items: Vec<(String, String)> = options.into_iter().flatten()
+        .map(|(k, v)| Ok((k.extract()?, v.repr()?.to_str()?.to_string())))
+        .collect::<PyResult<_>>()?;
+    if items.iter().any(|(_, repr)| repr.contains("...")) { return Ok(None); }
+    // Whether a search times out depends on the machine's load
+    if items.iter().any(|(key, _)| key == "timeout_ms") { return Ok(None); }
+    // Debug images are only written by searches that run
+    if items.iter().any(|(key, _)| key == "debug_dir") { return Ok(None); }
+    items.sort();
+    items.hash(&mut hasher);
+    Ok(Some(hasher.finish()))
+}
+
+/// Results for `key` from the cache, or computed by `search` and stored.
+/// Errors are never cached.
+fn cached_results(key: Option<u64>, search: impl FnOnce() -> PyResult<Vec<MatchResult>>) -> PyResult<Vec<MatchResult>> {
+    let Some(key) = key else { return search() };
+    if let Some(results) = lock_result_cache().get(key) {
+        log::debug!("result cache hit");
+        return Ok(results);
+    }
+    let results = search()?;
+    lock_result_cache().insert(key, results.clone());
+    Ok(results)
+}
+
+// ============================================================================
+// AVIF / HEIF Decoding (optional `avif` and `heif` features)
+// ============================================================================
+
+/// Image formats stored in an ISO-BMFF container, which the image crate's
+/// default build can't decode
+#[derive(Clone, Copy)]
+enum IsoImage {
+    Avif,
+    Heif,
+}
+
+impl IsoImage {
+    fn name(self) -> &'static str {
+        match self {
+            IsoImage::Avif => "avif",
+            IsoImage::Heif => "heif",
+        }
+    }
+}
+
+/// Detect AVIF/HEIF data from the major brand of its leading `ftyp` box
+fn iso_image_kind(data: &[u8]) -> Option<IsoImage> {
+    if data.len() < 12 || &data[4..8] != b"ftyp" { return None; }
+    match &data[8..12] {
+        b"avif" | b"avis" => Some(IsoImage::Avif),
+        b"heic" | b"heix" | b"hevc" | b"hevx" | b"heim" | b"heis" | b"mif1" | b"msf1" => Some(IsoImage::Heif),
+        _ => None,
+    }
+}
+
+/// Decode AVIF/HEIF data with whichever decoder is compiled in (the image
+/// crate's dav1d decoder for AVIF, libheif for both); `None` for other formats
+fn decode_iso_image(data: &[u8]) -> Option<PyResult<DynamicImage>> {
+    let kind = iso_image_kind(data)?;
+    Some(match kind {
+        #[cfg(feature = "avif")]
+        IsoImage::Avif => image::load_from_memory_with_format(data, image::ImageFormat::Avif)
+            .map_err(|e| DecodeError::new_err(format!("Failed to decode image: {}", e))),
+        #[cfg(feature = "heif")]
+        _ => decode_heif(data),
+        #[cfg(not(feature = "heif"))]
+        _ => {
+            let (what, feature) = match kind {
+                IsoImage::Avif => ("AVIF", "avif"),
+                IsoImage::Heif => ("HEIC/HEIF", "heif"),
+            };
+            Err(DecodeError::new_err(format!(
+                "Failed to decode image: {} support is not available, rustmatch was built without the '{}' feature", what, feature
+            )))
+        }
+    })
+}
+
+#[cfg(feature = "heif")]
+fn decode_heif(data: &[u8]) -> PyResult<DynamicImage> {
+    use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+    let failed = |e: libheif_rs::HeifError| DecodeError::new_err(format!("Failed to decode image: {}", e));
+    let ctx = HeifContext::read_from_bytes(data).map_err(failed)?;
+    let handle = ctx.primary_image_handle().map_err(failed)?;
+    let image = LibHeif::new().decode(&handle, ColorSpace::Rgb(RgbChroma::Rgb), None).map_err(failed)?;
+    let plane = image.planes().interleaved
+        .ok_or_else(|| DecodeError::new_err("Failed to decode image: libheif returned no RGB plane"))?;
+    // Rows are padded to `stride` bytes
+    let row = plane.width as usize * 3;
+    let pixels: Vec<u8> = plane.data.chunks(plane.stride).take(plane.height as usize)
+        .flat_map(|r| r[..row].iter().copied())
+        .collect();
+    image::RgbImage::from_raw(plane.width, plane.height, pixels)
+        .map(DynamicImage::ImageRgb8)
+        .ok_or_else(|| DecodeError::new_err("Failed to decode image: truncated HEIF plane"))
+}
+
+// ============================================================================
+// Streamed Decoding (PNG rows, TIFF strips and tiles)
+// ============================================================================
+
+/// Convert interleaved samples to 8-bit luma exactly like
+/// `DynamicImage::to_luma8`: Rec. 709 weights at the source depth, alpha
+/// ignored, then 16-bit rounded down to 8-bit
+fn luma_row<T: Copy + Into<u32>>(samples: &[T], channels: usize, sixteen: bool, out: &mut [u8]) {
+    for (px, o) in samples.chunks_exact(channels).zip(out.iter_mut()) {
+        let l = if channels >= 3 {
+            (2126 * px[0].into() + 7152 * px[1].into() + 722 * px[2].into()) / 10000
+        } else {
+            px[0].into()
+        };
+        *o = if sixteen { (l + 128) / 257 } else { l } as u8;
+    }
+}
+
+/// Decode a PNG or TIFF file to 8-bit grayscale one row, strip or tile at a
+/// time, so peak memory is the grayscale output plus one chunk instead of the
+/// whole full-colour image. `None` for other formats, layouts this path
+/// doesn't handle (interlaced PNG; palette, CMYK, planar or float TIFF) and
+/// any error, which the regular decoder then reports.
+fn stream_gray_from_path(path: &str) -> Option<GrayImage> {
+    let (w, h, mut rows) = stream_rows(path)?;
+    let mut gray = vec![0u8; w * h];
+    for out in gray.chunks_exact_mut(w) {
+        rows.read_row(w, out)?;
+    }
+    GrayImage::from_raw(w as u32, h as u32, gray)
+}
+
+/// Width, height and row reader of a PNG or TIFF file `stream_gray_from_path`
+/// can decode incrementally
+fn stream_rows(path: &str) -> Option<(usize, usize, RowSource)> {
+    let mut file = BufReader::new(File::open(path).ok()?);
+    let mut magic = [0u8; 4];
+    file.read_exact(&mut magic).ok()?;
+    file.rewind().ok()?;
+    match &magic {
+        [0x89, b'P', b'N', b'G'] => {
+            let mut decoder = png::Decoder::new(file);
+            // Same expansion as the image crate: palette to RGB(A), low bit depths to 8-bit
+            decoder.set_transformations(png::Transformations::EXPAND);
+            let reader = decoder.read_info().ok()?;
+            if reader.info().interlaced { return None; }
+            let (w, h) = reader.info().size();
+            let (color, depth) = reader.output_color_type();
+            let (channels, sixteen) = (color.samples(), depth == png::BitDepth::Sixteen);
+            Some((w as usize, h as usize, RowSource::Png { reader: Box::new(reader), channels, sixteen }))
+        }
+        b"II*\0" | b"MM\0*" => {
+            let mut decoder = tiff::decoder::Decoder::new(file).ok()?;
+            let layout = TiffLayout::read(&mut decoder)?;
+            let (w, h) = (layout.width, layout.height);
+            Some((w, h, RowSource::Tiff { decoder: Box::new(decoder), layout, band: Vec::new(), band_row: 0, next_chunk_row: 0 }))
+        }
+        _ => None,
+    }
+}
+
+/// 8-bit grayscale rows of an image, read top to bottom
+enum RowSource {
+    Png { reader: Box<png::Reader<BufReader<File>>>, channels: usize, sixteen: bool },
+    /// TIFF strips or tiles, decoded one chunk row at a time into `band`
+    Tiff {
+        decoder: Box<tiff::decoder::Decoder<BufReader<File>>>,
+        layout: TiffLayout,
+        band: Vec<u8>,
+        /// Rows of `band` already read
+        band_row: usize,
+        next_chunk_row: usize,
+    },
+    /// Image decoded whole
+    Decoded { gray: GrayImage, next: usize },
+}
+
+impl RowSource {
+    /// Fill `out` with the next row of the `width`-pixel image; `None` past
+    /// the last row or on corrupt data
+    fn read_row(&mut self, width: usize, out: &mut [u8]) -> Option<()> {
+        match self {
+            RowSource::Png { reader, channels, sixteen } => {
+                let row = reader.next_row().ok()??;
+                if *sixteen {
+                    let samples: Vec<u16> = row.data().chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]])).collect();
+                    luma_row(&samples, *channels, true, out);
+                } else {
+                    luma_row(row.data(), *channels, false, out);
+                }
+            }
+            RowSource::Tiff { decoder, layout, band, band_row, next_chunk_row } => {
+                if *band_row * width >= band.len() {
+                    let y0 = *next_chunk_row * layout.chunk.1;
+                    if y0 >= layout.height { return None; }
+                    band.resize(layout.chunk.1.min(layout.height - y0) * width, 0);
+                    tiff_chunk_row(decoder, layout, *next_chunk_row, band)?;
+                    *next_chunk_row += 1;
+                    *band_row = 0;
+                }
+                out.copy_from_slice(&band[*band_row * width..][..width]);
+                *band_row += 1;
+            }
+            RowSource::Decoded { gray, next } => {
+                out.copy_from_slice(gray.as_raw().get(*next * width..(*next + 1) * width)?);
+                *next += 1;
+            }
+        }
+        Some(())
+    }
+}
+
+/// Strip or tile layout of a TIFF page, in a form the streamed decoder handles
+struct TiffLayout {
+    width: usize,
+    height: usize,
+    /// Size of a strip or tile
+    chunk: (usize, usize),
+    /// Chunks per chunk row
+    across: usize,
+    channels: usize,
+    sixteen: bool,
+}
+
+impl TiffLayout {
+    /// Layout of the decoder's current page; `None` for colour types and
+    /// planar files left to the full decoder
+    fn read<R: Read + Seek>(decoder: &mut tiff::decoder::Decoder<R>) -> Option<Self> {
+        use tiff::decoder::ChunkType;
+        use tiff::ColorType;
+        let (w, h) = decoder.dimensions().ok()?;
+        let (channels, sixteen) = match decoder.colortype().ok()? {
+            ColorType::Gray(8) => (1, false),
+            ColorType::Gray(16) => (1, true),
+            ColorType::GrayA(8) => (2, false),
+            ColorType::GrayA(16) => (2, true),
+            ColorType::RGB(8) => (3, false),
+            ColorType::RGB(16) => (3, true),
+            ColorType::RGBA(8) => (4, false),
+            ColorType::RGBA(16) => (4, true),
+            _ => return None,
+        };
+        let (cw, ch) = decoder.chunk_dimensions();
+        if w == 0 || h == 0 || cw == 0 || ch == 0 { return None; }
+        let (across, down) = ((w + cw - 1) / cw, (h + ch - 1) / ch);
+        let count = match decoder.get_chunk_type() {
+            ChunkType::Strip => decoder.strip_count().ok()?,
+            ChunkType::Tile => decoder.tile_count().ok()?,
+        };
+        // Planar files store one chunk per channel; leave them to the full decoder
+        if count != across * down { return None; }
+        Some(Self {
+            width: w as usize, height: h as usize, chunk: (cw as usize, ch as usize), across: across as usize, channels, sixteen,
+        })
+    }
+}
+
+/// Decode chunk row `row` of the current TIFF page into `out`, which holds
+/// its rows of 8-bit luma (fewer than a chunk's height at the bottom edge)
+fn tiff_chunk_row<R: Read + Seek>(
+    decoder: &mut tiff::decoder::Decoder<R>, layout: &TiffLayout, row: usize, out: &mut [u8],
+) -> Option<()> {
+    use tiff::decoder::DecodingResult;
+    let (w, channels, sixteen) = (layout.width, layout.channels, layout.sixteen);
+    for col in 0..layout.across {
+        let i = (row * layout.across + col) as u32;
+        let (dw, dh) = decoder.chunk_data_dimensions(i);
+        let (x0, dw, dh) = (col * layout.chunk.0, dw as usize, dh as usize);
+        if x0 + dw > w || dh * w > out.len() { return None; }
+        let chunk = decoder.read_chunk(i).ok()?;
+        for r in 0..dh {
+            let out = &mut out[r * w + x0..][..dw];
+            let span = r * dw * channels..(r + 1) * dw * channels;
+            match &chunk {
+                DecodingResult::U8(data) if !sixteen => luma_row(data.get(span)?, channels, false, out),
+                DecodingResult::U16(data) if sixteen => luma_row(data.get(span)?, channels, true, out),
+                _ => return None,
+            }
+        }
+    }
+    Some(())
+}
+
+/// Grayscale pixels of the decoder's current TIFF page, chunk by chunk
+fn tiff_page_gray<R: Read + Seek>(decoder: &mut tiff::decoder::Decoder<R>) -> Option<GrayImage> {
+    let layout = TiffLayout::read(decoder)?;
+    let mut gray = vec![0u8; layout.width * layout.height];
+    for (row, band) in gray.chunks_mut(layout.chunk.1 * layout.width).enumerate() {
+        tiff_chunk_row(decoder, &layout, row, band)?;
+    }
+    GrayImage::from_raw(layout.width as u32, layout.height as u32, gray)
+}
+
+fn is_tiff_file(path: &str) -> bool {
+    let mut magic = [0u8; 4];
+    File::open(path).and_then(|mut f| f.read_exact(&mut magic)).is_ok() && matches!(&magic, b"II*\0" | b"MM\0*")
+}
+
+/// Decode every page of a TIFF file in turn, handing each to `f` with its
+/// index; only one page is held in memory at a time
+fn for_each_tiff_page(path: &str, mut f: impl FnMut(usize, GrayImage) -> PyResult<()>) -> PyResult<()> {
+    let file = File::open(path).map_err(|e| PyIOError::new_err(format!("Failed to load image '{}': {}", path, e)))?;
+    let corrupt = |e: tiff::TiffError| DecodeError::new_err(format!("Failed to load image '{}': {}", path, e));
+    let mut decoder = tiff::decoder::Decoder::new(BufReader::new(file)).map_err(corrupt)?;
+    let mut page = 0;
+    loop {
+        let gray = tiff_page_gray(&mut decoder).ok_or_else(|| DecodeError::new_err(format!(
+            "Failed to load image '{}': page {} is corrupt or uses an unsupported layout", path, page
+        )))?;
+        f(page, gray)?;
+        if !decoder.more_images() { return Ok(()); }
+        decoder.next_image().map_err(corrupt)?;
+        page += 1;
+    }
+}
+
+// ============================================================================
+// Colour Spaces (`color_space` option)
+// ============================================================================
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum ColorSpace {
+    Hsv,
+    Lab,
+}
+
+impl ColorSpace {
+    fn channel_names(self) -> [char; 3] {
+        match self {
+            ColorSpace::Hsv => ['h', 's', 'v'],
+            ColorSpace::Lab => ['l', 'a', 'b'],
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            ColorSpace::Hsv => "hsv",
+            ColorSpace::Lab => "lab",
+        }
+    }
+}
+
+/// sRGB to linear light, per 8-bit channel value
+fn srgb_linear_table() -> [f64; 256] {
+    let mut table = [0.0; 256];
+    for (v, out) in table.iter_mut().enumerate() {
+        let c = v as f64 / 255.0;
+        *out = if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) };
+    }
+    table
+}
+
+/// H, S, V of an RGB pixel, each scaled to 0-255. Hue is circular: pure red
+/// sits at 0 and reds shading towards magenta wrap round to near 255.
+fn rgb_to_hsv([r, g, b]: [u8; 3]) -> [f64; 3] {
+    let (r, g, b) = (r as f64, g as f64, b as f64);
+    let max = r.max(g).max(b);
+    let delta = max - r.min(g).min(b);
+    let sextant = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / delta + 2.0
+    } else {
+        (r - g) / delta + 4.0
+    };
+    let saturation = if max > 0.0 { delta / max * 255.0 } else { 0.0 };
+    [sextant * 255.0 / 6.0, saturation, max]
+}
+
+/// CIE L*, a*, b* (D65) of an sRGB pixel: L scaled from 0-100 to 0-255,
+/// a and b offset by 128 so neutral grey sits at 128
+fn rgb_to_lab(rgb: [u8; 3], linear: &[f64; 256]) -> [f64; 3] {
+    let [r, g, b] = rgb.map(|c| linear[c as usize]);
+    let f = |t: f64| if t > 216.0 / 24389.0 { t.cbrt() } else { (24389.0 / 27.0 * t + 16.0) / 116.0 };
+    let fx = f((0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047);
+    let fy = f(0.2126 * r + 0.7152 * g + 0.0722 * b);
+    let fz = f((0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883);
+    [
+        ((116.0 * fy - 16.0) * 2.55).clamp(0.0, 255.0),
+        (500.0 * (fx - fy) + 128.0).clamp(0.0, 255.0),
+        (200.0 * (fy - fz) + 128.0).clamp(0.0, 255.0),
+    ]
+}
+
+/// Weighted mix of colour-space channels matched in place of luma
+#[derive(Clone, Copy, Debug)]
+struct ColorProjection {
+    space: ColorSpace,
+    /// Non-negative weight per channel, in `channel_names` order
+    weights: [f64; 3],
+}
+
+impl ColorProjection {
+    /// Convert every pixel and mix its channels; the weights are normalized
+    /// so the result stays in 0-255 like luma
+    fn project(&self, img: &DynamicImage) -> GrayImageData {
+        let rgb = img.to_rgb8();
+        let (w, h) = rgb.dimensions();
+        let total: f64 = self.weights.iter().sum();
+        let weights = self.weights.map(|w| w / total);
+        let linear = srgb_linear_table();
+        let data = rgb.as_raw().par_chunks_exact(3)
+            .map(|p| {
+                let pixel = [p[0], p[1], p[2]];
+                let channels = match self.space {
+                    ColorSpace::Hsv => rgb_to_hsv(pixel),
+                    ColorSpace::Lab => rgb_to_lab(pixel, &linear),
+                };
+                channels.iter().zip(weights).map(|(c, w)| c * w).sum()
+            })
+            .collect();
+        GrayImageData { data, width: w as usize, height: h as usize }
+    }
+}
+
+// ============================================================================
+// Colour Prefilter (`color_prefilter` option)
+// ============================================================================
+
+/// RGB cube split into 4 levels per channel
+const COLOR_BINS: usize = 64;
+
+/// Tolerance of `color_prefilter=True`: absorbs anti-aliased edges and
+/// compression noise that push some pixels into a neighbouring bin
+const DEFAULT_COLOR_PREFILTER_TOLERANCE: f64 = 0.1;
+
+#[inline(always)]
+fn color_bin(p: &[u8]) -> usize {
+    (p[0] as usize >> 6) << 4 | (p[1] as usize >> 6) << 2 | p[2] as usize >> 6
+}
+
+/// Pixel count per coarse RGB bin
+#[derive(Clone, Debug)]
+struct ColorHistogram {
+    counts: [u32; COLOR_BINS],
+    total: u32,
+}
+
+impl ColorHistogram {
+    fn new(img: &DynamicImage) -> Self {
+        let mut counts = [0u32; COLOR_BINS];
+        let rgb = img.to_rgb8();
+        for p in rgb.as_raw().chunks_exact(3) { counts[color_bin(p)] += 1; }
+        Self { counts, total: rgb.width() * rgb.height() }
+    }
+}
+
+/// Window positions `(x1, y1, x2, y2)` that may contain the template by
+/// colour. The source is cut into blocks half the template's smaller side;
+/// windows starting in a block are skipped when the blocks they can reach
+/// together hold fewer of the template's colours (histogram intersection)
+/// than `1 - tolerance` of its pixels. Those blocks contain every such
+/// window, so a window with the template's exact colours is never skipped.
+fn color_prefilter_regions(
+    source: &image::RgbImage, tpl: &ColorHistogram, tw: usize, th: usize, tolerance: f64,
+) -> Vec<(usize, usize, usize, usize)> {
+    let (sw, sh) = (source.width() as usize, source.height() as usize);
+    if tw > sw || th > sh { return vec![]; }
+    let block = (tw.min(th) / 2).max(4);
+    let (gw, gh) = ((sw + block - 1) / block, (sh + block - 1) / block);
+    // Per-bin summed-area table over the block grid
+    let stride = (gw + 1) * COLOR_BINS;
+    let mut sums = vec![0u32; stride * (gh + 1)];
+    for (y, row) in source.as_raw().chunks_exact(sw * 3).enumerate() {
+        for (x, p) in row.chunks_exact(3).enumerate() {
+            sums[(y / block + 1) * stride + (x / block + 1) * COLOR_BINS + color_bin(p)] += 1;
+        }
+    }
+    for by in 1..=gh {
+        for bx in 1..=gw {
+            for c in 0..COLOR_BINS {
+                let i = by * stride + bx * COLOR_BINS + c;
+                sums[i] = sums[i] + sums[i - COLOR_BINS] + sums[i - stride] - sums[i - stride - COLOR_BINS];
+            }
+        }
+    }
+    let needed = (1.0 - tolerance) * tpl.total as f64;
+    let (end_x, end_y) = (sw - tw, sh - th);
+    let possible = |bx: usize, by: usize| {
+        let (bx2, by2) = (((bx + 1) * block + tw - 2) / block + 1, ((by + 1) * block + th - 2) / block + 1);
+        let (bx2, by2) = (bx2.min(gw), by2.min(gh));
+        let shared: u32 = (0..COLOR_BINS)
+            .map(|c| {
+                let at = |x: usize, y: usize| sums[y * stride + x * COLOR_BINS + c];
+                (at(bx2, by2) + at(bx, by) - at(bx, by2) - at(bx2, by)).min(tpl.counts[c])
+            })
+            .sum();
+        shared as f64 >= needed
+    };
+
+    // Runs of possible blocks per row, stacked with identical runs of the row above
+    let (cols, rows) = (end_x / block + 1, end_y / block + 1);
+    let mut regions: Vec<(usize, usize, usize, usize)> = Vec::new();
+    let mut open: HashMap<(usize, usize), usize> = HashMap::new();
+    let mut kept = 0;
+    for by in 0..rows {
+        let (y1, y2) = (by * block, ((by + 1) * block - 1).min(end_y));
+        let mut next = HashMap::new();
+        let mut bx = 0;
+        while bx < cols {
+            if !possible(bx, by) { bx += 1; continue; }
+            let start = bx;
+            while bx < cols && possible(bx, by) { bx += 1; }
+            kept += bx - start;
+            let (x1, x2) = (start * block, (bx * block - 1).min(end_x));
+            let index = match open.get(&(x1, x2)) {
+                Some(&i) => { regions[i].3 = y2; i }
+                None => { regions.push((x1, y1, x2, y2)); regions.len() - 1 }
+            };
+            next.insert((x1, x2), index);
+        }
+        open = next;
+    }
+    log::debug!("color_prefilter kept {} of {} blocks in {} regions", kept, cols * rows, regions.len());
+    regions
+}
+
+// ============================================================================
+// Image Loading Helpers
+// ============================================================================
+
+/// EXIF orientation to apply to an image decoded from `data`; `None` when
+/// untagged or the image is kept as stored (`exif_orientation=False`)
+fn load_orientation(data: &[u8], upright: bool) -> Option<u16> {
+    if !upright { return None; }
+    exif_orientation(&mut Cursor::new(data))
+}
+
+/// `load_orientation` of an image file, read from its metadata alone
+fn file_orientation(path: &str, upright: bool) -> Option<u16> {
+    if !upright { return None; }
+    exif_orientation(&mut File::open(path).ok()?)
+}
+
+/// Turn a decoded image upright for its EXIF orientation (1-8), so match
+/// coordinates refer to the image as viewers display it
+fn apply_orientation(img: DynamicImage, orientation: Option<u16>) -> DynamicImage {
+    match orientation {
+        Some(2) => img.fliph(),
+        Some(3) => img.rotate180(),
+        Some(4) => img.flipv(),
+        // Transpose and transverse: mirrored across a diagonal
+        Some(5) => img.rotate90().fliph(),
+        Some(6) => img.rotate90(),
+        Some(7) => img.rotate270().fliph(),
+        Some(8) => img.rotate270(),
+        _ => img,
+    }
+}
+
+fn load_dynamic_from_path(path: &str, upright: bool) -> PyResult<DynamicImage> {
+    let mut head = [0u8; 12];
+    let is_iso = File::open(path).and_then(|mut f| f.read_exact(&mut head)).is_ok() && iso_image_kind(&head).is_some();
+    if is_iso {
+        let data = std::fs::read(path).map_err(|e| PyIOError::new_err(format!("Failed to load image '{}': {}", path, e)))?;
+        if let Some(decoded) = decode_iso_image(&data) { return decoded; }
+    }
+    let img = image::open(path).map_err(|e| load_error(path, e))?;
+    Ok(apply_orientation(img, file_orientation(path, upright)))
+}
+
+/// Grayscale pixels of an image file, from the decoded image cache while the
+/// file is unchanged
+fn load_image_from_path(path: &str, upright: bool) -> PyResult<GrayImageData> {
+    let key = cache_key(path, upright);
+    if let Some(gray) = key.as_ref().and_then(|key| lock_cache().get(key)) {
+        return Ok(GrayImageData::from_gray_image(&gray));
+    }
+    let gray = Arc::new(load_gray_from_path(path, upright)?);
+    let data = GrayImageData::from_gray_image(&gray);
+    if let Some(key) = key { lock_cache().insert(key, gray); }
+    Ok(data)
+}
+
+/// Image argument accepted either as a file path or as encoded bytes
+#[derive(FromPyObject)]
+enum ImageInput<'a> {
+    Bytes(&'a [u8]),
+    Path(&'a str),
+}
+
+impl ImageInput<'_> {
+    fn load(&self) -> PyResult<GrayImageData> {
+        self.load_as(true)
+    }
+
+    /// `load`, turned upright by the EXIF orientation only when `upright`
+    fn load_as(&self, upright: bool) -> PyResult<GrayImageData> {
+        match self {
+            ImageInput::Bytes(data) => load_image_from_bytes(data, upright),
+            ImageInput::Path(path) => load_image_from_path(path, upright),
+        }
+    }
+
+    /// Decode without the grayscale conversion, for the image utilities
+    fn load_dynamic(&self) -> PyResult<DynamicImage> {
+        self.load_dynamic_as(true)
+    }
+
+    fn load_dynamic_as(&self, upright: bool) -> PyResult<DynamicImage> {
+        match self {
+            ImageInput::Bytes(data) => load_dynamic_from_bytes(data, upright),
+            ImageInput::Path(path) => load_dynamic_from_path(path, upright),
+        }
+    }
+}
+
+/// Decode straight to 8-bit grayscale, keeping 1 byte per pixel for tiling;
+/// PNG and TIFF files are streamed so the full-colour image never exists
+fn load_gray_from_path(path: &str, upright: bool) -> PyResult<GrayImage> {
+    if let Some(gray) = stream_gray_from_path(path) {
+        return Ok(apply_orientation(DynamicImage::ImageLuma8(gray), file_orientation(path, upright)).into_luma8());
+    }
+    Ok(load_dynamic_from_path(path, upright)?.into_luma8())
+}
+
+fn check_tile_size(tile_size: usize, tw: usize, th: usize) -> PyResult<()> {
+    if tile_size < tw || tile_size < th {
+        return Err(InvalidDimensionsError::new_err(format!(
+            "tile_size ({}) must be at least the template size ({}x{})", tile_size, tw, th
+        )));
+    }
+    Ok(())
+}
+
+/// Options that depend on the image edges, which tile edges are not
+fn check_tiled_options(opts: &MatchOptions) -> PyResult<()> {
+    if opts.partial_border || opts.wrap {
+        return Err(PyValueError::new_err("partial_border and wrap are not supported by the tiled functions"));
+    }
+    if opts.hint.is_some() || opts.flip.is_some() || opts.invert || opts.border_margin > 0 || opts.path.is_some() {
+        return Err(PyValueError::new_err("hint, flip, invert, border_margin and path are not supported by the tiled functions"));
+    }
+    if opts.timeout.is_some() || opts.debug_dir.is_some() {
+        return Err(PyValueError::new_err("timeout_ms and debug_dir are not supported by the tiled functions"));
+    }
+    Ok(())
+}
+
+fn load_dynamic_from_bytes(data: &[u8], upright: bool) -> PyResult<DynamicImage> {
+    if let Some(decoded) = decode_iso_image(data) { return decoded; }
+    let img = image::load_from_memory(data)
+        .map_err(|e| DecodeError::new_err(format!("Failed to decode image: {}", e)))?;
+    Ok(apply_orientation(img, load_orientation(data, upright)))
+}
+
+fn load_image_from_bytes(data: &[u8], upright: bool) -> PyResult<GrayImageData> {
+    Ok(GrayImageData::from_dynamic(&load_dynamic_from_bytes(data, upright)?))
+}
+
+/// Encode an image as PNG bytes
+fn encode_png(img: &DynamicImage) -> PyResult<Vec<u8>> {
+    let mut buf = Vec::new();
+    img.write_to(&mut Cursor::new(&mut buf), image::ImageOutputFormat::Png)
+        .map_err(|e| PyValueError::new_err(format!("Failed to encode PNG: {}", e)))?;
+    Ok(buf)
+}
+
+// ============================================================================
+// EXIF Metadata
+// ============================================================================
+
+/// Orientation tag (0x0112) from the first IFD of the TIFF-structured EXIF
+/// block starting at `base`
+fn tiff_orientation<R: Read + Seek>(r: &mut R, base: u64) -> Option<u16> {
+    let mut head = [0u8; 8];
+    r.seek(SeekFrom::Start(base)).ok()?;
+    r.read_exact(&mut head).ok()?;
+    let le = match &head[0..2] {
+        b"II" => true,
+        b"MM" => false,
+        _ => return None,
+    };
+    let u16_of = |b: &[u8]| if le { u16::from_le_bytes([b[0], b[1]]) } else { u16::from_be_bytes([b[0], b[1]]) };
+    let ifd = if le { u32::from_le_bytes([head[4], head[5], head[6], head[7]]) } else { u32::from_be_bytes([head[4], head[5], head[6], head[7]]) };
+    r.seek(SeekFrom::Start(base + ifd as u64)).ok()?;
+    let mut count = [0u8; 2];
+    r.read_exact(&mut count).ok()?;
+    let mut entry = [0u8; 12];
+    for _ in 0..u16_of(&count) {
+        r.read_exact(&mut entry).ok()?;
+        if u16_of(&entry[0..2]) == 0x0112 {
+            return Some(u16_of(&entry[8..10])).filter(|v| (1..=8).contains(v));
+        }
+    }
+    None
+}
+
+/// EXIF orientation (1-8) of an encoded JPEG, PNG, TIFF or WebP image, if
+/// tagged. Only the metadata is read: pixel data is skipped by seeking, so
+/// the tag is found without reading the image whole, wherever it sits.
+fn exif_orientation<R: Read + Seek>(r: &mut R) -> Option<u16> {
+    let mut head = [0u8; 12];
+    r.read_exact(&mut head).ok()?;
+    // EXIF blocks in PNG and WebP may or may not keep the JPEG "Exif\0\0" header
+    let block = |r: &mut R| -> Option<u16> {
+        let base = r.stream_position().ok()?;
+        let mut prefix = [0u8; 6];
+        r.read_exact(&mut prefix).ok()?;
+        tiff_orientation(r, if &prefix == b"Exif\0\0" { base + 6 } else { base })
+    };
+    if head.starts_with(&[0xFF, 0xD8]) {
+        // JPEG: walk marker segments up to the start of scan, looking for APP1
+        let mut pos = 2;
+        loop {
+            let mut marker = [0u8; 4];
+            r.seek(SeekFrom::Start(pos)).ok()?;
+            r.read_exact(&mut marker).ok()?;
+            if marker[0] != 0xFF || marker[1] == 0xDA || marker[1] == 0xD9 { return None; }
+            let len = u16::from_be_bytes([marker[2], marker[3]]) as u64;
+            let mut exif = [0u8; 6];
+            if marker[1] == 0xE1 && len >= 8 && r.read_exact(&mut exif).is_ok() && &exif == b"Exif\0\0" {
+                return tiff_orientation(r, pos + 10);
+            }
+            pos += 2 + len;
+        }
+    } else if head.starts_with(b"\x89PNG\r\n\x1a\n") {
+        let mut pos = 8;
+        loop {
+            let mut chunk = [0u8; 8];
+            r.seek(SeekFrom::Start(pos)).ok()?;
+            r.read_exact(&mut chunk).ok()?;
+            if &chunk[4..8] == b"eXIf" { return block(r); }
+            pos += 12 + u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]) as u64;
+        }
+    } else if head.starts_with(b"II*\0") || head.starts_with(b"MM\0*") {
+        tiff_orientation(r, 0)
+    } else if head.starts_with(b"RIFF") && &head[8..12] == b"WEBP" {
+        let mut pos = 12;
+        loop {
+            let mut chunk = [0u8; 8];
+            r.seek(SeekFrom::Start(pos)).ok()?;
+            r.read_exact(&mut chunk).ok()?;
+            if &chunk[0..4] == b"EXIF" { return block(r); }
+            let len = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as u64;
+            pos += 8 + len + (len & 1);
+        }
+    } else {
+        None
+    }
+}
+
+// ============================================================================
+// Zip Archives (template packs)
+// ============================================================================
+
+const ZIP_END_OF_DIRECTORY: u32 = 0x0605_4b50;
+const ZIP_DIRECTORY_ENTRY: u32 = 0x0201_4b50;
+const ZIP_LOCAL_HEADER: u32 = 0x0403_4b50;
+
+/// Files of a zip archive as (path, contents), directories skipped. Covers
+/// what template packs need: stored and deflated entries, checked against
+/// their CRC-32; encrypted and ZIP64 archives are refused
+fn read_zip(data: &[u8]) -> PyResult<Vec<(String, Vec<u8>)>> {
+    let corrupt = || DecodeError::new_err("Corrupt or truncated zip archive");
+    let u16_at = |p: usize| data.get(p..p + 2).map(|b| u16::from_le_bytes([b[0], b[1]]) as usize).ok_or_else(corrupt);
+    let u32_at = |p: usize| data.get(p..p + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).ok_or_else(corrupt);
+
+    // The end record is 22 bytes, followed by a comment of up to 64 KiB
+    let last = data.len().checked_sub(22).ok_or_else(|| DecodeError::new_err("Not a zip archive"))?;
+    let end = (last.saturating_sub(0xFFFF)..=last).rev()
+        .find(|&p| u32_at(p).ok() == Some(ZIP_END_OF_DIRECTORY))
+        .ok_or_else(|| DecodeError::new_err("Not a zip archive"))?;
+    let count = u16_at(end + 10)?;
+    let mut p = u32_at(end + 16)? as usize;
+    if count == 0xFFFF || p == 0xFFFF_FFFF {
+        return Err(DecodeError::new_err("ZIP64 archives are not supported"));
+    }
+
+    let mut files = Vec::with_capacity(count);
+    for _ in 0..count {
+        if u32_at(p)? != ZIP_DIRECTORY_ENTRY { return Err(corrupt()); }
+        let (flags, method, crc) = (u16_at(p + 8)?, u16_at(p + 10)?, u32_at(p + 16)?);
+        let (packed_size, size) = (u32_at(p + 20)? as usize, u32_at(p + 24)? as usize);
+        let (name_len, extra_len, comment_len) = (u16_at(p + 28)?, u16_at(p + 30)?, u16_at(p + 32)?);
+        let local = u32_at(p + 42)? as usize;
+        let name = String::from_utf8_lossy(data.get(p + 46..p + 46 + name_len).ok_or_else(corrupt)?).into_owned();
+        p += 46 + name_len + extra_len + comment_len;
+        if name.ends_with('/') { continue; }
+        if flags & 1 != 0 {
+            return Err(DecodeError::new_err(format!("'{}' in zip archive is encrypted", name)));
+        }
+
+        // Sizes in the local header may be zero (streamed archives), so the
+        // central directory's are used
+        if u32_at(local)? != ZIP_LOCAL_HEADER { return Err(corrupt()); }
+        let start = local + 30 + u16_at(local + 26)? + u16_at(local + 28)?;
+        let packed = data.get(start..start + packed_size).ok_or_else(corrupt)?;
+        let contents = match method {
+            0 => packed.to_vec(),
+            8 => {
+                let mut out = Vec::with_capacity(size);
+                flate2::read::DeflateDecoder::new(packed).read_to_end(&mut out)
+                    .map_err(|e| DecodeError::new_err(format!("Failed to inflate '{}' in zip archive: {}", name, e)))?;
+                out
+            }
+            m => return Err(DecodeError::new_err(format!("'{}' in zip archive uses unsupported compression method {}", name, m))),
+        };
+        let mut check = flate2::Crc::new();
+        check.update(&contents);
+        if contents.len() != size || check.sum() != crc {
+            return Err(DecodeError::new_err(format!("'{}' in zip archive is corrupt (CRC mismatch)", name)));
+        }
+        files.push((name, contents));
+    }
+    Ok(files)
+}
+
+// ============================================================================
+// Matching Options (shared `**options` of the find functions)
+// ============================================================================
+
+struct MatchOptions {
+    autocrop: bool,
+    autocrop_tolerance: f64,
+    robust: bool,
+    robust_grid: usize,
+    robust_keep: f64,
+    weights: Option<Vec<f64>>,
+    gamma: Option<Gamma>,
+    stretch: bool,
+    /// Median filter radius (1 = 3x3, 2 = 5x5)
+    denoise: Option<usize>,
+    pyramid_filter: DownsampleFilter,
+    auto_scale: bool,
+    scale_candidates: Option<Vec<f64>>,
+    return_crop: bool,
+    /// Reject low-contrast templates instead of matching them
+    strict: bool,
+    /// Coarse pyramid hits refined by single-match searches
+    coarse_candidates: usize,
+    /// Score every window position at full resolution (no pyramid, no stride)
+    exact: bool,
+    /// Retry single-match searches exhaustively when the pyramid finds nothing
+    exhaustive_fallback: bool,
+    /// Also score windows that overhang the source border
+    partial_border: bool,
+    /// Treat the source as toroidal, so matches may cross the image seam
+    wrap: bool,
+    /// Score binarized masks by intersection over union instead of NCC
+    binary: bool,
+    /// Gray level at or above which a pixel is foreground in `binary` mode
+    binary_threshold: f64,
+    /// Score census-transformed windows by Hamming similarity instead of NCC
+    census: bool,
+    /// Score windows by normalized mutual information instead of NCC
+    mutual_information: bool,
+    /// Scan with NCC over this fraction of the template pixels, then re-score exactly
+    sample_fraction: Option<f64>,
+    /// Match Sobel gradient magnitude instead of intensity
+    gradient: bool,
+    /// Physical pixels per logical pixel, reported on results
+    pixel_ratio: f64,
+    /// Match a mix of HSV or Lab channels instead of luma
+    color: Option<ColorProjection>,
+    /// Skip source regions lacking the template's colours, with this tolerance
+    color_prefilter: Option<f64>,
+    /// Skip windows whose variance is below this fraction of the template's
+    min_variance_ratio: Option<f64>,
+    /// Expected position of a single match, searched around first
+    hint: Option<(usize, usize)>,
+    /// Initial search radius around `hint`
+    hint_margin: usize,
+    /// Also search the template mirrored about these axes
+    flip: Option<Flip>,
+    /// Also search the template with inverted grey levels
+    invert: bool,
+    /// Stop searches running longer than this
+    timeout: Option<Duration>,
+    /// Return what a timed-out search found instead of raising TimeoutError
+    timeout_partial: bool,
+    /// Write the images the pyramid stages worked on to this directory
+    debug_dir: Option<PathBuf>,
+    /// Grey levels mapped onto the working range at load, for deep images
+    window: Option<(f64, f64)>,
+    /// Skip windows whose box comes closer than this to an image edge
+    border_margin: usize,
+    /// Polyline the centres of matches lie along, in source pixels
+    path: Option<Vec<(f64, f64)>>,
+    /// Distance from `path` within which match centres are searched
+    path_tolerance: f64,
+    /// Factor float tensors are multiplied by to give 0-255 grey levels
+    float_scale: f64,
+    /// Turn decoded images upright by their EXIF orientation tag
+    exif_orientation: bool,
+}
+
+impl Default for MatchOptions {
+    fn default() -> Self {
+        Self {
+            autocrop: false, autocrop_tolerance: 2.0,
+            robust: false, robust_grid: 4, robust_keep: 0.75,
+            weights: None,
+            gamma: None, stretch: false, denoise: None,
+            pyramid_filter: DownsampleFilter::Area,
+            auto_scale: false, scale_candidates: None, return_crop: false, strict: false,
+            coarse_candidates: 1, exact: false, exhaustive_fallback: false, partial_border: false,
+            wrap: false, binary: false, binary_threshold: 128.0, census: false, mutual_information: false, sample_fraction: None,
+            gradient: false, pixel_ratio: 1.0, color: None, color_prefilter: None,
+            min_variance_ratio: None, hint: None, hint_margin: 32, flip: None, invert: false,
+            timeout: None, timeout_partial: false, debug_dir: None, window: None, border_margin: 0,
+            path: None, path_tolerance: 4.0, float_scale: 255.0, exif_orientation: true,
+        }
+    }
+}
+
+impl MatchOptions {
+    fn from_kwargs(kwargs: Option<&PyDict>) -> PyResult<Self> {
+        // The preset only sets defaults, so explicit options win whatever their order
+        let mut opts = match kwargs.and_then(|k| k.get_item("mode").transpose()).transpose()? {
+            Some(mode) => Self::preset(mode)?,
+            None => Self::default(),
+        };
+        let (mut color_space, mut channels) = (None, None);
+        for (key, value) in kwargs.into_iter().flatten() {
+            let key: &str = key.extract()?;
+            match key {
+                "mode" => {}
+                "autocrop" => opts.autocrop = value.is_true()?,
+                "autocrop_tolerance" => opts.autocrop_tolerance = value.extract()?,
+                "robust" => opts.robust = value.is_true()?,
+                "robust_grid" => opts.robust_grid = value.extract()?,
+                "robust_keep" => opts.robust_keep = value.extract()?,
+                "weights" => opts.weights = Some(extract_weights(value)?),
+                "gamma" => opts.gamma = extract_gamma(value)?,
+                "stretch" => opts.stretch = value.is_true()?,
+                "denoise" => opts.denoise = extract_denoise(value)?,
+                "pyramid_filter" => opts.pyramid_filter = extract_pyramid_filter(value)?,
+                "auto_scale" => opts.auto_scale = value.is_true()?,
+                "scale_candidates" => opts.scale_candidates = extract_scale_candidates(value)?,
+                "return_crop" => opts.return_crop = value.is_true()?,
+                "strict" => opts.strict = value.is_true()?,
+                "coarse_candidates" => opts.coarse_candidates = value.extract()?,
+                "exact" => opts.exact = value.is_true()?,
+                "exhaustive_fallback" => opts.exhaustive_fallback = value.is_true()?,
+                "partial_border" => opts.partial_border = value.is_true()?,
+                "wrap" => opts.wrap = value.is_true()?,
+                "binary" => opts.binary = value.is_true()?,
+                "binary_threshold" => opts.binary_threshold = value.extract()?,
+                "census" => opts.census = value.is_true()?,
+                "mutual_information" => opts.mutual_information = value.is_true()?,
+                "sample_fraction" => opts.sample_fraction = value.extract()?,
+                "gradient" => opts.gradient = value.is_true()?,
+                "pixel_ratio" => opts.pixel_ratio = value.extract()?,
+                "color_space" => color_space = Some(value),
+                "channels" => channels = Some(value),
+                "color_prefilter" => opts.color_prefilter = extract_color_prefilter(value)?,
+                "min_variance_ratio" => opts.min_variance_ratio = value.extract()?,
+                "hint" => opts.hint = value.extract()?,
+                "hint_margin" => opts.hint_margin = value.extract()?,
+                "flip" => opts.flip = extract_flip(value)?,
+                "invert" => opts.invert = value.is_true()?,
+                "timeout_ms" => opts.timeout = extract_timeout(value)?,
+                "on_timeout" => opts.timeout_partial = match value.extract::<&str>()? {
+                    "raise" => false,
+                    "partial" => true,
+                    other => return Err(PyValueError::new_err(format!("on_timeout must be 'raise' or 'partial', got '{}'", other))),
+                },
+                "debug_dir" => opts.debug_dir = value.extract()?,
+                "window" => opts.window = value.extract()?,
+                "border_margin" => opts.border_margin = value.extract()?,
+                "path" => opts.path = extract_path(value)?,
+                "path_tolerance" => opts.path_tolerance = value.extract()?,
+                "float_scale" => opts.float_scale = value.extract()?,
+                "exif_orientation" => opts.exif_orientation = value.is_true()?,
+                _ => return Err(PyTypeError::new_err(format!("unexpected matching option '{}'", key))),
+            }
+        }
+        opts.color = extract_color(color_space, channels)?;
+        if opts.autocrop_tolerance < 0.0 {
+            return Err(PyValueError::new_err("autocrop_tolerance must be non-negative"));
+        }
+        if !(1..=MAX_ROBUST_GRID).contains(&opts.robust_grid) {
+            return Err(PyValueError::new_err(format!("robust_grid must be between 1 and {}", MAX_ROBUST_GRID)));
+        }
+        if !(opts.robust_keep > 0.0 && opts.robust_keep <= 1.0) {
+            return Err(PyValueError::new_err("robust_keep must be in (0, 1]"));
+        }
+        if !(opts.pixel_ratio.is_finite() && opts.pixel_ratio > 0.0) {
+            return Err(PyValueError::new_err("pixel_ratio must be a positive number"));
+        }
+        if opts.min_variance_ratio.is_some_and(|r| !(0.0..=1.0).contains(&r)) {
+            return Err(PyValueError::new_err("min_variance_ratio must be in [0, 1]"));
+        }
+        if opts.sample_fraction.is_some_and(|f| !(f > 0.0 && f <= 1.0)) {
+            return Err(PyValueError::new_err("sample_fraction must be in (0, 1]"));
+        }
+        if opts.coarse_candidates == 0 {
+            return Err(PyValueError::new_err("coarse_candidates must be at least 1"));
+        }
+        if opts.window.is_some_and(|(lo, hi)| !(lo.is_finite() && hi.is_finite() && lo < hi)) {
+            return Err(PyValueError::new_err("window must be (min, max) with min < max"));
+        }
+        if opts.window.is_some() && opts.color.is_some() {
+            return Err(PyValueError::new_err("window can't be combined with color_space"));
+        }
+        if !(opts.path_tolerance.is_finite() && opts.path_tolerance >= 0.0) {
+            return Err(PyValueError::new_err("path_tolerance must be a non-negative number"));
+        }
+        if !(opts.float_scale.is_finite() && opts.float_scale > 0.0) {
+            return Err(PyValueError::new_err("float_scale must be a positive number"));
+        }
+        if opts.hint_margin == 0 {
+            return Err(PyValueError::new_err("hint_margin must be at least 1"));
+        }
+        if opts.robust && opts.weights.is_some() {
+            return Err(PyValueError::new_err("robust and weights can't be combined"));
+        }
+        if opts.auto_scale && opts.weights.is_some() {
+            return Err(PyValueError::new_err("auto_scale and weights can't be combined"));
+        }
+        if opts.partial_border && (opts.robust || opts.weights.is_some()) {
+            return Err(PyValueError::new_err("partial_border can't be combined with robust or weights"));
+        }
+        if opts.binary && (opts.robust || opts.weights.is_some() || opts.partial_border) {
+            return Err(PyValueError::new_err("binary can't be combined with robust, weights or partial_border"));
+        }
+        if opts.census && (opts.binary || opts.robust || opts.weights.is_some() || opts.partial_border) {
+            return Err(PyValueError::new_err("census can't be combined with binary, robust, weights or partial_border"));
+        }
+        if opts.mutual_information && (opts.binary || opts.census || opts.robust || opts.weights.is_some() || opts.partial_border) {
+            return Err(PyValueError::new_err(
+                "mutual_information can't be combined with binary, census, robust, weights or partial_border",
+            ));
+        }
+        let other_score = opts.binary || opts.census || opts.mutual_information || opts.robust || opts.weights.is_some();
+        if opts.sample_fraction.is_some() && (other_score || opts.partial_border) {
+            return Err(PyValueError::new_err(
+                "sample_fraction only applies to plain NCC and can't be combined with partial_border",
+            ));
+        }
+        if opts.min_variance_ratio.is_some() && (other_score || opts.sample_fraction.is_some()) {
+            return Err(PyValueError::new_err(
+                "min_variance_ratio only applies to plain NCC and can't be combined with sample_fraction",
+            ));
+        }
+        if opts.wrap && (opts.partial_border || opts.autocrop) {
+            return Err(PyValueError::new_err("wrap can't be combined with partial_border or autocrop"));
+        }
+        if opts.color_prefilter.is_some() && (opts.wrap || opts.partial_border || opts.auto_scale) {
+            return Err(PyValueError::new_err("color_prefilter can't be combined with wrap, partial_border or auto_scale"));
+        }
+        if opts.flip.is_some() && opts.weights.is_some() {
+            return Err(PyValueError::new_err("flip and weights can't be combined"));
+        }
+        if opts.hint.is_some() && (opts.wrap || opts.partial_border || opts.auto_scale || opts.color_prefilter.is_some()) {
+            return Err(PyValueError::new_err("hint can't be combined with wrap, partial_border, auto_scale or color_prefilter"));
+        }
+        if opts.border_margin > 0 && (opts.hint.is_some() || opts.wrap || opts.partial_border || opts.auto_scale) {
+            return Err(PyValueError::new_err("border_margin can't be combined with hint, wrap, partial_border or auto_scale"));
+        }
+        if opts.path.is_some() && (other_score || opts.sample_fraction.is_some()) {
+            return Err(PyValueError::new_err("path only applies to plain NCC and can't be combined with sample_fraction"));
+        }
+        if opts.path.is_some() && (opts.hint.is_some() || opts.wrap || opts.partial_border || opts.auto_scale || opts.color_prefilter.is_some()) {
+            return Err(PyValueError::new_err("path can't be combined with hint, wrap, partial_border, auto_scale or color_prefilter"));
+        }
+        Ok(opts)
+    }
+
+    /// `mode` option: "fast" (the defaults), "balanced" (Gaussian pyramid,
+    /// three coarse candidates) or "accurate" (exhaustive search)
+    fn preset(mode: &PyAny) -> PyResult<Self> {
+        let mut opts = Self::default();
+        match mode.extract::<&str>()? {
+            "fast" => {}
+            "balanced" => {
+                opts.pyramid_filter = DownsampleFilter::Gaussian;
+                opts.coarse_candidates = 3;
+            }
+            "accurate" => opts.exact = true,
+            other => return Err(PyValueError::new_err(format!(
+                "Unknown mode '{}', expected 'fast', 'balanced' or 'accurate'", other
+            ))),
+        }
+        Ok(opts)
+    }
+
+    /// Template rescaled to the factor estimated against this source, if
+    /// `auto_scale` is set and the best factor isn't 1
+    fn scaled_template(&self, src: &[f64], sw: usize, sh: usize, tpl: &GrayImageData) -> Option<GrayImageData> {
+        if !self.auto_scale { return None; }
+        let candidates = self.scale_candidates.as_deref().unwrap_or(&DEFAULT_SCALE_CANDIDATES);
+        let (factor, score) = estimate_template_scale(src, sw, sh, tpl, candidates)?;
+        log::debug!("auto_scale picked factor {} (coarse score {:.4})", factor, score);
+        if factor == 1.0 { None } else { scale_image(tpl, factor) }
+    }
+
+    /// Single best match with the scoring selected by the options
+    fn find_best(&self, src: &[f64], sw: usize, sh: usize, tpl: &GrayImageData, threshold: f64) -> Option<MatchResult> {
+        self.find_best_with(src, sw, sh, tpl, self.exact, threshold)
+    }
+
+    /// `find_best`, with or without the pyramid
+    fn find_best_with(&self, src: &[f64], sw: usize, sh: usize, tpl: &GrayImageData, exact: bool, threshold: f64) -> Option<MatchResult> {
+        let scaled = self.scaled_template(src, sw, sh, tpl);
+        let tpl = scaled.as_ref().unwrap_or(tpl);
+        let padded = self.wrap.then(|| wrap_pad(src, sw, sh, tpl.width, tpl.height));
+        let (src, sw, sh) = padded.as_ref().map_or((src, sw, sh), |(data, w, h)| (data.as_slice(), *w, *h));
+        let encoded = self.encode_source(src, sw, sh);
+        let src = encoded.as_deref().unwrap_or(src);
+        self.log_variance_skips(src, sw, sh, tpl);
+        let mut found = self.search(src, sw, sh, tpl, exact, threshold);
+        if found.is_none() && !exact && self.exhaustive_fallback && tpl.width <= sw && tpl.height <= sh {
+            log::debug!("pyramid search found no match, falling back to the exhaustive search");
+            found = self.search(src, sw, sh, tpl, true, threshold);
+        }
+        if self.partial_border {
+            let partial = PartialTemplate::new(&tpl.data, tpl.width, tpl.height);
+            if let Some(edge) = border_best(src, sw, sh, &partial, threshold) {
+                if found.as_ref().map_or(true, |f| edge.confidence > f.confidence) { found = Some(edge); }
+            }
+        }
+        found
+    }
+
+    /// Source mapped into the domain the template is scored in, for the
+    /// `binary` and `census` modes
+    fn encode_source(&self, src: &[f64], sw: usize, sh: usize) -> Option<Vec<f64>> {
+        if self.binary {
+            Some(binarize(src, self.binary_threshold))
+        } else if self.census {
+            Some(census_transform(src, sw, sh))
+        } else { None }
+    }
+
+    /// `find_best` for an already scaled template, with or without the pyramid
+    fn search(&self, src: &[f64], sw: usize, sh: usize, tpl: &GrayImageData, exact: bool, threshold: f64) -> Option<MatchResult> {
+        let filter = self.pyramid_filter;
+        if self.robust {
+            let (full, coarse) = robust_levels(tpl, (self.robust_grid, self.robust_keep), filter);
+            let coarse = coarse.as_ref().filter(|_| !exact).map(|(small, scale, calibration)| (small, *scale, *calibration));
+            scored_match(src, sw, sh, &full, coarse, filter, threshold)
+        } else if let Some(weights) = &self.weights {
+            let (full, coarse) = weighted_levels(tpl, weights, filter);
+            let coarse = coarse.as_ref().filter(|_| !exact).map(|(small, scale, calibration)| (small, *scale, *calibration));
+            scored_match(src, sw, sh, &full, coarse, filter, threshold)
+        } else if self.binary {
+            let full = BinaryTemplate::new(&tpl.data, tpl.width, tpl.height, self.binary_threshold);
+            scored_match(src, sw, sh, &full, None, filter, threshold)
+        } else if self.census {
+            let full = CensusTemplate::new(&tpl.data, tpl.width, tpl.height);
+            scored_match(src, sw, sh, &full, None, filter, threshold)
+        } else if self.mutual_information {
+            let (full, coarse) = mutual_info_levels(tpl, filter);
+            let coarse = coarse.as_ref().filter(|_| !exact).map(|(small, scale, calibration)| (small, *scale, *calibration));
+            scored_match(src, sw, sh, &full, coarse, filter, threshold)
+        } else if let Some(fraction) = self.sample_fraction {
+            sampled_match(src, sw, sh, tpl, fraction, exact, filter, threshold)
+        } else if exact {
+            scored_match(src, sw, sh, &self.ncc_template(tpl), None, filter, threshold)
+        } else {
+            pyramid_match_prepared(src, sw, sh, &self.prepared_template(tpl), threshold)
+        }
+    }
+
+    /// Plain NCC template with the `min_variance_ratio` floor
+    fn ncc_template(&self, tpl: &GrayImageData) -> Template {
+        let full = Template::new(&tpl.data, tpl.width, tpl.height);
+        match self.min_variance_ratio {
+            Some(ratio) => full.with_variance_ratio(ratio),
+            None => full,
+        }
+    }
+
+    /// Pyramid template with the options' filter, coarse candidates and
+    /// `min_variance_ratio` floor
+    fn prepared_template(&self, tpl: &GrayImageData) -> PreparedTemplate {
+        let mut prepared = PreparedTemplate::with_filter(&tpl.data, tpl.width, tpl.height, self.pyramid_filter);
+        prepared.coarse_candidates = self.coarse_candidates;
+        match self.min_variance_ratio {
+            Some(ratio) => prepared.with_variance_ratio(ratio),
+            None => prepared,
+        }
+    }
+
+    /// Debug log of the windows `min_variance_ratio` lets the scan skip
+    fn log_variance_skips(&self, src: &[f64], sw: usize, sh: usize, tpl: &GrayImageData) {
+        if self.min_variance_ratio.is_none() || !log::log_enabled!(log::Level::Debug) { return; }
+        let (tw, th) = (tpl.width, tpl.height);
+        if tw > sw || th > sh { return; }
+        let min_var = self.ncc_template(tpl).min_var;
+        let skipped = count_low_variance_windows(src, sw, sh, tw, th, min_var);
+        log::debug!(
+            "min_variance_ratio: {} of {} windows have variance below {:.2} and skip the cross term",
+            skipped, (sw - tw + 1) * (sh - th + 1), min_var
+        );
+    }
+
+    /// All matches with the scoring selected by the options
+    fn find_all(
+        &self, src: &[f64], sw: usize, sh: usize, tpl: &GrayImageData, threshold: f64, max_count: usize,
+    ) -> Vec<MatchResult> {
+        let (candidates, (tw, th)) = self.find_candidates(src, sw, sh, tpl, threshold);
+        non_max_suppression(candidates, tw, th, max_count)
+    }
+
+    /// Matches before non-maximum suppression, with the size of the template
+    /// actually searched (it differs from `tpl` under `auto_scale`)
+    fn find_candidates(
+        &self, src: &[f64], sw: usize, sh: usize, tpl: &GrayImageData, threshold: f64,
+    ) -> (Vec<MatchResult>, (usize, usize)) {
+        let scaled = self.scaled_template(src, sw, sh, tpl);
+        let tpl = scaled.as_ref().unwrap_or(tpl);
+        let padded = self.wrap.then(|| wrap_pad(src, sw, sh, tpl.width, tpl.height));
+        let (src, sw, sh) = padded.as_ref().map_or((src, sw, sh), |(data, w, h)| (data.as_slice(), *w, *h));
+        let encoded = self.encode_source(src, sw, sh);
+        let src = encoded.as_deref().unwrap_or(src);
+        self.log_variance_skips(src, sw, sh, tpl);
+        let (tw, th) = (tpl.width, tpl.height);
+        let candidates = if tw > sw || th > sh {
+            log::debug!("template {}x{} is larger than source {}x{}", tw, th, sw, sh);
+            vec![]
+        } else if self.robust {
+            let blocks = BlockTemplate::new(&tpl.data, tw, th, self.robust_grid, self.robust_keep);
+            if self.exact { exact_candidates(src, sw, sh, &blocks, threshold) } else { scored_candidates(src, sw, sh, &blocks, threshold) }
+        } else if let Some(weights) = &self.weights {
+            let weighted = WeightedTemplate::new(&tpl.data, weights, tw, th);
+            if self.exact { exact_candidates(src, sw, sh, &weighted, threshold) } else { scored_candidates(src, sw, sh, &weighted, threshold) }
+        } else if self.binary {
+            // Shifted masks lose overlap fast, so the stride-2 prescan would miss peaks
+            exact_candidates(src, sw, sh, &BinaryTemplate::new(&tpl.data, tw, th, self.binary_threshold), threshold)
+        } else if self.census {
+            // Codes are not smooth in position either, so every window is scored
+            exact_candidates(src, sw, sh, &CensusTemplate::new(&tpl.data, tw, th), threshold)
+        } else if self.mutual_information {
+            let mi = MutualInfoTemplate::new(&tpl.data, tw, th);
+            if self.exact { exact_candidates(src, sw, sh, &mi, threshold) } else { scored_candidates(src, sw, sh, &mi, threshold) }
+        } else if let Some(fraction) = self.sample_fraction {
+            sampled_candidates(src, sw, sh, tpl, fraction, self.exact, self.pyramid_filter, threshold)
+        } else if self.exact {
+            exact_candidates(src, sw, sh, &self.ncc_template(tpl), threshold)
+        } else {
+            match_candidates(src, sw, sh, &self.prepared_template(tpl), threshold)
+        };
+        let candidates = if self.partial_border {
+            let partial = PartialTemplate::new(&tpl.data, tw, th);
+            candidates.into_iter().chain(border_candidates(src, sw, sh, &partial, threshold)).collect()
+        } else { candidates };
+        (candidates, (tw, th))
+    }
+
+    /// Reject a template that can't fit the source. With `auto_scale` the
+    /// template may still shrink to fit, so the check is left to the search.
+    fn check_fits(&self, tpl: &SearchTemplate, sw: usize, sh: usize) -> PyResult<()> {
+        let (tw, th) = (tpl.image.width, tpl.image.height);
+        if !self.auto_scale && (tw > sw || th > sh) {
+            return Err(TemplateTooLargeError::new_err(format!(
+                "Template ({}x{}) is larger than the source ({}x{})", tw, th, sw, sh
+            )));
+        }
+        Ok(())
+    }
+
+    /// Pixels a decoded image is matched on: luma (through the `window`),
+    /// or the `color_space` channel mix
+    fn plane(&self, img: &DynamicImage) -> GrayImageData {
+        match (&self.color, self.window) {
+            (Some(color), _) => color.project(img),
+            (None, Some(window)) => GrayImageData::from_dynamic_windowed(img, window),
+            (None, None) => GrayImageData::from_dynamic(img),
+        }
+    }
+
+    /// `plane` of an image argument; only 8-bit luma goes through the
+    /// decoded image cache
+    fn load_plane(&self, image: &ImageInput) -> PyResult<GrayImageData> {
+        if self.color.is_none() && self.window.is_none() {
+            return image.load_as(self.exif_orientation);
+        }
+        Ok(self.plane(&image.load_dynamic_as(self.exif_orientation)?))
+    }
+
+    /// Decode a source to its `plane`, keeping the decoded image when
+    /// results are cropped from it or `color_prefilter` needs its colours
+    fn load_source(&self, image: &ImageInput) -> PyResult<(GrayImageData, Option<DynamicImage>)> {
+        Stats::current().time("decode", || {
+            if !self.return_crop && self.color_prefilter.is_none() {
+                return Ok((self.load_plane(image)?, None));
+            }
+            let img = image.load_dynamic_as(self.exif_orientation)?;
+            Ok((self.plane(&img), Some(img)))
+        })
+    }
+
+    /// Decode and prepare a template, with the colours `color_prefilter`
+    /// looks for
+    fn load_template(&mut self, image: &ImageInput) -> PyResult<SearchTemplate> {
+        let stats = Stats::current();
+        if self.color_prefilter.is_none() {
+            let plane = stats.time("decode", || self.load_plane(image))?;
+            return self.prepare_template(plane);
+        }
+        let img = stats.time("decode", || image.load_dynamic_as(self.exif_orientation))?;
+        let mut tpl = self.prepare_template(self.plane(&img))?;
+        self.attach_colors(&mut tpl, &img);
+        Ok(tpl)
+    }
+
+    /// Record the colours of the searched part of the template (after
+    /// autocrop) for `color_prefilter`
+    fn attach_colors(&self, tpl: &mut SearchTemplate, img: &DynamicImage) {
+        if self.color_prefilter.is_none() { return; }
+        let (w, h) = (tpl.image.width as u32, tpl.image.height as u32);
+        let mut crop = img.crop_imm(tpl.offset.0, tpl.offset.1, w, h);
+        tpl.colors = Some(ColorHistogram::new(&crop));
+        // Mirroring doesn't change which colours a template holds; inverting does
+        crop.invert();
+        let inverted = ColorHistogram::new(&crop);
+        for (variant, searched) in &mut tpl.variants {
+            searched.colors = Some(if variant.inverted { inverted.clone() } else { tpl.colors.clone().unwrap() });
+        }
+    }
+
+    /// Window positions left by `color_prefilter`, or None to search everywhere
+    fn color_regions(&self, source: Option<&DynamicImage>, tpl: &SearchTemplate) -> Option<Vec<(usize, usize, usize, usize)>> {
+        let (tolerance, colors, source) = (self.color_prefilter?, tpl.colors.as_ref()?, source?);
+        Some(color_prefilter_regions(&source.to_rgb8(), colors, tpl.image.width, tpl.image.height, tolerance))
+    }
+
+    /// Window positions `(x1, y1, x2, y2)` of `tpl` whose reported box, the
+    /// template's footprint, stays `border_margin` pixels inside the source;
+    /// None without a margin, Some(None) when no position does
+    fn margin_positions(&self, src: &GrayImageData, tpl: &SearchTemplate) -> Option<Option<(usize, usize, usize, usize)>> {
+        if self.border_margin == 0 { return None; }
+        let m = self.border_margin;
+        let (fw, fh) = (tpl.footprint.0 as usize, tpl.footprint.1 as usize);
+        let (ox, oy) = (tpl.offset.0 as usize, tpl.offset.1 as usize);
+        // A margin too large to add up can't leave any position inside
+        let fits = |size: usize, extent: usize| {
+            m.checked_mul(2).and_then(|both| both.checked_add(extent)).is_some_and(|need| size >= need)
+        };
+        let inside = fits(src.width, fw) && fits(src.height, fh);
+        Some(inside.then(|| (m + ox, m + oy, src.width - m - fw + ox, src.height - m - fh + oy)))
+    }
+
+    /// Window positions to search: the `color_prefilter` regions clipped to
+    /// the `border_margin` positions, or None to search everywhere
+    fn search_regions(
+        &self, src: &GrayImageData, source: Option<&DynamicImage>, tpl: &SearchTemplate,
+    ) -> Option<Vec<(usize, usize, usize, usize)>> {
+        let regions = self.color_regions(source, tpl);
+        let Some(inner) = self.margin_positions(src, tpl) else { return regions };
+        let Some((ix1, iy1, ix2, iy2)) = inner else { return Some(Vec::new()) };
+        let Some(regions) = regions else { return Some(vec![(ix1, iy1, ix2, iy2)]) };
+        Some(regions.into_iter()
+            .map(|(x1, y1, x2, y2)| (x1.max(ix1), y1.max(iy1), x2.min(ix2), y2.min(iy2)))
+            .filter(|&(x1, y1, x2, y2)| x1 <= x2 && y1 <= y2)
+            .collect())
+    }
+
+    /// Window positions of `tpl` whose reported centre lies within
+    /// `path_tolerance` of the `path`, as row spans `(y, x1, x2)` kept inside
+    /// the `border_margin` positions; None without a path
+    fn path_spans(&self, src: &GrayImageData, tpl: &SearchTemplate) -> Option<Vec<(usize, usize, usize)>> {
+        let path = self.path.as_deref()?;
+        let (tw, th) = (tpl.image.width, tpl.image.height);
+        if tw > src.width || th > src.height { return Some(Vec::new()); }
+        // Reported centre of the window at search position (0, 0)
+        let centre = (
+            (tpl.footprint.0 / 2) as f64 - tpl.offset.0 as f64,
+            (tpl.footprint.1 / 2) as f64 - tpl.offset.1 as f64,
+        );
+        let spans = corridor_spans(path, self.path_tolerance, centre, (src.width - tw, src.height - th));
+        let Some(inner) = self.margin_positions(src, tpl) else { return Some(spans) };
+        let Some((ix1, iy1, ix2, iy2)) = inner else { return Some(Vec::new()) };
+        Some(spans.into_iter()
+            .filter(|&(y, _, _)| (iy1..=iy2).contains(&y))
+            .map(|(y, x1, x2)| (y, x1.max(ix1), x2.min(ix2)))
+            .filter(|&(_, x1, x2)| x1 <= x2)
+            .collect())
+    }
+
+    /// `find_best` around the `hint`, growing the window until it finds a
+    /// match. Windows are searched exhaustively while they cover less than a
+    /// quarter of the window positions; past that the whole source is searched.
+    fn find_near(&self, src: &GrayImageData, tpl: &SearchTemplate, hint: (usize, usize), threshold: f64) -> Option<MatchResult> {
+        let (tw, th) = (tpl.image.width, tpl.image.height);
+        let end = (src.width - tw, src.height - th);
+        let positions = (end.0 + 1) * (end.1 + 1);
+        let centre = (hint.0.saturating_add(tpl.offset.0 as usize), hint.1.saturating_add(tpl.offset.1 as usize));
+        for (x1, y1, x2, y2) in expanding_windows(end, centre, self.hint_margin) {
+            if (x2 - x1 + 1) * (y2 - y1 + 1) * 4 >= positions { break; }
+            let (cw, ch) = (x2 - x1 + tw, y2 - y1 + th);
+            let crop = crop_region(&src.data, src.width, x1, y1, cw, ch);
+            if let Some(mut r) = self.find_best_with(&crop, cw, ch, &tpl.image, true, threshold) {
+                log::debug!("hint: found within {}x{} positions around ({}, {})", x2 - x1 + 1, y2 - y1 + 1, hint.0, hint.1);
+                r.x += x1 as i32;
+                r.y += y1 as i32;
+                return Some(r);
+            }
+        }
+        log::debug!("hint: no match near ({}, {}), searching the whole source", hint.0, hint.1);
+        self.find_best(&src.data, src.width, src.height, &tpl.image, threshold)
+    }
+
+    /// `find_best` over the windows `color_prefilter` keeps in `source`, the
+    /// decoded image `src` was made from, `border_margin` keeps off the edges
+    /// and `path` keeps along its corridor, or around the `hint` first. With
+    /// `flip` or `invert`, a variant wins only when it scores strictly higher.
+    fn find_best_in(
+        &self, src: &GrayImageData, source: Option<&DynamicImage>, tpl: &SearchTemplate, threshold: f64,
+    ) -> PyResult<Option<MatchResult>> {
+        let search = || Stats::current().time("search", || self.within_timeout(|| {
+            tpl.variants.iter().fold(self.find_best_variant(src, source, tpl, threshold), |best, (variant, searched)| {
+                match self.find_best_variant(src, source, searched, threshold) {
+                    Some(r) if best.as_ref().map_or(true, |b| r.confidence > b.confidence) => Some(variant.tag(r)),
+                    _ => best,
+                }
+            })
+        }));
+        self.debugged(src, tpl, search, |best| best.iter().cloned().collect())
+    }
+
+    /// Run `search` and, with `debug_dir`, write the images its pyramid
+    /// stages worked on there, with the `matches` it found outlined
+    fn debugged<T>(
+        &self, src: &GrayImageData, tpl: &SearchTemplate,
+        search: impl FnOnce() -> PyResult<T>, matches: impl FnOnce(&T) -> Vec<MatchResult>,
+    ) -> PyResult<T> {
+        let Some(dir) = &self.debug_dir else { return search() };
+        // Share the statistics of a `return_diagnostics` call
+        let stats = Stats::current().0.unwrap_or_default();
+        let found = SearchStats::collect(&stats, search)?;
+        let matches = matches(&found);
+        // Scoring the dump must not count towards the diagnostics
+        SearchStats::collect(&Arc::default(), || {
+            write_debug_images(dir, src, &tpl.image, self.pyramid_filter, &stats.detail(), &matches)
+        })?;
+        Ok(found)
+    }
+
+    /// Run `search` with the `timeout_ms` deadline set for this thread. Past
+    /// the deadline the search's loops stop early; the partial result is
+    /// returned with `on_timeout="partial"`, else TimeoutError is raised.
+    fn within_timeout<T>(&self, search: impl FnOnce() -> T) -> PyResult<T> {
+        let Some(timeout) = self.timeout else { return Ok(search()) };
+        let deadline = Instant::now() + timeout;
+        let outer = SEARCH_DEADLINE.with(|d| d.replace(Some(deadline)));
+        let found = search();
+        SEARCH_DEADLINE.with(|d| d.set(outer));
+        if Instant::now() < deadline {
+            return Ok(found);
+        }
+        let ms = timeout.as_secs_f64() * 1000.0;
+        log::debug!("search exceeded timeout_ms={}, {}", ms, if self.timeout_partial { "returning partial result" } else { "raising" });
+        if self.timeout_partial {
+            return Ok(found);
+        }
+        Err(pyo3::exceptions::PyTimeoutError::new_err(format!("Search exceeded timeout_ms={}", ms)))
+    }
+
+    /// `find_best_in` for one variant of the template
+    fn find_best_variant(
+        &self, src: &GrayImageData, source: Option<&DynamicImage>, tpl: &SearchTemplate, threshold: f64,
+    ) -> Option<MatchResult> {
+        if let Some(hint) = self.hint {
+            return self.find_near(src, tpl, hint, threshold);
+        }
+        if let Some(spans) = self.path_spans(src, tpl) {
+            return spans_best(&src.data, src.width, src.height, &self.ncc_template(&tpl.image), &spans, threshold);
+        }
+        let regions = match self.search_regions(src, source, tpl) {
+            Some(regions) => regions,
+            None => return self.find_best(&src.data, src.width, src.height, &tpl.image, threshold),
+        };
+        let (tw, th) = (tpl.image.width, tpl.image.height);
+        regions.into_iter()
+            .filter_map(|(x1, y1, x2, y2)| {
+                let (cw, ch) = (x2 - x1 + tw, y2 - y1 + th);
+                let crop = crop_region(&src.data, src.width, x1, y1, cw, ch);
+                self.find_best(&crop, cw, ch, &tpl.image, threshold).map(|mut r| {
+                    r.x += x1 as i32;
+                    r.y += y1 as i32;
+                    r
+                })
+            })
+            .max_by(|a, b| a.confidence.total_cmp(&b.confidence))
+    }
+
+    /// `find_candidates` over the windows `color_prefilter`, `border_margin`
+    /// and `path` keep, for the template and each `flip`/`invert` variant
+    fn find_candidates_in(
+        &self, src: &GrayImageData, source: Option<&DynamicImage>, tpl: &SearchTemplate, threshold: f64,
+    ) -> (Vec<MatchResult>, (usize, usize)) {
+        let (mut candidates, size) = self.find_candidates_variant(src, source, tpl, threshold);
+        for (variant, searched) in &tpl.variants {
+            let (found, _) = self.find_candidates_variant(src, source, searched, threshold);
+            candidates.extend(found.into_iter().map(|r| variant.tag(r)));
+        }
+        (candidates, size)
+    }
+
+    /// `find_candidates_in` for one variant of the template
+    fn find_candidates_variant(
+        &self, src: &GrayImageData, source: Option<&DynamicImage>, tpl: &SearchTemplate, threshold: f64,
+    ) -> (Vec<MatchResult>, (usize, usize)) {
+        if let Some(spans) = self.path_spans(src, tpl) {
+            let candidates = spans_candidates(&src.data, src.width, src.height, &self.ncc_template(&tpl.image), &spans, threshold);
+            return (candidates, (tpl.image.width, tpl.image.height));
+        }
+        let (mut candidates, size) = match self.color_regions(source, tpl) {
+            Some(regions) => self.find_candidates_regions(src, tpl, regions, threshold),
+            None => self.find_candidates(&src.data, src.width, src.height, &tpl.image, threshold),
+        };
+        // Filtered rather than cropped, which would shift the stride-2 prescan grid
+        if let Some(inner) = self.margin_positions(src, tpl) {
+            candidates.retain(|r| inner.is_some_and(|(x1, y1, x2, y2)| {
+                (x1 as i32..=x2 as i32).contains(&r.x) && (y1 as i32..=y2 as i32).contains(&r.y)
+            }));
+        }
+        (candidates, size)
+    }
+
+    /// `find_candidates` over the window positions in `regions`
+    fn find_candidates_regions(
+        &self, src: &GrayImageData, tpl: &SearchTemplate, regions: Vec<(usize, usize, usize, usize)>, threshold: f64,
+    ) -> (Vec<MatchResult>, (usize, usize)) {
+        let (tw, th) = (tpl.image.width, tpl.image.height);
+        let candidates = regions.into_iter()
+            .flat_map(|(x1, y1, x2, y2)| {
+                let (cw, ch) = (x2 - x1 + tw, y2 - y1 + th);
+                let crop = crop_region(&src.data, src.width, x1, y1, cw, ch);
+                let (found, _) = self.find_candidates(&crop, cw, ch, &tpl.image, threshold);
+                found.into_iter().map(move |mut r| {
+                    r.x += x1 as i32;
+                    r.y += y1 as i32;
+                    r
+                })
+            })
+            .collect();
+        (candidates, (tw, th))
+    }
+
+    /// `find_all` over the windows `color_prefilter`, `border_margin` and
+    /// `path` keep, so windows they drop never suppress others
+    fn find_all_in(
+        &self, src: &GrayImageData, source: Option<&DynamicImage>, tpl: &SearchTemplate, threshold: f64, max_count: usize,
+    ) -> PyResult<Vec<MatchResult>> {
+        let search = || Stats::current().time("search", || self.within_timeout(|| {
+            let (candidates, (tw, th)) = self.find_candidates_in(src, source, tpl, threshold);
+            non_max_suppression(candidates, tw, th, max_count)
+        }));
+        self.debugged(src, tpl, search, Vec::clone)
+    }
+
+    /// Reject `color_space`, `color_prefilter` and `window` for functions
+    /// whose source only carries 8-bit luma
+    fn check_luma_only(&self, what: &str) -> PyResult<()> {
+        let option = if self.color.is_some() {
+            "color_space"
+        } else if self.color_prefilter.is_some() {
+            "color_prefilter"
+        } else if self.window.is_some() {
+            "window"
+        } else { return Ok(()) };
+        Err(PyValueError::new_err(format!("{} is not supported by {}", option, what)))
+    }
+
+    fn adjusts_tone(&self) -> bool {
+        self.stretch || self.gamma.is_some()
+    }
+
+    /// Apply image preprocessing options to a source
+    fn prepare_source(&self, mut src: GrayImageData) -> GrayImageData {
+        Stats::current().time("preprocess", || {
+            if let Some(radius) = self.denoise {
+                src.data = median_filter(&src.data, src.width, src.height, radius);
+            }
+            if self.adjusts_tone() {
+                let curve = ToneCurve::fit(&src.data, self.stretch, self.gamma);
+                src.data.iter_mut().for_each(|v| *v = curve.apply(*v));
+            }
+            if self.gradient {
+                src.data = sobel_magnitude(&src.data, src.width, src.height);
+            }
+            src
+        })
+    }
+
+    /// `prepare_source` for 8-bit sources decoded for tiling
+    fn prepare_gray(&self, mut gray: GrayImage) -> GrayImage {
+        if let Some(radius) = self.denoise {
+            let (w, h) = gray.dimensions();
+            let filtered = median_filter(gray.as_raw(), w as usize, h as usize, radius);
+            gray = GrayImage::from_raw(w, h, filtered).expect("filtered buffer has the source size");
+        }
+        if self.adjusts_tone() {
+            let curve = ToneCurve::fit_gray(&gray, self.stretch, self.gamma);
+            let lut: Vec<u8> = (0..256).map(|v| curve.apply(v as f64).round() as u8).collect();
+            gray.iter_mut().for_each(|v| *v = lut[*v as usize]);
+        }
+        if self.gradient {
+            let (w, h) = gray.dimensions();
+            let data: Vec<f64> = gray.as_raw().iter().map(|&v| v as f64).collect();
+            let edges = sobel_magnitude(&data, w as usize, h as usize).into_iter().map(|v| v.round() as u8).collect();
+            gray = GrayImage::from_raw(w, h, edges).expect("gradient buffer has the source size");
+        }
+        gray
+    }
+
+    /// Apply template-side options. Returns the template to search for and
+    /// the offset of its top-left corner within the original template.
+    fn prepare_template(&mut self, tpl: GrayImageData) -> PyResult<SearchTemplate> {
+        let footprint = (tpl.width as u32, tpl.height as u32);
+        let tpl = self.prepare_source(tpl);
+        if self.strict {
+            let (std, entropy) = template_stats(&tpl.data);
+            let issues = template_issues(std, entropy, MIN_TEMPLATE_STD, MIN_TEMPLATE_ENTROPY);
+            if !issues.is_empty() {
+                return Err(TemplateQualityError::new_err(format!("Template can't be matched reliably: {}", issues.join("; "))));
+            }
+        }
+        if let Some(weights) = &self.weights {
+            if weights.len() != tpl.data.len() {
+                return Err(InvalidDimensionsError::new_err(format!(
+                    "weights has {} values, expected one per template pixel ({}x{})",
+                    weights.len(), tpl.width, tpl.height
+                )));
+            }
+        }
+        let uncropped = |image| SearchTemplate { image, offset: (0, 0), footprint, colors: None, variants: Vec::new() };
+        if !self.autocrop { return Ok(self.with_variants(uncropped(tpl))); }
+        let (x, y, w, h) = autocrop_box(&tpl.data, tpl.width, tpl.height, self.autocrop_tolerance);
+        if (w, h) == (tpl.width, tpl.height) { return Ok(self.with_variants(uncropped(tpl))); }
+        if let Some(weights) = &mut self.weights {
+            *weights = crop_region(weights, tpl.width, x, y, w, h);
+        }
+        let data = crop_region(&tpl.data, tpl.width, x, y, w, h);
+        Ok(self.with_variants(SearchTemplate {
+            image: GrayImageData { data, width: w, height: h }, offset: (x as u32, y as u32), footprint, colors: None,
+            variants: Vec::new(),
+        }))
+    }
+
+    /// Add the mirrored variants selected by `flip` and, with `invert`, an
+    /// inverted copy of the template and of each mirror
+    fn with_variants(&self, mut tpl: SearchTemplate) -> SearchTemplate {
+        let mirrors = self.flip.map_or(&[][..], Flip::variants);
+        let mut variants: Vec<_> = mirrors.iter()
+            .map(|&(name, horizontal, vertical)| (Variant { flip: Some(name), inverted: false }, tpl.mirrored(horizontal, vertical)))
+            .collect();
+        if self.invert {
+            let inverted: Vec<_> = std::iter::once((Variant::default(), &tpl))
+                .chain(variants.iter().map(|(variant, searched)| (*variant, searched)))
+                .map(|(variant, searched)| (Variant { inverted: true, ..variant }, searched.inverted()))
+                .collect();
+            variants.extend(inverted);
+        }
+        tpl.variants = variants;
+        tpl
+    }
+
+    /// Report a match in terms of the original template: undo the autocrop
+    /// offset, which leaves the position negative when the stripped margin
+    /// would reach past the source, and attach the crop of the visible part
+    /// if requested
+    fn finish(&self, tpl: &SearchTemplate, mut r: MatchResult, source: Option<&DynamicImage>) -> PyResult<MatchResult> {
+        let tpl = tpl.variant(&r);
+        r.x -= tpl.offset.0 as i32;
+        r.y -= tpl.offset.1 as i32;
+        r.pixel_ratio = self.pixel_ratio;
+        if let (true, Some(img)) = (self.return_crop, source) {
+            let crop = if self.wrap {
+                wrapped_crop(img, r.x.max(0) as u32, r.y.max(0) as u32, tpl.footprint.0, tpl.footprint.1)
+            } else {
+                let (x, y, w, h) = visible_box(&r, tpl.footprint, img.dimensions());
+                img.crop_imm(x, y, w, h)
+            };
+            r.crop = Some(encode_png(&crop)?);
+        }
+        Ok(r)
+    }
+}
+
+/// The part of a `footprint`-sized match at the reported position that lies
+/// within a `size` source, as x, y, width and height
+fn visible_box(r: &MatchResult, footprint: (u32, u32), size: (u32, u32)) -> (u32, u32, u32, u32) {
+    let (x, y) = (r.x.max(0) as u32, r.y.max(0) as u32);
+    let w = footprint.0.saturating_sub(r.x.min(0).unsigned_abs()).min(size.0 - x);
+    let h = footprint.1.saturating_sub(r.y.min(0).unsigned_abs()).min(size.1 - y);
+    (x, y, w, h)
+}
+
+/// Template after the template-side options, plus what is needed to report
+/// results in terms of the original template
+struct SearchTemplate {
+    image: GrayImageData,
+    /// Offset of `image` within the original template (from autocrop)
+    offset: (u32, u32),
+    /// Size of the original template
+    footprint: (u32, u32),
+    /// Colours of `image`, kept for `color_prefilter`
+    colors: Option<ColorHistogram>,
+    /// Copies searched with the `flip` and `invert` options
+    variants: Vec<(Variant, SearchTemplate)>,
+}
+
+impl SearchTemplate {
+    /// This template mirrored left-right and/or top-bottom; the autocrop
+    /// offset is mirrored within the footprint along with the pixels
+    fn mirrored(&self, horizontal: bool, vertical: bool) -> SearchTemplate {
+        let (w, h) = (self.image.width, self.image.height);
+        let data = (0..w * h)
+            .map(|i| {
+                let (x, y) = (i % w, i / w);
+                let sx = if horizontal { w - 1 - x } else { x };
+                let sy = if vertical { h - 1 - y } else { y };
+                self.image.data[sy * w + sx]
+            })
+            .collect();
+        let offset = (
+            if horizontal { self.footprint.0 - self.offset.0 - w as u32 } else { self.offset.0 },
+            if vertical { self.footprint.1 - self.offset.1 - h as u32 } else { self.offset.1 },
+        );
+        SearchTemplate {
+            image: GrayImageData { data, width: w, height: h }, offset, footprint: self.footprint,
+            colors: self.colors.clone(), variants: Vec::new(),
+        }
+    }
+
+    /// This template with inverted grey levels, whose NCC with any window is
+    /// the negated NCC of the template
+    fn inverted(&self) -> SearchTemplate {
+        let data = self.image.data.iter().map(|v| 255.0 - v).collect();
+        SearchTemplate {
+            image: GrayImageData { data, ..self.image }, offset: self.offset, footprint: self.footprint,
+            colors: None, variants: Vec::new(),
+        }
+    }
+
+    /// The variant a result was found with, from its `flip` and `inverted` tags
+    fn variant(&self, r: &MatchResult) -> &SearchTemplate {
+        self.variants.iter()
+            .find(|(variant, _)| variant.flip == r.flip.as_deref() && variant.inverted == r.inverted)
+            .map_or(self, |(_, searched)| searched)
+    }
+}
+
+/// How a searched copy of the template differs from the template as given
+#[derive(Clone, Copy, Default)]
+struct Variant {
+    flip: Option<&'static str>,
+    inverted: bool,
+}
+
+impl Variant {
+    /// Tag a result found with this variant
+    fn tag(self, r: MatchResult) -> MatchResult {
+        MatchResult { flip: self.flip.map(str::to_string), inverted: self.inverted, ..r }
+    }
+}
+
+/// `flip` option: which mirrored copies of the template are searched too
+#[derive(Clone, Copy)]
+enum Flip {
+    Horizontal,
+    Vertical,
+    /// Both mirrors and their combination (a 180 degree rotation)
+    Both,
+}
+
+impl Flip {
+    /// (name, mirror left-right, mirror top-bottom) of each extra variant
+    fn variants(self) -> &'static [(&'static str, bool, bool)] {
+        match self {
+            Flip::Horizontal => &[("horizontal", true, false)],
+            Flip::Vertical => &[("vertical", false, true)],
+            Flip::Both => &[("horizontal", true, false), ("vertical", false, true), ("both", true, true)],
+        }
+    }
+}
+
+/// `flip` option: "horizontal", "vertical", "both" or None
+fn extract_flip(value: &PyAny) -> PyResult<Option<Flip>> {
+    if value.is_none() { return Ok(None); }
+    match value.extract::<&str>()? {
+        "horizontal" => Ok(Some(Flip::Horizontal)),
+        "vertical" => Ok(Some(Flip::Vertical)),
+        "both" => Ok(Some(Flip::Both)),
+        other => Err(PyValueError::new_err(format!(
+            "Unknown flip '{}', expected 'horizontal', 'vertical' or 'both'", other
+        ))),
+    }
+}
+
+/// Weight map given either as an image (path or bytes, 0-255 scaled to 0-1)
+/// or as a flat row-major sequence of non-negative numbers
+fn extract_weights(value: &PyAny) -> PyResult<Vec<f64>> {
+    let weights: Vec<f64> = match value.extract::<ImageInput>() {
+        Ok(image) => image.load()?.data.iter().map(|&v| v / 255.0).collect(),
+        Err(_) => value.extract()?,
+    };
+    if weights.iter().any(|&w| !(w >= 0.0 && w.is_finite())) {
+        return Err(PyValueError::new_err("weights must be finite and non-negative"));
+    }
+    if !weights.iter().any(|&w| w > 0.0) {
+        return Err(PyValueError::new_err("weights must not be all zero"));
+    }
+    Ok(weights)
+}
+
+/// `color_space` ("hsv" or "lab") and `channels` options: channel letters
+/// weighted equally ("hv"), or a dict of letter to weight ({"h": 1, "v": 0.5}).
+/// Without `channels` all three channels are mixed equally.
+fn extract_color(space: Option<&PyAny>, channels: Option<&PyAny>) -> PyResult<Option<ColorProjection>> {
+    let channels = channels.filter(|c| !c.is_none());
+    let space = match space.filter(|s| !s.is_none()) {
+        Some(space) => match space.extract::<&str>()? {
+            "hsv" => ColorSpace::Hsv,
+            "lab" => ColorSpace::Lab,
+            other => return Err(PyValueError::new_err(format!(
+                "Unknown color_space '{}', expected 'hsv' or 'lab'", other
+            ))),
+        },
+        None if channels.is_some() => return Err(PyValueError::new_err("channels requires color_space")),
+        None => return Ok(None),
+    };
+    let names = space.channel_names();
+    let mut weights = [0.0; 3];
+    let mut set = |name: &str, weight: f64| -> PyResult<()> {
+        let mut letters = name.chars().map(|c| c.to_ascii_lowercase());
+        let index = match (letters.next(), letters.next()) {
+            (Some(c), None) => names.iter().position(|&n| n == c),
+            _ => None,
+        };
+        let index = index.ok_or_else(|| PyValueError::new_err(format!(
+            "Unknown channel '{}' for color_space '{}', expected one of '{}', '{}', '{}'",
+            name, space.name(), names[0], names[1], names[2]
+        )))?;
+        if !(weight.is_finite() && weight >= 0.0) {
+            return Err(PyValueError::new_err("channel weights must be non-negative numbers"));
+        }
+        weights[index] = weight;
+        Ok(())
+    };
+    match channels {
+        None => weights = [1.0; 3],
+        Some(channels) => match channels.downcast::<PyDict>() {
+            Ok(dict) => for (name, weight) in dict { set(name.extract()?, weight.extract()?)?; },
+            Err(_) => for c in channels.extract::<&str>()?.chars() { set(&c.to_string(), 1.0)?; },
+        },
+    }
+    if weights.iter().sum::<f64>() <= 0.0 {
+        return Err(PyValueError::new_err("channels must give at least one channel a positive weight"));
+    }
+    Ok(Some(ColorProjection { space, weights }))
+}
+
+/// `color_prefilter` option: True (tolerance 0.1), False/None, or the
+/// fraction of template pixels whose colours a region may lack, in [0, 1)
+fn extract_color_prefilter(value: &PyAny) -> PyResult<Option<f64>> {
+    if value.is_none() { return Ok(None); }
+    if let Ok(flag) = value.extract::<bool>() {
+        return Ok(flag.then_some(DEFAULT_COLOR_PREFILTER_TOLERANCE));
+    }
+    let tolerance: f64 = value.extract()?;
+    if !(0.0..1.0).contains(&tolerance) {
+        return Err(PyValueError::new_err("color_prefilter tolerance must be in [0, 1)"));
+    }
+    Ok(Some(tolerance))
+}
+
+/// `gamma` option: a positive number or "auto"
+fn extract_gamma(value: &PyAny) -> PyResult<Option<Gamma>> {
+    if value.is_none() { return Ok(None); }
+    if let Ok(name) = value.extract::<&str>() {
+        return match name {
+            "auto" => Ok(Some(Gamma::Auto)),
+            _ => Err(PyValueError::new_err(format!("gamma must be a positive number or 'auto', got '{}'", name))),
+        };
+    }
+    let g: f64 = value.extract()?;
+    if !(g > 0.0 && g.is_finite()) {
+        return Err(PyValueError::new_err("gamma must be a positive number or 'auto'"));
+    }
+    Ok(Some(Gamma::Fixed(g)))
+}
+
+/// `timeout_ms` option: a positive number of milliseconds (or None)
+fn extract_timeout(value: &PyAny) -> PyResult<Option<Duration>> {
+    if value.is_none() { return Ok(None); }
+    let ms: f64 = value.extract()?;
+    if !(ms > 0.0 && ms.is_finite()) {
+        return Err(PyValueError::new_err("timeout_ms must be a positive number"));
+    }
+    Ok(Some(Duration::from_secs_f64(ms / 1000.0)))
+}
+
+/// `denoise` option: "median3" or "median5" (or None)
+fn extract_denoise(value: &PyAny) -> PyResult<Option<usize>> {
+    if value.is_none() { return Ok(None); }
+    match value.extract::<&str>()? {
+        "median3" => Ok(Some(1)),
+        "median5" => Ok(Some(2)),
+        other => Err(PyValueError::new_err(format!(
+            "Unknown denoise filter '{}', expected 'median3' or 'median5'", other
+        ))),
+    }
+}
+
+/// `pyramid_filter` option: "area", "bilinear" or "gaussian"
+fn extract_pyramid_filter(value: &PyAny) -> PyResult<DownsampleFilter> {
+    match value.extract::<&str>()? {
+        "area" => Ok(DownsampleFilter::Area),
+        "bilinear" => Ok(DownsampleFilter::Bilinear),
+        "gaussian" => Ok(DownsampleFilter::Gaussian),
+        other => Err(PyValueError::new_err(format!(
+            "Unknown pyramid_filter '{}', expected 'area', 'bilinear' or 'gaussian'", other
+        ))),
+    }
+}
+
+/// `path` option: None, or a non-empty list of `(x, y)` points
+fn extract_path(value: &PyAny) -> PyResult<Option<Vec<(f64, f64)>>> {
+    if value.is_none() { return Ok(None); }
+    let points: Vec<(f64, f64)> = value.extract()?;
+    if points.is_empty() || points.iter().any(|(x, y)| !(x.is_finite() && y.is_finite())) {
+        return Err(PyValueError::new_err("path must be a non-empty list of (x, y) points"));
+    }
+    Ok(Some(points))
+}
+
+/// Scale factors to try: a non-empty sequence of positive numbers (or None)
+fn extract_scale_candidates(value: &PyAny) -> PyResult<Option<Vec<f64>>> {
+    if value.is_none() { return Ok(None); }
+    let candidates: Vec<f64> = value.extract()?;
+    if candidates.is_empty() || candidates.iter().any(|&f| !(f > 0.0 && f.is_finite())) {
+        return Err(PyValueError::new_err("scale_candidates must be a non-empty list of positive numbers"));
+    }
+    Ok(Some(candidates))
+}
+
+// ============================================================================
+// Python Interface - File Path Based (No numpy needed!)
+// ============================================================================
+
+/// Run the work of a find call, returning its results alone or, with
+/// `return_diagnostics`, as `(results, MatchDiagnostics)` collected while it ran
+fn diagnosed<T: IntoPy<PyObject>>(
+    py: Python<'_>, return_diagnostics: bool, work: impl FnOnce() -> PyResult<T>,
+) -> PyResult<PyObject> {
+    if !return_diagnostics { return Ok(work()?.into_py(py)); }
+    let stats = Arc::new(SearchStats::default());
+    let found = SearchStats::collect(&stats, || Stats::current().time("total", work))?;
+    Ok((found, MatchDiagnostics::new(&stats)).into_py(py))
+}
+
+/// Find single best match using file paths
+/// 
+/// Args:
+///     source_path: Path to source image file
+///     template_path: Path to template image file
+///     threshold: Matching threshold (0.0-1.0), default 0.8
+///     return_diagnostics: Return (result, MatchDiagnostics) instead
+///     **options: Matching options, e.g. autocrop=True
+/// 
+/// Returns:
+///     MatchResult or None
+#[pyfunction]
+#[pyo3(signature = (source_path, template_path, threshold=0.8, return_diagnostics=false, **options))]
+fn find_template(
+    py: Python<'_>,
+    source_path: &str,
+    template_path: &str,
+    threshold: f64,
+    return_diagnostics: bool,
+    options: Option<&PyDict>,
+) -> PyResult<PyObject> {
+    let mut opts = MatchOptions::from_kwargs(options)?;
+    let inputs = [ImageInput::Path(source_path), ImageInput::Path(template_path)];
+    // Cached results carry no statistics
+    let key = if return_diagnostics { None } else { result_key("find", &inputs, &[threshold], options)? };
+    diagnosed(py, return_diagnostics, || {
+        let found = cached_results(key, || {
+            let (src, img) = opts.load_source(&inputs[0])?;
+            let src = opts.prepare_source(src);
+            let tpl = opts.load_template(&inputs[1])?;
+            opts.check_fits(&tpl, src.width, src.height)?;
+            
+            opts.find_best_in(&src, img.as_ref(), &tpl, threshold)?
+                .map(|r| opts.finish(&tpl, r, img.as_ref()))
+                .into_iter().collect()
+        })?;
+        Ok(found.into_iter().next())
+    })
+}
+
+/// Find all matches using file paths
+/// 
+/// Args:
+///     source_path: Path to source image file
+///     template_path: Path to template image file
+///     threshold: Matching threshold (0.0-1.0), default 0.8
+///     max_count: Maximum number of matches, default 10
+///     return_diagnostics: Return (results, MatchDiagnostics) instead
+///     **options: Matching options, e.g. autocrop=True
+/// 
+/// Returns:
+///     List of MatchResult objects
+#[pyfunction]
+#[pyo3(signature = (source_path, template_path, threshold=0.8, max_count=10, return_diagnostics=false, **options))]
+fn find_all_templates(
+    py: Python<'_>,
+    source_path: &str,
+    template_path: &str,
+    threshold: f64,
+    max_count: usize,
+    return_diagnostics: bool,
+    options: Option<&PyDict>,
+) -> PyResult<PyObject> {
+    let mut opts = MatchOptions::from_kwargs(options)?;
+    let inputs = [ImageInput::Path(source_path), ImageInput::Path(template_path)];
+    let key = if return_diagnostics { None } else { result_key("find_all", &inputs, &[threshold, max_count as f64], options)? };
+    diagnosed(py, return_diagnostics, || cached_results(key, || {
+        let (src, img) = opts.load_source(&inputs[0])?;
+        let src = opts.prepare_source(src);
+        let tpl = opts.load_template(&inputs[1])?;
+        opts.check_fits(&tpl, src.width, src.height)?;
+        
+        opts.find_all_in(&src, img.as_ref(), &tpl, threshold, max_count)?
+            .into_iter().map(|r| opts.finish(&tpl, r, img.as_ref())).collect()
+    }))
+}
+
+/// Find single best match in a very large image, one tile at a time
+/// 
+/// Args:
+///     source_path: Path to source image file
+///     template_path: Path to template image file
+///     threshold: Matching threshold (0.0-1.0), default 0.8
+///     tile_size: Tile edge length in pixels, default 2048
+///     **options: Matching options, e.g. autocrop=True
+/// 
+/// Returns:
+///     MatchResult or None
+#[pyfunction]
+#[pyo3(signature = (source_path, template_path, threshold=0.8, tile_size=2048, **options))]
+fn find_template_tiled(
+    source_path: &str,
+    template_path: &str,
+    threshold: f64,
+    tile_size: usize,
+    options: Option<&PyDict>,
+) -> PyResult<Option<MatchResult>> {
+    let mut opts = MatchOptions::from_kwargs(options)?;
+    check_tiled_options(&opts)?;
+    opts.check_luma_only("the tiled functions")?;
+    let tpl = opts.prepare_template(load_image_from_path(template_path, opts.exif_orientation)?)?;
+    check_tile_size(tile_size, tpl.image.width, tpl.image.height)?;
+    let mut rows = GrayRows::open(source_path, &opts)?;
+    opts.check_fits(&tpl, rows.width, rows.height)?;
+    
+    let result = tiled_match(&mut rows, &tpl.image, threshold, tile_size, &opts)?;
+    drop(rows);
+    let mut results: Vec<MatchResult> = result.map(|r| opts.finish(&tpl, r, None)).transpose()?.into_iter().collect();
+    // Crops come from the 8-bit grayscale source that was searched
+    attach_tiled_crops(source_path, &opts, &tpl, &mut results)?;
+    Ok(results.pop())
+}
+
+/// Find all matches in a very large image, one tile at a time
+/// 
+/// Args:
+///     source_path: Path to source image file
+///     template_path: Path to template image file
+///     threshold: Matching threshold (0.0-1.0), default 0.8
+///     max_count: Maximum number of matches, default 10
+///     tile_size: Tile edge length in pixels, default 2048
+///     **options: Matching options, e.g. autocrop=True
+/// 
+/// Returns:
+///     List of MatchResult objects
+#[pyfunction]
+#[pyo3(signature = (source_path, template_path, threshold=0.8, max_count=10, tile_size=2048, **options))]
+fn find_all_templates_tiled(
+    source_path: &str,
+    template_path: &str,
+    threshold: f64,
+    max_count: usize,
+    tile_size: usize,
+    options: Option<&PyDict>,
+) -> PyResult<Vec<MatchResult>> {
+    let mut opts = MatchOptions::from_kwargs(options)?;
+    check_tiled_options(&opts)?;
+    opts.check_luma_only("the tiled functions")?;
+    let tpl = opts.prepare_template(load_image_from_path(template_path, opts.exif_orientation)?)?;
+    check_tile_size(tile_size, tpl.image.width, tpl.image.height)?;
+    let mut rows = GrayRows::open(source_path, &opts)?;
+    opts.check_fits(&tpl, rows.width, rows.height)?;
+    
+    let results = tiled_match_multi(&mut rows, &tpl.image, threshold, max_count, tile_size, &opts)?;
+    drop(rows);
+    let mut results = results.into_iter().map(|r| opts.finish(&tpl, r, None)).collect::<PyResult<Vec<_>>>()?;
+    attach_tiled_crops(source_path, &opts, &tpl, &mut results)?;
+    Ok(results)
+}
+
+/// Find the best match on every page of a multi-page TIFF
+/// 
+/// Args:
+///     path: Path to a TIFF file (other formats are treated as a single page)
+///     template: Template as file path or encoded image bytes
+///     threshold: Matching threshold (0.0-1.0), default 0.8
+///     **options: Matching options, e.g. autocrop=True
+/// 
+/// Returns:
+///     List of (page_index, MatchResult) for pages with a match
+#[pyfunction]
+#[pyo3(signature = (path, template, threshold=0.8, **options))]
+fn find_template_pages(
+    path: &str,
+    template: ImageInput,
+    threshold: f64,
+    options: Option<&PyDict>,
+) -> PyResult<Vec<(usize, MatchResult)>> {
+    let mut opts = MatchOptions::from_kwargs(options)?;
+    opts.check_luma_only("find_pages")?;
+    let tpl = opts.prepare_template(template.load_as(opts.exif_orientation)?)?;
+    let mut hits = Vec::new();
+    let mut search = |page: usize, gray: GrayImage| -> PyResult<()> {
+        let src = opts.prepare_source(GrayImageData::from_gray_image(&gray));
+        // Thumbnail pages smaller than the template can't contain it
+        if opts.check_fits(&tpl, src.width, src.height).is_err() { return Ok(()); }
+        if let Some(r) = opts.find_best_in(&src, None, &tpl, threshold)? {
+            let img = opts.return_crop.then_some(DynamicImage::ImageLuma8(gray));
+            hits.push((page, opts.finish(&tpl, r, img.as_ref())?));
+        }
+        Ok(())
+    };
+    if is_tiff_file(path) {
+        for_each_tiff_page(path, search)?;
+    } else {
+        search(0, load_gray_from_path(path, opts.exif_orientation)?)?;
+    }
+    Ok(hits)
+}
+
+// ============================================================================
+// Python Interface - Bytes Based (No numpy needed!)
+// ============================================================================
+
+/// Size of the `read()` calls made on file-like inputs
+const READ_CHUNK: usize = 1 << 20;
+
+/// Encoded image given as bytes, or as a binary file-like object (anything
+/// with `read()`: open files, `io.BytesIO`, `sqlite3.Blob`, ...). File-like
+/// objects are read in chunks straight into a Rust buffer, so the image
+/// never exists as one large Python bytes object
+enum EncodedBytes<'a> {
+    Borrowed(&'a [u8]),
+    Read(Vec<u8>),
+}
+
+impl<'a> FromPyObject<'a> for EncodedBytes<'a> {
+    fn extract(ob: &'a PyAny) -> PyResult<Self> {
+        if let Ok(data) = ob.extract::<&[u8]>() {
+            return Ok(Self::Borrowed(data));
+        }
+        if !ob.hasattr("read")? {
+            return Err(PyTypeError::new_err("Expected image bytes or a binary file-like object with read()"));
+        }
+        let py = ob.py();
+        let read = ob.getattr("read")?.into_py(py);
+        let mut data = Vec::new();
+        loop {
+            // Owned, so each chunk is released as soon as it is copied
+            let chunk = read.call1(py, (READ_CHUNK,))?;
+            let chunk: &[u8] = chunk.extract(py)
+                .map_err(|_| PyTypeError::new_err("read() must return bytes; open the file in binary mode"))?;
+            if chunk.is_empty() { break; }
+            data.extend_from_slice(chunk);
+        }
+        Ok(Self::Read(data))
+    }
+}
+
+impl std::ops::Deref for EncodedBytes<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Borrowed(data) => data,
+            Self::Read(data) => data,
+        }
+    }
+}
+
+/// Find single best match using image bytes
+/// 
+/// Args:
+///     source_bytes: Source image as bytes (PNG, JPEG, etc.) or a binary
+///         file-like object
+///     template_bytes: Template image as bytes or a binary file-like object
+///     threshold: Matching threshold (0.0-1.0), default 0.8
+///     return_diagnostics: Return (result, MatchDiagnostics) instead
+///     **options: Matching options, e.g. autocrop=True
+/// 
+/// Returns:
+///     MatchResult or None
+#[pyfunction]
+#[pyo3(signature = (source_bytes, template_bytes, threshold=0.8, return_diagnostics=false, **options))]
+fn find_template_bytes(
+    py: Python<'_>,
+    source_bytes: EncodedBytes,
+    template_bytes: EncodedBytes,
+    threshold: f64,
+    return_diagnostics: bool,
+    options: Option<&PyDict>,
+) -> PyResult<PyObject> {
+    let mut opts = MatchOptions::from_kwargs(options)?;
+    let inputs = [ImageInput::Bytes(&source_bytes), ImageInput::Bytes(&template_bytes)];
+    let key = if return_diagnostics { None } else { result_key("find_bytes", &inputs, &[threshold], options)? };
+    diagnosed(py, return_diagnostics, || {
+        let found = cached_results(key, || {
+            let img = Stats::current().time("decode", || load_dynamic_from_bytes(&source_bytes, opts.exif_orientation))?;
+            let src = opts.prepare_source(opts.plane(&img));
+            let tpl = opts.load_template(&inputs[1])?;
+            opts.check_fits(&tpl, src.width, src.height)?;
+            
+            opts.find_best_in(&src, Some(&img), &tpl, threshold)?
+                .map(|r| opts.finish(&tpl, r, Some(&img)))
+                .into_iter().collect()
+        })?;
+        Ok(found.into_iter().next())
+    })
+}
+
+/// Find all matches using image bytes
+/// 
+/// Args:
+///     source_bytes: Source image as bytes (PNG, JPEG, etc.) or a binary
+///         file-like object
+///     template_bytes: Template image as bytes or a binary file-like object
+///     threshold: Matching threshold (0.0-1.0), default 0.8
+///     max_count: Maximum number of matches, default 10
+///     return_diagnostics: Return (results, MatchDiagnostics) instead
+///     **options: Matching options, e.g. autocrop=True
+/// 
+/// Returns:
+///     List of MatchResult objects
+#[pyfunction]
+#[pyo3(signature = (source_bytes, template_bytes, threshold=0.8, max_count=10, return_diagnostics=false, **options))]
+fn find_all_templates_bytes(
+    py: Python<'_>,
+    source_bytes: EncodedBytes,
+    template_bytes: EncodedBytes,
+    threshold: f64,
+    max_count: usize,
+    return_diagnostics: bool,
+    options: Option<&PyDict>,
+) -> PyResult<PyObject> {
+    let mut opts = MatchOptions::from_kwargs(options)?;
+    let inputs = [ImageInput::Bytes(&source_bytes), ImageInput::Bytes(&template_bytes)];
+    let key = if return_diagnostics { None } else { result_key("find_all_bytes", &inputs, &[threshold, max_count as f64], options)? };
+    diagnosed(py, return_diagnostics, || cached_results(key, || {
+        let img = Stats::current().time("decode", || load_dynamic_from_bytes(&source_bytes, opts.exif_orientation))?;
+        let src = opts.prepare_source(opts.plane(&img));
+        let tpl = opts.load_template(&inputs[1])?;
+        opts.check_fits(&tpl, src.width, src.height)?;
+        
+        opts.find_all_in(&src, Some(&img), &tpl, threshold, max_count)?
+            .into_iter().map(|r| opts.finish(&tpl, r, Some(&img))).collect()
+    }))
+}
+
+/// Bytes per record in `find_all_templates_packed` output
+const MATCH_RECORD_SIZE: usize = 16;
+
+/// Find all matches, packed into one bytes object instead of one MatchResult
+/// per match
+/// 
+/// Each match is a 16-byte little-endian record: x (int32), y (int32) and
+/// confidence (float64), strongest first. The Python side views it as a
+/// numpy structured array without copying.
+/// 
+/// Args:
+///     source: Source image as file path or encoded bytes
+///     template: Template image as file path or encoded bytes
+///     threshold: Matching threshold (0.0-1.0), default 0.8
+///     max_count: Maximum number of matches, default 10
+///     **options: Matching options, e.g. autocrop=True
+/// 
+/// Returns:
+///     bytes of `16 * len(matches)`
+#[pyfunction]
+#[pyo3(signature = (source, template, threshold=0.8, max_count=10, **options))]
+fn find_all_templates_packed<'py>(
+    py: Python<'py>,
+    source: ImageInput,
+    template: ImageInput,
+    threshold: f64,
+    max_count: usize,
+    options: Option<&PyDict>,
+) -> PyResult<&'py PyBytes> {
+    let mut opts = MatchOptions::from_kwargs(options)?;
+    if opts.return_crop {
+        return Err(PyValueError::new_err("return_crop is not supported for packed results"));
+    }
+    let (src, img) = opts.load_source(&source)?;
+    let src = opts.prepare_source(src);
+    let tpl = opts.load_template(&template)?;
+    opts.check_fits(&tpl, src.width, src.height)?;
+    
+    let results = opts.find_all_in(&src, img.as_ref(), &tpl, threshold, max_count)?;
+    PyBytes::new_with(py, results.len() * MATCH_RECORD_SIZE, |buf| {
+        for (record, r) in buf.chunks_exact_mut(MATCH_RECORD_SIZE).zip(results) {
+            let r = opts.finish(&tpl, r, None)?;
+            record[..4].copy_from_slice(&r.x.to_le_bytes());
+            record[4..8].copy_from_slice(&r.y.to_le_bytes());
+            record[8..].copy_from_slice(&r.confidence.to_le_bytes());
+        }
+        Ok(())
+    })
+}
+
+// ============================================================================
+// Python Interface - Raw Pixel Data (List of integers, no numpy!)
+// ============================================================================
+
+/// Raw 8-bit pixels handed over from Python. Anything exporting a contiguous
+/// byte buffer (bytes, bytearray, memoryview, `SharedMemory.buf`, a uint8
+/// array) is read in place, so frames living in shared memory are never
+/// copied into a Python object first; lists of ints still work
+enum PixelBuffer {
+    Buffer(PyBuffer<u8>),
+    List(Vec<u8>),
+}
+
+impl<'a> FromPyObject<'a> for PixelBuffer {
+    fn extract(ob: &'a PyAny) -> PyResult<Self> {
+        match PyBuffer::<u8>::get(ob) {
+            Ok(buffer) if buffer.is_c_contiguous() => Ok(Self::Buffer(buffer)),
+            Ok(_) => Err(PyValueError::new_err("Pixel buffer must be C-contiguous")),
+            Err(_) => Ok(Self::List(ob.extract()?)),
+        }
+    }
+}
+
+impl PixelBuffer {
+    fn bytes(&self) -> &[u8] {
+        match self {
+            // The view stays exported (and the memory mapped) until `self` is dropped
+            Self::Buffer(b) => unsafe { std::slice::from_raw_parts(b.buf_ptr() as *const u8, b.item_count()) },
+            Self::List(v) => v,
+        }
+    }
+
+    /// The `width * height` pixels of one frame. Without an offset the buffer
+    /// must hold exactly one frame; with one, the frame starts `offset` bytes
+    /// in and anything after it is ignored (ring buffers, page-rounded blocks)
+    fn frame(&self, width: usize, height: usize, offset: Option<usize>, what: &str) -> PyResult<&[u8]> {
+        let bytes = self.bytes();
+        let len = width.checked_mul(height);
+        let frame = match offset {
+            None => len.filter(|&n| n == bytes.len()).map(|_| bytes),
+            Some(start) => len.and_then(|n| bytes.get(start..start.checked_add(n)?)),
+        };
+        frame.ok_or_else(|| match offset {
+            None => InvalidDimensionsError::new_err(format!("{} pixel count doesn't match dimensions", what)),
+            Some(start) => InvalidDimensionsError::new_err(format!(
+                "{} buffer ({} bytes) is too small for a {}x{} frame at offset {}", what, bytes.len(), width, height, start
+            )),
+        })
+    }
+}
+
+fn raw_gray(pixels: &[u8], width: usize, height: usize) -> GrayImageData {
+    GrayImageData { data: pixels.iter().map(|&v| v as f64).collect(), width, height }
+}
+
+/// Find single best match using raw pixel data as flat list
+/// 
+/// Args:
+///     source_pixels: Source pixels (0-255) as a flat list or any contiguous
+///         byte buffer (bytes, bytearray, memoryview, `SharedMemory.buf`)
+///     source_width: Source image width
+///     source_height: Source image height
+///     template_pixels: Template pixels, in any of the same forms
+///     template_width: Template width
+///     template_height: Template height
+///     threshold: Matching threshold (0.0-1.0), default 0.8
+///     source_offset: Byte offset of the frame inside `source_pixels`; when
+///         set, the buffer may be larger than one frame
+///     **options: Matching options, e.g. autocrop=True
+/// 
+/// Returns:
+///     MatchResult or None
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (source_pixels, source_width, source_height, template_pixels, template_width, template_height, threshold=0.8, source_offset=None, **options))]
+fn find_template_raw(
+    source_pixels: PixelBuffer,
+    source_width: usize,
+    source_height: usize,
+    template_pixels: PixelBuffer,
+    template_width: usize,
+    template_height: usize,
+    threshold: f64,
+    source_offset: Option<usize>,
+    options: Option<&PyDict>,
+) -> PyResult<Option<MatchResult>> {
+    let mut opts = MatchOptions::from_kwargs(options)?;
+    opts.check_luma_only("find_raw")?;
+    let source = source_pixels.frame(source_width, source_height, source_offset, "Source")?;
+    let template = template_pixels.frame(template_width, template_height, None, "Template")?;
+    
+    let src = opts.prepare_source(raw_gray(source, source_width, source_height));
+    let tpl = opts.prepare_template(raw_gray(template, template_width, template_height))?;
+    opts.check_fits(&tpl, src.width, src.height)?;
+    let original = if opts.return_crop {
+        GrayImage::from_raw(source_width as u32, source_height as u32, source.to_vec()).map(DynamicImage::ImageLuma8)
+    } else { None };
+    
+    opts.find_best_in(&src, None, &tpl, threshold)?
+        .map(|r| opts.finish(&tpl, r, original.as_ref()))
+        .transpose()
+}
+
+/// Find all matches using raw pixel data as flat list
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (source_pixels, source_width, source_height, template_pixels, template_width, template_height, threshold=0.8, max_count=10, source_offset=None, **options))]
+fn find_all_templates_raw(
+    source_pixels: PixelBuffer,
+    source_width: usize,
+    source_height: usize,
+    template_pixels: PixelBuffer,
+    template_width: usize,
+    template_height: usize,
+    threshold: f64,
+    max_count: usize,
+    source_offset: Option<usize>,
+    options: Option<&PyDict>,
+) -> PyResult<Vec<MatchResult>> {
+    let mut opts = MatchOptions::from_kwargs(options)?;
+    opts.check_luma_only("find_raw")?;
+    let source = source_pixels.frame(source_width, source_height, source_offset, "Source")?;
+    let template = template_pixels.frame(template_width, template_height, None, "Template")?;
+    
+    let src = opts.prepare_source(raw_gray(source, source_width, source_height));
+    let tpl = opts.prepare_template(raw_gray(template, template_width, template_height))?;
+    opts.check_fits(&tpl, src.width, src.height)?;
+    let original = if opts.return_crop {
+        GrayImage::from_raw(source_width as u32, source_height as u32, source.to_vec()).map(DynamicImage::ImageLuma8)
+    } else { None };
+    
+    opts.find_all_in(&src, None, &tpl, threshold, max_count)?
+        .into_iter().map(|r| opts.finish(&tpl, r, original.as_ref())).collect()
+}
+
+/// Luma taken straight from the Y plane of a planar YUV frame (NV12, NV21,
+/// I420, YV12, ...), which comes first in the buffer; chroma is ignored
+fn y_plane(frame: &[u8], width: usize, height: usize, stride: Option<usize>) -> PyResult<GrayImageData> {
+    let stride = stride.unwrap_or(width);
+    if width == 0 || height == 0 || stride < width {
+        return Err(InvalidDimensionsError::new_err("Frame width and height must be positive and stride at least the width"));
+    }
+    let needed = stride.checked_mul(height - 1).and_then(|n| n.checked_add(width));
+    if needed.map_or(true, |n| frame.len() < n) {
+        return Err(InvalidDimensionsError::new_err(format!(
+            "Frame buffer ({} bytes) is too small for a {}x{} Y plane with stride {}", frame.len(), width, height, stride
+        )));
+    }
+    let data = frame.chunks(stride).take(height).flat_map(|row| row[..width].iter().map(|&v| v as f64)).collect();
+    Ok(GrayImageData { data, width, height })
+}
+
+/// Y plane as an 8-bit image, for `return_crop`
+fn y_plane_image(src: &GrayImageData) -> Option<DynamicImage> {
+    let pixels = src.data.iter().map(|&v| v as u8).collect();
+    GrayImage::from_raw(src.width as u32, src.height as u32, pixels).map(DynamicImage::ImageLuma8)
+}
+
+/// Find single best match in a planar YUV frame, using its Y plane as luma
+/// 
+/// Args:
+///     frame: Frame buffer (NV12, I420, ...) or just its Y plane
+///     width: Frame width in pixels
+///     height: Frame height in pixels
+///     template: Template as file path or encoded image bytes
+///     threshold: Matching threshold (0.0-1.0), default 0.8
+///     stride: Bytes per Y row, default width
+///     **options: Matching options, e.g. autocrop=True
+/// 
+/// Returns:
+///     MatchResult or None
+#[pyfunction]
+#[pyo3(signature = (frame, width, height, template, threshold=0.8, stride=None, **options))]
+fn find_template_yuv(
+    frame: &[u8],
+    width: usize,
+    height: usize,
+    template: ImageInput,
+    threshold: f64,
+    stride: Option<usize>,
+    options: Option<&PyDict>,
+) -> PyResult<Option<MatchResult>> {
+    let mut opts = MatchOptions::from_kwargs(options)?;
+    opts.check_luma_only("find_yuv")?;
+    let luma = y_plane(frame, width, height, stride)?;
+    let original = if opts.return_crop { y_plane_image(&luma) } else { None };
+    let src = opts.prepare_source(luma);
+    let tpl = opts.prepare_template(template.load_as(opts.exif_orientation)?)?;
+    opts.check_fits(&tpl, src.width, src.height)?;
+    
+    opts.find_best_in(&src, None, &tpl, threshold)?
+        .map(|r| opts.finish(&tpl, r, original.as_ref()))
+        .transpose()
+}
+
+/// Find all matches in a planar YUV frame, using its Y plane as luma
+#[pyfunction]
+#[allow(clippy::too_many_arguments)]
+#[pyo3(signature = (frame, width, height, template, threshold=0.8, max_count=10, stride=None, **options))]
+fn find_all_templates_yuv(
+    frame: &[u8],
+    width: usize,
+    height: usize,
+    template: ImageInput,
+    threshold: f64,
+    max_count: usize,
+    stride: Option<usize>,
+    options: Option<&PyDict>,
+) -> PyResult<Vec<MatchResult>> {
+    let mut opts = MatchOptions::from_kwargs(options)?;
+    opts.check_luma_only("find_yuv")?;
+    let luma = y_plane(frame, width, height, stride)?;
+    let original = if opts.return_crop { y_plane_image(&luma) } else { None };
+    let src = opts.prepare_source(luma);
+    let tpl = opts.prepare_template(template.load_as(opts.exif_orientation)?)?;
+    opts.check_fits(&tpl, src.width, src.height)?;
+    
+    opts.find_all_in(&src, None, &tpl, threshold, max_count)?
+        .into_iter().map(|r| opts.finish(&tpl, r, original.as_ref())).collect()
+}
+
+// ============================================================================
+// Python Interface - Tensors (__array_interface__ / DLPack)
+// ============================================================================
+
+#[derive(Clone, Copy)]
+enum TensorDtype { U8, U16, F32, F64 }
+
+impl TensorDtype {
+    fn size(self) -> usize {
+        match self { Self::U8 => 1, Self::U16 => 2, Self::F32 => 4, Self::F64 => 8 }
+    }
+
+    /// numpy typestr such as "|u1" or "<f4", in native byte order
+    fn from_typestr(typestr: &str) -> Option<Self> {
+        let native = if cfg!(target_endian = "little") { '<' } else { '>' };
+        match typestr.strip_prefix(['|', '=', native])? {
+            "u1" => Some(Self::U8), "u2" => Some(Self::U16), "f4" => Some(Self::F32), "f8" => Some(Self::F64),
+            _ => None,
+        }
+    }
+
+    /// DLPack type code (1 = unsigned int, 2 = float), bits and lanes
+    fn from_dlpack(code: u8, bits: u8, lanes: u16) -> Option<Self> {
+        match (code, bits, lanes) {
+            (1, 8, 1) => Some(Self::U8), (1, 16, 1) => Some(Self::U16),
+            (2, 32, 1) => Some(Self::F32), (2, 64, 1) => Some(Self::F64),
+            _ => None,
+        }
+    }
+
+    /// # Safety
+    /// `p` must point to a readable value of this type
+    unsafe fn read(self, p: *const u8) -> f64 {
+        match self {
+            Self::U8 => *p as f64,
+            Self::U16 => (p as *const u16).read_unaligned() as f64,
+            Self::F32 => (p as *const f32).read_unaligned() as f64,
+            Self::F64 => (p as *const f64).read_unaligned(),
+        }
+    }
+}
+
+/// Header of a DLPack tensor, which is also the start of `DLManagedTensor`
+#[repr(C)]
+struct DLTensor {
+    data: *mut std::os::raw::c_void,
+    device_type: i32,
+    device_id: i32,
+    ndim: i32,
+    dtype_code: u8,
+    dtype_bits: u8,
+    dtype_lanes: u16,
+    shape: *const i64,
+    /// In elements; null for C-contiguous
+    strides: *const i64,
+    byte_offset: u64,
+}
+
+/// Pixels borrowed in place from an object exposing `__array_interface__`
+/// (numpy, ...) or `__dlpack__` (torch, jax, ...). `_owner` is the array or
+/// the DLPack capsule, which keeps the memory alive while it is read
+struct TensorView<'py> {
+    data: *const u8,
+    dtype: TensorDtype,
+    shape: Vec<usize>,
+    /// In bytes, one per dimension
+    strides: Vec<isize>,
+    _owner: &'py PyAny,
+}
+
+fn contiguous_strides(shape: &[usize], item: usize) -> Vec<isize> {
+    let mut strides = vec![item as isize; shape.len()];
+    for i in (0..shape.len().saturating_sub(1)).rev() {
+        strides[i] = strides[i + 1] * shape[i + 1] as isize;
+    }
+    strides
+}
+
+fn unsupported_dtype(name: &str) -> PyErr {
+    PyTypeError::new_err(format!("Unsupported tensor dtype {} (expected uint8, uint16, float32 or float64)", name))
+}
+
+impl<'py> TensorView<'py> {
+    fn from_array_interface(ob: &'py PyAny) -> PyResult<Self> {
+        let iface: &PyDict = ob.getattr("__array_interface__")?.downcast()?;
+        let field = |key: &str| -> PyResult<&PyAny> {
+            iface.get_item(key)?.filter(|v| !v.is_none())
+                .ok_or_else(|| PyTypeError::new_err(format!("__array_interface__ has no '{}'", key)))
+        };
+        let typestr: &str = field("typestr")?.extract()?;
+        let dtype = TensorDtype::from_typestr(typestr).ok_or_else(|| unsupported_dtype(typestr))?;
+        let shape: Vec<usize> = field("shape")?.extract()?;
+        let (ptr, _readonly): (usize, bool) = field("data")?.extract()
+            .map_err(|_| PyTypeError::new_err("__array_interface__ data must be a (pointer, read-only) tuple"))?;
+        let strides = match field("strides") {
+            Ok(strides) => strides.extract()?,
+            Err(_) => contiguous_strides(&shape, dtype.size()),
+        };
+        Ok(Self { data: ptr as *const u8, dtype, shape, strides, _owner: ob })
+    }
+
+    fn from_dlpack(ob: &'py PyAny) -> PyResult<Self> {
+        let exported = ob.call_method0("__dlpack__")?;
+        let capsule: &PyCapsule = exported.downcast()?;
+        if capsule.name()?.map_or(true, |name| name.to_bytes() != b"dltensor") {
+            return Err(PyTypeError::new_err("__dlpack__ did not return an unused 'dltensor' capsule"));
+        }
+        // The capsule is read without being consumed, so the producer's
+        // destructor still frees the tensor once it is dropped
+        let t = unsafe { &*(capsule.pointer() as *const DLTensor) };
+        // kDLCPU or kDLCUDAHost (pinned host memory)
+        if !matches!(t.device_type, 1 | 3) {
+            return Err(PyValueError::new_err("Only CPU tensors are supported; move the tensor to the CPU first"));
+        }
+        let dtype = TensorDtype::from_dlpack(t.dtype_code, t.dtype_bits, t.dtype_lanes).ok_or_else(|| {
+            unsupported_dtype(&format!("(code {}, {} bits, {} lanes)", t.dtype_code, t.dtype_bits, t.dtype_lanes))
+        })?;
+        let ndim = t.ndim.max(0) as usize;
+        let shape: Vec<usize> = (0..ndim).map(|i| unsafe { *t.shape.add(i) } as usize).collect();
+        let strides = if t.strides.is_null() {
+            contiguous_strides(&shape, dtype.size())
+        } else {
+            (0..ndim).map(|i| unsafe { *t.strides.add(i) } as isize * dtype.size() as isize).collect()
+        };
+        let data = unsafe { (t.data as *const u8).add(t.byte_offset as usize) };
+        Ok(Self { data, dtype, shape, strides, _owner: exported })
+    }
+
+    /// Grayscale copy: (H, W) and (H, W, 1) as they are, (H, W, 3|4) through
+    /// the same luma weights as decoded images. Float data is multiplied by
+    /// `float_scale`, bringing it to 0-255, the range the flat-window cutoff
+    /// assumes.
+    fn to_gray(&self, float_scale: f64) -> PyResult<GrayImageData> {
+        let (height, width, channels) = match self.shape[..] {
+            [h, w] => (h, w, 1),
+            [h, w, c @ (1 | 3 | 4)] => (h, w, c),
+            _ => return Err(InvalidDimensionsError::new_err(format!(
+                "Tensor must be (H, W) or channels-last (H, W, 1|3|4), got shape {:?}", self.shape
+            ))),
+        };
+        if width == 0 || height == 0 {
+            return Err(InvalidDimensionsError::new_err("Tensor is empty"));
+        }
+        let channel_stride = self.strides.get(2).copied().unwrap_or(0);
+        let at = |y: usize, x: usize, c: usize| unsafe {
+            self.dtype.read(self.data.offset(y as isize * self.strides[0] + x as isize * self.strides[1] + c as isize * channel_stride))
+        };
+        let mut data: Vec<f64> = (0..height).flat_map(|y| (0..width).map(move |x| (y, x))).map(|(y, x)| {
+            if channels < 3 { at(y, x, 0) } else { 0.2126 * at(y, x, 0) + 0.7152 * at(y, x, 1) + 0.0722 * at(y, x, 2) }
+        }).collect();
+        if matches!(self.dtype, TensorDtype::F32 | TensorDtype::F64) {
+            data.iter_mut().for_each(|v| *v *= float_scale);
+        }
+        Ok(GrayImageData { data, width, height })
+    }
+
+    /// 8-bit image for `return_crop` from `to_gray`'s pixels: uint16 is
+    /// scaled down, floats are already in 0-255
+    fn crop_image(&self, gray: &GrayImageData) -> Option<DynamicImage> {
+        let scale = match self.dtype {
+            TensorDtype::U16 => 1.0 / 257.0,
+            TensorDtype::U8 | TensorDtype::F32 | TensorDtype::F64 => 1.0,
+        };
+        let pixels = gray.data.iter().map(|&v| (v * scale).round().clamp(0.0, 255.0) as u8).collect();
+        GrayImage::from_raw(gray.width as u32, gray.height as u32, pixels).map(DynamicImage::ImageLuma8)
+    }
+}
+
+/// Image argument given as a tensor, a file path or encoded bytes
+enum TensorInput<'py> {
+    Tensor(TensorView<'py>),
+    Image(ImageInput<'py>),
+}
+
+impl<'py> FromPyObject<'py> for TensorInput<'py> {
+    fn extract(ob: &'py PyAny) -> PyResult<Self> {
+        if ob.hasattr("__array_interface__")? {
+            Ok(Self::Tensor(TensorView::from_array_interface(ob)?))
+        } else if ob.hasattr("__dlpack__")? {
+            Ok(Self::Tensor(TensorView::from_dlpack(ob)?))
+        } else {
+            ob.extract().map(Self::Image).map_err(|_| PyTypeError::new_err(
+                "Expected a tensor (__array_interface__ or __dlpack__), a file path or image bytes"
+            ))
+        }
+    }
+}
+
+impl TensorInput<'_> {
+    fn load(&self, opts: &MatchOptions) -> PyResult<GrayImageData> {
+        match self {
+            Self::Tensor(t) => t.to_gray(opts.float_scale),
+            Self::Image(image) => image.load_as(opts.exif_orientation),
+        }
+    }
+
+    /// Grayscale source plus, with `return_crop`, the image crops are cut from
+    fn load_source(&self, opts: &MatchOptions) -> PyResult<(GrayImageData, Option<DynamicImage>)> {
+        let gray = self.load(opts)?;
+        let original = match self {
+            _ if !opts.return_crop => None,
+            Self::Tensor(t) => t.crop_image(&gray),
+            Self::Image(image) => Some(image.load_dynamic_as(opts.exif_orientation)?),
+        };
+        Ok((gray, original))
+    }
+}
+
+/// numpy array argument of the `_np` functions: a C-contiguous (H, W) or
+/// channels-last (H, W, 1|3|4) array of dtype uint8, uint16, float32 or
+/// float64, read in place
+struct NdArray<'py>(TensorView<'py>);
+
+impl<'py> FromPyObject<'py> for NdArray<'py> {
+    fn extract(ob: &'py PyAny) -> PyResult<Self> {
+        if !ob.hasattr("__array_interface__")? {
+            return Err(PyTypeError::new_err(format!("Expected a numpy array, got {}", ob.get_type().name()?)));
+        }
+        let view = TensorView::from_array_interface(ob)?;
+        if !matches!(view.shape[..], [_, _] | [_, _, 1 | 3 | 4]) {
+            return Err(InvalidDimensionsError::new_err(format!(
+                "Array must be 2D (H, W) or 3D channels-last (H, W, 1|3|4), got shape {:?}", view.shape
+            )));
+        }
+        if view.strides != contiguous_strides(&view.shape, view.dtype.size()) {
+            return Err(PyValueError::new_err(format!(
+                "Array of shape {:?} is not C-contiguous; pass numpy.ascontiguousarray(array)", view.shape
+            )));
+        }
+        Ok(Self(view))
+    }
+}
+
+/// Find single best match in numpy arrays
+/// 
+/// Args:
+///     source_array: numpy array, (H, W) grayscale or (H, W, C) channels-last
+///         with C = 1, 3 or 4, of dtype uint8, uint16, float32 or float64
+///         (floats in 0-1 unless `float_scale` says otherwise), C-contiguous
+///     template_array: Template array with the same requirements
+///     threshold: Matching threshold (0.0-1.0), default 0.8
+///     **options: Matching options, e.g. autocrop=True
+/// 
+/// Returns:
+///     MatchResult or None
+#[pyfunction]
+#[pyo3(signature = (source_array, template_array, threshold=0.8, **options))]
+fn find_template_np(
+    source_array: NdArray,
+    template_array: NdArray,
+    threshold: f64,
+    options: Option<&PyDict>,
+) -> PyResult<Option<MatchResult>> {
+    find_template_tensor(TensorInput::Tensor(source_array.0), TensorInput::Tensor(template_array.0), threshold, options)
+}
+
+/// Find all matches in numpy arrays
+#[pyfunction]
+#[pyo3(signature = (source_array, template_array, threshold=0.8, max_count=10, **options))]
+fn find_all_templates_np(
+    source_array: NdArray,
+    template_array: NdArray,
+    threshold: f64,
+    max_count: usize,
+    options: Option<&PyDict>,
+) -> PyResult<Vec<MatchResult>> {
+    find_all_templates_tensor(TensorInput::Tensor(source_array.0), TensorInput::Tensor(template_array.0), threshold, max_count, options)
+}
+
+/// Find single best match in a tensor, read in place
+/// 
+/// Args:
+///     source: Object exposing `__array_interface__` (numpy) or `__dlpack__`
+///         (CPU torch tensor, ...), shaped (H, W) or (H, W, 1|3|4), of dtype
+///         uint8, uint16, float32 or float64; or a file path or image bytes
+///     template: Template in any of the same forms
+///     threshold: Matching threshold (0.0-1.0), default 0.8
+///     **options: Matching options, e.g. autocrop=True
+/// 
+/// Returns:
+///     MatchResult or None
+#[pyfunction]
+#[pyo3(signature = (source, template, threshold=0.8, **options))]
+fn find_template_tensor(
+    source: TensorInput,
+    template: TensorInput,
+    threshold: f64,
+    options: Option<&PyDict>,
+) -> PyResult<Option<MatchResult>> {
+    let mut opts = MatchOptions::from_kwargs(options)?;
+    opts.check_luma_only("find_tensor")?;
+    let (gray, original) = source.load_source(&opts)?;
+    let src = opts.prepare_source(gray);
+    let tpl = opts.prepare_template(template.load(&opts)?)?;
+    opts.check_fits(&tpl, src.width, src.height)?;
+    
+    opts.find_best_in(&src, None, &tpl, threshold)?
+        .map(|r| opts.finish(&tpl, r, original.as_ref()))
+        .transpose()
+}
+
+/// Find all matches in a tensor, read in place
+#[pyfunction]
+#[pyo3(signature = (source, template, threshold=0.8, max_count=10, **options))]
+fn find_all_templates_tensor(
+    source: TensorInput,
+    template: TensorInput,
+    threshold: f64,
+    max_count: usize,
+    options: Option<&PyDict>,
+) -> PyResult<Vec<MatchResult>> {
+    let mut opts = MatchOptions::from_kwargs(options)?;
+    opts.check_luma_only("find_tensor")?;
+    let (gray, original) = source.load_source(&opts)?;
+    let src = opts.prepare_source(gray);
+    let tpl = opts.prepare_template(template.load(&opts)?)?;
+    opts.check_fits(&tpl, src.width, src.height)?;
+    
+    opts.find_all_in(&src, None, &tpl, threshold, max_count)?
+        .into_iter().map(|r| opts.finish(&tpl, r, original.as_ref())).collect()
+}
+
+// ============================================================================
+// Python Interface - Lazy Iteration
+// ============================================================================
+
+/// Iterate over matches in descending confidence, suppressing overlaps lazily
+/// 
+/// The source is scanned once up front; non-maximum suppression then runs
+/// one result at a time, so consumers can stop early on their own criteria.
+/// 
+/// Args:
+///     source: Source image as file path or encoded bytes
+///     template: Template image as file path or encoded bytes
+///     threshold: Matching threshold (0.0-1.0), default 0.8
+///     **options: Matching options, e.g. autocrop=True
+/// 
+/// Returns:
+///     MatchIterator yielding MatchResult objects
+#[pyfunction]
+#[pyo3(signature = (source, template, threshold=0.8, **options))]
+fn find_iter(
+    py: Python<'_>, source: ImageInput, template: ImageInput, threshold: f64, options: Option<&PyDict>,
+) -> PyResult<MatchIterator> {
+    let mut opts = MatchOptions::from_kwargs(options)?;
+    let img = source.load_dynamic_as(opts.exif_orientation)?;
+    let src = opts.prepare_source(opts.plane(&img));
+    let tpl = opts.load_template(&template)?;
+    opts.check_fits(&tpl, src.width, src.height)?;
+    
+    let (mut candidates, size) = py.allow_threads(|| {
+        let search = || opts.within_timeout(|| opts.find_candidates_in(&src, Some(&img), &tpl, threshold));
+        opts.debugged(&src, &tpl, search, |(candidates, _)| candidates.clone())
+    })?;
+    sort_by_confidence(&mut candidates);
+    let source = opts.return_crop.then_some(img);
+    Ok(MatchIterator { candidates: candidates.into_iter(), kept: OverlapGrid::new(size.0, size.1), opts, tpl, source })
+}
+
+/// Iterator returned by `find_iter()`, yielding matches in descending confidence
+#[pyclass]
+pub struct MatchIterator {
+    /// Refined matches not yet considered, strongest first
+    candidates: std::vec::IntoIter<MatchResult>,
+    /// Matches yielded so far, in search coordinates, bucketed by the size of
+    /// the template searched
+    kept: OverlapGrid,
+    opts: MatchOptions,
+    tpl: SearchTemplate,
+    /// Decoded source, kept only for `return_crop`
+    source: Option<DynamicImage>,
+}
+
+#[pymethods]
+impl MatchIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+    
+    fn __next__(&mut self) -> PyResult<Option<MatchResult>> {
+        for r in self.candidates.by_ref() {
+            if self.kept.overlaps(&r) { continue; }
+            self.kept.insert(r.clone());
+            return self.opts.finish(&self.tpl, r, self.source.as_ref()).map(Some);
+        }
+        Ok(None)
+    }
+}
+
+/// Region already handled, passed to `find_next`: a previous match, which
+/// covers the template's footprint, or an `(x, y, width, height)` box
+#[derive(FromPyObject)]
+enum FoundRegion<'a> {
+    Match(PyRef<'a, MatchResult>),
+    Box((i32, i32, u32, u32)),
+}
+
+/// Find the best match outside the regions already handled
+/// 
+/// Matches whose centre falls inside a found region are skipped, so a
+/// previous match rules out the same neighbourhood `find_iter` suppresses
+/// around each match it yields.
+/// 
+/// Args:
+///     source: Source image as file path or encoded bytes
+///     template: Template image as file path or encoded bytes
+///     found: MatchResult objects and (x, y, width, height) boxes to exclude
+///     threshold: Matching threshold (0.0-1.0), default 0.8
+///     **options: Matching options, e.g. autocrop=True
+/// 
+/// Returns:
+///     MatchResult or None
+#[pyfunction]
+#[pyo3(signature = (source, template, found, threshold=0.8, **options))]
+fn find_next(
+    py: Python<'_>, source: ImageInput, template: ImageInput, found: Vec<FoundRegion>, threshold: f64,
+    options: Option<&PyDict>,
+) -> PyResult<Option<MatchResult>> {
+    let mut opts = MatchOptions::from_kwargs(options)?;
+    let (gray, original) = opts.load_source(&source)?;
+    let src = opts.prepare_source(gray);
+    let tpl = opts.load_template(&template)?;
+    opts.check_fits(&tpl, src.width, src.height)?;
+    // Containment is checked in i64, so no box size can overflow the bounds
+    let (fw, fh) = (tpl.footprint.0 as i64, tpl.footprint.1 as i64);
+    let excluded: Vec<(i64, i64, i64, i64)> = found.iter()
+        .map(|region| match region {
+            FoundRegion::Match(r) => (r.x as i64, r.y as i64, fw, fh),
+            FoundRegion::Box((x, y, w, h)) => (*x as i64, *y as i64, *w as i64, *h as i64),
+        })
+        .collect();
+    
+    let (mut candidates, _) = py.allow_threads(|| {
+        let search = || opts.within_timeout(|| opts.find_candidates_in(&src, original.as_ref(), &tpl, threshold));
+        opts.debugged(&src, &tpl, search, |(candidates, _)| candidates.clone())
+    })?;
+    sort_by_confidence(&mut candidates);
+    // Candidates are in search coordinates; excluded regions in reported ones
+    let next = candidates.into_iter().find(|r| {
+        let offset = tpl.variant(r).offset;
+        let (cx, cy) = (r.x as i64 - offset.0 as i64 + fw / 2, r.y as i64 - offset.1 as i64 + fh / 2);
+        !excluded.iter().any(|&(x, y, w, h)| (x..x + w).contains(&cx) && (y..y + h).contains(&cy))
+    });
+    next.map(|r| opts.finish(&tpl, r, original.as_ref())).transpose()
+}
+
+// ============================================================================
+// Python Interface - Match Clustering
+// ============================================================================
+
+/// Group of nearby detections reported as one match
+#[pyclass]
+#[derive(Clone)]
+pub struct MatchCluster {
+    /// Confidence-weighted centroid of the members' positions
+    #[pyo3(get)]
+    pub x: f64,
+    #[pyo3(get)]
+    pub y: f64,
+    /// Highest member confidence
+    #[pyo3(get)]
+    pub confidence: f64,
+    #[pyo3(get)]
+    pub mean_confidence: f64,
+    /// Standard deviation of the member confidences
+    #[pyo3(get)]
+    pub score_std: f64,
+    /// Number of detections in the cluster
+    #[pyo3(get)]
+    pub count: usize,
+    /// Strongest member
+    #[pyo3(get)]
+    pub best: MatchResult,
+}
+
+#[pymethods]
+impl MatchCluster {
+    fn __repr__(&self) -> String {
+        format!(
+            "MatchCluster(x={:.2}, y={:.2}, confidence={:.4}, count={}, score_std={:.4})",
+            self.x, self.y, self.confidence, self.count, self.score_std
+        )
+    }
+
+    /// Fields as a dict, with `best` as a nested dict
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        let dict = PyDict::new(py);
+        dict.set_item("x", self.x)?;
+        dict.set_item("y", self.y)?;
+        dict.set_item("confidence", self.confidence)?;
+        dict.set_item("mean_confidence", self.mean_confidence)?;
+        dict.set_item("score_std", self.score_std)?;
+        dict.set_item("count", self.count)?;
+        dict.set_item("best", self.best.to_dict(py)?)?;
+        Ok(dict)
+    }
+}
+
+impl MatchCluster {
+    /// Statistics of the members of one cluster, in search coordinates
+    fn from_members(members: &[&MatchResult]) -> Self {
+        let best = (*members.iter().max_by(|a, b| a.confidence.partial_cmp(&b.confidence).unwrap()).unwrap()).clone();
+        let n = members.len() as f64;
+        let mean = members.iter().map(|m| m.confidence).sum::<f64>() / n;
+        let var = members.iter().map(|m| (m.confidence - mean).powi(2)).sum::<f64>() / n;
+        // Weights are clamped so anti-correlated members can't pull the centroid away
+        let weight = |m: &MatchResult| m.confidence.max(1e-6);
+        let total: f64 = members.iter().map(|m| weight(m)).sum();
+        let x = members.iter().map(|m| weight(m) * m.x as f64).sum::<f64>() / total;
+        let y = members.iter().map(|m| weight(m) * m.y as f64).sum::<f64>() / total;
+        Self { x, y, confidence: best.confidence, mean_confidence: mean, score_std: var.sqrt(), count: members.len(), best }
+    }
+}
+
+/// Find matches and merge nearby detections into clusters instead of keeping
+/// only the strongest one
+/// 
+/// Args:
+///     source: Source image as file path or encoded bytes
+///     template: Template image as file path or encoded bytes
+///     threshold: Matching threshold (0.0-1.0), default 0.8
+///     max_count: Maximum number of clusters, default 10
+///     **options: Matching options, e.g. autocrop=True
+/// 
+/// Returns:
+///     List of MatchCluster, strongest first
+#[pyfunction]
+#[pyo3(signature = (source, template, threshold=0.8, max_count=10, **options))]
+fn find_clusters(
+    py: Python<'_>, source: ImageInput, template: ImageInput, threshold: f64, max_count: usize, options: Option<&PyDict>,
+) -> PyResult<Vec<MatchCluster>> {
+    let mut opts = MatchOptions::from_kwargs(options)?;
+    let img = source.load_dynamic_as(opts.exif_orientation)?;
+    let src = opts.prepare_source(opts.plane(&img));
+    let tpl = opts.load_template(&template)?;
+    opts.check_fits(&tpl, src.width, src.height)?;
+
+    let mut clusters: Vec<MatchCluster> = py.allow_threads(|| {
+        let search = || opts.within_timeout(|| opts.find_candidates_in(&src, Some(&img), &tpl, threshold));
+        let (candidates, (tw, th)) = opts.debugged(&src, &tpl, search, |(candidates, _)| candidates.clone())?;
+        Ok::<_, PyErr>(cluster_matches(&candidates, tw, th)
+            .into_iter()
+            .map(|members| MatchCluster::from_members(&members.iter().map(|&i| &candidates[i]).collect::<Vec<_>>()))
+            .collect())
+    })?;
+    clusters.sort_by(|a, b| b.confidence.partial_cmp(&a.confidence).unwrap().then(a.y.total_cmp(&b.y)).then(a.x.total_cmp(&b.x)));
+    clusters.truncate(max_count);
+    let source = opts.return_crop.then_some(img);
+    clusters.into_iter()
+        .map(|mut c| {
+            let raw = (c.best.x, c.best.y);
+            c.best = opts.finish(&tpl, c.best, source.as_ref())?;
+            // Report the centroid in the same frame as the finished best match
+            c.x += (c.best.x - raw.0) as f64;
+            c.y += (c.best.y - raw.1) as f64;
+            Ok(c)
+        })
+        .collect()
+}
+
+// ============================================================================
+// Python Interface - Templates
+// ============================================================================
+
+/// Template cut from a screenshot, ready to pass to the find functions
+#[pyclass(frozen, name = "Template")]
+pub struct TemplateImage {
+    image: DynamicImage,
+    /// Position and size of the template in the screenshot it was cut from,
+    /// after autocrop
+    #[pyo3(get)]
+    pub region: (u32, u32, u32, u32),
+}
+
+#[pymethods]
+impl TemplateImage {
+    /// Cut a template out of a screenshot, preprocess it and optionally save it
+    ///
+    /// Args:
+    ///     source: Screenshot as file path or encoded bytes
+    ///     x, y: Top-left corner of the marked region
+    ///     width, height: Region size, must lie inside the screenshot
+    ///     grayscale: Store the template as 8-bit grayscale, default False
+    ///     autocrop: Strip near-constant margins from the region, default False
+    ///     autocrop_tolerance: Maximum grey-level spread of a stripped margin, default 2.0
+    ///     save_path: Also save the template here (format from extension)
+    #[staticmethod]
+    #[pyo3(signature = (source, x, y, width, height, grayscale=false, autocrop=false, autocrop_tolerance=2.0, save_path=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn from_region(
+        source: ImageInput, x: u32, y: u32, width: u32, height: u32, grayscale: bool, autocrop: bool,
+        autocrop_tolerance: f64, save_path: Option<&str>,
+    ) -> PyResult<Self> {
+        if autocrop_tolerance < 0.0 {
+            return Err(PyValueError::new_err("autocrop_tolerance must be non-negative"));
+        }
+        let img = source.load_dynamic()?;
+        check_crop(&img, x, y, width, height)?;
+        let mut image = img.crop_imm(x, y, width, height);
+        if grayscale { image = DynamicImage::ImageLuma8(image.to_luma8()); }
+        let mut region = (x, y, width, height);
+        if autocrop {
+            let gray = GrayImageData::from_dynamic(&image);
+            let (cx, cy, cw, ch) = autocrop_box(&gray.data, gray.width, gray.height, autocrop_tolerance);
+            let (cx, cy, cw, ch) = (cx as u32, cy as u32, cw as u32, ch as u32);
+            image = image.crop_imm(cx, cy, cw, ch);
+            region = (x + cx, y + cy, cw, ch);
+        }
+        let template = TemplateImage { image, region };
+        if let Some(path) = save_path { template.save(path)?; }
+        Ok(template)
+    }
+
+    /// Template as PNG bytes, accepted wherever a template image is
+    #[getter]
+    fn data<'py>(&self, py: Python<'py>) -> PyResult<&'py PyBytes> {
+        Ok(PyBytes::new(py, &encode_png(&self.image)?))
+    }
+
+    #[getter]
+    fn width(&self) -> u32 {
+        self.image.width()
+    }
+
+    #[getter]
+    fn height(&self) -> u32 {
+        self.image.height()
+    }
+
+    /// Write the template to a file (format from extension)
+    fn save(&self, path: &str) -> PyResult<()> {
+        self.image.save(path).map_err(|e| PyIOError::new_err(format!("Failed to save image '{}': {}", path, e)))
+    }
+
+    fn __bytes__<'py>(&self, py: Python<'py>) -> PyResult<&'py PyBytes> {
+        self.data(py)
+    }
+
+    fn __repr__(&self) -> String {
+        let (x, y, w, h) = self.region;
+        format!("Template(width={}, height={}, region=({}, {}, {}, {}))", self.width(), self.height(), x, y, w, h)
+    }
+}
+
+// ============================================================================
+// Python Interface - Template Banks
+// ============================================================================
+
+const BANK_MAGIC: &[u8; 4] = b"RMTB";
+const BANK_VERSION: u32 = 1;
+
+/// Search structures for one bank variant
+enum VariantSearch {
+    /// Unrotated: plain NCC with the coarse pyramid
+    Plain(PreparedTemplate),
+    /// Rotated: weighted NCC masked to the rotated footprint
+    Masked(WeightedTemplate, Option<(WeightedTemplate, usize, f64)>),
+}
+
+/// One transformed template, kept with its pixels for serialization
+struct BankVariant {
+    scale: f64,
+    angle: f64,
+    image: GrayImageData,
+    mask: Option<Vec<f64>>,
+    search: VariantSearch,
+}
+
+impl BankVariant {
+    fn new(scale: f64, angle: f64, image: GrayImageData, mask: Option<Vec<f64>>) -> Self {
+        let search = match &mask {
+            Some(mask) => {
+                let (full, coarse) = weighted_levels(&image, mask, DownsampleFilter::Area);
+                VariantSearch::Masked(full, coarse)
+            }
+            None => VariantSearch::Plain(PreparedTemplate::new(&image.data, image.width, image.height)),
+        };
+        Self { scale, angle, image, mask, search }
+    }
+
+    fn fits(&self, sw: usize, sh: usize) -> bool {
+        self.image.width <= sw && self.image.height <= sh
+    }
+
+    fn tag(&self, mut r: MatchResult) -> MatchResult {
+        r.scale = Some(self.scale);
+        r.angle = Some(self.angle);
+        r
+    }
+
+    fn find(&self, src: &[f64], sw: usize, sh: usize, threshold: f64) -> Option<MatchResult> {
+        if !self.fits(sw, sh) { return None; }
+        let found = match &self.search {
+            VariantSearch::Plain(prepared) => pyramid_match_prepared(src, sw, sh, prepared, threshold),
+            VariantSearch::Masked(full, coarse) => {
+                let coarse = coarse.as_ref().map(|(small, scale, calibration)| (small, *scale, *calibration));
+                scored_match(src, sw, sh, full, coarse, DownsampleFilter::Area, threshold)
+            }
+        };
+        found.map(|r| self.tag(r))
+    }
+
+    fn find_candidates(&self, src: &[f64], sw: usize, sh: usize, threshold: f64) -> Vec<MatchResult> {
+        if !self.fits(sw, sh) { return vec![]; }
+        let candidates = match &self.search {
+            VariantSearch::Plain(prepared) => match_candidates(src, sw, sh, prepared, threshold),
+            VariantSearch::Masked(full, _) => scored_candidates(src, sw, sh, full, threshold),
+        };
+        candidates.into_iter().map(|r| self.tag(r)).collect()
+    }
+}
+
+/// Cursor over serialized bank bytes; every read is bounds-checked
+struct BankReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> BankReader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        if n > self.data.len() { return None; }
+        let (head, rest) = self.data.split_at(n);
+        self.data = rest;
+        Some(head)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4).map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+    }
+
+    fn f64s(&mut self, n: usize) -> Option<Vec<f64>> {
+        let bytes = self.take(n.checked_mul(8)?)?;
+        Some(bytes.chunks_exact(8).map(|b| f64::from_le_bytes(b.try_into().unwrap())).collect())
+    }
+}
+
+/// Template transformed to every requested scale and rotation ahead of time
+///
+/// Building the variants (resampling, rotation, normalization, coarse
+/// pyramid levels) happens once; `find` and `find_all` then search all of
+/// them. Banks can be saved to disk or pickled and loaded without redoing
+/// that work. Pass a bank as the template of `find`/`find_all` (and their
+/// `_bytes` variants) or call its methods directly.
+#[pyclass(module = "rustmatch._core")]
+pub struct TemplateBank {
+    variants: Vec<BankVariant>,
+}
+
+impl TemplateBank {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = BANK_MAGIC.to_vec();
+        out.extend(BANK_VERSION.to_le_bytes());
+        out.extend((self.variants.len() as u32).to_le_bytes());
+        for v in &self.variants {
+            out.extend(v.scale.to_le_bytes());
+            out.extend(v.angle.to_le_bytes());
+            out.extend((v.image.width as u32).to_le_bytes());
+            out.extend((v.image.height as u32).to_le_bytes());
+            out.push(v.mask.is_some() as u8);
+            for value in v.image.data.iter().chain(v.mask.iter().flatten()) {
+                out.extend(value.to_le_bytes());
+            }
+        }
+        out
+    }
+
+    fn decode(data: &[u8]) -> PyResult<Self> {
+        let invalid = || DecodeError::new_err("Invalid template bank data");
+        let mut reader = BankReader { data };
+        if reader.take(4) != Some(BANK_MAGIC.as_slice()) { return Err(invalid()); }
+        if reader.u32() != Some(BANK_VERSION) {
+            return Err(DecodeError::new_err("Unsupported template bank version"));
+        }
+        let count = reader.u32().ok_or_else(invalid)?;
+        let mut parts = Vec::new();
+        for _ in 0..count {
+            let mut read = || -> Option<_> {
+                let scale = f64::from_le_bytes(reader.take(8)?.try_into().unwrap());
+                let angle = f64::from_le_bytes(reader.take(8)?.try_into().unwrap());
+                let (w, h) = (reader.u32()? as usize, reader.u32()? as usize);
+                let has_mask = reader.take(1)?[0] != 0;
+                let n = w.checked_mul(h).filter(|&n| n > 0)?;
+                let data = reader.f64s(n)?;
+                let mask = if has_mask { Some(reader.f64s(n)?) } else { None };
+                Some((scale, angle, GrayImageData { data, width: w, height: h }, mask))
+            };
+            parts.push(read().ok_or_else(invalid)?);
+        }
+        if parts.is_empty() || !reader.data.is_empty() { return Err(invalid()); }
+        let variants = parts.into_par_iter().map(|(scale, angle, image, mask)| BankVariant::new(scale, angle, image, mask)).collect();
+        Ok(Self { variants })
+    }
+
+    fn min_size(&self) -> (usize, usize) {
+        let w = self.variants.iter().map(|v| v.image.width).min().unwrap_or(1);
+        let h = self.variants.iter().map(|v| v.image.height).min().unwrap_or(1);
+        (w, h)
+    }
+}
+
+#[pymethods]
+impl TemplateBank {
+    /// Precompute the variants of a template
+    ///
+    /// Args:
+    ///     template: Template image as file path or encoded bytes
+    ///     scales: Scale factors, default [1.0]
+    ///     angles: Rotations in degrees (clockwise on screen), default [0.0]
+    #[new]
+    #[pyo3(signature = (template, scales=None, angles=None))]
+    fn new(py: Python<'_>, template: ImageInput, scales: Option<Vec<f64>>, angles: Option<Vec<f64>>) -> PyResult<Self> {
+        let scales = scales.unwrap_or_else(|| vec![1.0]);
+        let angles = angles.unwrap_or_else(|| vec![0.0]);
+        if scales.is_empty() || scales.iter().any(|s| !(s.is_finite() && *s > 0.0)) {
+            return Err(PyValueError::new_err("scales must be a non-empty list of positive numbers"));
+        }
+        if angles.is_empty() || angles.iter().any(|a| !a.is_finite()) {
+            return Err(PyValueError::new_err("angles must be a non-empty list of numbers"));
+        }
+        let tpl = template.load()?;
+        let variants: Vec<BankVariant> = py.allow_threads(|| {
+            let pairs: Vec<(f64, f64)> = scales.iter().flat_map(|&s| angles.iter().map(move |&a| (s, a))).collect();
+            pairs.into_par_iter()
+                .filter_map(|(scale, angle)| {
+                    let scaled = if scale == 1.0 { Some(GrayImageData { data: tpl.data.clone(), width: tpl.width, height: tpl.height }) } else { scale_image(&tpl, scale) }?;
+                    if angle.rem_euclid(360.0) == 0.0 { return Some(BankVariant::new(scale, angle, scaled, None)); }
+                    let (rotated, mask) = rotate_image(&scaled, angle);
+                    Some(BankVariant::new(scale, angle, rotated, Some(mask)))
+                })
+                .collect()
+        });
+        if variants.is_empty() {
+            return Err(InvalidDimensionsError::new_err("Every scaled template would be smaller than 4x4"));
+        }
+        Ok(Self { variants })
+    }
+
+    fn __len__(&self) -> usize {
+        self.variants.len()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("TemplateBank({} variants)", self.variants.len())
+    }
+
+    /// `(scale, angle, width, height)` of every variant
+    #[getter]
+    fn variants(&self) -> Vec<(f64, f64, usize, usize)> {
+        self.variants.iter().map(|v| (v.scale, v.angle, v.image.width, v.image.height)).collect()
+    }
+
+    /// Best match over all variants, with `scale` and `angle` set
+    ///
+    /// Args:
+    ///     source: Source image as file path or encoded bytes
+    ///     threshold: Matching threshold (0.0-1.0), default 0.8
+    #[pyo3(signature = (source, threshold=0.8))]
+    fn find(&self, py: Python<'_>, source: ImageInput, threshold: f64) -> PyResult<Option<MatchResult>> {
+        let src = source.load()?;
+        Ok(py.allow_threads(|| {
+            self.variants.iter()
+                .filter_map(|v| v.find(&src.data, src.width, src.height, threshold))
+                .max_by(|a, b| a.confidence.partial_cmp(&b.confidence).unwrap())
+        }))
+    }
+
+    /// All matches over all variants; overlapping hits keep the best variant
+    ///
+    /// Args:
+    ///     source: Source image as file path or encoded bytes
+    ///     threshold: Matching threshold (0.0-1.0), default 0.8
+    ///     max_count: Maximum number of matches, default 10
+    #[pyo3(signature = (source, threshold=0.8, max_count=10))]
+    fn find_all(&self, py: Python<'_>, source: ImageInput, threshold: f64, max_count: usize) -> PyResult<Vec<MatchResult>> {
+        let src = source.load()?;
+        let (tw, th) = self.min_size();
+        Ok(py.allow_threads(|| {
+            let candidates = self.variants.iter()
+                .flat_map(|v| v.find_candidates(&src.data, src.width, src.height, threshold))
+                .collect();
+            non_max_suppression(candidates, tw, th, max_count)
+        }))
+    }
+
+    /// Serialize the precomputed variants
+    fn to_bytes<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, &self.encode())
+    }
+
+    /// Rebuild a bank from `to_bytes` output
+    #[staticmethod]
+    fn from_bytes(py: Python<'_>, data: &[u8]) -> PyResult<Self> {
+        py.allow_threads(|| Self::decode(data))
+    }
+
+    /// Write the bank to a file
+    fn save(&self, path: &str) -> PyResult<()> {
+        std::fs::write(path, self.encode())
+            .map_err(|e| PyIOError::new_err(format!("Failed to write '{}': {}", path, e)))
+    }
+
+    /// Read a bank written by `save`
+    #[staticmethod]
+    fn load(py: Python<'_>, path: &str) -> PyResult<Self> {
+        let data = std::fs::read(path)
+            .map_err(|e| PyIOError::new_err(format!("Failed to read '{}': {}", path, e)))?;
+        py.allow_threads(|| Self::decode(&data))
+    }
+
+    /// Pickle through `to_bytes`, so banks can be sent to worker processes
+    fn __reduce__<'py>(&self, py: Python<'py>) -> PyResult<(&'py PyAny, (&'py PyBytes,))> {
+        let from_bytes = py.get_type::<TemplateBank>().getattr("from_bytes")?;
+        Ok((from_bytes, (self.to_bytes(py),)))
+    }
+}
+
+// ============================================================================
+// Python Interface - Repeated Regions
+// ============================================================================
+
+/// Find regions that repeat within one image (duplicate icons, copy-pasted blocks)
+/// 
+/// Args:
+///     source: Source image as file path or encoded bytes
+///     patch_size: Side of the square regions compared, at least 8
+///     threshold: Matching threshold (0.0-1.0), default 0.9
+///     stride: Spacing of the seed patches, default patch_size / 2
+/// 
+/// Returns:
+///     List of groups, largest first; each group lists the locations of one
+///     repeated region as MatchResult, the seed patch first
+#[pyfunction]
+#[pyo3(signature = (source, patch_size=32, threshold=0.9, stride=None))]
+fn find_repeats(
+    py: Python<'_>, source: ImageInput, patch_size: usize, threshold: f64, stride: Option<usize>,
+) -> PyResult<Vec<Vec<MatchResult>>> {
+    if patch_size < 2 * REPEAT_GRID {
+        return Err(InvalidDimensionsError::new_err(format!("patch_size must be at least {}", 2 * REPEAT_GRID)));
+    }
+    let stride = stride.unwrap_or((patch_size / 2).max(1));
+    if stride == 0 {
+        return Err(PyValueError::new_err("stride must be at least 1"));
+    }
+    let src = source.load()?;
+    if patch_size > src.width || patch_size > src.height {
+        return Err(TemplateTooLargeError::new_err(format!(
+            "patch_size {} is larger than the source ({}x{})", patch_size, src.width, src.height
+        )));
+    }
+    Ok(py.allow_threads(|| find_repeated_regions(&src.data, src.width, src.height, patch_size, stride, threshold)))
+}
+
+// ============================================================================
+// Python Interface - Rotated Search
+// ============================================================================
+
+/// Find a template at an unknown rotation
+/// 
+/// A rotation-invariant ring projection prescreen picks the most promising
+/// window centres; only those are searched with each rotated template.
+/// 
+/// Args:
+///     source: Source image as file path or encoded bytes
+///     template: Template as file path or encoded image bytes
+///     threshold: Matching threshold (0.0-1.0), default 0.8
+///     angles: Rotations to try in degrees, default every 10 degrees
+///     candidates: Number of prescreened centres to verify, default 8
+/// 
+/// Returns:
+///     MatchResult with `angle` set, or None
+#[pyfunction]
+#[pyo3(signature = (source, template, threshold=0.8, angles=None, candidates=8))]
+fn find_rotated(
+    py: Python<'_>,
+    source: ImageInput,
+    template: ImageInput,
+    threshold: f64,
+    angles: Option<Vec<f64>>,
+    candidates: usize,
+) -> PyResult<Option<MatchResult>> {
+    let angles = angles.unwrap_or_else(|| (0..36).map(|i| i as f64 * DEFAULT_ROTATION_STEP).collect());
+    if angles.is_empty() || angles.iter().any(|a| !a.is_finite()) {
+        return Err(PyValueError::new_err("angles must be a non-empty list of numbers"));
+    }
+    if candidates == 0 {
+        return Err(PyValueError::new_err("candidates must be at least 1"));
+    }
+    let src = source.load()?;
+    let tpl = template.load()?;
+    if tpl.width > src.width || tpl.height > src.height {
+        return Err(TemplateTooLargeError::new_err(format!(
+            "Template ({}x{}) is larger than the source ({}x{})", tpl.width, tpl.height, src.width, src.height
+        )));
+    }
+    if tpl.width.min(tpl.height) < MIN_ROTATED_TEMPLATE {
+        return Err(InvalidDimensionsError::new_err(format!(
+            "Template must be at least {0}x{0} for the ring projection", MIN_ROTATED_TEMPLATE
+        )));
+    }
+    Ok(py.allow_threads(|| find_rotated_impl(&src.data, src.width, src.height, &tpl, &angles, candidates, threshold)))
+}
+
+// ============================================================================
+// Python Interface - Rotation and Scale Registration
+// ============================================================================
+
+/// Global rotation, scale and shift between two images
+#[pyclass]
+#[derive(Clone)]
+pub struct RotScale {
+    /// Rotation from the first image to the second in degrees, clockwise on
+    /// screen, in (-180, 180]
+    #[pyo3(get)]
+    pub angle: f64,
+    /// Size in the second image over size in the first
+    #[pyo3(get)]
+    pub scale: f64,
+    /// Where the first image's centre lands, relative to the second image's
+    /// centre, in the second image's pixels
+    #[pyo3(get)]
+    pub dx: f64,
+    #[pyo3(get)]
+    pub dy: f64,
+    /// NCC of the overlap once the first image is mapped onto the second
+    #[pyo3(get)]
+    pub confidence: f64,
+    /// Template found in the second image at the estimated rotation and scale
+    #[pyo3(get)]
+    pub template_match: Option<MatchResult>,
+}
+
+#[pymethods]
+impl RotScale {
+    fn __repr__(&self) -> String {
+        format!(
+            "RotScale(angle={:.2}, scale={:.4}, dx={:.1}, dy={:.1}, confidence={:.4})",
+            self.angle, self.scale, self.dx, self.dy, self.confidence
+        )
+    }
+
+    /// Fields as a dict, with `template_match` as a nested dict or None
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        let dict = PyDict::new(py);
+        dict.set_item("angle", self.angle)?;
+        dict.set_item("scale", self.scale)?;
+        dict.set_item("dx", self.dx)?;
+        dict.set_item("dy", self.dy)?;
+        dict.set_item("confidence", self.confidence)?;
+        match &self.template_match {
+            Some(m) => dict.set_item("template_match", m.to_dict(py)?)?,
+            None => dict.set_item("template_match", py.None())?,
+        }
+        Ok(dict)
+    }
+}
+
+/// Search `b` for a template taken from the first image, scaled and rotated
+/// by the registration estimate
+fn find_registered_template(b: &GrayImageData, tpl: &GrayImageData, reg: &RotScale, threshold: f64) -> Option<MatchResult> {
+    let scaled = scale_image(tpl, reg.scale)?;
+    let (rotated, mask) = rotate_image(&scaled, reg.angle);
+    let (full, coarse) = weighted_levels(&rotated, &mask, DownsampleFilter::Area);
+    let result = scored_match(&b.data, b.width, b.height, &full, coarse.as_ref().map(|(t, s, c)| (t, *s, *c)), DownsampleFilter::Area, threshold)?;
+    Some(MatchResult { scale: Some(reg.scale), angle: Some(reg.angle), ..result })
+}
+
+/// Estimate the global rotation and scale between two images (camera roll
+/// and zoom) by Fourier-Mellin registration
+/// 
+/// Args:
+///     img_a: Reference image as file path or encoded bytes
+///     img_b: Rotated / zoomed image as file path or encoded bytes
+///     template: Optional template cut from img_a; it is scaled and rotated by
+///         the estimate and searched in img_b
+///     threshold: Matching threshold for the template search (0.0-1.0), default 0.8
+/// 
+/// Returns:
+///     RotScale mapping img_a onto img_b
+#[pyfunction]
+#[pyo3(signature = (img_a, img_b, template=None, threshold=0.8))]
+fn register_rotscale(
+    py: Python<'_>, img_a: ImageInput, img_b: ImageInput, template: Option<ImageInput>, threshold: f64,
+) -> PyResult<RotScale> {
+    let a = img_a.load()?;
+    let b = img_b.load()?;
+    for img in [&a, &b] {
+        if img.width.min(img.height) < MIN_REGISTER_SIZE {
+            return Err(InvalidDimensionsError::new_err(format!(
+                "Images must be at least {0}x{0} to register, got {1}x{2}", MIN_REGISTER_SIZE, img.width, img.height
+            )));
+        }
+    }
+    let tpl = template.map(|t| t.load()).transpose()?;
+    Ok(py.allow_threads(|| {
+        let mut reg = register_rotscale_impl(&a, &b);
+        reg.template_match = tpl.and_then(|t| find_registered_template(&b, &t, &reg, threshold));
+        reg
+    }))
+}
+
+// ============================================================================
+// Python Interface - Alignment Refinement
+// ============================================================================
+
+/// Sub-pixel alignment of a template in a source, refined by ECC
+#[pyclass]
+#[derive(Clone)]
+pub struct Alignment {
+    warp: Warp,
+    /// NCC between the template and the source under the final warp
+    #[pyo3(get)]
+    pub correlation: f64,
+    /// Warp updates applied
+    #[pyo3(get)]
+    pub iterations: usize,
+    /// Whether the correlation settled within epsilon before max_iterations
+    #[pyo3(get)]
+    pub converged: bool,
+    /// Warp model: "translation", "euclidean" or "affine"
+    #[pyo3(get)]
+    pub motion: String,
+}
+
+#[pymethods]
+impl Alignment {
+    /// 2x3 warp as two rows: template pixel (u, v) lands at
+    /// `(a*u + b*v + c, d*u + e*v + f)` in the source
+    #[getter]
+    fn warp(&self) -> Vec<Vec<f64>> {
+        self.warp.iter().map(|row| row.to_vec()).collect()
+    }
+
+    /// Sub-pixel source position of the template's top-left pixel
+    #[getter]
+    fn x(&self) -> f64 {
+        self.warp[0][2]
+    }
+
+    #[getter]
+    fn y(&self) -> f64 {
+        self.warp[1][2]
+    }
+
+    /// Rotation of the warp in degrees, clockwise on screen
+    #[getter]
+    fn angle(&self) -> f64 {
+        self.warp[1][0].atan2(self.warp[0][0]).to_degrees()
+    }
+
+    /// Map a template pixel position into source coordinates
+    fn transform(&self, u: f64, v: f64) -> (f64, f64) {
+        apply_warp(&self.warp, u, v)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Alignment(x={:.3}, y={:.3}, motion='{}', correlation={:.5}, converged={})",
+            self.x(), self.y(), self.motion, self.correlation, if self.converged { "True" } else { "False" }
+        )
+    }
+
+    /// Fields as a dict, with `warp` as a list of two rows
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        let dict = PyDict::new(py);
+        dict.set_item("warp", self.warp())?;
+        dict.set_item("x", self.x())?;
+        dict.set_item("y", self.y())?;
+        dict.set_item("angle", self.angle())?;
+        dict.set_item("correlation", self.correlation)?;
+        dict.set_item("iterations", self.iterations)?;
+        dict.set_item("converged", self.converged)?;
+        dict.set_item("motion", &self.motion)?;
+        Ok(dict)
+    }
+}
+
+/// Starting warp for a match. Rotated or scaled results (`TemplateBank`,
+/// `find_rotated`) report the bounding box of the transformed template, so
+/// the warp pivots on its centre.
+fn initial_warp(result: &MatchResult, tw: usize, th: usize) -> Warp {
+    let (x, y) = (result.x as f64, result.y as f64);
+    if result.scale.is_none() && result.angle.is_none() {
+        return [[1.0, 0.0, x], [0.0, 1.0, y]];
+    }
+    let scale = result.scale.unwrap_or(1.0);
+    let (sin, cos) = result.angle.unwrap_or(0.0).to_radians().sin_cos();
+    let (sw, sh) = ((tw as f64 * scale).round(), (th as f64 * scale).round());
+    let (bw, bh) = ((sw * cos.abs() + sh * sin.abs()).round(), (sw * sin.abs() + sh * cos.abs()).round());
+    let (cx, cy) = (x + (bw - 1.0) / 2.0, y + (bh - 1.0) / 2.0);
+    let (tcx, tcy) = ((tw as f64 - 1.0) / 2.0, (th as f64 - 1.0) / 2.0);
+    let (a, b, c, d) = (scale * cos, -scale * sin, scale * sin, scale * cos);
+    [[a, b, cx - a * tcx - b * tcy], [c, d, cy - c * tcx - d * tcy]]
+}
+
+/// Refine a match to a sub-pixel warp with enhanced correlation coefficient
+/// (ECC) maximization, like OpenCV's findTransformECC
+/// 
+/// Args:
+///     source: Source image as file path or encoded bytes
+///     template: Template image as file path or encoded bytes
+///     result: MatchResult to start from, e.g. from find()
+///     motion: "translation" (default), "euclidean" or "affine"
+///     max_iterations: Maximum number of warp updates, default 50
+///     epsilon: Stop once the correlation changes less than this, default 1e-5
+/// 
+/// Returns:
+///     Alignment with the refined warp and final correlation
+#[pyfunction]
+#[pyo3(signature = (source, template, result, motion="translation", max_iterations=50, epsilon=1e-5))]
+fn refine_alignment(
+    py: Python<'_>, source: ImageInput, template: ImageInput, result: PyRef<MatchResult>, motion: &str,
+    max_iterations: usize, epsilon: f64,
+) -> PyResult<Alignment> {
+    let model = MotionModel::parse(motion).ok_or_else(|| {
+        PyValueError::new_err(format!("Unknown motion '{}', expected 'translation', 'euclidean' or 'affine'", motion))
+    })?;
+    if epsilon.is_nan() || epsilon < 0.0 {
+        return Err(PyValueError::new_err("epsilon must be non-negative"));
+    }
+    let src = source.load()?;
+    let tpl = template.load()?;
+    if tpl.width > src.width || tpl.height > src.height {
+        return Err(TemplateTooLargeError::new_err(format!(
+            "Template ({}x{}) is larger than the source ({}x{})", tpl.width, tpl.height, src.width, src.height
+        )));
+    }
+    let warp = initial_warp(&result, tpl.width, tpl.height);
+    let ecc = py.allow_threads(|| ecc_refine(&src.data, src.width, src.height, &tpl, model, warp, max_iterations, epsilon));
+    Ok(Alignment {
+        warp: ecc.warp, correlation: ecc.correlation, iterations: ecc.iterations, converged: ecc.converged,
+        motion: model.name().to_string(),
+    })
+}
+
+// ============================================================================
+// Python Interface - Perspective Matching
+// ============================================================================
+
+/// Template found under perspective by `find_perspective`
+#[pyclass]
+#[derive(Clone)]
+pub struct PerspectiveMatch {
+    homography: Homography,
+    template_size: (usize, usize),
+    /// Fraction of the tentative keypoint matches that agree with the homography
+    #[pyo3(get)]
+    pub confidence: f64,
+    /// NCC between the template and the source sampled through the homography
+    #[pyo3(get)]
+    pub score: f64,
+    /// Keypoint matches consistent with the homography
+    #[pyo3(get)]
+    pub inliers: usize,
+    /// Tentative keypoint matches RANSAC chose from
+    #[pyo3(get)]
+    pub matches: usize,
+}
+
+#[pymethods]
+impl PerspectiveMatch {
+    /// 3x3 homography as three rows: template point (u, v) lands at
+    /// `(a*u + b*v + c, d*u + e*v + f) / (g*u + h*v + 1)` in the source
+    #[getter]
+    fn homography(&self) -> Vec<Vec<f64>> {
+        self.homography.iter().map(|row| row.to_vec()).collect()
+    }
+
+    /// Template corners in the source: top-left, top-right, bottom-right, bottom-left
+    #[getter]
+    fn corners(&self) -> Vec<(f64, f64)> {
+        let (w, h) = (self.template_size.0 as f64, self.template_size.1 as f64);
+        // `find_perspective` only accepts homographies that keep the corners finite
+        [(0.0, 0.0), (w, 0.0), (w, h), (0.0, h)].iter()
+            .map(|&(u, v)| apply_homography(&self.homography, u, v).unwrap_or((f64::NAN, f64::NAN)))
+            .collect()
+    }
+
+    /// Map a template position into source coordinates
+    fn transform(&self, u: f64, v: f64) -> PyResult<(f64, f64)> {
+        apply_homography(&self.homography, u, v)
+            .ok_or_else(|| PyValueError::new_err(format!("({}, {}) maps to infinity", u, v)))
+    }
+
+    /// Axis-aligned bounding box of the corners as (x, y, width, height)
+    fn bbox(&self) -> (i32, i32, u32, u32) {
+        let corners = self.corners();
+        let (x0, y0) = corners.iter().fold((f64::INFINITY, f64::INFINITY), |(x, y), c| (x.min(c.0), y.min(c.1)));
+        let (x1, y1) = corners.iter().fold((f64::NEG_INFINITY, f64::NEG_INFINITY), |(x, y), c| (x.max(c.0), y.max(c.1)));
+        let (x, y) = (x0.floor() as i32, y0.floor() as i32);
+        (x, y, (x1.ceil() as i32 - x) as u32, (y1.ceil() as i32 - y) as u32)
+    }
+
+    fn __repr__(&self) -> String {
+        let corners: Vec<String> = self.corners().iter().map(|(x, y)| format!("({:.1}, {:.1})", x, y)).collect();
+        format!(
+            "PerspectiveMatch(corners=[{}], confidence={:.4}, score={:.4}, inliers={})",
+            corners.join(", "), self.confidence, self.score, self.inliers
+        )
+    }
+
+    /// Fields as a dict, with `homography` as a list of three rows
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        let dict = PyDict::new(py);
+        dict.set_item("corners", self.corners())?;
+        dict.set_item("homography", self.homography())?;
+        dict.set_item("confidence", self.confidence)?;
+        dict.set_item("score", self.score)?;
+        dict.set_item("inliers", self.inliers)?;
+        dict.set_item("matches", self.matches)?;
+        Ok(dict)
+    }
+}
+
+/// Find a template seen under perspective, e.g. in a photo of a screen
+/// taken at an angle: Harris keypoints are matched by patch NCC, RANSAC
+/// estimates a homography, and the template is verified by NCC against the
+/// source sampled through it
+/// 
+/// Args:
+///     source: Source image as file path or encoded bytes
+///     template: Template image as file path or encoded bytes
+///     threshold: Minimum verification NCC (0.0-1.0), default 0.7
+///     min_inliers: Minimum number of keypoint matches agreeing with the homography, default 8
+///     max_keypoints: Keypoints detected in the source (and in the template,
+///         shared across its scales), default 2000
+/// 
+/// Returns:
+///     PerspectiveMatch, or None if no homography is supported and verified
+#[pyfunction]
+#[pyo3(signature = (source, template, threshold=0.7, min_inliers=8, max_keypoints=2000))]
+fn find_perspective(
+    py: Python<'_>, source: ImageInput, template: ImageInput, threshold: f64, min_inliers: usize, max_keypoints: usize,
+) -> PyResult<Option<PerspectiveMatch>> {
+    if min_inliers < 4 {
+        return Err(PyValueError::new_err("min_inliers must be at least 4"));
+    }
+    let src = source.load()?;
+    let tpl = template.load()?;
+    let side = 2 * KEYPOINT_PATCH_RADIUS + 3;
+    if tpl.width.min(tpl.height) < side || src.width.min(src.height) < side {
+        return Err(InvalidDimensionsError::new_err(format!("Images must be at least {0}x{0} for keypoint matching", side)));
+    }
+    Ok(py.allow_threads(|| {
+        let estimate = estimate_homography(&src, &tpl, max_keypoints, min_inliers)?;
+        let score = homography_ncc(&src, &tpl, &estimate.homography)?;
+        log::debug!("perspective: verification NCC {:.4} (threshold {})", score, threshold);
+        (score >= threshold).then(|| PerspectiveMatch {
+            homography: estimate.homography,
+            template_size: (tpl.width, tpl.height),
+            confidence: estimate.inliers as f64 / estimate.matches as f64,
+            score,
+            inliers: estimate.inliers,
+            matches: estimate.matches,
+        })
+    }))
+}
+
+// ============================================================================
+// Utility Functions
+// ============================================================================
+
+/// Get image dimensions from file path
+/// 
+/// Returns:
+///     Tuple of (width, height)
+#[pyfunction]
+fn get_image_size(path: &str) -> PyResult<(u32, u32)> {
+    let img = load_dynamic_from_path(path, true)?;
+    Ok(img.dimensions())
+}
+
+/// Get image dimensions from bytes
+#[pyfunction]
+fn get_image_size_bytes(data: &[u8]) -> PyResult<(u32, u32)> {
+    let img = load_dynamic_from_bytes(data, true)?;
+    Ok(img.dimensions())
+}
+
+/// Compare two whole images of the same size
+/// 
+/// Args:
+///     a: First image as file path or encoded bytes
+///     b: Second image as file path or encoded bytes
+///     method: "ssim" (default, 0-1), "psnr" (dB, inf if identical) or "ncc" (-1 to 1)
+/// 
+/// Returns:
+///     Similarity score (higher = more similar)
+#[pyfunction]
+#[pyo3(signature = (a, b, method="ssim"))]
+fn compare_images(py: Python<'_>, a: ImageInput, b: ImageInput, method: &str) -> PyResult<f64> {
+    let img_a = a.load()?;
+    let img_b = b.load()?;
+    if img_a.width != img_b.width || img_a.height != img_b.height {
+        return Err(InvalidDimensionsError::new_err(format!(
+            "Image sizes differ: {}x{} vs {}x{}", img_a.width, img_a.height, img_b.width, img_b.height
+        )));
+    }
+    let score = match method {
+        "ssim" => py.allow_threads(|| ssim(&img_a.data, &img_b.data, img_a.width, img_a.height)),
+        "psnr" => psnr(&img_a.data, &img_b.data),
+        "ncc" => global_ncc(&img_a.data, &img_b.data),
+        other => {
+            return Err(PyValueError::new_err(format!(
+                "Unknown method '{}', expected 'ssim', 'psnr' or 'ncc'", other
+            )))
+        }
+    };
+    Ok(score)
+}
+
+/// Find the regions that changed between two screenshots of the same size
+/// 
+/// Args:
+///     baseline: Reference image as file path or encoded bytes
+///     current: New image as file path or encoded bytes
+///     tolerance: Grey-level difference a pixel may have without counting as changed, default 16
+///     min_area: Smallest number of changed pixels a region must have, default 1
+///     merge_distance: Changes up to this many pixels apart share one box, default 4
+///     return_image: Also return a PNG highlighting the differences
+/// 
+/// Returns:
+///     List of (x, y, width, height) boxes sorted top to bottom, or a tuple of
+///     (boxes, PNG bytes) with return_image=True
+#[pyfunction]
+#[pyo3(signature = (baseline, current, tolerance=16.0, min_area=1, merge_distance=4, return_image=false))]
+fn diff_images(
+    py: Python<'_>, baseline: ImageInput, current: ImageInput, tolerance: f64, min_area: usize, merge_distance: usize,
+    return_image: bool,
+) -> PyResult<PyObject> {
+    if tolerance.is_nan() || tolerance < 0.0 {
+        return Err(PyValueError::new_err("tolerance must be non-negative"));
+    }
+    let img_a = baseline.load()?;
+    let img_b = current.load()?;
+    if img_a.width != img_b.width || img_a.height != img_b.height {
+        return Err(InvalidDimensionsError::new_err(format!(
+            "Image sizes differ: {}x{} vs {}x{}", img_a.width, img_a.height, img_b.width, img_b.height
+        )));
+    }
+    let (w, h) = (img_a.width, img_a.height);
+    let (changed, regions) = py.allow_threads(|| diff_regions(&img_a.data, &img_b.data, w, h, tolerance, merge_distance, min_area));
+    let boxes: Vec<(usize, usize, usize, usize)> = regions.iter().map(|r| (r.x, r.y, r.width, r.height)).collect();
+    if !return_image {
+        return Ok(boxes.into_py(py));
+    }
+    let png = encode_png(&DynamicImage::ImageRgb8(render_diff(&img_b.data, w, h, &changed, &regions)))?;
+    Ok((boxes, PyBytes::new(py, &png)).into_py(py))
+}
+
+/// Visual regression check of a screenshot against its golden image
+/// 
+/// `current` is first aligned to `golden` by the shift (up to `max_shift`
+/// pixels on each axis) with the highest NCC, then diffed over the overlap
+/// like `diff_images`. Pixels in `ignore_regions` (golden coordinates) never
+/// count as changed and are left out of the alignment.
+/// 
+/// Args:
+///     golden: Reference image as file path or encoded bytes
+///     current: New image of the same size as file path or encoded bytes
+///     tolerance: Grey-level difference a pixel may have without counting as changed, default 16
+///     ignore_regions: (x, y, width, height) boxes to mask out, e.g. clocks or ads
+///     max_shift: Largest shift in pixels searched on each axis, default 4
+///     min_area: Smallest number of changed pixels a region must have, default 1
+///     merge_distance: Changes up to this many pixels apart share one region, default 4
+/// 
+/// Returns:
+///     Dict with passed, shift (dx, dy), alignment_score and regions, a list
+///     of dicts with x, y, width, height, score (NCC of the region), max_diff
+///     and changed_pixels sorted top to bottom
+#[pyfunction]
+#[pyo3(signature = (golden, current, tolerance=16.0, ignore_regions=Vec::new(), max_shift=4, min_area=1, merge_distance=4))]
+#[allow(clippy::too_many_arguments)]
+fn compare_to_golden<'py>(
+    py: Python<'py>, golden: ImageInput, current: ImageInput, tolerance: f64, ignore_regions: Vec<(usize, usize, usize, usize)>,
+    max_shift: usize, min_area: usize, merge_distance: usize,
+) -> PyResult<&'py PyDict> {
+    if tolerance.is_nan() || tolerance < 0.0 {
+        return Err(PyValueError::new_err("tolerance must be non-negative"));
+    }
+    let img_a = golden.load()?;
+    let img_b = current.load()?;
+    if img_a.width != img_b.width || img_a.height != img_b.height {
+        return Err(InvalidDimensionsError::new_err(format!(
+            "Image sizes differ: {}x{} vs {}x{}", img_a.width, img_a.height, img_b.width, img_b.height
+        )));
+    }
+    let (w, h) = (img_a.width, img_a.height);
+    if max_shift >= w || max_shift >= h {
+        return Err(PyValueError::new_err(format!("max_shift must be smaller than the image ({}x{})", w, h)));
+    }
+    let (shift, alignment, regions) = py.allow_threads(|| {
+        compare_golden(&img_a.data, &img_b.data, w, h, &ignore_regions, max_shift, tolerance, merge_distance, min_area)
+    });
+    let entries = regions
+        .iter()
+        .map(|r| {
+            let entry = PyDict::new(py);
+            entry.set_item("x", r.region.x)?;
+            entry.set_item("y", r.region.y)?;
+            entry.set_item("width", r.region.width)?;
+            entry.set_item("height", r.region.height)?;
+            entry.set_item("score", r.score)?;
+            entry.set_item("max_diff", r.max_diff)?;
+            entry.set_item("changed_pixels", r.changed_pixels)?;
+            Ok(entry)
+        })
+        .collect::<PyResult<Vec<&PyDict>>>()?;
+    let report = PyDict::new(py);
+    report.set_item("passed", entries.is_empty())?;
+    report.set_item("shift", shift)?;
+    report.set_item("alignment_score", alignment)?;
+    report.set_item("regions", entries)?;
+    Ok(report)
+}
+
+/// Perceptual (DCT) hash of an image
+/// 
+/// Robust to rescaling, mild blur and brightness changes; compare hashes
+/// with `hamming_distance`.
+/// 
+/// Returns:
+///     64-bit hash as int
+#[pyfunction]
+fn phash(image: ImageInput) -> PyResult<u64> {
+    let img = image.load()?;
+    Ok(phash_bits(&img.data, img.width, img.height))
+}
+
+/// Difference hash of an image (gradient direction of a 9x8 thumbnail)
+/// 
+/// Returns:
+///     64-bit hash as int
+#[pyfunction]
+fn dhash(image: ImageInput) -> PyResult<u64> {
+    let img = image.load()?;
+    Ok(dhash_bits(&img.data, img.width, img.height))
+}
+
+/// Number of differing bits between two 64-bit hashes
+#[pyfunction]
+fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Bounding box of a template with its near-constant margins stripped
+/// 
+/// This is the crop applied by the `autocrop=True` matching option; `x` and
+/// `y` are the offset of the trimmed template within the original.
+/// 
+/// Args:
+///     image: Template as file path or encoded bytes
+///     tolerance: Maximum grey-level spread of a margin row/column, default 2.0
+/// 
+/// Returns:
+///     Tuple of (x, y, width, height)
+#[pyfunction]
+#[pyo3(signature = (image, tolerance=2.0))]
+fn autocrop(image: ImageInput, tolerance: f64) -> PyResult<(usize, usize, usize, usize)> {
+    if tolerance < 0.0 {
+        return Err(PyValueError::new_err("tolerance must be non-negative"));
+    }
+    let img = image.load()?;
+    Ok(autocrop_box(&img.data, img.width, img.height, tolerance))
+}
+
+/// Check whether a template has enough contrast to match reliably
+/// 
+/// Near-uniform templates produce confidences dominated by noise. The same
+/// check with the default limits runs before matching with `strict=True`.
+/// 
+/// Args:
+///     template: Template as file path or encoded bytes
+///     min_std: Minimum grey-level standard deviation, default 5.0
+///     min_entropy: Minimum grey-level histogram entropy in bits, default 0.2
+/// 
+/// Returns:
+///     Dict with std, entropy, valid (bool) and issues (list of messages)
+#[pyfunction]
+#[pyo3(signature = (template, min_std=MIN_TEMPLATE_STD, min_entropy=MIN_TEMPLATE_ENTROPY))]
+fn validate_template<'py>(py: Python<'py>, template: ImageInput, min_std: f64, min_entropy: f64) -> PyResult<&'py PyDict> {
+    let tpl = template.load()?;
+    let (std, entropy) = template_stats(&tpl.data);
+    let issues = template_issues(std, entropy, min_std, min_entropy);
+    let report = PyDict::new(py);
+    report.set_item("std", std)?;
+    report.set_item("entropy", entropy)?;
+    report.set_item("valid", issues.is_empty())?;
+    report.set_item("issues", issues)?;
+    Ok(report)
+}
+
+/// Suggest a threshold from the template's scores on images known not to contain it
+/// 
+/// Every window position of every negative image is scored, and the threshold
+/// is the score that only `false_positive_rate` of those windows reach.
+/// 
+/// Args:
+///     template: Template as file path or encoded bytes
+///     negative_sources: Images (paths or bytes) that don't contain the template
+///     false_positive_rate: Tolerated fraction of negative windows at or above the threshold
+/// 
+/// Returns:
+///     Dict with threshold, max_score (best score on any negative), windows
+///     (number of scored positions) and false_positive_rate (achieved rate)
+#[pyfunction]
+#[pyo3(signature = (template, negative_sources, false_positive_rate=0.001))]
+fn calibrate_threshold<'py>(
+    py: Python<'py>, template: ImageInput, negative_sources: Vec<ImageInput>, false_positive_rate: f64,
+) -> PyResult<&'py PyDict> {
+    if !(0.0..=1.0).contains(&false_positive_rate) {
+        return Err(PyValueError::new_err("false_positive_rate must be between 0 and 1"));
+    }
+    if negative_sources.is_empty() {
+        return Err(PyValueError::new_err("negative_sources must not be empty"));
+    }
+    let tpl = template.load()?;
+    let prepared = Template::new(&tpl.data, tpl.width, tpl.height);
+    let mut histogram = ScoreHistogram::new();
+    for source in &negative_sources {
+        let src = source.load()?;
+        if tpl.width > src.width || tpl.height > src.height {
+            return Err(TemplateTooLargeError::new_err(format!(
+                "Template ({}x{}) is larger than a negative source ({}x{})", tpl.width, tpl.height, src.width, src.height
+            )));
+        }
+        let scores = py.allow_threads(|| score_map(&src.data, src.width, src.height, &prepared));
+        histogram.add(&scores);
+    }
+
+    let threshold = histogram.threshold_at(false_positive_rate);
+    let report = PyDict::new(py);
+    report.set_item("threshold", threshold)?;
+    report.set_item("max_score", histogram.max)?;
+    report.set_item("windows", histogram.total)?;
+    report.set_item("false_positive_rate", histogram.rate_above(threshold))?;
+    Ok(report)
+}
+
+/// Window score at or above which a background window is taken to be the
+/// template itself (e.g. in the screenshot it was cut from)
+const SELF_MATCH_SCORE: f64 = 0.99;
+
+/// Best window of a score map, skipping the template's own occurrence: if
+/// the best window reaches `SELF_MATCH_SCORE`, windows overlapping it are
+/// left out. Returns (score, x, y, whether an occurrence was skipped).
+fn best_spurious_window(scores: &[f64], cols: usize, tw: usize, th: usize) -> Option<(f64, usize, usize, bool)> {
+    let best = |skip: Option<(usize, usize)>| {
+        scores.iter().enumerate()
+            .map(|(i, &s)| (s, i % cols, i / cols))
+            .filter(|&(_, x, y)| skip.map_or(true, |(sx, sy)| x.abs_diff(sx) >= tw || y.abs_diff(sy) >= th))
+            .max_by(|a, b| a.0.total_cmp(&b.0))
+    };
+    let (score, x, y) = best(None)?;
+    if score < SELF_MATCH_SCORE { return Some((score, x, y, false)); }
+    best(Some((x, y))).map(|(s, x, y)| (s, x, y, true))
+}
+
+/// Report how distinctive a template is before shipping it
+/// 
+/// Combines the contrast checks of `validate_template` with the best
+/// spurious score against background images: every window position of each
+/// background is scored, and windows overlapping the template's own
+/// occurrence (a window scoring at least 0.99) are left out.
+/// 
+/// Args:
+///     template: Template as file path or encoded bytes
+///     background_sources: Images (paths or bytes) the template will be searched in
+///     threshold: Matching threshold the template will be used with, default 0.8
+///     margin: Spurious scores within this distance below the threshold are flagged, default 0.05
+///     min_std: Minimum grey-level standard deviation, default 5.0
+///     min_entropy: Minimum grey-level histogram entropy in bits, default 0.2
+/// 
+/// Returns:
+///     Dict with std, entropy, max_spurious_score, backgrounds (one dict per
+///     background with max_score, x, y and contains_template), distinct
+///     (bool) and issues (list of messages)
+#[pyfunction]
+#[pyo3(signature = (template, background_sources=Vec::new(), threshold=0.8, margin=0.05, min_std=MIN_TEMPLATE_STD, min_entropy=MIN_TEMPLATE_ENTROPY))]
+fn template_quality<'py>(
+    py: Python<'py>, template: ImageInput, background_sources: Vec<ImageInput>, threshold: f64, margin: f64, min_std: f64,
+    min_entropy: f64,
+) -> PyResult<&'py PyDict> {
+    if margin < 0.0 {
+        return Err(PyValueError::new_err("margin must be non-negative"));
+    }
+    let tpl = template.load()?;
+    let (std, entropy) = template_stats(&tpl.data);
+    let mut issues = template_issues(std, entropy, min_std, min_entropy);
+    let prepared = Template::new(&tpl.data, tpl.width, tpl.height);
+    let mut backgrounds = Vec::with_capacity(background_sources.len());
+    let mut max_spurious: Option<f64> = None;
+    for (i, source) in background_sources.iter().enumerate() {
+        let src = source.load()?;
+        if tpl.width > src.width || tpl.height > src.height {
+            return Err(TemplateTooLargeError::new_err(format!(
+                "Template ({}x{}) is larger than background {} ({}x{})", tpl.width, tpl.height, i, src.width, src.height
+            )));
+        }
+        let cols = src.width - tpl.width + 1;
+        let best = py.allow_threads(|| {
+            best_spurious_window(&score_map(&src.data, src.width, src.height, &prepared), cols, tpl.width, tpl.height)
+        });
+        let entry = PyDict::new(py);
+        match best {
+            Some((score, x, y, contains)) => {
+                entry.set_item("max_score", score)?;
+                entry.set_item("x", x)?;
+                entry.set_item("y", y)?;
+                entry.set_item("contains_template", contains)?;
+                if score >= threshold - margin {
+                    issues.push(format!(
+                        "spurious match {:.4} at ({}, {}) in background {} is within {} of threshold {}",
+                        score, x, y, i, margin, threshold
+                    ));
+                }
+                max_spurious = Some(max_spurious.map_or(score, |m| m.max(score)));
+            }
+            // The template's own occurrence was the only window
+            None => {
+                entry.set_item("max_score", py.None())?;
+                entry.set_item("x", py.None())?;
+                entry.set_item("y", py.None())?;
+                entry.set_item("contains_template", true)?;
+            }
+        }
+        backgrounds.push(entry);
+    }
+    let report = PyDict::new(py);
+    report.set_item("std", std)?;
+    report.set_item("entropy", entropy)?;
+    report.set_item("max_spurious_score", max_spurious)?;
+    report.set_item("backgrounds", backgrounds)?;
+    report.set_item("distinct", issues.is_empty())?;
+    report.set_item("issues", issues)?;
+    Ok(report)
+}
+
+/// Smallest side, in coarse pixels, that `suggest_template_region` scores a
+/// candidate region at
+const SUGGEST_COARSE_SIDE: usize = 12;
+
+/// Candidate rectangles `(x, y, width, height)` containing `(px, py)`: sides
+/// grow by about 1.5x from `min_side` to `max_side` with aspect ratios up to
+/// 2, and the point sits at the centre or a quarter of the way in along each
+/// axis. Rectangles are shifted to fit the image.
+fn template_region_candidates(
+    sw: usize, sh: usize, px: usize, py: usize, min_side: usize, max_side: usize,
+) -> Vec<(usize, usize, usize, usize)> {
+    let mut sides = vec![min_side];
+    while let Some(&last) = sides.last() {
+        if last >= max_side { break; }
+        sides.push((last * 3 / 2).max(last + 1).min(max_side));
+    }
+    let mut candidates = Vec::new();
+    for &w in sides.iter().filter(|&&w| w <= sw) {
+        for &h in sides.iter().filter(|&&h| h <= sh && h <= 2 * w && w <= 2 * h) {
+            for (fx, fy) in [(2, 2), (1, 1), (3, 1), (1, 3), (3, 3)] {
+                let x = px.saturating_sub(w * fx / 4).min(sw - w);
+                let y = py.saturating_sub(h * fy / 4).min(sh - h);
+                if !candidates.contains(&(x, y, w, h)) { candidates.push((x, y, w, h)); }
+            }
+        }
+    }
+    candidates
+}
+
+/// Best NCC of the region `(x, y, w, h)` with any window of the image that
+/// doesn't overlap it, scored on a pyramid level where the region's shorter
+/// side is about `SUGGEST_COARSE_SIDE` pixels; None if no such window exists
+fn region_self_similarity(levels: &HashMap<usize, (Vec<f64>, usize, usize)>, factor: usize, region: (usize, usize, usize, usize)) -> Option<f64> {
+    let (data, cw, ch) = &levels[&factor];
+    let (x, y, w, h) = (region.0 / factor, region.1 / factor, region.2 / factor, region.3 / factor);
+    let (w, h) = (w.min(cw - x), h.min(ch - y));
+    let tpl = Template::new(&crop_region(data, *cw, x, y, w, h), w, h);
+    let cols = cw - w + 1;
+    score_map(data, *cw, *ch, &tpl).iter().enumerate()
+        .filter(|&(i, _)| (i % cols).abs_diff(x) >= w || (i / cols).abs_diff(y) >= h)
+        .map(|(_, &s)| s)
+        .max_by(f64::total_cmp)
+}
+
+/// Region picked by `suggest_region`
+struct RegionSuggestion {
+    region: (usize, usize, usize, usize),
+    self_similarity: f64,
+    std: f64,
+    entropy: f64,
+    /// Candidates with enough contrast that were scored
+    candidates: usize,
+}
+
+/// The candidate region around `(x, y)` least similar to the rest of the
+/// image, among those passing the template contrast checks
+fn suggest_region(src: &GrayImageData, x: usize, y: usize, min_side: usize, max_side: usize) -> Option<RegionSuggestion> {
+    let candidates: Vec<_> = template_region_candidates(src.width, src.height, x, y, min_side, max_side)
+        .into_iter()
+        .filter_map(|r| {
+            let (std, entropy) = template_stats(&crop_region(&src.data, src.width, r.0, r.1, r.2, r.3));
+            template_issues(std, entropy, MIN_TEMPLATE_STD, MIN_TEMPLATE_ENTROPY).is_empty().then_some((r, std, entropy))
+        })
+        .collect();
+    log::debug!("suggest_template_region: {} candidates with enough contrast around ({}, {})", candidates.len(), x, y);
+    let factor_of = |r: &(usize, usize, usize, usize)| (r.2.min(r.3) / SUGGEST_COARSE_SIDE).max(1);
+    let mut levels = HashMap::new();
+    for (r, _, _) in &candidates {
+        let factor = factor_of(r);
+        levels.entry(factor).or_insert_with(|| {
+            if factor == 1 { (src.data.clone(), src.width, src.height) } else { downsample(&src.data, src.width, src.height, factor) }
+        });
+    }
+    candidates.par_iter()
+        .map(|&(region, std, entropy)| {
+            let self_similarity = region_self_similarity(&levels, factor_of(&region), region).unwrap_or(0.0);
+            RegionSuggestion { region, self_similarity, std, entropy, candidates: candidates.len() }
+        })
+        .min_by(|a, b| a.self_similarity.total_cmp(&b.self_similarity))
+}
+
+/// Suggest the most distinctive template region around a point, e.g. where
+/// an automation script should click
+/// 
+/// Candidate rectangles containing the point (sides from size_range, aspect
+/// ratios up to 2) that pass the contrast checks of `validate_template` are
+/// scored by their best NCC with any other, non-overlapping window of the
+/// image, on a pyramid level where their shorter side is about 12 pixels.
+/// The candidate least similar to the rest of the image wins.
+/// 
+/// Args:
+///     source: Screenshot as file path or encoded bytes
+///     x, y: Target point, inside the image
+///     size_range: (min_side, max_side) of the candidate rectangles, default (16, 64)
+/// 
+/// Returns:
+///     Dict with x, y, width, height, self_similarity, std, entropy and
+///     candidates (number scored), or None if no candidate has enough contrast
+#[pyfunction]
+#[pyo3(signature = (source, x, y, size_range=(16, 64)))]
+fn suggest_template_region<'py>(
+    py: Python<'py>, source: ImageInput, x: usize, y: usize, size_range: (usize, usize),
+) -> PyResult<Option<&'py PyDict>> {
+    let (min_side, max_side) = size_range;
+    if min_side < 4 || min_side > max_side {
+        return Err(PyValueError::new_err("size_range must be (min_side, max_side) with 4 <= min_side <= max_side"));
+    }
+    let src = source.load()?;
+    if x >= src.width || y >= src.height {
+        return Err(InvalidDimensionsError::new_err(format!(
+            "Point ({}, {}) is outside the {}x{} image", x, y, src.width, src.height
+        )));
+    }
+    let Some(best) = py.allow_threads(|| suggest_region(&src, x, y, min_side, max_side)) else { return Ok(None) };
+    let (rx, ry, rw, rh) = best.region;
+    let report = PyDict::new(py);
+    report.set_item("x", rx)?;
+    report.set_item("y", ry)?;
+    report.set_item("width", rw)?;
+    report.set_item("height", rh)?;
+    report.set_item("self_similarity", best.self_similarity)?;
+    report.set_item("std", best.std)?;
+    report.set_item("entropy", best.entropy)?;
+    report.set_item("candidates", best.candidates)?;
+    Ok(Some(report))
+}
+
+/// Count the matches each threshold would return, from a single dense scan
+/// 
+/// Greedy non-maximum suppression keeps a result only if no stronger one
+/// overlaps it, so the matches kept at a threshold are exactly those kept at
+/// the lowest threshold that also reach it; one suppression pass covers all.
+/// 
+/// Args:
+///     source: Source image as file path or encoded bytes
+///     template: Template image as file path or encoded bytes
+///     thresholds: Thresholds to report, default 0.50 to 0.95 in steps of 0.05
+/// 
+/// Returns:
+///     List of (threshold, match count) in the order given
+#[pyfunction]
+#[pyo3(signature = (source, template, thresholds=None))]
+fn score_sweep(
+    py: Python<'_>, source: ImageInput, template: ImageInput, thresholds: Option<Vec<f64>>,
+) -> PyResult<Vec<(f64, usize)>> {
+    let thresholds = thresholds.unwrap_or_else(|| DEFAULT_SWEEP_THRESHOLDS.to_vec());
+    if thresholds.is_empty() || thresholds.iter().any(|t| !t.is_finite()) {
+        return Err(PyValueError::new_err("thresholds must be a non-empty list of numbers"));
+    }
+    let src = source.load()?;
+    let tpl = template.load()?;
+    if tpl.width > src.width || tpl.height > src.height {
+        return Err(TemplateTooLargeError::new_err(format!(
+            "Template ({}x{}) is larger than the source ({}x{})", tpl.width, tpl.height, src.width, src.height
+        )));
+    }
+    let lowest = thresholds.iter().copied().fold(f64::INFINITY, f64::min);
+    let kept = py.allow_threads(|| {
+        let scores = score_map(&src.data, src.width, src.height, &Template::new(&tpl.data, tpl.width, tpl.height));
+        let (cols, rows) = (src.width - tpl.width + 1, src.height - tpl.height + 1);
+        non_max_suppression(score_peaks(&scores, cols, rows, lowest), tpl.width, tpl.height, usize::MAX)
+    });
+    Ok(thresholds.iter().map(|&t| (t, kept.iter().filter(|r| r.confidence >= t).count())).collect())
+}
+
+/// Maximum NCC score per grid cell, for "where might it be" overlays
+/// 
+/// The source is scanned exhaustively, but only the best score among the
+/// window positions (top-left corners) in each `cell x cell` block is kept.
+/// 
+/// Args:
+///     source: Source image as file path or encoded bytes
+///     template: Template image as file path or encoded bytes
+///     cell: Cell size in pixels, default 32
+/// 
+/// Returns:
+///     Rows of per-cell maxima; row `j`, column `i` covers window positions
+///     `x` in `[i * cell, (i + 1) * cell)` and `y` in `[j * cell, (j + 1) * cell)`
+#[pyfunction]
+#[pyo3(signature = (source, template, cell=32))]
+fn score_grid(py: Python<'_>, source: ImageInput, template: ImageInput, cell: usize) -> PyResult<Vec<Vec<f64>>> {
+    if cell == 0 {
+        return Err(PyValueError::new_err("cell must be at least 1"));
+    }
+    let src = source.load()?;
+    let tpl = template.load()?;
+    if tpl.width > src.width || tpl.height > src.height {
+        return Err(TemplateTooLargeError::new_err(format!(
+            "Template ({}x{}) is larger than the source ({}x{})", tpl.width, tpl.height, src.width, src.height
+        )));
+    }
+    let (grid, gw, _) = py.allow_threads(|| {
+        pooled_score_map(&src.data, src.width, src.height, &Template::new(&tpl.data, tpl.width, tpl.height), cell)
+    });
+    Ok(grid.chunks(gw).map(<[f64]>::to_vec).collect())
+}
+
+/// Break the NCC score of the window at `(x, y)` down into per-block terms
+/// 
+/// Args:
+///     source: Source image as file path or encoded bytes
+///     template: Template image as file path or encoded bytes
+///     x, y: Top-left corner of the window, e.g. a match position
+///     block: Cell size in template pixels, default 1 (per pixel)
+///     return_image: Also return a PNG of the window with agreeing cells
+///         tinted green and disagreeing cells red
+/// 
+/// Returns:
+///     Rows of per-cell contributions summing to the window's NCC score, or a
+///     tuple of (rows, PNG bytes) with return_image=True
+#[pyfunction]
+#[pyo3(signature = (source, template, x, y, block=1, return_image=false))]
+fn explain_match(
+    py: Python<'_>, source: ImageInput, template: ImageInput, x: usize, y: usize, block: usize, return_image: bool,
+) -> PyResult<PyObject> {
+    if block == 0 {
+        return Err(PyValueError::new_err("block must be at least 1"));
+    }
+    let src = source.load()?;
+    let tpl = template.load()?;
+    if tpl.width > src.width || tpl.height > src.height {
+        return Err(TemplateTooLargeError::new_err(format!(
+            "Template ({}x{}) is larger than the source ({}x{})", tpl.width, tpl.height, src.width, src.height
+        )));
+    }
+    if x > src.width - tpl.width || y > src.height - tpl.height {
+        return Err(PyValueError::new_err(format!(
+            "Window at ({}, {}) doesn't fit the source; x must be at most {} and y at most {}",
+            x, y, src.width - tpl.width, src.height - tpl.height
+        )));
+    }
+    let (cells, gw, _) = ncc_contributions(&src.data, src.width, &tpl, x, y, block);
+    let rows: Vec<Vec<f64>> = cells.chunks(gw).map(<[f64]>::to_vec).collect();
+    if !return_image {
+        return Ok(rows.into_py(py));
+    }
+    let window = crop_region(&src.data, src.width, x, y, tpl.width, tpl.height);
+    let heatmap = render_contributions(&window, tpl.width, tpl.height, &cells, gw, block);
+    let png = encode_png(&DynamicImage::ImageRgb8(heatmap))?;
+    Ok((rows, PyBytes::new(py, &png)).into_py(py))
+}
+
+/// Estimate the scale factor between a template and its appearance in a source
+/// 
+/// Each candidate factor is tried at a shared coarse pyramid level and the
+/// best-correlating one is returned. Pass `auto_scale=True` to the find
+/// functions to apply it automatically.
+/// 
+/// Args:
+///     source: Source image as file path or encoded bytes
+///     template: Template image as file path or encoded bytes
+///     scale_candidates: Factors to try, default 0.5-2.5
+/// 
+/// Returns:
+///     Tuple of (scale, coarse score) or None if no scaled template fits the source
+#[pyfunction]
+#[pyo3(signature = (source, template, scale_candidates=None))]
+fn estimate_scale(
+    py: Python<'_>, source: ImageInput, template: ImageInput, scale_candidates: Option<&PyAny>,
+) -> PyResult<Option<(f64, f64)>> {
+    let candidates = match scale_candidates {
+        Some(value) => extract_scale_candidates(value)?,
+        None => None,
+    }.unwrap_or_else(|| DEFAULT_SCALE_CANDIDATES.to_vec());
+    let src = source.load()?;
+    let tpl = template.load()?;
+    Ok(py.allow_threads(|| estimate_template_scale(&src.data, src.width, src.height, &tpl, &candidates)))
+}
+
+/// Serialize a list of results as a JSON array
+/// 
+/// Returns:
+///     JSON string, one object per result as produced by `MatchResult.to_json()`
+#[pyfunction]
+fn results_to_json(results: Vec<PyRef<MatchResult>>) -> String {
+    let items: Vec<String> = results.iter().map(|r| r.json()).collect();
+    format!("[{}]", items.join(","))
+}
+
+/// Render results as an SVG layer to overlay on the source image
+/// 
+/// Each match becomes a `<g class="match">` holding an outline `<rect>`
+/// (with `data-confidence`) and a `<text>` label with its confidence, above
+/// the box or, at the top edge, just inside it.
+/// 
+/// Args:
+///     matches: MatchResult list
+///     template_size: (width, height) of the template; boxes are scaled by
+///         each result's `scale`
+///     image_size: (width, height) of the source, the SVG viewport
+/// 
+/// Returns:
+///     SVG document as a string
+#[pyfunction]
+fn matches_to_svg(matches: Vec<PyRef<MatchResult>>, template_size: (u32, u32), image_size: (u32, u32)) -> String {
+    let (tw, th) = template_size;
+    let (iw, ih) = image_size;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">\n\
+         <g class=\"matches\" fill=\"none\" stroke=\"#ff0000\" stroke-width=\"2\" font-family=\"sans-serif\" font-size=\"12\">\n",
+        iw, ih, iw, ih
+    );
+    for r in &matches {
+        let scale = r.scale.unwrap_or(1.0);
+        let (w, h) = ((tw as f64 * scale).round(), (th as f64 * scale).round());
+        let label_y = if r.y >= 14 { r.y - 3 } else { r.y + 13 };
+        svg += &format!(
+            "<g class=\"match\"><rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" data-confidence=\"{}\"/>\
+             <text x=\"{}\" y=\"{}\" fill=\"#ff0000\" stroke=\"none\">{:.3}</text></g>\n",
+            r.x, r.y, w, h, r.confidence, r.x + 2, label_y, r.confidence
+        );
+    }
+    svg + "</g>\n</svg>\n"
+}
+
+/// Set how many decoded images loaded by path are kept in memory
+/// 
+/// Args:
+///     size: Maximum number of cached images (0 disables the cache)
+#[pyfunction]
+fn set_image_cache_size(size: usize) {
+    let mut cache = lock_cache();
+    cache.capacity = size;
+    let excess = cache.entries.len().saturating_sub(size);
+    cache.entries.drain(..excess);
+}
+
+/// Drop every cached image and reset the hit/miss counters
+#[pyfunction]
+fn clear_image_cache() {
+    let mut cache = lock_cache();
+    cache.entries.clear();
+    cache.hits = 0;
+    cache.misses = 0;
+}
+
+/// Decoded image cache statistics
+/// 
+/// Returns:
+///     Dict with hits, misses, size (images cached) and capacity
+#[pyfunction]
+fn image_cache_info(py: Python<'_>) -> PyResult<&PyDict> {
+    let cache = lock_cache();
+    let info = PyDict::new(py);
+    info.set_item("hits", cache.hits)?;
+    info.set_item("misses", cache.misses)?;
+    info.set_item("size", cache.entries.len())?;
+    info.set_item("capacity", cache.capacity)?;
+    Ok(info)
+}
+
+/// Enable the result cache, or disable it with size 0
+/// 
+/// Args:
+///     size: Maximum number of cached calls (0 disables the cache)
+///     ttl: Seconds a cached result stays valid, default None (until evicted).
+///         Changing it drops the cached results; 0 stores nothing.
+#[pyfunction]
+#[pyo3(signature = (size, ttl=None))]
+fn set_result_cache(size: usize, ttl: Option<f64>) -> PyResult<()> {
+    let ttl = ttl.map(|t| Duration::try_from_secs_f64(t).map_err(|_| PyValueError::new_err("ttl must be a non-negative number of seconds"))).transpose()?;
+    let mut cache = lock_result_cache();
+    cache.capacity = size;
+    // Entries were stored under the old TTL, so they are not carried over
+    if cache.ttl != ttl { cache.entries.clear(); }
+    cache.ttl = ttl;
+    let excess = cache.entries.len().saturating_sub(size);
+    cache.entries.drain(..excess);
+    Ok(())
+}
+
+/// Drop every cached result and reset the hit/miss counters
+#[pyfunction]
+fn clear_result_cache() {
+    let mut cache = lock_result_cache();
+    cache.entries.clear();
+    cache.hits = 0;
+    cache.misses = 0;
+}
+
+/// Result cache statistics
+/// 
+/// Returns:
+///     Dict with hits, misses, size (calls cached), capacity and ttl
+#[pyfunction]
+fn result_cache_info(py: Python<'_>) -> PyResult<&PyDict> {
+    let cache = lock_result_cache();
+    let info = PyDict::new(py);
+    info.set_item("hits", cache.hits)?;
+    info.set_item("misses", cache.misses)?;
+    info.set_item("size", cache.entries.len())?;
+    info.set_item("capacity", cache.capacity)?;
+    info.set_item("ttl", cache.ttl.map(|t| t.as_secs_f64()))?;
+    Ok(info)
+}
+
+/// Set number of threads for parallel processing
+#[pyfunction]
+fn set_num_threads(num_threads: usize) -> PyResult<()> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(if num_threads == 0 { num_cpus::get() } else { num_threads })
+        .build_global()
+        .map_err(|e| PyValueError::new_err(format!("Failed to set threads: {}", e)))
+}
+
+/// Dedicated pool of `num_threads` workers (0 = one per CPU)
+fn thread_pool(num_threads: usize) -> PyResult<rayon::ThreadPool> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(if num_threads == 0 { num_cpus::get() } else { num_threads })
+        .build()
+        .map_err(|e| PyValueError::new_err(format!("Failed to create thread pool: {}", e)))
+}
+
+/// Set how much work a search needs before it is spread over the thread pool
+/// 
+/// Parallel loops are split into tasks of at least `min_work` pixel
+/// comparisons (window positions times template pixels), so searches below
+/// it run on the calling thread.
+/// 
+/// Args:
+///     min_work: Minimum comparisons per task; 0 parallelizes every search
+///         and None restores the default (262144)
+#[pyfunction]
+#[pyo3(signature = (min_work=None))]
+fn set_parallel_threshold(min_work: Option<usize>) {
+    PARALLEL_MIN_WORK.store(min_work.unwrap_or(DEFAULT_PARALLEL_MIN_WORK), std::sync::atomic::Ordering::Relaxed);
+}
+
+/// Get library version
+#[pyfunction]
+fn version() -> &'static str {
+    env!("CARGO_PKG_VERSION")
+}
+
+// ============================================================================
+// Python Interface - Image Utilities
+// ============================================================================
+
+/// Encoded bytes of `img`, or `None` after saving it to `out_path`
+/// (format picked from the file extension)
+fn save_or_encode<'py>(py: Python<'py>, img: &DynamicImage, out_path: Option<&str>) -> PyResult<Option<&'py PyBytes>> {
+    match out_path {
+        Some(path) => {
+            img.save(path)
+                .map_err(|e| PyIOError::new_err(format!("Failed to save image '{}': {}", path, e)))?;
+            Ok(None)
+        }
+        None => Ok(Some(PyBytes::new(py, &encode_png(img)?))),
+    }
+}
+
+/// Cut a rectangle out of an image
+/// 
+/// Args:
+///     image: Image as file path or encoded bytes
+///     x, y: Top-left corner of the rectangle
+///     width, height: Rectangle size, must lie inside the image
+///     out_path: Save the crop here instead of returning it (format from extension)
+/// 
+/// Returns:
+///     PNG bytes, or None when out_path is given
+#[pyfunction]
+#[pyo3(signature = (image, x, y, width, height, out_path=None))]
+fn crop_image<'py>(
+    py: Python<'py>, image: ImageInput, x: u32, y: u32, width: u32, height: u32, out_path: Option<&str>,
+) -> PyResult<Option<&'py PyBytes>> {
+    let img = image.load_dynamic()?;
+    check_crop(&img, x, y, width, height)?;
+    save_or_encode(py, &img.crop_imm(x, y, width, height), out_path)
+}
+
+/// Reject empty crops and crops reaching outside the image
+fn check_crop(img: &DynamicImage, x: u32, y: u32, width: u32, height: u32) -> PyResult<()> {
+    if width == 0 || height == 0 {
+        return Err(InvalidDimensionsError::new_err("Crop width and height must be positive"));
+    }
+    if x as u64 + width as u64 > img.width() as u64 || y as u64 + height as u64 > img.height() as u64 {
+        return Err(InvalidDimensionsError::new_err(format!(
+            "Crop ({}, {}, {}, {}) is outside the {}x{} image", x, y, width, height, img.width(), img.height()
+        )));
+    }
+    Ok(())
+}
+
+fn parse_resize_filter(name: &str) -> PyResult<image::imageops::FilterType> {
+    use image::imageops::FilterType;
+    match name {
+        "nearest" => Ok(FilterType::Nearest),
+        "bilinear" | "triangle" => Ok(FilterType::Triangle),
+        "bicubic" | "catmullrom" => Ok(FilterType::CatmullRom),
+        "gaussian" => Ok(FilterType::Gaussian),
+        "lanczos" => Ok(FilterType::Lanczos3),
+        other => Err(PyValueError::new_err(format!(
+            "Unknown resize filter '{}', expected 'nearest', 'bilinear', 'bicubic', 'gaussian' or 'lanczos'", other
+        ))),
+    }
+}
+
+/// Resize an image to an exact size
+/// 
+/// Args:
+///     image: Image as file path or encoded bytes
+///     width, height: New size in pixels (aspect ratio is not preserved)
+///     filter: "nearest", "bilinear", "bicubic", "gaussian" or "lanczos" (default)
+/// 
+/// Returns:
+///     PNG bytes
+#[pyfunction]
+#[pyo3(signature = (image, width, height, filter="lanczos"))]
+fn resize_image<'py>(py: Python<'py>, image: ImageInput, width: u32, height: u32, filter: &str) -> PyResult<&'py PyBytes> {
+    let filter = parse_resize_filter(filter)?;
+    if width == 0 || height == 0 {
+        return Err(InvalidDimensionsError::new_err("Resize width and height must be positive"));
+    }
+    let img = image.load_dynamic()?;
+    let resized = py.allow_threads(|| img.resize_exact(width, height, filter));
+    Ok(PyBytes::new(py, &encode_png(&resized)?))
+}
+
+/// Convert an image to 8-bit grayscale with the matcher's own luma formula
+/// 
+/// Args:
+///     image: Image as file path or encoded bytes
+/// 
+/// Returns:
+///     Tuple of (row-major pixel bytes, width, height), ready for the raw-pixel API
+#[pyfunction]
+fn to_grayscale<'py>(py: Python<'py>, image: ImageInput) -> PyResult<(&'py PyBytes, u32, u32)> {
+    let gray = match image {
+        ImageInput::Path(path) => load_gray_from_path(path, true)?,
+        ImageInput::Bytes(_) => image.load_dynamic()?.to_luma8(),
+    };
+    let (w, h) = gray.dimensions();
+    Ok((PyBytes::new(py, gray.as_raw()), w, h))
+}
+
+/// Describe an image without matching it
+/// 
+/// The file is decoded once, so the colour type and bit depth are those of
+/// the decoded pixels (e.g. palette PNGs report "rgb" or "rgba").
+/// 
+/// Args:
+///     image: Image as file path or encoded bytes
+/// 
+/// Returns:
+///     Dict with width, height, format, color_type, channels, bit_depth,
+///     has_alpha and orientation (EXIF orientation 1-8, or None if untagged)
+#[pyfunction]
+fn get_image_info<'py>(py: Python<'py>, image: ImageInput) -> PyResult<&'py PyDict> {
+    let data = match image {
+        ImageInput::Bytes(data) => std::borrow::Cow::Borrowed(data),
+        ImageInput::Path(path) => std::borrow::Cow::Owned(std::fs::read(path)
+            .map_err(|e| PyIOError::new_err(format!("Failed to load image '{}': {}", path, e)))?),
+    };
+    let (format, img) = if let Some(kind) = iso_image_kind(&data) {
+        (kind.name().to_string(), load_dynamic_from_bytes(&data, true)?)
+    } else {
+        let format = image::guess_format(&data)
+            .or_else(|e| match image {
+                ImageInput::Path(path) => image::ImageFormat::from_path(path),
+                ImageInput::Bytes(_) => Err(e),
+            })
+            .map_err(|e| DecodeError::new_err(format!("Unrecognized image format: {}", e)))?;
+        let img = image::load_from_memory_with_format(&data, format)
+            .map_err(|e| DecodeError::new_err(format!("Failed to decode image: {}", e)))?;
+        (format!("{:?}", format).to_lowercase(), img)
+    };
+
+    let color = img.color();
+    let channels = color.channel_count();
+    let info = PyDict::new(py);
+    info.set_item("width", img.width())?;
+    info.set_item("height", img.height())?;
+    info.set_item("format", format)?;
+    info.set_item("color_type", match channels { 1 => "gray", 2 => "gray_alpha", 3 => "rgb", _ => "rgba" })?;
+    info.set_item("channels", channels)?;
+    info.set_item("bit_depth", color.bits_per_pixel() / channels as u16)?;
+    info.set_item("has_alpha", color.has_alpha())?;
+    info.set_item("orientation", exif_orientation(&mut Cursor::new(&data[..])))?;
+    Ok(info)
+}
+
+// ============================================================================
+// Python Interface - Test Assertions
+// ============================================================================
+
+/// Blue (low) to red (high) colour for a score in [-1, 1]
+fn heat_colour(score: f64) -> image::Rgb<u8> {
+    let t = ((score + 1.0) / 2.0).clamp(0.0, 1.0);
+    let channel = |centre: f64| ((1.5 - (4.0 * t - centre).abs()).clamp(0.0, 1.0) * 255.0).round() as u8;
+    image::Rgb([channel(3.0), channel(2.0), channel(1.0)])
+}
+
+/// Failure artifact for `assert_template_found`, three panels side by side:
+/// the source with the best window outlined, the template above that window,
+/// and the NCC score of every window position as a heatmap (computed on the
+/// coarse pyramid level, so each cell covers `scale` pixels)
+fn render_match_report(
+    src: &GrayImageData, raw_tpl: &GrayImageData, tpl: &GrayImageData, best: Option<&MatchResult>,
+) -> image::RgbImage {
+    const GAP: usize = 8;
+    let (sw, sh, tw, th) = (src.width, src.height, raw_tpl.width, raw_tpl.height);
+    let (panel_x, heat_x) = (sw + GAP, sw + GAP + tw + GAP);
+    let mut img = image::RgbImage::from_pixel((heat_x + sw) as u32, sh.max(2 * th + GAP) as u32, image::Rgb([255, 255, 255]));
+    let grey = |v: f64| {
+        let v = v.round().clamp(0.0, 255.0) as u8;
+        image::Rgb([v, v, v])
+    };
+    for y in 0..sh {
+        for x in 0..sw {
+            img.put_pixel(x as u32, y as u32, grey(src.data[y * sw + x]));
+        }
+    }
+    for y in 0..th {
+        for x in 0..tw {
+            img.put_pixel((panel_x + x) as u32, y as u32, grey(raw_tpl.data[y * tw + x]));
+        }
+    }
+    if let Some(r) = best {
+        let (bx, by) = (r.x.max(0) as usize, r.y.max(0) as usize);
+        let (x1, y1) = ((bx + tw).min(sw), (by + th).min(sh));
+        for y in by..y1 {
+            for x in bx..x1 {
+                img.put_pixel((panel_x + x - bx) as u32, (th + GAP + y - by) as u32, grey(src.data[y * sw + x]));
+            }
+        }
+        let red = image::Rgb([255, 0, 0]);
+        for x in bx..x1 {
+            img.put_pixel(x as u32, by as u32, red);
+            img.put_pixel(x as u32, (y1 - 1) as u32, red);
+        }
+        for y in by..y1 {
+            img.put_pixel(bx as u32, y as u32, red);
+            img.put_pixel((x1 - 1) as u32, y as u32, red);
+        }
+    }
+    let scale = pyramid_scale(tpl.width, tpl.height);
+    let (small_src, ssw, ssh) = downsample(&src.data, sw, sh, scale);
+    let (small_tpl, stw, sth) = downsample(&tpl.data, tpl.width, tpl.height, scale);
+    if stw <= ssw && sth <= ssh {
+        let scores = score_map(&small_src, ssw, ssh, &Template::new(&small_tpl, stw, sth));
+        let cols = ssw - stw + 1;
+        for y in 0..sh {
+            for x in 0..sw {
+                let (cx, cy) = (x / scale, y / scale);
+                let colour = if cx < cols && cy <= ssh - sth { heat_colour(scores[cy * cols + cx]) } else { image::Rgb([0, 0, 0]) };
+                img.put_pixel((heat_x + x) as u32, y as u32, colour);
+            }
+        }
+    }
+    img
+}
+
+/// Outline the `w`x`h` box at `(x, y)` in `img`, clipped to its edges
+fn outline_box(img: &mut image::RgbImage, x: usize, y: usize, w: usize, h: usize, colour: image::Rgb<u8>) {
+    let (iw, ih) = (img.width() as usize, img.height() as usize);
+    if x >= iw || y >= ih || w == 0 || h == 0 { return; }
+    let (x1, y1) = ((x + w).min(iw) - 1, (y + h).min(ih) - 1);
+    for px in x..=x1 {
+        img.put_pixel(px as u32, y as u32, colour);
+        img.put_pixel(px as u32, y1 as u32, colour);
+    }
+    for py in y..=y1 {
+        img.put_pixel(x as u32, py as u32, colour);
+        img.put_pixel(x1 as u32, py as u32, colour);
+    }
+}
+
+/// `debug_dir` dump of one search. With a coarse pyramid stage:
+/// `coarse_source.png` and `coarse_template.png` (the downsampled images)
+/// and `coarse_scores.png` (NCC of every coarse position as a heatmap, the
+/// peak marked by a white cross). Always: `refinement.png`, the source with
+/// the full-resolution windows searched outlined in yellow and the matches
+/// in red. Each search overwrites the previous one's files.
+fn write_debug_images(
+    dir: &Path, src: &GrayImageData, tpl: &GrayImageData, filter: DownsampleFilter,
+    detail: &StatsDetail, matches: &[MatchResult],
+) -> PyResult<()> {
+    std::fs::create_dir_all(dir)?;
+    let save = |img: image::RgbImage, name: &str| {
+        let path = dir.join(name);
+        img.save(&path).map_err(|e| PyIOError::new_err(format!("Failed to save {}: {}", path.display(), e)))
+    };
+    let grey = |data: &[f64], w: usize, h: usize| image::RgbImage::from_fn(w as u32, h as u32, |x, y| {
+        let v = data[y as usize * w + x as usize].round().clamp(0.0, 255.0) as u8;
+        image::Rgb([v, v, v])
+    });
+    let (tw, th) = (tpl.width, tpl.height);
+    if let (Some(scale), Some((_, px, py))) = (detail.scale, detail.coarse) {
+        let (small_src, ssw, ssh) = downsample_filtered(&src.data, src.width, src.height, scale, filter);
+        let (small_tpl, stw, sth) = downsample_filtered(&tpl.data, tw, th, scale, filter);
+        save(grey(&small_src, ssw, ssh), "coarse_source.png")?;
+        save(grey(&small_tpl, stw, sth), "coarse_template.png")?;
+        if stw <= ssw && sth <= ssh {
+            let scores = score_map(&small_src, ssw, ssh, &Template::new(&small_tpl, stw, sth));
+            let (cols, rows) = (ssw - stw + 1, ssh - sth + 1);
+            let mut heat = image::RgbImage::from_fn(cols as u32, rows as u32, |x, y| heat_colour(scores[y as usize * cols + x as usize]));
+            let (cx, cy) = ((px / scale).min(cols - 1), (py / scale).min(rows - 1));
+            let white = image::Rgb([255, 255, 255]);
+            for x in cx.saturating_sub(3)..=(cx + 3).min(cols - 1) { heat.put_pixel(x as u32, cy as u32, white); }
+            for y in cy.saturating_sub(3)..=(cy + 3).min(rows - 1) { heat.put_pixel(cx as u32, y as u32, white); }
+            save(heat, "coarse_scores.png")?;
+        }
+    }
+    let mut overlay = grey(&src.data, src.width, src.height);
+    for &(x1, y1, x2, y2) in &detail.windows {
+        outline_box(&mut overlay, x1, y1, x2 - x1 + tw, y2 - y1 + th, image::Rgb([255, 255, 0]));
+    }
+    for r in matches {
+        outline_box(&mut overlay, r.x.max(0) as usize, r.y.max(0) as usize, tw, th, image::Rgb([255, 0, 0]));
+    }
+    save(overlay, "refinement.png")
+}
+
+/// First unused `<stem>.png`, `<stem>-1.png`, ... in `dir`
+fn artifact_path(dir: &Path, stem: &str) -> PathBuf {
+    (0..)
+        .map(|i| dir.join(if i == 0 { format!("{}.png", stem) } else { format!("{}-{}.png", stem, i) }))
+        .find(|p| !p.exists())
+        .unwrap()
+}
+
+/// Assert that a template is found, for test suites
+/// 
+/// Args:
+///     source: Source image as file path or encoded bytes
+///     template: Template image as file path or encoded bytes
+///     threshold: Matching threshold (0.0-1.0), default 0.8
+///     artifact_dir: Directory to save a failure report PNG in
+///     **options: Matching options, e.g. autocrop=True
+/// 
+/// Returns:
+///     The MatchResult, when found
+/// 
+/// Raises:
+///     AssertionError with the best score and its location otherwise
+#[pyfunction]
+#[pyo3(signature = (source, template, threshold=0.8, artifact_dir=None, **options))]
+fn assert_template_found(
+    py: Python<'_>, source: ImageInput, template: ImageInput, threshold: f64, artifact_dir: Option<PathBuf>,
+    options: Option<&PyDict>,
+) -> PyResult<MatchResult> {
+    let mut opts = MatchOptions::from_kwargs(options)?;
+    let img = source.load_dynamic_as(opts.exif_orientation)?;
+    let src = opts.prepare_source(opts.plane(&img));
+    let raw_tpl = opts.load_plane(&template)?;
+    let mut tpl = opts.prepare_template(GrayImageData { data: raw_tpl.data.clone(), width: raw_tpl.width, height: raw_tpl.height })?;
+    if opts.color_prefilter.is_some() { opts.attach_colors(&mut tpl, &template.load_dynamic_as(opts.exif_orientation)?); }
+    opts.check_fits(&tpl, src.width, src.height)?;
+
+    // Threshold -1 keeps the best window whatever its score
+    let best = py.allow_threads(|| opts.find_best_in(&src, Some(&img), &tpl, -1.0))?;
+    let source_img = opts.return_crop.then_some(&img);
+    let best = best.map(|r| opts.finish(&tpl, r, source_img)).transpose()?;
+    if let Some(r) = best.as_ref().filter(|r| r.confidence >= threshold) {
+        return Ok(r.clone());
+    }
+
+    let mut message = match &best {
+        Some(r) => format!(
+            "Template not found: best score {:.4} at ({}, {}) is below threshold {}", r.confidence, r.x, r.y, threshold
+        ),
+        None => format!("Template not found: no window could be scored (threshold {})", threshold),
+    };
+    message += &format!(" (source {}x{}, template {}x{})", src.width, src.height, raw_tpl.width, raw_tpl.height);
+    if let Some(dir) = artifact_dir {
+        let stem = match &template {
+            ImageInput::Path(path) => Path::new(path).file_stem().and_then(|s| s.to_str()).unwrap_or("template").to_string(),
+            ImageInput::Bytes(_) => "template".to_string(),
+        };
+        let raw_src = GrayImageData::from_dynamic(&img);
+        let searched = &best.as_ref().map_or(&tpl, |r| tpl.variant(r)).image;
+        let report = py.allow_threads(|| render_match_report(&raw_src, &raw_tpl, searched, best.as_ref()));
+        std::fs::create_dir_all(&dir)?;
+        let path = artifact_path(&dir, &format!("{}-not-found", stem));
+        report.save(&path).map_err(|e| PyIOError::new_err(format!("Failed to save {}: {}", path.display(), e)))?;
+        message += &format!("; report saved to {}", path.display());
+    }
+    Err(PyAssertionError::new_err(message))
+}
+
+// ============================================================================
+// Python Interface - Benchmarking
+// ============================================================================
+
+/// Stages timed by `benchmark`, in pipeline order
+const BENCHMARK_STAGES: [&str; 8] = ["decode", "grayscale", "preprocess", "integral", "coarse", "refine", "match", "total"];
+
+/// Time the matching pipeline stage by stage
+/// 
+/// Each repeat decodes both images, converts them to grayscale, applies the
+/// preprocessing options and runs the full option-aware match ("match").
+/// "integral", "coarse" and "refine" break down the plain NCC pyramid search
+/// on the same inputs; "total" is decode + grayscale + preprocess + match.
+/// 
+/// Args:
+///     source: Source image as file path or encoded bytes
+///     template: Template image as file path or encoded bytes
+///     repeats: Number of timed runs, default 10
+///     threshold: Matching threshold (0.0-1.0), default 0.8
+///     **options: Matching options, e.g. autocrop=True
+/// 
+/// Returns:
+///     Dict with "repeats", "result" (MatchResult or None) and "stages",
+///     mapping each stage to {"min_ms": ..., "median_ms": ...}
+#[pyfunction]
+#[pyo3(signature = (source, template, repeats=10, threshold=0.8, **options))]
+fn benchmark<'py>(
+    py: Python<'py>, source: ImageInput, template: ImageInput, repeats: usize, threshold: f64, options: Option<&PyDict>,
+) -> PyResult<&'py PyDict> {
+    if repeats == 0 {
+        return Err(PyValueError::new_err("repeats must be at least 1"));
+    }
+    let ms = |start: std::time::Instant| start.elapsed().as_secs_f64() * 1000.0;
+    let mut samples = vec![Vec::with_capacity(repeats); BENCHMARK_STAGES.len()];
+    let mut result = None;
+
+    for _ in 0..repeats {
+        let mut opts = MatchOptions::from_kwargs(options)?;
+        let mut times = [0.0f64; BENCHMARK_STAGES.len()];
+        let start = std::time::Instant::now();
+        let (src_img, tpl_img) = (source.load_dynamic_as(opts.exif_orientation)?, template.load_dynamic_as(opts.exif_orientation)?);
+        times[0] = ms(start);
+
+        let start = std::time::Instant::now();
+        let (src, tpl) = (opts.plane(&src_img), opts.plane(&tpl_img));
+        times[1] = ms(start);
+
+        let start = std::time::Instant::now();
+        let src = opts.prepare_source(src);
+        let mut tpl = opts.prepare_template(tpl)?;
+        opts.attach_colors(&mut tpl, &tpl_img);
+        opts.check_fits(&tpl, src.width, src.height)?;
+        times[2] = ms(start);
+
+        let (sw, sh) = (src.width, src.height);
+        if tpl.image.width <= sw && tpl.image.height <= sh {
+            let start = std::time::Instant::now();
+            let integral = IntegralImage::new(&src.data, sw, sh);
+            times[3] = ms(start);
+
+            let start = std::time::Instant::now();
+            let mut prepared = PreparedTemplate::with_filter(&tpl.image.data, tpl.image.width, tpl.image.height, opts.pyramid_filter);
+            prepared.coarse_candidates = opts.coarse_candidates;
+            let windows = coarse_windows(&src.data, sw, sh, &prepared, threshold);
+            times[4] = ms(start);
+
+            let start = std::time::Instant::now();
+            for window in windows {
+                std::hint::black_box(refine_window(&src.data, sw, sh, &integral, &prepared, window, threshold));
+            }
+            times[5] = ms(start);
+        }
+
+        let start = std::time::Instant::now();
+        let found = opts.find_best_in(&src, Some(&src_img), &tpl, threshold)?;
+        times[6] = ms(start);
+        times[7] = times[0] + times[1] + times[2] + times[6];
+
+        result = found.map(|r| opts.finish(&tpl, r, Some(&src_img))).transpose()?;
+        for (stage, t) in samples.iter_mut().zip(times) { stage.push(t); }
+    }
+
+    let stages = PyDict::new(py);
+    for (name, mut times) in BENCHMARK_STAGES.iter().zip(samples) {
+        times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let mid = times.len() / 2;
+        let median = if times.len() % 2 == 0 { (times[mid - 1] + times[mid]) / 2.0 } else { times[mid] };
+        let timing = PyDict::new(py);
+        timing.set_item("min_ms", times[0])?;
+        timing.set_item("median_ms", median)?;
+        stages.set_item(*name, timing)?;
+    }
+    let report = PyDict::new(py);
+    report.set_item("repeats", repeats)?;
+    report.set_item("result", result.map(|r| Py::new(py, r)).transpose()?)?;
+    report.set_item("stages", stages)?;
+    Ok(report)
+}
+
+// ============================================================================
+// Python Interface - Directory Batches
+// ============================================================================
+
+/// Shell-style wildcard match of a whole file name: `*` matches any run of
+/// characters and `?` any single one. Case-sensitive.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let (p, n): (Vec<char>, Vec<char>) = (pattern.chars().collect(), name.chars().collect());
+    let (mut pi, mut ni) = (0, 0);
+    // Position after the last `*` and the name position it currently absorbs up to
+    let mut star: Option<(usize, usize)> = None;
+    while ni < n.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == n[ni]) {
+            (pi, ni) = (pi + 1, ni + 1);
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi + 1, ni));
+            pi += 1;
+        } else if let Some((sp, sn)) = star {
+            star = Some((sp, sn + 1));
+            (pi, ni) = (sp, sn + 1);
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+/// Files under `dir` whose names match `pattern`, as paths relative to `dir`
+/// with `/` separators, sorted. Subdirectories are walked with `recursive`.
+fn directory_files(dir: &Path, pattern: &str, recursive: bool) -> PyResult<Vec<String>> {
+    let mut files = Vec::new();
+    let mut pending = vec![(dir.to_path_buf(), String::new())];
+    while let Some((path, prefix)) = pending.pop() {
+        let entries = std::fs::read_dir(&path)
+            .map_err(|e| PyIOError::new_err(format!("Failed to read directory '{}': {}", path.display(), e)))?;
+        for entry in entries {
+            let entry = entry.map_err(|e| PyIOError::new_err(format!("Failed to read directory '{}': {}", path.display(), e)))?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            // Follows symlinks, so linked files and directories count as what they point to
+            let Ok(meta) = std::fs::metadata(entry.path()) else { continue };
+            if meta.is_dir() {
+                if recursive { pending.push((entry.path(), format!("{}{}/", prefix, name))); }
+            } else if wildcard_match(pattern, &name) {
+                files.push(prefix.clone() + &name);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Match `tpl` in `files` (relative to `dir`) with decoding and matching
+/// pipelined: `workers` decoder threads feed prepared sources through a
+/// channel holding at most `workers` images to the matching stage, which
+/// runs on `pool` (the global pool if None). Results are in `files` order,
+/// each with the milliseconds spent decoding and matching that file.
+#[allow(clippy::too_many_arguments)]
+fn match_directory(
+    dir: &Path, files: &[String], opts: &MatchOptions, tpl: &SearchTemplate, threshold: f64, max_count: usize,
+    workers: usize, pool: Option<&rayon::ThreadPool>,
+) -> PyResult<Vec<(Vec<MatchResult>, f64)>> {
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let (sender, receiver) = std::sync::mpsc::sync_channel(workers);
+    let mut found = std::thread::scope(|scope| {
+        for _ in 0..workers.min(files.len()) {
+            let (sender, next) = (sender.clone(), &next);
+            scope.spawn(move || {
+                while let Some(name) = files.get(next.fetch_add(1, std::sync::atomic::Ordering::Relaxed)) {
+                    let path = dir.join(name).to_string_lossy().into_owned();
+                    let start = Instant::now();
+                    let decoded = opts.load_source(&ImageInput::Path(&path)).map(|(src, img)| (opts.prepare_source(src), img));
+                    // The matching stage stopped early on an error
+                    if sender.send((name, decoded, start.elapsed())).is_err() { break; }
+                }
+            });
+        }
+        drop(sender);
+        let stage = || {
+            receiver
+                .into_iter()
+                .par_bridge()
+                .map(|(name, decoded, decoding)| {
+                    let (src, img) = decoded?;
+                    let start = Instant::now();
+                    let ms = |start: Instant| (decoding + start.elapsed()).as_secs_f64() * 1000.0;
+                    // An image smaller than the template can't contain it
+                    if opts.check_fits(tpl, src.width, src.height).is_err() {
+                        return Ok((name, Vec::new(), ms(start)));
+                    }
+                    let results = match max_count {
+                        1 => opts.find_best_in(&src, img.as_ref(), tpl, threshold)?.into_iter().collect(),
+                        n => opts.find_all_in(&src, img.as_ref(), tpl, threshold, n)?,
+                    };
+                    let results = results.into_iter().map(|r| opts.finish(tpl, r, img.as_ref())).collect::<PyResult<Vec<_>>>()?;
+                    Ok((name, results, ms(start)))
+                })
+                .collect::<PyResult<Vec<_>>>()
+        };
+        match pool {
+            Some(pool) => pool.install(stage),
+            None => stage(),
+        }
+    })?;
+    found.sort_by(|a, b| a.0.cmp(b.0));
+    Ok(found.into_iter().map(|(_, results, ms)| (results, ms)).collect())
+}
+
+/// Find a template in every matching image of a directory
+/// 
+/// The template is prepared once and the images are decoded and matched in
+/// parallel with the GIL released, decoding of the next files overlapping
+/// with matching of the previous ones.
+/// 
+/// Args:
+///     dir_path: Directory to search
+///     template: Template as file path or encoded image bytes
+///     pattern: Wildcard file names must match (`*` and `?`), default "*.png"
+///     threshold: Matching threshold (0.0-1.0), default 0.8
+///     max_count: Maximum number of matches per image, default 1 (best match only)
+///     recursive: Also search subdirectories
+///     max_workers: Decoder threads and matching threads of a pool owned by
+///         this call (0 = one per CPU); default None matches on the global pool
+///     return_timings: Also return the milliseconds spent on each file
+///     **options: Matching options, e.g. autocrop=True
+/// 
+/// Returns:
+///     Dict of file path relative to dir_path to its list of MatchResult,
+///     strongest first, in sorted path order (empty for images without a
+///     match or smaller than the template), or a tuple of (results, dict of
+///     file path to milliseconds) with return_timings=True
+#[pyfunction]
+#[pyo3(signature = (
+    dir_path, template, pattern="*.png", threshold=0.8, max_count=1, recursive=false, max_workers=None, return_timings=false,
+    **options
+))]
+#[allow(clippy::too_many_arguments)]
+fn find_in_directory<'py>(
+    py: Python<'py>,
+    dir_path: &str,
+    template: ImageInput,
+    pattern: &str,
+    threshold: f64,
+    max_count: usize,
+    recursive: bool,
+    max_workers: Option<usize>,
+    return_timings: bool,
+    options: Option<&PyDict>,
+) -> PyResult<PyObject> {
+    let mut opts = MatchOptions::from_kwargs(options)?;
+    let tpl = opts.load_template(&template)?;
+    let pool = max_workers.map(thread_pool).transpose()?;
+    let workers = pool.as_ref().map_or_else(rayon::current_num_threads, |pool| pool.current_num_threads());
+    let dir = Path::new(dir_path);
+    let (files, found) = py.allow_threads(|| {
+        let files = directory_files(dir, pattern, recursive)?;
+        log::debug!("{} files matching '{}' in {}, {} workers", files.len(), pattern, dir.display(), workers);
+        let found = match_directory(dir, &files, &opts, &tpl, threshold, max_count, workers, pool.as_ref())?;
+        Ok::<_, PyErr>((files, found))
+    })?;
+    let (mapping, timings) = (PyDict::new(py), PyDict::new(py));
+    for (name, (results, ms)) in files.into_iter().zip(found) {
+        timings.set_item(&name, ms)?;
+        mapping.set_item(name, results.into_py(py))?;
+    }
+    Ok(if return_timings { (mapping, timings).into_py(py) } else { mapping.into_py(py) })
+}
+
+// ============================================================================
+// Python Interface - Result Export
+// ============================================================================
+
+/// Version of the `export_results` schema, bumped when columns change
+const EXPORT_SCHEMA_VERSION: u32 = 1;
+
+/// One exported row: a match, or a file without one (`result` None)
+struct ExportRow {
+    file: Option<String>,
+    result: Option<MatchResult>,
+}
+
+/// Rows of `results`: a dict of file to None, a MatchResult or a list of
+/// them (e.g. from `find_in_directory`), or a plain list of MatchResult
+fn export_rows(results: &PyAny) -> PyResult<Vec<ExportRow>> {
+    let Ok(files) = results.downcast::<PyDict>() else {
+        let results: Vec<MatchResult> = results.extract()?;
+        return Ok(results.into_iter().map(|r| ExportRow { file: None, result: Some(r) }).collect());
+    };
+    let mut rows = Vec::new();
+    for (file, found) in files {
+        let file: String = file.extract()?;
+        let found: Vec<MatchResult> = match found.extract::<Option<MatchResult>>() {
+            Ok(single) => single.into_iter().collect(),
+            Err(_) => found.extract()?,
+        };
+        if found.is_empty() {
+            rows.push(ExportRow { file: Some(file), result: None });
+            continue;
+        }
+        rows.extend(found.into_iter().map(|r| ExportRow { file: Some(file.clone()), result: Some(r) }));
+    }
+    Ok(rows)
+}
+
+/// `s` as a JSON string literal
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// `s` as a CSV field, quoted when it holds a separator, quote or line break
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) { format!("\"{}\"", s.replace('"', "\"\"")) } else { s.to_string() }
+}
+
+/// Write batch results as a JSON or CSV report with a fixed schema
+/// 
+/// Every row has the columns file, template, x, y, confidence, width,
+/// height and time_ms. Files without a match get one row whose match
+/// columns are empty (CSV) or null (JSON).
+/// 
+/// Args:
+///     results: Dict of file to results (as from `find_in_directory`) or a
+///         list of MatchResult
+///     path: Report file to write
+///     format: "json" (default) or "csv"
+///     template: Template the results came from, as file path or encoded
+///         bytes; its path fills the template column and its size (times
+///         the result's scale) the width and height columns
+///     timings: Dict of file to milliseconds (as from `find_in_directory`
+///         with return_timings=True) filling the time_ms column
+/// 
+/// Returns:
+///     Number of rows written
+#[pyfunction]
+#[pyo3(signature = (results, path, format="json", template=None, timings=None))]
+fn export_results(
+    results: &PyAny, path: &str, format: &str, template: Option<ImageInput>, timings: Option<HashMap<String, f64>>,
+) -> PyResult<usize> {
+    if format != "json" && format != "csv" {
+        return Err(PyValueError::new_err(format!("Unknown format '{}', expected 'json' or 'csv'", format)));
+    }
+    let rows = export_rows(results)?;
+    let template_path = match &template {
+        Some(ImageInput::Path(path)) => Some(path.to_string()),
+        _ => None,
+    };
+    let template_size = template.map(|t| t.load().map(|img| (img.width, img.height))).transpose()?;
+    let number = |v: f64| if v.is_finite() { v.to_string() } else { String::new() };
+    // Columns of one row, empty where the value is unknown
+    let fields = |row: &ExportRow| -> [String; 8] {
+        let r = row.result.as_ref();
+        let size = r.zip(template_size).map(|(r, (w, h))| {
+            let scale = r.scale.unwrap_or(1.0);
+            ((w as f64 * scale).round().to_string(), (h as f64 * scale).round().to_string())
+        });
+        let time = row.file.as_ref().and_then(|f| timings.as_ref()?.get(f));
+        [
+            row.file.clone().unwrap_or_default(),
+            template_path.clone().unwrap_or_default(),
+            r.map_or(String::new(), |r| r.x.to_string()),
+            r.map_or(String::new(), |r| r.y.to_string()),
+            r.map_or(String::new(), |r| number(r.confidence)),
+            size.as_ref().map_or(String::new(), |s| s.0.clone()),
+            size.as_ref().map_or(String::new(), |s| s.1.clone()),
+            time.map_or(String::new(), |&t| number(t)),
+        ]
+    };
+    const COLUMNS: [&str; 8] = ["file", "template", "x", "y", "confidence", "width", "height", "time_ms"];
+    let report = if format == "csv" {
+        let mut csv = COLUMNS.join(",") + "\n";
+        for row in &rows {
+            csv += &fields(row).iter().map(|f| csv_field(f)).collect::<Vec<_>>().join(",");
+            csv += "\n";
+        }
+        csv
+    } else {
+        let items: Vec<String> = rows
+            .iter()
+            .map(|row| {
+                let values = fields(row);
+                let pairs: Vec<String> = COLUMNS.iter().zip(&values).enumerate().map(|(i, (column, value))| {
+                    let value = match (i, value.is_empty()) {
+                        (_, true) => "null".to_string(),
+                        (0 | 1, false) => json_string(value),
+                        _ => value.clone(),
+                    };
+                    format!("{}:{}", json_string(column), value)
+                }).collect();
+                format!("{{{}}}", pairs.join(","))
+            })
+            .collect();
+        format!("{{\"version\":{},\"results\":[{}]}}\n", EXPORT_SCHEMA_VERSION, items.join(","))
+    };
+    std::fs::write(path, report).map_err(|e| PyIOError::new_err(format!("Failed to write report '{}': {}", path, e)))?;
+    Ok(rows.len())
+}
+
+/// 3x5 pixel glyphs for match labels, one row per byte with bit 2 the left column
+const LABEL_GLYPHS: [(char, [u8; 5]); 13] = [
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b111, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b001, 0b001, 0b001]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    (',', [0b000, 0b000, 0b000, 0b010, 0b100]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+];
+
+/// Pixel scale of `LABEL_GLYPHS` on a match sheet
+const LABEL_SCALE: u32 = 2;
+
+/// Width of `text` drawn by `draw_label`
+fn label_width(text: &str) -> u32 {
+    (text.chars().count() as u32 * 4).saturating_sub(1) * LABEL_SCALE
+}
+
+/// Draw `text` with its top-left corner at `(x, y)`, clipped to the image;
+/// characters without a glyph are left blank
+fn draw_label(img: &mut image::RgbImage, text: &str, x: u32, y: u32, colour: image::Rgb<u8>) {
+    for (i, c) in text.chars().enumerate() {
+        let Some((_, rows)) = LABEL_GLYPHS.iter().find(|(g, _)| *g == c) else { continue };
+        for (row, bits) in rows.iter().enumerate() {
+            for col in (0..3).filter(|col| bits & (4 >> col) != 0) {
+                for dy in 0..LABEL_SCALE {
+                    for dx in 0..LABEL_SCALE {
+                        let px = x + (i as u32 * 4 + col) * LABEL_SCALE + dx;
+                        let py = y + row as u32 * LABEL_SCALE + dy;
+                        if px < img.width() && py < img.height() { img.put_pixel(px, py, colour); }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The `w`x`h` region at `(x, y)` of `img` (scaled by `scale`, then resized
+/// back to `w`x`h`); parts outside the image are left white
+fn match_crop(img: &image::RgbImage, x: i32, y: i32, w: u32, h: u32, scale: f64) -> image::RgbImage {
+    let (cw, ch) = (((w as f64 * scale).round() as u32).max(1), ((h as f64 * scale).round() as u32).max(1));
+    let crop = image::RgbImage::from_fn(cw, ch, |dx, dy| {
+        let (sx, sy) = (x as i64 + dx as i64, y as i64 + dy as i64);
+        if sx < 0 || sy < 0 || sx >= img.width() as i64 || sy >= img.height() as i64 {
+            return image::Rgb([255, 255, 255]);
+        }
+        *img.get_pixel(sx as u32, sy as u32)
+    });
+    if (cw, ch) == (w, h) { return crop; }
+    image::imageops::resize(&crop, w, h, image::imageops::FilterType::Triangle)
+}
+
+/// Save a contact sheet of matched regions for visual review
+/// 
+/// Each match is cropped from the source at `template_size` (times its
+/// `scale`, resized back to `template_size`) and placed in a grid cell,
+/// labelled with its position `x,y` and its confidence.
+/// 
+/// Args:
+///     source: Source image the matches were found in, as file path or encoded bytes
+///     matches: MatchResult list, e.g. from `find_all`, laid out in order
+///     template_size: (width, height) of the template
+///     out_path: Image file to write (format from extension)
+///     columns: Cells per row, default 6
+#[pyfunction]
+#[pyo3(signature = (source, matches, template_size, out_path, columns=6))]
+fn save_match_sheet(
+    py: Python<'_>, source: ImageInput, matches: Vec<PyRef<MatchResult>>, template_size: (u32, u32), out_path: &str, columns: usize,
+) -> PyResult<()> {
+    const PAD: u32 = 6;
+    let (tw, th) = template_size;
+    if tw == 0 || th == 0 {
+        return Err(InvalidDimensionsError::new_err("template_size must be positive"));
+    }
+    if columns == 0 {
+        return Err(PyValueError::new_err("columns must be at least 1"));
+    }
+    if matches.is_empty() {
+        return Err(PyValueError::new_err("matches is empty, nothing to save"));
+    }
+    let matches: Vec<MatchResult> = matches.iter().map(|r| (**r).clone()).collect();
+    let img = source.load_dynamic()?.to_rgb8();
+    let sheet = py.allow_threads(|| {
+        let labels: Vec<[String; 2]> = matches.iter()
+            .map(|r| [format!("{},{}", r.x, r.y), format!("{:.3}", r.confidence)])
+            .collect();
+        let label_w = labels.iter().flatten().map(|l| label_width(l)).max().unwrap_or(0);
+        let line_h = 6 * LABEL_SCALE;
+        let (cell_w, cell_h) = (tw.max(label_w) + PAD, th + PAD / 2 + 2 * line_h + PAD);
+        let cols = columns.min(matches.len()) as u32;
+        let rows = ((matches.len() + columns - 1) / columns) as u32;
+        let mut sheet = image::RgbImage::from_pixel(cols * cell_w + PAD, rows * cell_h + PAD, image::Rgb([255, 255, 255]));
+        for (i, (r, label)) in matches.iter().zip(&labels).enumerate() {
+            let (cx, cy) = (PAD + (i as u32 % cols) * cell_w, PAD + (i as u32 / cols) * cell_h);
+            let crop = match_crop(&img, r.x, r.y, tw, th, r.scale.unwrap_or(1.0));
+            let left = cx + (cell_w - PAD - tw) / 2;
+            image::imageops::replace(&mut sheet, &crop, left as i64, cy as i64);
+            outline_box(&mut sheet, left as usize - 1, cy as usize - 1, tw as usize + 2, th as usize + 2, image::Rgb([160, 160, 160]));
+            for (line, text) in label.iter().enumerate() {
+                let x = cx + (cell_w - PAD - label_width(text)) / 2;
+                draw_label(&mut sheet, text, x, cy + th + PAD / 2 + line as u32 * line_h, image::Rgb([0, 0, 0]));
+            }
+        }
+        sheet
+    });
+    sheet.save(out_path).map_err(|e| PyIOError::new_err(format!("Failed to save image '{}': {}", out_path, e)))
+}
+
+// ============================================================================
+// Python Interface - Animated Images
+// ============================================================================
+
+/// Decode the composited frames of an animated GIF, WebP or PNG (APNG) one at
+/// a time, handing each to `f` with its index and delay in milliseconds.
+/// Still images yield a single frame with delay 0, turned upright by their
+/// EXIF orientation when `upright`.
+fn for_each_animation_frame(data: &[u8], upright: bool, mut f: impl FnMut(usize, u32, GrayImage) -> PyResult<()>) -> PyResult<()> {
+    use image::codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder};
+    use image::{AnimationDecoder, ImageFormat};
+    let frames = match image::guess_format(data) {
+        Ok(ImageFormat::Gif) => Some(GifDecoder::new(Cursor::new(data)).map(|d| d.into_frames())),
+        Ok(ImageFormat::WebP) => match WebPDecoder::new(Cursor::new(data)) {
+            Ok(d) if d.has_animation() => Some(Ok(d.into_frames())),
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        },
+        Ok(ImageFormat::Png) => match PngDecoder::new(Cursor::new(data)) {
+            Ok(d) if d.is_apng() => Some(Ok(d.apng().into_frames())),
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        },
+        _ => None,
+    };
+    let Some(frames) = frames else {
+        return f(0, 0, load_dynamic_from_bytes(data, upright)?.to_luma8());
+    };
+    let corrupt = |e: image::ImageError| DecodeError::new_err(format!("Failed to decode animation: {}", e));
+    for (index, frame) in frames.map_err(corrupt)?.enumerate() {
+        let frame = frame.map_err(corrupt)?;
+        let (numer, denom) = frame.delay().numer_denom_ms();
+        let delay = (numer + denom / 2).checked_div(denom).unwrap_or(0);
+        f(index, delay, DynamicImage::ImageRgba8(frame.into_buffer()).to_luma8())?;
+    }
+    Ok(())
+}
+
+/// Find a template in every frame of an animated GIF, WebP or PNG
+/// 
+/// Args:
+///     image: Animation as file path or encoded bytes
+///     template: Template as file path or encoded image bytes
+///     threshold: Matching threshold (0.0-1.0), default 0.8
+///     **options: Matching options, e.g. autocrop=True
+/// 
+/// Returns:
+///     List of (frame_index, delay_ms, MatchResult) for frames with a match
+#[pyfunction]
+#[pyo3(signature = (image, template, threshold=0.8, **options))]
+fn find_in_animation(
+    image: ImageInput,
+    template: ImageInput,
+    threshold: f64,
+    options: Option<&PyDict>,
+) -> PyResult<Vec<(usize, u32, MatchResult)>> {
+    let mut opts = MatchOptions::from_kwargs(options)?;
+    opts.check_luma_only("find_in_animation")?;
+    let tpl = opts.prepare_template(template.load_as(opts.exif_orientation)?)?;
+    let data = match image {
+        ImageInput::Bytes(data) => data.to_vec(),
+        ImageInput::Path(path) => std::fs::read(path)
+            .map_err(|e| PyIOError::new_err(format!("Failed to load image '{}': {}", path, e)))?,
+    };
+    let mut hits = Vec::new();
+    for_each_animation_frame(&data, opts.exif_orientation, |index, delay, gray| {
+        let src = opts.prepare_source(GrayImageData::from_gray_image(&gray));
+        opts.check_fits(&tpl, src.width, src.height)?;
+        if let Some(r) = opts.find_best_in(&src, None, &tpl, threshold)? {
+            let img = opts.return_crop.then_some(DynamicImage::ImageLuma8(gray));
+            hits.push((index, delay, opts.finish(&tpl, r, img.as_ref())?));
+        }
+        Ok(())
+    })?;
+    Ok(hits)
+}
+
+// ============================================================================
+// Python Interface - Video Files (optional `video` feature)
+// ============================================================================
+
+#[cfg(not(all(feature = "video", feature = "capture", feature = "http")))]
+fn feature_disabled(what: &str, feature: &str) -> PyErr {
+    PyRuntimeError::new_err(format!(
+        "{} is not available: rustmatch was built without the '{}' feature", what, feature
+    ))
+}
+
+/// Decode every `frame_step`-th video frame to grayscale and hand it to `f`
+/// together with its index and presentation timestamp in seconds
+#[cfg(feature = "video")]
+fn for_each_video_frame<F>(path: &str, frame_step: usize, mut f: F) -> Result<(), ffmpeg_next::Error>
+where
+    F: FnMut(usize, f64, GrayImageData),
+{
+    use ffmpeg_next::format::Pixel;
+    use ffmpeg_next::software::scaling;
+    use ffmpeg_next::util::frame::video::Video;
+
+    ffmpeg_next::init()?;
+    let mut ictx = ffmpeg_next::format::input(&path)?;
+    let input = ictx
+        .streams()
+        .best(ffmpeg_next::media::Type::Video)
+        .ok_or(ffmpeg_next::Error::StreamNotFound)?;
+    let stream_index = input.index();
+    let time_base = f64::from(input.time_base());
+    let context = ffmpeg_next::codec::context::Context::from_parameters(input.parameters())?;
+    let mut decoder = context.decoder().video()?;
+    let (w, h) = (decoder.width(), decoder.height());
+    let mut scaler = scaling::Context::get(
+        decoder.format(), w, h, Pixel::GRAY8, w, h, scaling::Flags::BILINEAR,
+    )?;
+
+    let mut frame_index = 0usize;
+    let mut receive = |decoder: &mut ffmpeg_next::decoder::Video| -> Result<(), ffmpeg_next::Error> {
+        let mut decoded = Video::empty();
+        while decoder.receive_frame(&mut decoded).is_ok() {
+            if frame_index % frame_step == 0 {
+                let mut gray = Video::empty();
+                scaler.run(&decoded, &mut gray)?;
+                let timestamp = decoded.timestamp().map_or(0.0, |t| t as f64 * time_base);
+                // Rows of the scaled plane may be padded beyond the frame width
+                let (fw, fh, stride) = (w as usize, h as usize, gray.stride(0));
+                let plane = gray.data(0);
+                let mut data = Vec::with_capacity(fw * fh);
+                for y in 0..fh {
+                    data.extend(plane[y * stride..y * stride + fw].iter().map(|&v| v as f64));
+                }
+                f(frame_index, timestamp, GrayImageData { data, width: fw, height: fh });
+            }
+            frame_index += 1;
+        }
+        Ok(())
+    };
+
+    for (stream, packet) in ictx.packets() {
+        if stream.index() == stream_index {
+            decoder.send_packet(&packet)?;
+            receive(&mut decoder)?;
+        }
+    }
+    decoder.send_eof()?;
+    receive(&mut decoder)
+}
+
+/// Find a template in every `frame_step`-th frame of a video file
+/// 
+/// Requires the `video` cargo feature (FFmpeg).
+/// 
+/// Args:
+///     video_path: Path to video file (any container/codec FFmpeg can decode)
+///     template: Template as file path or encoded image bytes
+///     threshold: Matching threshold (0.0-1.0), default 0.8
+///     frame_step: Only match every N-th frame, default 1
+/// 
+/// Returns:
+///     List of (frame_index, timestamp_seconds, MatchResult) for frames with a match
+#[pyfunction]
+#[pyo3(signature = (video_path, template, threshold=0.8, frame_step=1))]
+fn find_in_video(
+    py: Python<'_>,
+    video_path: &str,
+    template: ImageInput,
+    threshold: f64,
+    frame_step: usize,
+) -> PyResult<Vec<(usize, f64, MatchResult)>> {
+    if frame_step == 0 {
+        return Err(PyValueError::new_err("frame_step must be at least 1"));
+    }
+    #[cfg(feature = "video")]
+    {
+        let tpl = template.load()?;
+        let prepared = PreparedTemplate::new(&tpl.data, tpl.width, tpl.height);
+        py.allow_threads(|| {
+            let mut hits = Vec::new();
+            for_each_video_frame(video_path, frame_step, |index, timestamp, frame| {
+                if let Some(r) = pyramid_match_prepared(&frame.data, frame.width, frame.height, &prepared, threshold) {
+                    hits.push((index, timestamp, r));
+                }
+            })
+            .map_err(|e| PyIOError::new_err(format!("Failed to decode video '{}': {}", video_path, e)))?;
+            Ok(hits)
+        })
+    }
+    #[cfg(not(feature = "video"))]
+    {
+        let _ = (py, video_path, template, threshold);
+        Err(feature_disabled("find_in_video", "video"))
+    }
+}
+
+// ============================================================================
+// Python Interface - Camera Capture (optional `capture` feature)
+// ============================================================================
+
+/// Open a camera on a worker thread that grabs frames and matches every
+/// `frame_step`-th one, sending `(frame_index, result)` back over a channel.
+/// The camera is owned by the worker thread (it is not `Send`) and is closed
+/// once the receiver is dropped.
+#[cfg(feature = "capture")]
+fn spawn_camera_matcher(
+    device_index: u32, template: PreparedTemplate, threshold: f64, frame_step: usize,
+) -> std::sync::mpsc::Receiver<Result<(usize, Option<MatchResult>), String>> {
+    use nokhwa::pixel_format::LumaFormat;
+    use nokhwa::utils::{CameraIndex, RequestedFormat, RequestedFormatType};
+
+    let (tx, rx) = std::sync::mpsc::sync_channel(2);
+    std::thread::spawn(move || {
+        let requested = RequestedFormat::new::<LumaFormat>(RequestedFormatType::AbsoluteHighestFrameRate);
+        let camera = nokhwa::Camera::new(CameraIndex::Index(device_index), requested)
+            .and_then(|mut camera| camera.open_stream().map(|_| camera));
+        let mut camera = match camera {
+            Ok(camera) => camera,
+            Err(e) => {
+                let _ = tx.send(Err(e.to_string()));
+                return;
+            }
+        };
+        let mut frame_index = 0usize;
+        loop {
+            let frame = camera.frame().and_then(|buffer| buffer.decode_image::<LumaFormat>());
+            let message = frame.map_err(|e| e.to_string()).map(|gray| {
+                let result = if frame_index % frame_step == 0 {
+                    let src = GrayImageData::from_gray_image(&gray);
+                    pyramid_match_prepared(&src.data, src.width, src.height, &template, threshold)
+                } else { None };
+                (frame_index, result)
+            });
+            let failed = message.is_err();
+            if tx.send(message).is_err() || failed { break; }
+            frame_index += 1;
+        }
+        let _ = camera.stop_stream();
+    });
+    rx
+}
+
+/// Watch a live camera and report where the template appears
+/// 
+/// Frames are captured and matched on a Rust worker thread. `callback` is
+/// called as `callback(frame_index, match_result)` for every frame where the
+/// template is found; return True from it to stop watching. Ctrl+C also stops.
+/// Requires the `capture` cargo feature.
+/// 
+/// Args:
+///     device_index: Camera index (0 = default camera)
+///     template: Template as file path or encoded image bytes
+///     callback: Called with (frame_index, MatchResult) on each detection
+///     threshold: Matching threshold (0.0-1.0), default 0.8
+///     frame_step: Only match every N-th frame, default 1
+///     max_frames: Stop after this many frames, default None (run until stopped)
+/// 
+/// Returns:
+///     Number of frames captured
+#[pyfunction]
+#[pyo3(signature = (device_index, template, callback, threshold=0.8, frame_step=1, max_frames=None))]
+fn watch_camera(
+    py: Python<'_>,
+    device_index: u32,
+    template: ImageInput,
+    callback: PyObject,
+    threshold: f64,
+    frame_step: usize,
+    max_frames: Option<usize>,
+) -> PyResult<usize> {
+    if frame_step == 0 {
+        return Err(PyValueError::new_err("frame_step must be at least 1"));
+    }
+    #[cfg(feature = "capture")]
+    {
+        let tpl = template.load()?;
+        let prepared = PreparedTemplate::new(&tpl.data, tpl.width, tpl.height);
+        // Receiver is !Sync; the mutex lets the GIL-free wait borrow it
+        let results = std::sync::Mutex::new(spawn_camera_matcher(device_index, prepared, threshold, frame_step));
+        let mut frames = 0usize;
+        while max_frames.map_or(true, |m| frames < m) {
+            py.check_signals()?;
+            let received = py.allow_threads(|| results.lock().unwrap().recv());
+            let (frame_index, result) = match received {
+                Ok(Ok(message)) => message,
+                Ok(Err(e)) => return Err(PyIOError::new_err(format!("Camera {} failed: {}", device_index, e))),
+                Err(_) => break,
+            };
+            frames = frame_index + 1;
+            if let Some(r) = result {
+                if callback.call1(py, (frame_index, r))?.is_true(py)? { break; }
+            }
+        }
+        Ok(frames)
+    }
+    #[cfg(not(feature = "capture"))]
+    {
+        let _ = (py, device_index, template, callback, threshold, max_frames);
+        Err(feature_disabled("watch_camera", "capture"))
+    }
+}
+
+// ============================================================================
+// Python Interface - URL Sources (optional `http` feature)
+// ============================================================================
+
+/// Download a source image with a blocking HTTP(S) GET
+#[cfg(feature = "http")]
+fn fetch_url(url: &str, timeout: f64) -> PyResult<Vec<u8>> {
+    let fail = |e: reqwest::Error| {
+        let message = format!("Failed to fetch '{}': {}", url, e);
+        if e.is_timeout() { pyo3::exceptions::PyTimeoutError::new_err(message) } else { PyIOError::new_err(message) }
+    };
+    let client = reqwest::blocking::Client::builder()
+        .timeout(std::time::Duration::from_secs_f64(timeout.min(u32::MAX as f64)))
+        .user_agent(concat!("rustmatch/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .map_err(fail)?;
+    let response = client.get(url).send().and_then(|r| r.error_for_status()).map_err(fail)?;
+    Ok(response.bytes().map_err(fail)?.to_vec())
+}
+
+/// Find single best match in an image downloaded from a URL
+/// 
+/// Requires the `http` cargo feature.
+/// 
+/// Args:
+///     source_url: http:// or https:// URL of the source image
+///     template: Template as file path or encoded image bytes
+///     threshold: Matching threshold (0.0-1.0), default 0.8
+///     timeout: Seconds allowed for the whole request, default 30
+///     **options: Matching options, e.g. autocrop=True
+/// 
+/// Returns:
+///     MatchResult or None
+#[pyfunction]
+#[pyo3(signature = (source_url, template, threshold=0.8, timeout=30.0, **options))]
+fn find_template_url(
+    py: Python<'_>,
+    source_url: &str,
+    template: ImageInput,
+    threshold: f64,
+    timeout: f64,
+    options: Option<&PyDict>,
+) -> PyResult<Option<MatchResult>> {
+    if !timeout.is_finite() || timeout <= 0.0 {
+        return Err(PyValueError::new_err("timeout must be a positive number of seconds"));
+    }
+    let opts = MatchOptions::from_kwargs(options)?;
+    #[cfg(feature = "http")]
+    {
+        let mut opts = opts;
+        let data = py.allow_threads(|| fetch_url(source_url, timeout))?;
+        let img = load_dynamic_from_bytes(&data, opts.exif_orientation)?;
+        let src = opts.prepare_source(opts.plane(&img));
+        let tpl = opts.load_template(&template)?;
+        opts.check_fits(&tpl, src.width, src.height)?;
+        
+        opts.find_best_in(&src, Some(&img), &tpl, threshold)?
+            .map(|r| opts.finish(&tpl, r, Some(&img)))
+            .transpose()
+    }
+    #[cfg(not(feature = "http"))]
+    {
+        let _ = (py, source_url, template, threshold, opts);
+        Err(feature_disabled("find_template_url", "http"))
+    }
+}
+
+// ============================================================================
+// Python Interface - Reusable Matcher
+// ============================================================================
+
+/// Reusable, thread-safe matcher for one template
+/// 
+/// The template is decoded and preprocessed, and the options validated, once
+/// when the matcher is created. A Matcher is immutable afterwards, so any
+/// number of Python threads may call `find()` / `find_all()` on it at the
+/// same time; each call decodes and matches with the GIL released.
+/// 
+/// Args:
+///     template: Template as file path or encoded image bytes
+///     threshold: Default matching threshold (0.0-1.0), default 0.8
+///     num_threads: Threads of a pool owned by this matcher; default None
+///         uses the global pool shared with every other call
+///     **options: Matching options, e.g. autocrop=True
+#[pyclass(frozen)]
+pub struct Matcher {
+    opts: MatchOptions,
+    template: SearchTemplate,
+    /// Threshold used when a call doesn't pass one
+    #[pyo3(get)]
+    threshold: f64,
+    pool: Option<rayon::ThreadPool>,
+}
+
+impl Matcher {
+    /// Run `f` on the matcher's own pool, if it has one
+    fn run<T: Send>(&self, f: impl FnOnce() -> T + Send) -> T {
+        match &self.pool {
+            Some(pool) => pool.install(f),
+            None => f(),
+        }
+    }
+
+    /// Decode a source and apply the source-side options
+    fn load_source(&self, source: &ImageInput) -> PyResult<(GrayImageData, Option<DynamicImage>)> {
+        let (gray, img) = self.opts.load_source(source)?;
+        let src = self.opts.prepare_source(gray);
+        self.opts.check_fits(&self.template, src.width, src.height)?;
+        Ok((src, img))
+    }
+}
+
+#[pymethods]
+impl Matcher {
+    #[new]
+    #[pyo3(signature = (template, threshold=0.8, num_threads=None, **options))]
+    fn new(template: ImageInput, threshold: f64, num_threads: Option<usize>, options: Option<&PyDict>) -> PyResult<Self> {
+        let mut opts = MatchOptions::from_kwargs(options)?;
+        let template = opts.load_template(&template)?;
+        let pool = num_threads.map(thread_pool).transpose()?;
+        Ok(Self { opts, template, threshold, pool })
+    }
+
+    /// Find the best match in a source
+    /// 
+    /// Args:
+    ///     source: Source image as file path or encoded bytes
+    ///     threshold: Overrides the matcher's threshold for this call
+    /// 
+    /// Returns:
+    ///     MatchResult or None
+    #[pyo3(signature = (source, threshold=None))]
+    fn find(&self, py: Python<'_>, source: ImageInput, threshold: Option<f64>) -> PyResult<Option<MatchResult>> {
+        let threshold = threshold.unwrap_or(self.threshold);
+        py.allow_threads(|| self.run(|| {
+            let (src, img) = self.load_source(&source)?;
+            self.opts.find_best_in(&src, img.as_ref(), &self.template, threshold)?
+                .map(|r| self.opts.finish(&self.template, r, img.as_ref()))
+                .transpose()
+        }))
+    }
+
+    /// Find all matches in a source
+    /// 
+    /// Args:
+    ///     source: Source image as file path or encoded bytes
+    ///     threshold: Overrides the matcher's threshold for this call
+    ///     max_count: Maximum number of matches, default 10
+    /// 
+    /// Returns:
+    ///     List of MatchResult, strongest first
+    #[pyo3(signature = (source, threshold=None, max_count=10))]
+    fn find_all(&self, py: Python<'_>, source: ImageInput, threshold: Option<f64>, max_count: usize) -> PyResult<Vec<MatchResult>> {
+        let threshold = threshold.unwrap_or(self.threshold);
+        py.allow_threads(|| self.run(|| {
+            let (src, img) = self.load_source(&source)?;
+            self.opts.find_all_in(&src, img.as_ref(), &self.template, threshold, max_count)?
+                .into_iter()
+                .map(|r| self.opts.finish(&self.template, r, img.as_ref()))
+                .collect()
+        }))
+    }
+
+    /// Size of the template as given, before any autocrop
+    #[getter]
+    fn template_size(&self) -> (u32, u32) {
+        self.template.footprint
+    }
+
+    fn __repr__(&self) -> String {
+        let (w, h) = self.template.footprint;
+        match &self.pool {
+            Some(pool) => format!("Matcher(template={}x{}, threshold={}, num_threads={})", w, h, self.threshold, pool.current_num_threads()),
+            None => format!("Matcher(template={}x{}, threshold={})", w, h, self.threshold),
+        }
+    }
+}
+
+// ============================================================================
+// Python Interface - Template Library
+// ============================================================================
+
+/// Name of an archive entry as a template: its path without the extension,
+/// or None for entries that aren't images (and macOS resource forks)
+fn template_entry_name(path: &str) -> Option<String> {
+    let file = path.rsplit('/').next()?;
+    if path.starts_with("__MACOSX/") || file.starts_with('.') { return None; }
+    image::ImageFormat::from_path(file).ok()?;
+    Some(path[..path.len() - file.len()].to_string() + file.rsplit_once('.').map_or(file, |(stem, _)| stem))
+}
+
+/// Named templates, decoded and preprocessed once
+/// 
+/// Args:
+///     templates: Dict of name to template (file path or encoded image bytes)
+///     threshold: Default matching threshold (0.0-1.0), default 0.8
+#[pyclass(frozen)]
+pub struct TemplateLibrary {
+    names: Vec<String>,
+    templates: Vec<PreparedTemplate>,
+    /// Threshold used when a call doesn't pass one
+    #[pyo3(get)]
+    threshold: f64,
+}
+
+impl TemplateLibrary {
+    /// Decode and prepare every template in parallel, keeping the given order
+    fn build(entries: Vec<(String, &[u8])>, threshold: f64) -> PyResult<Self> {
+        let mut seen = std::collections::HashSet::new();
+        if let Some((name, _)) = entries.iter().find(|(name, _)| !seen.insert(name.as_str())) {
+            return Err(PyValueError::new_err(format!("Duplicate template name '{}'", name)));
+        }
+        let templates = entries.par_iter()
+            .map(|(name, data)| {
+                let img = load_dynamic_from_bytes(data, true)
+                    .map_err(|e| DecodeError::new_err(format!("Template '{}': {}", name, e)))?;
+                let gray = GrayImageData::from_dynamic(&img);
+                Ok(PreparedTemplate::new(&gray.data, gray.width, gray.height))
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(Self { names: entries.into_iter().map(|(name, _)| name).collect(), templates, threshold })
+    }
+
+    fn index(&self, name: &str) -> PyResult<usize> {
+        self.names.iter().position(|n| n == name)
+            .ok_or_else(|| PyKeyError::new_err(format!("No template named '{}'", name)))
+    }
+}
+
+#[pymethods]
+impl TemplateLibrary {
+    #[new]
+    #[pyo3(signature = (templates, threshold=0.8))]
+    fn new(py: Python<'_>, templates: &PyDict, threshold: f64) -> PyResult<Self> {
+        let mut files = Vec::with_capacity(templates.len());
+        for (name, template) in templates {
+            let data = match template.extract::<ImageInput>()? {
+                ImageInput::Bytes(data) => data.to_vec(),
+                ImageInput::Path(path) => std::fs::read(path)
+                    .map_err(|e| PyIOError::new_err(format!("Failed to load image '{}': {}", path, e)))?,
+            };
+            files.push((name.extract::<String>()?, data));
+        }
+        py.allow_threads(|| Self::build(files.iter().map(|(name, data)| (name.clone(), data.as_slice())).collect(), threshold))
+    }
+
+    /// Load every image in a zip archive, named by its path in the archive
+    /// without the extension (`icons/ok.png` becomes `icons/ok`)
+    /// 
+    /// Args:
+    ///     archive: Zip file path or the archive as bytes
+    ///     threshold: Default matching threshold (0.0-1.0), default 0.8
+    #[staticmethod]
+    #[pyo3(signature = (archive, threshold=0.8))]
+    fn from_zip(py: Python<'_>, archive: ImageInput, threshold: f64) -> PyResult<Self> {
+        let owned;
+        let data = match archive {
+            ImageInput::Bytes(data) => data,
+            ImageInput::Path(path) => {
+                owned = std::fs::read(path).map_err(|e| PyIOError::new_err(format!("Failed to read '{}': {}", path, e)))?;
+                &owned
+            }
+        };
+        py.allow_threads(|| {
+            let files = read_zip(data)?;
+            let entries = files.iter()
+                .filter_map(|(path, contents)| Some((template_entry_name(path)?, contents.as_slice())))
+                .collect();
+            Self::build(entries, threshold)
+        })
+    }
+
+    /// Template names, in order
+    #[getter]
+    fn names(&self) -> Vec<String> {
+        self.names.clone()
+    }
+
+    /// Find the best match of one template
+    /// 
+    /// Args:
+    ///     source: Source image as file path or encoded bytes
+    ///     name: Template name
+    ///     threshold: Overrides the library's threshold for this call
+    /// 
+    /// Returns:
+    ///     MatchResult or None
+    #[pyo3(signature = (source, name, threshold=None))]
+    fn find(&self, py: Python<'_>, source: ImageInput, name: &str, threshold: Option<f64>) -> PyResult<Option<MatchResult>> {
+        let tpl = &self.templates[self.index(name)?];
+        let threshold = threshold.unwrap_or(self.threshold);
+        py.allow_threads(|| {
+            let src = source.load()?;
+            Ok(pyramid_match_prepared(&src.data, src.width, src.height, tpl, threshold))
+        })
+    }
 
-    results.sort_by(|a, b| b.confidence.partial_cmp(&a.confidence).unwrap());
-    
-    let mut filtered = Vec::new();
-    for r in results {
-        let overlaps = filtered.iter().any(|f: &MatchResult| {
-            let dx = (r.x as i32 - f.x as i32).abs() as u32;
-            let dy = (r.y as i32 - f.y as i32).abs() as u32;
-            dx < tw as u32 / 2 && dy < th as u32 / 2
-        });
-        if !overlaps {
-            filtered.push(r);
-            if filtered.len() >= max_count { break; }
+    /// Match every template against one source, decoded once
+    /// 
+    /// Args:
+    ///     source: Source image as file path or encoded bytes
+    ///     threshold: Overrides the library's threshold for this call
+    /// 
+    /// Returns:
+    ///     Dict of name to MatchResult for the templates found, in library order
+    #[pyo3(signature = (source, threshold=None))]
+    fn find_each<'py>(&self, py: Python<'py>, source: ImageInput, threshold: Option<f64>) -> PyResult<&'py PyDict> {
+        let threshold = threshold.unwrap_or(self.threshold);
+        let results = py.allow_threads(|| {
+            let src = source.load()?;
+            let prepared = PreparedSource::new(&src.data, src.width, src.height, &self.templates);
+            Ok::<_, PyErr>(self.templates.par_iter().map(|tpl| prepared.find(tpl, threshold)).collect::<Vec<_>>())
+        })?;
+        let found = PyDict::new(py);
+        for (name, result) in self.names.iter().zip(results) {
+            if let Some(r) = result { found.set_item(name, r.into_py(py))?; }
         }
+        Ok(found)
+    }
+
+    fn __len__(&self) -> usize {
+        self.names.len()
+    }
+
+    fn __contains__(&self, name: &str) -> bool {
+        self.names.iter().any(|n| n == name)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("TemplateLibrary(templates={}, threshold={})", self.names.len(), self.threshold)
     }
-    filtered
 }
 
 // ============================================================================
-// Image Loading Helpers
+// Python Interface - Composite Templates
 // ============================================================================
 
-fn load_image_from_path(path: &str) -> PyResult<GrayImageData> {
-    let img = image::open(path)
-        .map_err(|e| PyIOError::new_err(format!("Failed to load image '{}': {}", path, e)))?;
-    Ok(GrayImageData::from_dynamic(&img))
+/// One sub-template of a `CompositeTemplate`
+struct CompositePart {
+    template: PreparedTemplate,
+    /// Position relative to the composite's origin
+    offset: (usize, usize),
+    weight: f64,
 }
 
-fn load_image_from_bytes(data: &[u8]) -> PyResult<GrayImageData> {
-    let img = image::load_from_memory(data)
-        .map_err(|e| PyValueError::new_err(format!("Failed to decode image: {}", e)))?;
-    Ok(GrayImageData::from_dynamic(&img))
+/// Match of a `CompositeTemplate`
+#[pyclass]
+#[derive(Clone)]
+pub struct CompositeMatch {
+    /// Origin of the composite, placed by its strongest part
+    #[pyo3(get)]
+    pub x: i32,
+    #[pyo3(get)]
+    pub y: i32,
+    /// Weighted mean of the part scores, negative scores counting as 0
+    #[pyo3(get)]
+    pub confidence: f64,
+    /// Number of parts scoring at least the threshold
+    #[pyo3(get)]
+    pub matched: usize,
+    /// Best position and score of each part near its expected position
+    #[pyo3(get)]
+    pub parts: Vec<MatchResult>,
 }
 
-// ============================================================================
-// Python Interface - File Path Based (No numpy needed!)
-// ============================================================================
+#[pymethods]
+impl CompositeMatch {
+    fn __repr__(&self) -> String {
+        format!(
+            "CompositeMatch(x={}, y={}, confidence={:.4}, matched={}/{})",
+            self.x, self.y, self.confidence, self.matched, self.parts.len()
+        )
+    }
 
-/// Find single best match using file paths
-/// 
-/// Args:
-///     source_path: Path to source image file
-///     template_path: Path to template image file
-///     threshold: Matching threshold (0.0-1.0), default 0.8
-/// 
-/// Returns:
-///     MatchResult or None
-#[pyfunction]
-#[pyo3(signature = (source_path, template_path, threshold=0.8))]
-fn find_template(
-    source_path: &str,
-    template_path: &str,
-    threshold: f64,
-) -> PyResult<Option<MatchResult>> {
-    let src = load_image_from_path(source_path)?;
-    let tpl = load_image_from_path(template_path)?;
-    
-    Ok(pyramid_match(
-        &src.data, src.width, src.height,
-        &tpl.data, tpl.width, tpl.height,
-        threshold
-    ))
+    /// Fields as a dict, with `parts` as a list of dicts
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<&'py PyDict> {
+        let dict = PyDict::new(py);
+        dict.set_item("x", self.x)?;
+        dict.set_item("y", self.y)?;
+        dict.set_item("confidence", self.confidence)?;
+        dict.set_item("matched", self.matched)?;
+        dict.set_item("parts", self.parts.iter().map(|p| p.to_dict(py)).collect::<PyResult<Vec<_>>>()?)?;
+        Ok(dict)
+    }
 }
 
-/// Find all matches using file paths
+/// Template made of several parts at fixed offsets, e.g. a widget's header
+/// and footer around variable content
 /// 
-/// Args:
-///     source_path: Path to source image file
-///     template_path: Path to template image file
-///     threshold: Matching threshold (0.0-1.0), default 0.8
-///     max_count: Maximum number of matches, default 10
+/// Every part's candidates propose a composite origin; each origin is then
+/// scored by searching every part within `tolerance` pixels of its expected
+/// position. A composite matches when at least `min_parts` parts score at
+/// least the threshold, and its confidence is the weighted mean part score.
 /// 
-/// Returns:
-///     List of MatchResult objects
-#[pyfunction]
-#[pyo3(signature = (source_path, template_path, threshold=0.8, max_count=10))]
-fn find_all_templates(
-    source_path: &str,
-    template_path: &str,
+/// Args:
+///     parts: List of (template, x, y) or (template, x, y, weight) tuples;
+///         templates are file paths or encoded bytes, (x, y) their offset
+///         from the composite's origin, and weight defaults to 1
+///     min_parts: Parts that must match, default all of them
+///     tolerance: Pixels each part may deviate from its offset, default 0
+///     threshold: Default part threshold (0.0-1.0), default 0.8
+#[pyclass(frozen)]
+pub struct CompositeTemplate {
+    parts: Vec<CompositePart>,
+    /// Parts that must match
+    #[pyo3(get)]
+    min_parts: usize,
+    /// Pixels each part may deviate from its offset
+    #[pyo3(get)]
+    tolerance: usize,
+    /// Threshold used when a call doesn't pass one
+    #[pyo3(get)]
     threshold: f64,
-    max_count: usize,
-) -> PyResult<Vec<MatchResult>> {
-    let src = load_image_from_path(source_path)?;
-    let tpl = load_image_from_path(template_path)?;
-    
-    Ok(match_multi(
-        &src.data, src.width, src.height,
-        &tpl.data, tpl.width, tpl.height,
-        threshold, max_count
-    ))
 }
 
-// ============================================================================
-// Python Interface - Bytes Based (No numpy needed!)
-// ============================================================================
+impl CompositeTemplate {
+    /// Bounding box of the parts, from the origin
+    fn extent(&self) -> (usize, usize) {
+        self.parts.iter().fold((0, 0), |(w, h), p| {
+            (w.max(p.offset.0 + p.template.full.width), h.max(p.offset.1 + p.template.full.height))
+        })
+    }
 
-/// Find single best match using image bytes
-/// 
-/// Args:
-///     source_bytes: Source image as bytes (PNG, JPEG, etc.)
-///     template_bytes: Template image as bytes
-///     threshold: Matching threshold (0.0-1.0), default 0.8
-/// 
-/// Returns:
-///     MatchResult or None
-#[pyfunction]
-#[pyo3(signature = (source_bytes, template_bytes, threshold=0.8))]
-fn find_template_bytes(
-    source_bytes: &[u8],
-    template_bytes: &[u8],
-    threshold: f64,
-) -> PyResult<Option<MatchResult>> {
-    let src = load_image_from_bytes(source_bytes)?;
-    let tpl = load_image_from_bytes(template_bytes)?;
-    
-    Ok(pyramid_match(
-        &src.data, src.width, src.height,
-        &tpl.data, tpl.width, tpl.height,
-        threshold
-    ))
+    /// Score every part around the origin `(ox, oy)`; None unless enough parts match
+    fn score_at(
+        &self, src: &GrayImageData, integral: &IntegralImage, (ox, oy): (usize, usize), threshold: f64, stats: &Stats,
+    ) -> Option<CompositeMatch> {
+        let tol = self.tolerance;
+        let parts: Vec<MatchResult> = self.parts.iter().map(|part| {
+            let (tw, th) = (part.template.full.width, part.template.full.height);
+            let (ex, ey) = (ox + part.offset.0, oy + part.offset.1);
+            let missing = MatchResult { x: ex as i32, y: ey as i32, confidence: 0.0, crop: None, scale: None, angle: None, pixel_ratio: 1.0, flip: None, inverted: false };
+            if tw > src.width || th > src.height { return missing; }
+            let window = (ex.saturating_sub(tol), ey.saturating_sub(tol), (ex + tol).min(src.width - tw), (ey + tol).min(src.height - th));
+            if window.0 > window.2 || window.1 > window.3 { return missing; }
+            search_region(&src.data, src.width, integral, &part.template.full, window, -1.0, stats).unwrap_or(missing)
+        }).collect();
+        let matched = parts.iter().filter(|p| p.confidence >= threshold).count();
+        if matched < self.min_parts { return None; }
+        let weighted: Vec<f64> = self.parts.iter().zip(&parts).map(|(p, r)| p.weight * r.confidence.max(0.0)).collect();
+        let confidence = weighted.iter().sum::<f64>() / self.parts.iter().map(|p| p.weight).sum::<f64>();
+        // Placing the origin by the strongest part (the first on ties) makes
+        // the origins proposed by different parts of one instance agree
+        let anchor = (0..parts.len()).fold(0, |a, i| if weighted[i] > weighted[a] { i } else { a });
+        let (x, y) = (parts[anchor].x - self.parts[anchor].offset.0 as i32, parts[anchor].y - self.parts[anchor].offset.1 as i32);
+        Some(CompositeMatch { x, y, confidence, matched, parts })
+    }
+
+    /// Composite matches, strongest first, without overlapping duplicates
+    fn search(&self, src: &GrayImageData, threshold: f64, max_count: usize) -> Vec<CompositeMatch> {
+        let integral = IntegralImage::new(&src.data, src.width, src.height);
+        let mut origins: Vec<(usize, usize)> = self.parts.iter()
+            .flat_map(|part| {
+                match_candidates(&src.data, src.width, src.height, &part.template, threshold).into_iter().filter_map(move |r| {
+                    Some(((r.x as usize).checked_sub(part.offset.0)?, (r.y as usize).checked_sub(part.offset.1)?))
+                })
+            })
+            .collect();
+        origins.sort_unstable();
+        origins.dedup();
+        log::debug!("composite: {} candidate origins", origins.len());
+        let stats = Stats::current();
+        let mut found: Vec<CompositeMatch> = origins.par_iter()
+            .filter_map(|&origin| self.score_at(src, &integral, origin, threshold, &stats))
+            .collect();
+        found.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+
+        // Origins of one instance proposed by different parts differ by up to the tolerance
+        let (w, h) = self.extent();
+        let (half_w, half_h) = ((w / 2).max(1) as i32, (h / 2).max(1) as i32);
+        let mut kept: Vec<CompositeMatch> = Vec::new();
+        for m in found {
+            if kept.len() >= max_count { break; }
+            if !kept.iter().any(|k| (k.x - m.x).abs() < half_w && (k.y - m.y).abs() < half_h) { kept.push(m); }
+        }
+        kept
+    }
 }
 
-/// Find all matches using image bytes
-/// 
-/// Args:
-///     source_bytes: Source image as bytes (PNG, JPEG, etc.)
-///     template_bytes: Template image as bytes
-///     threshold: Matching threshold (0.0-1.0), default 0.8
-///     max_count: Maximum number of matches, default 10
-/// 
-/// Returns:
-///     List of MatchResult objects
-#[pyfunction]
-#[pyo3(signature = (source_bytes, template_bytes, threshold=0.8, max_count=10))]
-fn find_all_templates_bytes(
-    source_bytes: &[u8],
-    template_bytes: &[u8],
-    threshold: f64,
-    max_count: usize,
-) -> PyResult<Vec<MatchResult>> {
-    let src = load_image_from_bytes(source_bytes)?;
-    let tpl = load_image_from_bytes(template_bytes)?;
-    
-    Ok(match_multi(
-        &src.data, src.width, src.height,
-        &tpl.data, tpl.width, tpl.height,
-        threshold, max_count
-    ))
+#[pymethods]
+impl CompositeTemplate {
+    #[new]
+    #[pyo3(signature = (parts, min_parts=None, tolerance=0, threshold=0.8))]
+    fn new(parts: Vec<&PyAny>, min_parts: Option<usize>, tolerance: usize, threshold: f64) -> PyResult<Self> {
+        if parts.is_empty() {
+            return Err(PyValueError::new_err("CompositeTemplate needs at least one part"));
+        }
+        let parts = parts.into_iter()
+            .map(|item| {
+                let (template, x, y, weight): (ImageInput, usize, usize, f64) = match item.extract::<(ImageInput, usize, usize)>() {
+                    Ok((template, x, y)) => (template, x, y, 1.0),
+                    Err(_) => item.extract().map_err(|_| PyTypeError::new_err(
+                        "parts must be (template, x, y) or (template, x, y, weight) tuples"
+                    ))?,
+                };
+                if !(weight.is_finite() && weight > 0.0) {
+                    return Err(PyValueError::new_err("part weights must be positive"));
+                }
+                let img = template.load()?;
+                Ok(CompositePart { template: PreparedTemplate::new(&img.data, img.width, img.height), offset: (x, y), weight })
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        let min_parts = min_parts.unwrap_or(parts.len());
+        if !(1..=parts.len()).contains(&min_parts) {
+            return Err(PyValueError::new_err(format!("min_parts must be between 1 and {}", parts.len())));
+        }
+        Ok(Self { parts, min_parts, tolerance, threshold })
+    }
+
+    /// Best composite match in a source
+    /// 
+    /// Args:
+    ///     source: Source image as file path or encoded bytes
+    ///     threshold: Overrides the part threshold for this call
+    /// 
+    /// Returns:
+    ///     CompositeMatch or None
+    #[pyo3(signature = (source, threshold=None))]
+    fn find(&self, py: Python<'_>, source: ImageInput, threshold: Option<f64>) -> PyResult<Option<CompositeMatch>> {
+        let threshold = threshold.unwrap_or(self.threshold);
+        let src = source.load()?;
+        Ok(py.allow_threads(|| self.search(&src, threshold, 1)).into_iter().next())
+    }
+
+    /// All composite matches in a source
+    /// 
+    /// Args:
+    ///     source: Source image as file path or encoded bytes
+    ///     threshold: Overrides the part threshold for this call
+    ///     max_count: Maximum number of matches, default 10
+    /// 
+    /// Returns:
+    ///     List of CompositeMatch, strongest first
+    #[pyo3(signature = (source, threshold=None, max_count=10))]
+    fn find_all(&self, py: Python<'_>, source: ImageInput, threshold: Option<f64>, max_count: usize) -> PyResult<Vec<CompositeMatch>> {
+        let threshold = threshold.unwrap_or(self.threshold);
+        let src = source.load()?;
+        Ok(py.allow_threads(|| self.search(&src, threshold, max_count)))
+    }
+
+    /// Width and height of the box spanned by the parts
+    #[getter]
+    fn size(&self) -> (usize, usize) {
+        self.extent()
+    }
+
+    fn __len__(&self) -> usize {
+        self.parts.len()
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "CompositeTemplate(parts={}, min_parts={}, tolerance={}, threshold={})",
+            self.parts.len(), self.min_parts, self.tolerance, self.threshold
+        )
+    }
 }
 
 // ============================================================================
-// Python Interface - Raw Pixel Data (List of integers, no numpy!)
+// Python Interface - Frame Streams
 // ============================================================================
 
-/// Find single best match using raw pixel data as flat list
+fn match_frame(src: &GrayImageData, templates: &[PreparedTemplate], threshold: f64) -> Vec<Option<MatchResult>> {
+    let source = PreparedSource::new(&src.data, src.width, src.height, templates);
+    templates.iter().map(|tpl| source.find(tpl, threshold)).collect()
+}
+
+/// Changed pixels this close together are re-searched as one region
+const DIRTY_MERGE_DISTANCE: usize = 8;
+
+/// Last frame of an incremental stream and the results found in it
+struct FrameState {
+    frame: GrayImageData,
+    results: Vec<Option<MatchResult>>,
+}
+
+/// Match a frame, re-searching only around the pixels that changed since
+/// `previous`. Window positions whose template footprint touches no changed
+/// region score exactly as before, so a template's previous best survives
+/// unless its own window changed, and only positions overlapping a changed
+/// region (the region grown by the template size) need a new search. A
+/// template whose previous best was touched, or whose dirty positions cover
+/// more than half the frame, gets a full search.
+fn match_frame_incremental(
+    src: &GrayImageData, templates: &[PreparedTemplate], threshold: f64, previous: Option<&FrameState>, tolerance: f64,
+) -> Vec<Option<MatchResult>> {
+    let previous = match previous {
+        Some(p) if p.frame.width == src.width && p.frame.height == src.height => p,
+        _ => return match_frame(src, templates, threshold),
+    };
+    let (_, regions) = diff_regions(&previous.frame.data, &src.data, src.width, src.height, tolerance, DIRTY_MERGE_DISTANCE, 1);
+    if regions.is_empty() {
+        log::debug!("incremental: frame unchanged, reusing {} results", templates.len());
+        return previous.results.clone();
+    }
+
+    let source = PreparedSource::new(&src.data, src.width, src.height, templates);
+    let mut full = 0;
+    let results = templates.iter().zip(&previous.results).map(|(tpl, last)| {
+        let (tw, th) = (tpl.full.width, tpl.full.height);
+        if tw > src.width || th > src.height { return None; }
+        let (end_x, end_y) = (src.width - tw, src.height - th);
+        // Top-left positions whose window overlaps each region
+        let windows: Vec<(usize, usize, usize, usize)> = regions.iter()
+            .map(|r| (
+                r.x.saturating_sub(tw - 1), r.y.saturating_sub(th - 1),
+                (r.x + r.width - 1).min(end_x), (r.y + r.height - 1).min(end_y),
+            ))
+            .collect();
+        let touched = last.as_ref().is_some_and(|m| {
+            let (mx, my) = (m.x as usize, m.y as usize);
+            regions.iter().any(|r| mx < r.x + r.width && r.x < mx + tw && my < r.y + r.height && r.y < my + th)
+        });
+        let dirty: usize = windows.iter().map(|&(x1, y1, x2, y2)| (x2 - x1 + 1) * (y2 - y1 + 1)).sum();
+        if touched || dirty * 2 > (end_x + 1) * (end_y + 1) {
+            full += 1;
+            return source.find(tpl, threshold);
+        }
+        windows.into_iter()
+            .filter_map(|w| search_window(&src.data, src.width, &tpl.full, w, threshold))
+            .chain(last.clone())
+            .fold(None, |best: Option<MatchResult>, r| match best {
+                Some(b) if b.confidence >= r.confidence => Some(b),
+                _ => Some(r),
+            })
+    }).collect();
+    log::debug!("incremental: {} changed regions, {} of {} templates needed a full search", regions.len(), full, templates.len());
+    results
+}
+
+/// Matcher for a continuous stream of frames
+/// 
+/// Templates are decoded and preprocessed once when the stream is created.
+/// Each frame is then decoded and matched entirely in Rust with the GIL
+/// released, returning one entry per template (MatchResult or None).
+/// 
+/// With `incremental=True` each frame is diffed against the previous one:
+/// an unchanged frame reuses the previous results, and otherwise only the
+/// positions whose window overlaps a changed region are searched again.
 /// 
 /// Args:
-///     source_pixels: Source image pixels as flat list of integers (0-255)
-///     source_width: Source image width
-///     source_height: Source image height
-///     template_pixels: Template pixels as flat list of integers (0-255)
-///     template_width: Template width
-///     template_height: Template height
+///     templates: List of templates as file paths or encoded image bytes
 ///     threshold: Matching threshold (0.0-1.0), default 0.8
-/// 
-/// Returns:
-///     MatchResult or None
-#[pyfunction]
-#[pyo3(signature = (source_pixels, source_width, source_height, template_pixels, template_width, template_height, threshold=0.8))]
-fn find_template_raw(
-    source_pixels: Vec<u8>,
-    source_width: usize,
-    source_height: usize,
-    template_pixels: Vec<u8>,
-    template_width: usize,
-    template_height: usize,
+///     incremental: Re-search only the regions that changed since the
+///         previous frame, default False
+///     change_tolerance: Grey-level difference a pixel must exceed to count
+///         as changed in incremental mode, default 0.0
+#[pyclass]
+pub struct MatcherStream {
+    templates: Arc<Vec<PreparedTemplate>>,
     threshold: f64,
-) -> PyResult<Option<MatchResult>> {
-    if source_pixels.len() != source_width * source_height {
-        return Err(PyValueError::new_err("Source pixel count doesn't match dimensions"));
+    /// Whether frames are matched incrementally
+    #[pyo3(get)]
+    incremental: bool,
+    change_tolerance: f64,
+    previous: Option<FrameState>,
+    /// Number of frames matched so far, pushed or yielded by `process()`
+    #[pyo3(get)]
+    frame_count: u64,
+}
+
+impl MatcherStream {
+    /// Match `src` and, in incremental mode, remember it for the next frame
+    fn match_next(&self, src: GrayImageData, previous: &mut Option<FrameState>) -> Vec<Option<MatchResult>> {
+        if !self.incremental {
+            return match_frame(&src, &self.templates, self.threshold);
+        }
+        let results = match_frame_incremental(&src, &self.templates, self.threshold, previous.as_ref(), self.change_tolerance);
+        *previous = Some(FrameState { frame: src, results: results.clone() });
+        results
     }
-    if template_pixels.len() != template_width * template_height {
-        return Err(PyValueError::new_err("Template pixel count doesn't match dimensions"));
+}
+
+#[pymethods]
+impl MatcherStream {
+    #[new]
+    #[pyo3(signature = (templates, threshold=0.8, incremental=false, change_tolerance=0.0))]
+    fn new(templates: Vec<ImageInput>, threshold: f64, incremental: bool, change_tolerance: f64) -> PyResult<Self> {
+        if templates.is_empty() {
+            return Err(PyValueError::new_err("MatcherStream needs at least one template"));
+        }
+        let prepared = templates
+            .iter()
+            .map(|t| t.load().map(|img| PreparedTemplate::new(&img.data, img.width, img.height)))
+            .collect::<PyResult<Vec<_>>>()?;
+        if change_tolerance < 0.0 {
+            return Err(PyValueError::new_err("change_tolerance must be non-negative"));
+        }
+        Ok(Self { templates: Arc::new(prepared), threshold, incremental, change_tolerance, previous: None, frame_count: 0 })
     }
     
-    let src: Vec<f64> = source_pixels.iter().map(|&v| v as f64).collect();
-    let tpl: Vec<f64> = template_pixels.iter().map(|&v| v as f64).collect();
+    /// Match one encoded frame (PNG, JPEG, ...) against every template
+    fn push(&mut self, py: Python<'_>, frame: &[u8]) -> PyResult<Vec<Option<MatchResult>>> {
+        let mut previous = self.previous.take();
+        let results = py.allow_threads(|| {
+            load_image_from_bytes(frame, true).map(|src| self.match_next(src, &mut previous))
+        });
+        self.previous = previous;
+        self.frame_count += 1;
+        results
+    }
     
-    Ok(pyramid_match(&src, source_width, source_height, &tpl, template_width, template_height, threshold))
-}
-
-/// Find all matches using raw pixel data as flat list
-#[pyfunction]
-#[pyo3(signature = (source_pixels, source_width, source_height, template_pixels, template_width, template_height, threshold=0.8, max_count=10))]
-fn find_all_templates_raw(
-    source_pixels: Vec<u8>,
-    source_width: usize,
-    source_height: usize,
-    template_pixels: Vec<u8>,
-    template_width: usize,
-    template_height: usize,
-    threshold: f64,
-    max_count: usize,
-) -> PyResult<Vec<MatchResult>> {
-    if source_pixels.len() != source_width * source_height {
-        return Err(PyValueError::new_err("Source pixel count doesn't match dimensions"));
+    /// Lazily match every frame yielded by an iterable of encoded frames
+    /// 
+    /// In incremental mode the iterator diffs its frames against each other,
+    /// starting from a full search; frames pushed to the stream aren't used.
+    /// Each frame it matches still counts towards the stream's `frame_count`.
+    fn process(slf: PyRef<'_, Self>, py: Python<'_>, frames: &PyAny) -> PyResult<MatcherStreamIter> {
+        Ok(MatcherStreamIter {
+            frames: frames.iter()?.into_py(py),
+            stream: MatcherStream {
+                templates: Arc::clone(&slf.templates),
+                threshold: slf.threshold,
+                incremental: slf.incremental,
+                change_tolerance: slf.change_tolerance,
+                previous: None,
+                frame_count: 0,
+            },
+            owner: slf.into(),
+        })
+    }
+    
+    /// Forget the previous frame so the next one gets a full search
+    fn reset(&mut self) {
+        self.previous = None;
     }
-    if template_pixels.len() != template_width * template_height {
-        return Err(PyValueError::new_err("Template pixel count doesn't match dimensions"));
+    
+    fn __len__(&self) -> usize {
+        self.templates.len()
     }
     
-    let src: Vec<f64> = source_pixels.iter().map(|&v| v as f64).collect();
-    let tpl: Vec<f64> = template_pixels.iter().map(|&v| v as f64).collect();
+    fn __repr__(&self) -> String {
+        format!(
+            "MatcherStream(templates={}, threshold={}, incremental={}, frame_count={})",
+            self.templates.len(), self.threshold, if self.incremental { "True" } else { "False" }, self.frame_count
+        )
+    }
+}
+
+/// Iterator returned by `MatcherStream.process()`, yielding per-frame results
+#[pyclass]
+pub struct MatcherStreamIter {
+    frames: PyObject,
+    stream: MatcherStream,
+    /// Stream that created the iterator, whose `frame_count` it advances
+    owner: Py<MatcherStream>,
+}
+
+#[pymethods]
+impl MatcherStreamIter {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
     
-    Ok(match_multi(&src, source_width, source_height, &tpl, template_width, template_height, threshold, max_count))
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<Vec<Option<MatchResult>>>> {
+        let mut frames: &PyIterator = self.frames.as_ref(py).downcast()?;
+        let frame: &[u8] = match frames.next() {
+            Some(item) => item?.extract()?,
+            None => return Ok(None),
+        };
+        let stream = &mut self.stream;
+        let mut previous = stream.previous.take();
+        let results = py.allow_threads(|| {
+            load_image_from_bytes(frame, true).map(|src| Some(stream.match_next(src, &mut previous)))
+        });
+        stream.previous = previous;
+        self.owner.try_borrow_mut(py)?.frame_count += 1;
+        results
+    }
 }
 
 // ============================================================================
-// Utility Functions
+// Python Interface - Tracking
 // ============================================================================
 
-/// Get image dimensions from file path
+/// Lightweight NCC tracker across frames
 /// 
-/// Returns:
-///     Tuple of (width, height)
-#[pyfunction]
-fn get_image_size(path: &str) -> PyResult<(u32, u32)> {
-    let img = image::open(path)
-        .map_err(|e| PyIOError::new_err(format!("Failed to load image: {}", e)))?;
-    Ok(img.dimensions())
+/// The first update runs a full search. Afterwards only a window of
+/// `search_margin` pixels around the last position is searched; if the
+/// target isn't found there the window doubles until it covers the whole
+/// frame, and the tracker is marked lost when even that fails.
+/// 
+/// Args:
+///     template: Template as file path or encoded image bytes
+///     threshold: Matching threshold (0.0-1.0), default 0.8
+///     search_margin: Initial search radius around the last position, default 32
+#[pyclass]
+pub struct Tracker {
+    template: PreparedTemplate,
+    threshold: f64,
+    search_margin: usize,
+    last: Option<MatchResult>,
+    /// Consecutive updates without a detection
+    #[pyo3(get)]
+    lost_frames: u32,
 }
 
-/// Get image dimensions from bytes
-#[pyfunction]
-fn get_image_size_bytes(data: &[u8]) -> PyResult<(u32, u32)> {
-    let img = image::load_from_memory(data)
-        .map_err(|e| PyValueError::new_err(format!("Failed to decode image: {}", e)))?;
-    Ok(img.dimensions())
-}
+impl Tracker {
+    fn track(&self, src: &GrayImageData) -> Option<MatchResult> {
+        let tpl = &self.template;
+        let (tw, th) = (tpl.full.width, tpl.full.height);
+        if tw > src.width || th > src.height { return None; }
+        let last = match &self.last {
+            Some(last) => last,
+            None => return pyramid_match_prepared(&src.data, src.width, src.height, tpl, self.threshold),
+        };
 
-/// Set number of threads for parallel processing
-#[pyfunction]
-fn set_num_threads(num_threads: usize) -> PyResult<()> {
-    rayon::ThreadPoolBuilder::new()
-        .num_threads(if num_threads == 0 { num_cpus::get() } else { num_threads })
-        .build_global()
-        .map_err(|e| PyValueError::new_err(format!("Failed to set threads: {}", e)))
+        let end = (src.width - tw, src.height - th);
+        expanding_windows(end, (last.x as usize, last.y as usize), self.search_margin)
+            .find_map(|window| search_window(&src.data, src.width, &tpl.full, window, self.threshold))
+    }
 }
 
-/// Get library version
-#[pyfunction]
-fn version() -> &'static str {
-    env!("CARGO_PKG_VERSION")
+#[pymethods]
+impl Tracker {
+    #[new]
+    #[pyo3(signature = (template, threshold=0.8, search_margin=32))]
+    fn new(template: ImageInput, threshold: f64, search_margin: usize) -> PyResult<Self> {
+        let tpl = template.load()?;
+        Ok(Self {
+            template: PreparedTemplate::new(&tpl.data, tpl.width, tpl.height),
+            threshold,
+            search_margin,
+            last: None,
+            lost_frames: 0,
+        })
+    }
+    
+    /// Locate the template in the next frame (file path or encoded bytes)
+    fn update(&mut self, py: Python<'_>, frame: ImageInput) -> PyResult<Option<MatchResult>> {
+        let src = frame.load()?;
+        let result = py.allow_threads(|| self.track(&src));
+        match &result {
+            Some(r) => {
+                self.last = Some(r.clone());
+                self.lost_frames = 0;
+            }
+            None => {
+                self.last = None;
+                self.lost_frames += 1;
+            }
+        }
+        Ok(result)
+    }
+    
+    /// Forget the last position so the next update runs a full search
+    fn reset(&mut self) {
+        self.last = None;
+        self.lost_frames = 0;
+    }
+    
+    /// Last known match, or None if the target is not being tracked
+    #[getter]
+    fn last(&self) -> Option<MatchResult> {
+        self.last.clone()
+    }
+    
+    fn __repr__(&self) -> String {
+        match &self.last {
+            Some(r) => format!("Tracker(tracking at ({}, {}), confidence={:.4})", r.x, r.y, r.confidence),
+            None => format!("Tracker(lost, lost_frames={})", self.lost_frames),
+        }
+    }
 }
 
 // ============================================================================
@@ -583,25 +11042,130 @@ fn version() -> &'static str {
 // ============================================================================
 
 #[pymodule]
-fn _core(_py: Python, m: &PyModule) -> PyResult<()> {
+fn _core(py: Python, m: &PyModule) -> PyResult<()> {
+    // Forward `log` events to `logging.getLogger("rustmatch")`. Levels are not
+    // cached, so logging configured after import takes effect immediately.
+    let _ = pyo3_log::Logger::new(py, pyo3_log::Caching::Nothing)?.install();
+    
     m.add_class::<MatchResult>()?;
+    m.add_class::<MatchDiagnostics>()?;
+    m.add_class::<MatcherStream>()?;
+    m.add_class::<MatcherStreamIter>()?;
+    m.add_class::<MatchIterator>()?;
+    m.add_class::<MatchCluster>()?;
+    m.add_class::<RotScale>()?;
+    m.add_class::<Alignment>()?;
+    m.add_class::<PerspectiveMatch>()?;
+    m.add_class::<Matcher>()?;
+    m.add_class::<TemplateLibrary>()?;
+    m.add_class::<CompositeTemplate>()?;
+    m.add_class::<CompositeMatch>()?;
+    m.add_class::<TemplateImage>()?;
+    m.add_class::<TemplateBank>()?;
+    m.add_class::<Tracker>()?;
+    
+    // Exceptions
+    m.add("RustMatchError", py.get_type::<RustMatchError>())?;
+    m.add("DecodeError", py.get_type::<DecodeError>())?;
+    m.add("TemplateTooLargeError", py.get_type::<TemplateTooLargeError>())?;
+    m.add("InvalidDimensionsError", py.get_type::<InvalidDimensionsError>())?;
+    m.add("TemplateQualityError", py.get_type::<TemplateQualityError>())?;
     
     // File path based (recommended, no numpy!)
     m.add_function(wrap_pyfunction!(find_template, m)?)?;
     m.add_function(wrap_pyfunction!(find_all_templates, m)?)?;
+    m.add_function(wrap_pyfunction!(find_template_tiled, m)?)?;
+    m.add_function(wrap_pyfunction!(find_all_templates_tiled, m)?)?;
+    m.add_function(wrap_pyfunction!(find_template_pages, m)?)?;
     
     // Bytes based (no numpy!)
     m.add_function(wrap_pyfunction!(find_template_bytes, m)?)?;
     m.add_function(wrap_pyfunction!(find_all_templates_bytes, m)?)?;
+    m.add_function(wrap_pyfunction!(find_all_templates_packed, m)?)?;
     
     // Raw pixel data (no numpy!)
     m.add_function(wrap_pyfunction!(find_template_raw, m)?)?;
     m.add_function(wrap_pyfunction!(find_all_templates_raw, m)?)?;
+    m.add_function(wrap_pyfunction!(find_template_yuv, m)?)?;
+    m.add_function(wrap_pyfunction!(find_all_templates_yuv, m)?)?;
+    m.add_function(wrap_pyfunction!(find_template_tensor, m)?)?;
+    m.add_function(wrap_pyfunction!(find_all_templates_tensor, m)?)?;
+    m.add_function(wrap_pyfunction!(find_template_np, m)?)?;
+    m.add_function(wrap_pyfunction!(find_all_templates_np, m)?)?;
+    
+    // Lazy iteration
+    m.add_function(wrap_pyfunction!(find_iter, m)?)?;
+    m.add_function(wrap_pyfunction!(find_next, m)?)?;
+    m.add_function(wrap_pyfunction!(find_clusters, m)?)?;
+    m.add_function(wrap_pyfunction!(find_repeats, m)?)?;
+    
+    // Rotated search
+    m.add_function(wrap_pyfunction!(find_rotated, m)?)?;
+    
+    // Rotation and scale registration
+    m.add_function(wrap_pyfunction!(register_rotscale, m)?)?;
+    
+    // Sub-pixel alignment
+    m.add_function(wrap_pyfunction!(refine_alignment, m)?)?;
+    
+    // Perspective matching
+    m.add_function(wrap_pyfunction!(find_perspective, m)?)?;
+    
+    // Animated images
+    m.add_function(wrap_pyfunction!(find_in_animation, m)?)?;
+    
+    // Remote images (optional `http` feature)
+    m.add_function(wrap_pyfunction!(find_template_url, m)?)?;
+    
+    // Directories of images
+    m.add_function(wrap_pyfunction!(find_in_directory, m)?)?;
+    
+    // Result export
+    m.add_function(wrap_pyfunction!(export_results, m)?)?;
+    
+    // Match sheets
+    m.add_function(wrap_pyfunction!(save_match_sheet, m)?)?;
+    
+    // Video files (optional `video` feature)
+    m.add_function(wrap_pyfunction!(find_in_video, m)?)?;
+    
+    // Live camera capture (optional `capture` feature)
+    m.add_function(wrap_pyfunction!(watch_camera, m)?)?;
     
     // Utilities
     m.add_function(wrap_pyfunction!(get_image_size, m)?)?;
     m.add_function(wrap_pyfunction!(get_image_size_bytes, m)?)?;
+    m.add_function(wrap_pyfunction!(compare_images, m)?)?;
+    m.add_function(wrap_pyfunction!(diff_images, m)?)?;
+    m.add_function(wrap_pyfunction!(compare_to_golden, m)?)?;
+    m.add_function(wrap_pyfunction!(assert_template_found, m)?)?;
+    m.add_function(wrap_pyfunction!(phash, m)?)?;
+    m.add_function(wrap_pyfunction!(dhash, m)?)?;
+    m.add_function(wrap_pyfunction!(hamming_distance, m)?)?;
+    m.add_function(wrap_pyfunction!(autocrop, m)?)?;
+    m.add_function(wrap_pyfunction!(validate_template, m)?)?;
+    m.add_function(wrap_pyfunction!(calibrate_threshold, m)?)?;
+    m.add_function(wrap_pyfunction!(template_quality, m)?)?;
+    m.add_function(wrap_pyfunction!(suggest_template_region, m)?)?;
+    m.add_function(wrap_pyfunction!(score_sweep, m)?)?;
+    m.add_function(wrap_pyfunction!(score_grid, m)?)?;
+    m.add_function(wrap_pyfunction!(explain_match, m)?)?;
+    m.add_function(wrap_pyfunction!(estimate_scale, m)?)?;
+    m.add_function(wrap_pyfunction!(crop_image, m)?)?;
+    m.add_function(wrap_pyfunction!(resize_image, m)?)?;
+    m.add_function(wrap_pyfunction!(to_grayscale, m)?)?;
+    m.add_function(wrap_pyfunction!(get_image_info, m)?)?;
+    m.add_function(wrap_pyfunction!(benchmark, m)?)?;
+    m.add_function(wrap_pyfunction!(results_to_json, m)?)?;
+    m.add_function(wrap_pyfunction!(matches_to_svg, m)?)?;
+    m.add_function(wrap_pyfunction!(set_image_cache_size, m)?)?;
+    m.add_function(wrap_pyfunction!(clear_image_cache, m)?)?;
+    m.add_function(wrap_pyfunction!(image_cache_info, m)?)?;
+    m.add_function(wrap_pyfunction!(set_result_cache, m)?)?;
+    m.add_function(wrap_pyfunction!(clear_result_cache, m)?)?;
+    m.add_function(wrap_pyfunction!(result_cache_info, m)?)?;
     m.add_function(wrap_pyfunction!(set_num_threads, m)?)?;
+    m.add_function(wrap_pyfunction!(set_parallel_threshold, m)?)?;
     m.add_function(wrap_pyfunction!(version, m)?)?;
     
     Ok(())
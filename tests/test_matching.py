@@ -3,8 +3,18 @@ Unit tests for RustMatch template matching library.
 Zero dependencies - no numpy required!
 """
 
+import copy
+import csv
+import itertools
+import json
+import logging
+import math
+import pickle
 import pytest
 import os
+import struct
+import time
+import zlib
 
 # Import will fail until the library is built
 try:
@@ -27,6 +37,153 @@ SOURCE_IMAGE = os.path.join(IMAGES_DIR, "A.png")
 TEMPLATE_IMAGE = os.path.join(IMAGES_DIR, "a3.png")
 
 
+def make_png(width, height, pixels, exif=None):
+    """Encode 8-bit grayscale pixels (row-major) as PNG bytes without pillow."""
+    raw = b"".join(
+        b"\x00" + bytes(pixels[y * width:(y + 1) * width]) for y in range(height)
+    )
+    
+    def chunk(tag, data):
+        body = tag + data
+        return struct.pack(">I", len(data)) + body + struct.pack(">I", zlib.crc32(body) & 0xFFFFFFFF)
+    
+    header = struct.pack(">IIBBBBB", width, height, 8, 0, 0, 0, 0)
+    return (
+        b"\x89PNG\r\n\x1a\n"
+        + chunk(b"IHDR", header)
+        + (chunk(b"eXIf", exif) if exif is not None else b"")
+        + chunk(b"IDAT", zlib.compress(raw))
+        + chunk(b"IEND", b"")
+    )
+
+
+def make_rgb_png(width, height, pixels):
+    """Encode 8-bit RGB pixels (row-major (r, g, b) tuples) as PNG bytes."""
+    raw = b"".join(
+        b"\x00" + bytes(c for p in pixels[y * width:(y + 1) * width] for c in p) for y in range(height)
+    )
+    
+    def chunk(tag, data):
+        body = tag + data
+        return struct.pack(">I", len(data)) + body + struct.pack(">I", zlib.crc32(body) & 0xFFFFFFFF)
+    
+    header = struct.pack(">IIBBBBB", width, height, 8, 2, 0, 0, 0)
+    return b"\x89PNG\r\n\x1a\n" + chunk(b"IHDR", header) + chunk(b"IDAT", zlib.compress(raw)) + chunk(b"IEND", b"")
+
+
+def make_png16(width, height, pixels):
+    """Encode 16-bit grayscale pixels (row-major) as PNG bytes."""
+    raw = b"".join(
+        b"\x00" + struct.pack(">%dH" % width, *pixels[y * width:(y + 1) * width]) for y in range(height)
+    )
+    
+    def chunk(tag, data):
+        body = tag + data
+        return struct.pack(">I", len(data)) + body + struct.pack(">I", zlib.crc32(body) & 0xFFFFFFFF)
+    
+    header = struct.pack(">IIBBBBB", width, height, 16, 0, 0, 0, 0)
+    return b"\x89PNG\r\n\x1a\n" + chunk(b"IHDR", header) + chunk(b"IDAT", zlib.compress(raw)) + chunk(b"IEND", b"")
+
+
+def blocky_pixels(width, height, cell=6):
+    """Deterministic texture of flat cells, smooth enough for the coarse pyramid level."""
+    return [
+        (x // cell * 73 + y // cell * 151 + (x // cell * (y // cell)) % 37) % 97 * 2
+        for y in range(height) for x in range(width)
+    ]
+
+
+def hash_noise(i):
+    """Deterministic noise byte for index i, from an integer hash with no visible structure."""
+    h = (i * 2654435761) & 0xFFFFFFFF
+    h ^= h >> 15
+    h = (h * 2246822519) & 0xFFFFFFFF
+    return (h ^ (h >> 13)) & 0xFF
+
+
+def uint16_view(width, height, pixels):
+    """Object exporting 16-bit grayscale pixels through __array_interface__."""
+    import ctypes
+    from array import array
+    
+    class View:
+        pass
+    
+    view = View()
+    view.buffer = ctypes.create_string_buffer(array("H", pixels).tobytes())
+    view.__array_interface__ = {
+        "version": 3, "shape": (height, width), "typestr": "=u2",
+        "data": (ctypes.addressof(view.buffer), False),
+    }
+    return view
+
+
+def make_gif(width, height, frames, delay_cs=10):
+    """Encode 8-bit grayscale frames as an animated GIF with a gray palette.
+    
+    The LZW stream sends only literal codes, resetting the table before it
+    would outgrow 9-bit codes, which every decoder accepts.
+    """
+    def lzw(pixels):
+        codes = [256]
+        for i, p in enumerate(pixels):
+            if i and i % 250 == 0:
+                codes.append(256)
+            codes.append(p)
+        codes.append(257)
+        bits = sum(c << (9 * i) for i, c in enumerate(codes))
+        data = bits.to_bytes((9 * len(codes) + 7) // 8, "little")
+        return b"".join(bytes([len(data[i:i + 255])]) + data[i:i + 255] for i in range(0, len(data), 255)) + b"\x00"
+    
+    out = b"GIF89a" + struct.pack("<HHBBB", width, height, 0xF7, 0, 0)
+    out += b"".join(bytes([v, v, v]) for v in range(256))
+    for pixels in frames:
+        out += b"\x21\xf9\x04\x04" + struct.pack("<H", delay_cs) + b"\x00\x00"
+        out += b"\x2c" + struct.pack("<HHHHB", 0, 0, width, height, 0) + b"\x08" + lzw(pixels)
+    return out + b"\x3b"
+
+
+def make_tiff(width, height, pixels, tile=None, rows_per_strip=None, extra_pages=()):
+    """Encode 8-bit grayscale pixels as an uncompressed little-endian TIFF, in strips or square tiles.
+    
+    ``extra_pages`` holds further ``(width, height, pixels)`` pages, each stored as one strip.
+    """
+    out = bytearray(b"II*\x00\x00\x00\x00\x00")
+    link = 4  # where the offset of the next IFD goes
+    for page, (width, height, pixels) in enumerate([(width, height, pixels), *extra_pages]):
+        if tile and page == 0:
+            at = lambda x, y: pixels[y * width + x] if x < width and y < height else 0
+            chunks = [
+                bytes(at(x, y) for y in range(ty, ty + tile) for x in range(tx, tx + tile))
+                for ty in range(0, height, tile) for tx in range(0, width, tile)
+            ]
+        else:
+            rows = (rows_per_strip if page == 0 else None) or height
+            chunks = [bytes(pixels[y * width:min(y + rows, height) * width]) for y in range(0, height, rows)]
+        offsets = list(itertools.accumulate([len(out)] + [len(c) for c in chunks[:-1]]))
+        out += b"".join(chunks)
+        layout = {322: [tile], 323: [tile], 324: offsets, 325: [len(c) for c in chunks]} if tile and page == 0 else {
+            273: offsets, 278: [rows], 279: [len(c) for c in chunks]}
+        tags = {256: [width], 257: [height], 258: [8], 259: [1], 262: [1], 277: [1], **layout}
+        entries = b""
+        for tag in sorted(tags):
+            kind, fmt = (3, "<H") if tag in (258, 259, 262, 277) else (4, "<I")
+            value = b"".join(struct.pack(fmt, v) for v in tags[tag])
+            if len(value) > 4:
+                out += b"\x00" * (len(out) % 2)
+                field = struct.pack("<I", len(out))
+                out += value
+            else:
+                field = value.ljust(4, b"\x00")
+            entries += struct.pack("<HHI", tag, kind, len(tags[tag])) + field
+        out += b"\x00" * (len(out) % 2)
+        struct.pack_into("<I", out, link, len(out))
+        out += struct.pack("<H", len(tags)) + entries
+        link = len(out)
+        out += b"\x00\x00\x00\x00"
+    return bytes(out)
+
+
 class TestMatchResult:
     """Tests for MatchResult class."""
     
@@ -132,6 +289,254 @@ class TestFindAll:
                 assert results[i].confidence >= results[i + 1].confidence
 
 
+    def test_find_all_dense_scene(self):
+        """Test a periodic scene with hundreds of matches returns each once, deterministically."""
+        cell = blocky_pixels(12, 12, 3)
+        source = [cell[(y % 12) * 12 + x % 12] for y in range(240) for x in range(240)]
+        
+        results = rustmatch.find_all_raw(source, 240, 240, cell, 12, 12, threshold=0.9, max_count=1000)
+        
+        assert len(results) == 400
+        assert {(r.x, r.y) for r in results} == {(x, y) for y in range(0, 240, 12) for x in range(0, 240, 12)}
+        assert results == rustmatch.find_all_raw(source, 240, 240, cell, 12, 12, threshold=0.9, max_count=1000)
+
+
+    def test_find_all_thousands_of_matches(self):
+        """Test overlap suppression keeps every separate match of a small repeated cell."""
+        cell = blocky_pixels(6, 6, 2)
+        source = [cell[(y % 6) * 6 + x % 6] for y in range(240) for x in range(240)]
+        
+        results = rustmatch.find_all_raw(source, 240, 240, cell, 6, 6, threshold=0.9, max_count=100000)
+        
+        assert len(results) == 1600
+        assert {(r.x, r.y) for r in results} == {(x, y) for y in range(0, 240, 6) for x in range(0, 240, 6)}
+
+
+class TestFindTiled:
+    """Tests for tiled matching of large images."""
+    
+    def test_find_tiled_matches_find(self, tmp_path):
+        """Test tiled search finds the same best match as a full search."""
+        w, h = 300, 200
+        pixels = blocky_pixels(w, h, cell=7)
+        template = [pixels[(120 + y) * w + 150 + x] for y in range(24) for x in range(24)]
+        source_path, template_path = tmp_path / "s.png", tmp_path / "t.png"
+        source_path.write_bytes(make_png(w, h, pixels))
+        template_path.write_bytes(make_png(24, 24, template))
+        
+        result = rustmatch.find(str(source_path), str(template_path), threshold=0.8)
+        tiled = rustmatch.find_tiled(str(source_path), str(template_path), threshold=0.8, tile_size=64)
+        
+        assert (result.x, result.y) == (150, 120)
+        assert tiled is not None
+        assert (tiled.x, tiled.y) == (result.x, result.y)
+        assert abs(tiled.confidence - result.confidence) < 0.001
+    
+    def test_find_all_tiled_no_seam_duplicates(self):
+        """Test matches on tile seams are reported once."""
+        results = rustmatch.find_all_tiled(
+            SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.8, max_count=50, tile_size=64
+        )
+        
+        assert len(results) > 0
+        for i, a in enumerate(results):
+            for b in results[i + 1:]:
+                assert abs(a.x - b.x) >= 7 or abs(a.y - b.y) >= 8
+    
+    def test_find_tiled_tile_too_small(self):
+        """Test error when tiles can't hold the template."""
+        with pytest.raises(ValueError):
+            rustmatch.find_tiled(SOURCE_IMAGE, TEMPLATE_IMAGE, tile_size=8)
+
+
+class TestMatcherStream:
+    """Tests for the frame stream pipeline."""
+    
+    @pytest.fixture
+    def image_bytes(self):
+        with open(SOURCE_IMAGE, "rb") as f:
+            source = f.read()
+        with open(TEMPLATE_IMAGE, "rb") as f:
+            template = f.read()
+        return source, template
+    
+    def test_push_matches_find_bytes(self, image_bytes):
+        """Test pushed frames give the same result as find_bytes."""
+        source, template = image_bytes
+        stream = rustmatch.MatcherStream([template], threshold=0.8)
+        
+        results = stream.push(source)
+        expected = rustmatch.find_bytes(source, template, threshold=0.8)
+        
+        assert len(results) == 1
+        assert results[0] is not None
+        assert (results[0].x, results[0].y) == (expected.x, expected.y)
+        assert stream.frame_count == 1
+    
+    def test_mixed_template_inputs(self, image_bytes):
+        """Test templates can be given as paths and bytes."""
+        source, template = image_bytes
+        stream = rustmatch.MatcherStream([TEMPLATE_IMAGE, template])
+        
+        assert len(stream) == 2
+        a, b = stream.push(source)
+        assert a is not None and b is not None
+        assert (a.x, a.y) == (b.x, b.y)
+    
+    def test_process_is_lazy(self, image_bytes):
+        """Test process() consumes the frame iterator on demand."""
+        source, template = image_bytes
+        stream = rustmatch.MatcherStream([template])
+        consumed = []
+        
+        def frames():
+            for i in range(3):
+                consumed.append(i)
+                yield source
+        
+        it = stream.process(frames())
+        first = next(it)
+        assert first[0] is not None
+        assert consumed == [0]
+        assert stream.frame_count == 1
+        assert len(list(it)) == 2
+        assert stream.frame_count == 3
+    
+    def test_empty_templates(self):
+        """Test error when no templates are given."""
+        with pytest.raises(ValueError):
+            rustmatch.MatcherStream([])
+    
+    def test_incremental_matches_full_search(self):
+        """Test incremental frames give the same results as searching every frame in full."""
+        w, h = 200, 150
+        base = blocky_pixels(w, h, cell=5)
+        crop = [base[(70 + y) * w + 100 + x] for y in range(32) for x in range(32)]
+        template = make_png(32, 32, crop)
+        
+        def paint(pixels, x0, y0, patch, pw):
+            pixels = list(pixels)
+            for i, v in enumerate(patch):
+                pixels[(y0 + i // pw) * w + x0 + i % pw] = v
+            return pixels
+        
+        unrelated = paint(base, 10, 10, [0] * 400, 20)
+        moved = paint(paint(unrelated, 100, 70, [255] * 1024, 32), 40, 90, crop, 32)
+        frames = [make_png(w, h, p) for p in (base, base, unrelated, moved)]
+        
+        full = rustmatch.MatcherStream([template])
+        incremental = rustmatch.MatcherStream([template], incremental=True)
+        assert incremental.incremental
+        for frame in frames:
+            (expected,), (found,) = full.push(frame), incremental.push(frame)
+            assert expected is not None and found is not None
+            assert (found.x, found.y) == (expected.x, expected.y)
+            assert found.confidence == pytest.approx(expected.confidence)
+        assert (found.x, found.y) == (40, 90)
+        
+        results = list(rustmatch.MatcherStream([template], incremental=True).process(frames))
+        assert [(r.x, r.y) for (r,) in results] == [(100, 70)] * 3 + [(40, 90)]
+    
+    def test_incremental_reset_and_size_change(self):
+        """Test reset() and a change of frame size both fall back to a full search."""
+        small = blocky_pixels(60, 48, cell=5)
+        crop = [small[(20 + y) * 60 + 25 + x] for y in range(12) for x in range(12)]
+        stream = rustmatch.MatcherStream([make_png(12, 12, crop)], incremental=True)
+        
+        (a,) = stream.push(make_png(60, 48, small))
+        stream.reset()
+        (b,) = stream.push(make_png(60, 48, small))
+        assert a is not None and b is not None
+        assert (a.x, a.y) == (b.x, b.y) == (25, 20)
+        
+        (c,) = stream.push(make_png(12, 12, crop))
+        assert c is not None and (c.x, c.y) == (0, 0)
+    
+    def test_negative_change_tolerance(self):
+        """Test change_tolerance must be non-negative."""
+        with pytest.raises(ValueError, match="change_tolerance"):
+            rustmatch.MatcherStream([TEMPLATE_IMAGE], change_tolerance=-1.0)
+
+
+class TestMatcher:
+    """Tests for the reusable, thread-safe matcher."""
+    
+    def test_same_results_as_functions(self):
+        """Test the matcher agrees with the one-shot functions, paths and bytes alike."""
+        matcher = rustmatch.Matcher(TEMPLATE_IMAGE, threshold=0.5)
+        with open(SOURCE_IMAGE, "rb") as f:
+            source = f.read()
+        
+        assert matcher.find(SOURCE_IMAGE) == rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.5)
+        assert matcher.find(source) == matcher.find(SOURCE_IMAGE)
+        assert matcher.find_all(SOURCE_IMAGE, max_count=5) == rustmatch.find_all(
+            SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.5, max_count=5,
+        )
+        assert matcher.find(SOURCE_IMAGE, threshold=1.01) is None
+        assert matcher.template_size == (15, 16)
+    
+    def test_options(self):
+        """Test options are applied to every call."""
+        matcher = rustmatch.Matcher(TEMPLATE_IMAGE, return_crop=True)
+        
+        r = matcher.find(SOURCE_IMAGE)
+        
+        assert r is not None and r.crop is not None
+        with pytest.raises(TypeError):
+            rustmatch.Matcher(TEMPLATE_IMAGE, no_such_option=True)
+    
+    @pytest.mark.parametrize("num_threads", [None, 2])
+    def test_concurrent_calls(self, num_threads):
+        """Test one matcher shared by a thread pool returns consistent results."""
+        from concurrent.futures import ThreadPoolExecutor
+        
+        matcher = rustmatch.Matcher(TEMPLATE_IMAGE, num_threads=num_threads)
+        expected = matcher.find(SOURCE_IMAGE)
+        
+        with ThreadPoolExecutor(max_workers=8) as pool:
+            results = list(pool.map(lambda _: matcher.find(SOURCE_IMAGE), range(32)))
+        
+        assert expected is not None
+        assert all(r == expected for r in results)
+    
+    def test_template_too_large(self):
+        """Test sources smaller than the template are rejected per call."""
+        matcher = rustmatch.Matcher(SOURCE_IMAGE)
+        with pytest.raises(rustmatch.TemplateTooLargeError):
+            matcher.find(TEMPLATE_IMAGE)
+
+
+class TestTracker:
+    """Tests for frame-to-frame tracking."""
+    
+    def test_update_tracks_position(self):
+        """Test the tracker follows the target across frames."""
+        expected = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.8)
+        tracker = rustmatch.Tracker(TEMPLATE_IMAGE, threshold=0.8)
+        
+        first = tracker.update(SOURCE_IMAGE)
+        second = tracker.update(SOURCE_IMAGE)
+        
+        assert first is not None and second is not None
+        assert (first.x, first.y) == (expected.x, expected.y)
+        assert (second.x, second.y) == (first.x, first.y)
+        assert tracker.last is not None
+        assert tracker.lost_frames == 0
+    
+    def test_lost_target(self):
+        """Test a frame without the target marks the tracker lost."""
+        tracker = rustmatch.Tracker(TEMPLATE_IMAGE, threshold=0.8)
+        assert tracker.update(SOURCE_IMAGE) is not None
+        
+        blank = make_png(64, 64, [128] * (64 * 64))
+        assert tracker.update(blank) is None
+        assert tracker.last is None
+        assert tracker.lost_frames == 1
+        
+        tracker.reset()
+        assert tracker.lost_frames == 0
+
+
 class TestFindBytes:
     """Tests for bytes-based matching."""
     
@@ -199,43 +604,3951 @@ class TestFindRaw:
             )
 
 
-class TestUtilities:
-    """Tests for utility functions."""
+class TestAutocrop:
+    """Tests for trimming flat template margins."""
     
-    def test_get_size(self):
-        """Test getting image dimensions."""
-        width, height = rustmatch.get_size(SOURCE_IMAGE)
+    WIDTH, HEIGHT = 80, 60
+    
+    @classmethod
+    def source_pixels(cls):
+        return [(x * 73 + y * 151 + (x * y) % 37) % 97 for y in range(cls.HEIGHT) for x in range(cls.WIDTH)]
+    
+    @classmethod
+    def padded_template(cls, x0, y0, w, h, pad):
+        """Crop the source and surround it with a white margin."""
+        src = cls.source_pixels()
+        tw, th = w + 2 * pad, h + 2 * pad
+        pixels = [255] * (tw * th)
+        for y in range(h):
+            for x in range(w):
+                pixels[(y + pad) * tw + x + pad] = src[(y0 + y) * cls.WIDTH + x0 + x]
+        return make_png(tw, th, pixels), tw, th
+    
+    def test_autocrop_box(self):
+        """Test the margin is located exactly."""
+        template, _, _ = self.padded_template(30, 20, 16, 12, 4)
         
-        assert width == 1602
-        assert height == 364
+        assert rustmatch.autocrop(template) == (4, 4, 16, 12)
     
-    def test_get_size_bytes(self):
-        """Test getting dimensions from bytes."""
-        with open(SOURCE_IMAGE, "rb") as f:
-            data = f.read()
+    def test_flat_image_untouched(self):
+        """Test a flat image keeps its full box."""
+        assert rustmatch.autocrop(make_png(8, 6, [42] * 48)) == (0, 0, 8, 6)
+    
+    def test_find_with_autocrop(self):
+        """Test matches are reported at the original template's corner."""
+        source = make_png(self.WIDTH, self.HEIGHT, self.source_pixels())
+        template, _, _ = self.padded_template(30, 20, 16, 12, 4)
         
-        width, height = rustmatch.get_size_bytes(data)
+        assert rustmatch.find_bytes(source, template, threshold=0.9) is None
+        result = rustmatch.find_bytes(source, template, threshold=0.9, autocrop=True)
         
-        assert width == 1602
-        assert height == 364
+        assert result is not None
+        assert (result.x, result.y) == (26, 16)
+        assert result.confidence > 0.99
     
-    def test_version(self):
-        """Test version function."""
-        ver = rustmatch.version()
+    def test_find_all_with_autocrop(self):
+        """Test find_all applies the same offset correction."""
+        source = make_png(self.WIDTH, self.HEIGHT, self.source_pixels())
+        template, _, _ = self.padded_template(30, 20, 16, 12, 4)
         
-        assert isinstance(ver, str)
-        assert "." in ver  # Should be semver format
+        results = rustmatch.find_all_bytes(source, template, threshold=0.9, autocrop=True)
+        
+        assert [(r.x, r.y) for r in results] == [(26, 16)]
     
-    def test_set_threads(self):
-        """Test setting thread count."""
-        # Note: set_threads can only be called once before any matching
-        # After that, the thread pool is already initialized
-        # This test just verifies the function exists and is callable
-        try:
-            rustmatch.set_threads(4)
-        except ValueError as e:
-            # Expected if thread pool already initialized
-            assert "already been initialized" in str(e)
+    def test_autocrop_near_edge(self):
+        """Test content closer to the corner than its margin reports a negative position."""
+        source = make_png(self.WIDTH, self.HEIGHT, self.source_pixels())
+        template, _, _ = self.padded_template(2, 1, 16, 12, 4)
+        
+        result = rustmatch.find_bytes(source, template, threshold=0.9, autocrop=True, return_crop=True)
+        
+        assert (result.x, result.y) == (-2, -3)
+        assert result.crop is not None
+    
+    def test_unknown_option(self):
+        """Test misspelled options are rejected."""
+        with pytest.raises(TypeError):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, autocorp=True)
+
+
+class TestRobustScoring:
+    """Tests for occlusion-robust block scoring."""
+    
+    WIDTH, HEIGHT = 120, 90
+    
+    @classmethod
+    def scene(cls, occluded):
+        """Blocky texture; optionally cover a quarter of the target with white."""
+        pixels = blocky_pixels(cls.WIDTH, cls.HEIGHT)
+        template = [pixels[(30 + y) * cls.WIDTH + 40 + x] for y in range(48) for x in range(48)]
+        if occluded:
+            for y in range(30, 54):
+                for x in range(40, 64):
+                    pixels[y * cls.WIDTH + x] = 255
+        return pixels, template
+    
+    def test_occlusion_breaks_plain_ncc(self):
+        """Test a 25% occlusion drops the plain score below threshold."""
+        source, template = self.scene(occluded=True)
+        
+        assert rustmatch.find_raw(source, self.WIDTH, self.HEIGHT, template, 48, 48, threshold=0.9) is None
+    
+    def test_robust_survives_occlusion(self):
+        """Test robust scoring still finds the occluded target."""
+        source, template = self.scene(occluded=True)
+        
+        result = rustmatch.find_raw(
+            source, self.WIDTH, self.HEIGHT, template, 48, 48, threshold=0.9, robust=True
+        )
+        
+        assert result is not None
+        assert (result.x, result.y) == (40, 30)
+        assert result.confidence > 0.99
+    
+    def test_robust_find_all(self):
+        """Test robust scoring in find_all."""
+        source, template = self.scene(occluded=True)
+        
+        results = rustmatch.find_all_raw(
+            source, self.WIDTH, self.HEIGHT, template, 48, 48, threshold=0.9, robust=True
+        )
+        
+        assert [(r.x, r.y) for r in results] == [(40, 30)]
+    
+    def test_robust_matches_plain_when_clean(self):
+        """Test robust and plain scoring agree on the real fixtures."""
+        plain = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.8)
+        robust = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.8, robust=True, robust_grid=2)
+        
+        assert plain is not None and robust is not None
+        assert (robust.x, robust.y) == (plain.x, plain.y)
+    
+    def test_invalid_robust_options(self):
+        """Test robust option validation."""
+        with pytest.raises(ValueError):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, robust=True, robust_keep=0.0)
+        with pytest.raises(ValueError):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, robust=True, robust_grid=9)
+
+
+class TestWeights:
+    """Tests for per-pixel weighted templates."""
+    
+    WIDTH, HEIGHT = 120, 90
+    
+    @classmethod
+    def scene(cls):
+        """32x32 template whose 8px fringe doesn't match the source; weights cover the core."""
+        source = blocky_pixels(cls.WIDTH, cls.HEIGHT)
+        template, weights = [], []
+        for y in range(32):
+            for x in range(32):
+                core = 8 <= x < 24 and 8 <= y < 24
+                template.append(source[(30 + y) * cls.WIDTH + 40 + x] if core else (x * 31 + y * 17) % 200)
+                weights.append(1.0 if core else 0.0)
+        return source, template, weights
+    
+    def test_weights_ignore_fringe(self):
+        """Test zero-weight pixels don't affect the score."""
+        source, template, weights = self.scene()
+        
+        assert rustmatch.find_raw(source, self.WIDTH, self.HEIGHT, template, 32, 32, threshold=0.9) is None
+        result = rustmatch.find_raw(
+            source, self.WIDTH, self.HEIGHT, template, 32, 32, threshold=0.9, weights=weights
+        )
+        
+        assert result is not None
+        assert (result.x, result.y) == (40, 30)
+        assert result.confidence > 0.99
+    
+    def test_weight_image(self):
+        """Test weights given as a grayscale image."""
+        source, template, weights = self.scene()
+        weight_png = make_png(32, 32, [int(w * 255) for w in weights])
+        
+        result = rustmatch.find_raw(
+            source, self.WIDTH, self.HEIGHT, template, 32, 32, threshold=0.9, weights=weight_png
+        )
+        
+        assert result is not None
+        assert (result.x, result.y) == (40, 30)
+    
+    def test_uniform_weights_match_plain(self):
+        """Test uniform weights reproduce the unweighted result."""
+        plain = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.8)
+        weighted = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.8, weights=[1.0] * (15 * 16))
+        
+        assert plain is not None and weighted is not None
+        assert (weighted.x, weighted.y) == (plain.x, plain.y)
+        assert abs(weighted.confidence - plain.confidence) < 1e-6
+    
+    def test_invalid_weights(self):
+        """Test weight map validation."""
+        with pytest.raises(ValueError):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, weights=[1.0] * 10)
+        with pytest.raises(ValueError):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, weights=[-1.0] * (15 * 16))
+
+
+class TestTonePreprocessing:
+    """Tests for gamma correction and contrast stretching."""
+    
+    WIDTH, HEIGHT = 120, 90
+    
+    def test_auto_gamma_compensates_night_mode(self):
+        """Test a gamma-shifted capture still matches with gamma='auto'."""
+        day = [20 + v for v in blocky_pixels(self.WIDTH, self.HEIGHT)]
+        night = [round(255 * (v / 255) ** 2.2) for v in day]
+        template = [day[(30 + y) * self.WIDTH + 40 + x] for y in range(32) for x in range(32)]
+        
+        assert rustmatch.find_raw(night, self.WIDTH, self.HEIGHT, template, 32, 32, threshold=0.99) is None
+        result = rustmatch.find_raw(
+            night, self.WIDTH, self.HEIGHT, template, 32, 32, threshold=0.99, gamma="auto"
+        )
+        
+        assert result is not None
+        assert (result.x, result.y) == (40, 30)
+    
+    def test_stretch_low_contrast(self):
+        """Test contrast stretching lifts windows below the flatness cutoff."""
+        source = [100 + v % 3 for v in blocky_pixels(self.WIDTH, self.HEIGHT)]
+        template = [source[(30 + y) * self.WIDTH + 40 + x] for y in range(32) for x in range(32)]
+        
+        assert rustmatch.find_raw(source, self.WIDTH, self.HEIGHT, template, 32, 32, threshold=0.9) is None
+        result = rustmatch.find_raw(
+            source, self.WIDTH, self.HEIGHT, template, 32, 32, threshold=0.9, stretch=True
+        )
+        
+        assert result is not None
+        assert result.confidence > 0.99
+    
+    def test_tiled_preprocessing(self):
+        """Test tiled search applies the same preprocessing."""
+        result = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.8, gamma=0.8, stretch=True)
+        tiled = rustmatch.find_tiled(
+            SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.8, tile_size=128, gamma=0.8, stretch=True
+        )
+        
+        assert result is not None and tiled is not None
+        assert (tiled.x, tiled.y) == (result.x, result.y)
+    
+    def test_invalid_gamma(self):
+        """Test gamma validation."""
+        with pytest.raises(ValueError):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, gamma=0)
+        with pytest.raises(ValueError):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, gamma="night")
+
+
+class TestWindow:
+    """Tests for window/level mapping of deep images."""
+    
+    WIDTH, HEIGHT = 120, 90
+    
+    @classmethod
+    def images(cls):
+        """16-bit frame whose structure spans 200 of 65536 levels, and a 32x32 cut of it."""
+        source = [30000 + v for v in blocky_pixels(cls.WIDTH, cls.HEIGHT)]
+        template = [source[(30 + y) * cls.WIDTH + 40 + x] for y in range(32) for x in range(32)]
+        return make_png16(cls.WIDTH, cls.HEIGHT, source), make_png16(32, 32, template)
+    
+    def test_window_recovers_narrow_band(self):
+        """Test a band the 8-bit squash flattens matches once windowed."""
+        source, template = self.images()
+        
+        assert rustmatch.find_bytes(source, template, threshold=0.9) is None
+        result = rustmatch.find_bytes(source, template, threshold=0.9, window=(30000, 30200))
+        
+        assert (result.x, result.y) == (40, 30)
+        assert result.confidence > 0.99
+    
+    def test_eight_bit_units(self):
+        """Test 8-bit images take the window in 0-255 units."""
+        result = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, window=(0, 255))
+        
+        assert (result.x, result.y) == (847, 297)
+    
+    def test_invalid_window(self):
+        """Test bad windows and unsupported combinations are rejected."""
+        with pytest.raises(ValueError):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, window=(200, 100))
+        with pytest.raises(ValueError):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, window=(0, 255), color_space="hsv")
+        with pytest.raises(ValueError):
+            rustmatch.find_tiled(SOURCE_IMAGE, TEMPLATE_IMAGE, window=(0, 255))
+
+
+class TestExifOrientation:
+    """Tests for applying the EXIF orientation tag at load."""
+    
+    WIDTH, HEIGHT = 120, 90
+    
+    @classmethod
+    def images(cls):
+        """Phone-style photo stored on its side with orientation 6, and a 32x32 cut of the upright view."""
+        upright = blocky_pixels(cls.WIDTH, cls.HEIGHT)
+        # Stored HEIGHT x WIDTH; turning it 90 degrees clockwise gives the upright view
+        stored = [upright[a * cls.WIDTH + cls.WIDTH - 1 - b] for b in range(cls.WIDTH) for a in range(cls.HEIGHT)]
+        exif = b"MM\x00*" + struct.pack(">IHHHIHHI", 8, 1, 0x0112, 3, 1, 6, 0, 0)
+        template = [upright[(30 + y) * cls.WIDTH + 40 + x] for y in range(32) for x in range(32)]
+        return make_png(cls.HEIGHT, cls.WIDTH, stored, exif=exif), make_png(32, 32, template)
+    
+    def test_bytes_upright(self):
+        """Test coordinates refer to the displayed image."""
+        source, template = self.images()
+        
+        assert rustmatch.get_size_bytes(source) == (self.WIDTH, self.HEIGHT)
+        result = rustmatch.find_bytes(source, template, threshold=0.95)
+        assert (result.x, result.y) == (40, 30)
+    
+    def test_path_and_disable(self, tmp_path):
+        """Test file paths are turned upright too, and exif_orientation=False keeps the stored pixels per call."""
+        source, template = self.images()
+        path, tpl_path = tmp_path / "photo.png", tmp_path / "label.png"
+        path.write_bytes(source)
+        tpl_path.write_bytes(template)
+        
+        result = rustmatch.find(str(path), str(tpl_path), threshold=0.95)
+        assert (result.x, result.y) == (40, 30)
+        assert rustmatch.get_size(str(path)) == (self.WIDTH, self.HEIGHT)
+        assert rustmatch.find(str(path), str(tpl_path), threshold=0.95, exif_orientation=False) is None
+        assert rustmatch.find_bytes(source, template, threshold=0.95, exif_orientation=False) is None
+        # The decoded image cache keeps the two views apart
+        result = rustmatch.find(str(path), str(tpl_path), threshold=0.95)
+        assert (result.x, result.y) == (40, 30)
+
+
+class TestDenoise:
+    """Tests for median filter denoising."""
+    
+    WIDTH, HEIGHT = 120, 90
+    
+    @classmethod
+    def scene(cls):
+        """Clean 24x24 template; source with salt-and-pepper noise on ~18% of pixels."""
+        clean = blocky_pixels(cls.WIDTH, cls.HEIGHT)
+        noise = {0: 255, 1: 0}
+        noisy = [noise.get(i * 7919 % 11, v) for i, v in enumerate(clean)]
+        template = [clean[(30 + y) * cls.WIDTH + 40 + x] for y in range(24) for x in range(24)]
+        return noisy, template
+    
+    def test_median_denoise(self):
+        """Test median filtering recovers a match lost to impulse noise."""
+        source, template = self.scene()
+        
+        assert rustmatch.find_raw(source, self.WIDTH, self.HEIGHT, template, 24, 24, threshold=0.85) is None
+        result = rustmatch.find_raw(
+            source, self.WIDTH, self.HEIGHT, template, 24, 24, threshold=0.85, denoise="median3"
+        )
+        
+        assert result is not None
+        assert (result.x, result.y) == (40, 30)
+    
+    def test_denoise_tiled(self):
+        """Test tiled and full search agree with denoising."""
+        result = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.5, denoise="median5")
+        tiled = rustmatch.find_tiled(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.5, tile_size=128, denoise="median5")
+        
+        # A.png repeats the glyph, so compare scores rather than positions
+        assert result is not None and tiled is not None
+        assert abs(tiled.confidence - result.confidence) < 1e-6
+    
+    def test_unknown_filter(self):
+        """Test unknown filters are rejected."""
+        with pytest.raises(ValueError):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, denoise="gaussian")
+
+
+class TestPyramidFilter:
+    """Tests for selectable pyramid downsampling filters."""
+    
+    WIDTH, HEIGHT = 160, 120
+    
+    @classmethod
+    def noise(cls):
+        """Deterministic white noise, the worst case for box-filter aliasing."""
+        seed, pixels = 7, []
+        for _ in range(cls.WIDTH * cls.HEIGHT):
+            seed = (seed * 6364136223846793005 + 1442695040888963407) % 2 ** 64
+            pixels.append((seed >> 33) % 256)
+        return pixels
+    
+    def test_gaussian_avoids_coarse_miss(self):
+        """Test the Gaussian prefilter finds a target the bilinear filter misses."""
+        source = self.noise()
+        template = [source[(21 + y) * self.WIDTH + 22 + x] for y in range(64) for x in range(64)]
+        
+        bilinear = rustmatch.find_raw(
+            source, self.WIDTH, self.HEIGHT, template, 64, 64, threshold=0.9, pyramid_filter="bilinear"
+        )
+        gaussian = rustmatch.find_raw(
+            source, self.WIDTH, self.HEIGHT, template, 64, 64, threshold=0.9, pyramid_filter="gaussian"
+        )
+        
+        assert bilinear is None
+        assert gaussian is not None
+        assert (gaussian.x, gaussian.y) == (22, 21)
+    
+    @pytest.mark.parametrize("name", ["area", "bilinear", "gaussian"])
+    def test_filters_find_fixture(self, name):
+        """Test every filter finds the fixture template."""
+        result = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.8, pyramid_filter=name)
+        
+        assert result is not None
+        assert result.confidence > 0.99
+    
+    def test_unknown_filter(self):
+        """Test unknown filters are rejected."""
+        with pytest.raises(ValueError):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, pyramid_filter="lanczos")
+
+
+class TestScaleEstimation:
+    """Tests for template scale estimation."""
+    
+    WIDTH, HEIGHT = 300, 200
+    
+    @classmethod
+    def scene(cls):
+        """Source containing the template enlarged 2x (pixel doubling) at (100, 60)."""
+        template = [
+            240 if (x // 5 + y // 8) % 3 == 0 else 10 + 3 * x
+            for y in range(40) for x in range(40)
+        ]
+        source = blocky_pixels(cls.WIDTH, cls.HEIGHT, cell=9)
+        for y in range(80):
+            for x in range(80):
+                source[(60 + y) * cls.WIDTH + 100 + x] = template[(y // 2) * 40 + x // 2]
+        return make_png(cls.WIDTH, cls.HEIGHT, source), make_png(40, 40, template)
+    
+    def test_estimate_scale(self):
+        """Test the 2x factor is recovered."""
+        source, template = self.scene()
+        
+        scale, score = rustmatch.estimate_scale(source, template)
+        
+        assert scale == 2.0
+        assert score > 0.9
+    
+    def test_custom_candidates(self):
+        """Test only the given factors are tried."""
+        source, template = self.scene()
+        
+        scale, _ = rustmatch.estimate_scale(source, template, scale_candidates=[0.5, 1.0])
+        
+        assert scale in (0.5, 1.0)
+    
+    def test_auto_scale(self):
+        """Test auto_scale finds the enlarged target."""
+        source, template = self.scene()
+        
+        assert rustmatch.find_bytes(source, template, threshold=0.9) is None
+        result = rustmatch.find_bytes(source, template, threshold=0.9, auto_scale=True)
+        
+        assert result is not None
+        assert (result.x, result.y) == (100, 60)
+    
+    def test_same_scale_fixture(self):
+        """Test the fixture template is reported at its native scale."""
+        scale, _ = rustmatch.estimate_scale(SOURCE_IMAGE, TEMPLATE_IMAGE)
+        
+        assert scale == 1.0
+    
+    def test_invalid_candidates(self):
+        """Test scale_candidates validation."""
+        with pytest.raises(ValueError):
+            rustmatch.estimate_scale(SOURCE_IMAGE, TEMPLATE_IMAGE, scale_candidates=[])
+        with pytest.raises(ValueError):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, auto_scale=True, scale_candidates=[-1.0])
+
+
+class TestReturnCrop:
+    """Tests for returning the matched window as PNG bytes."""
+    
+    def test_crop_default_none(self):
+        """Test no crop is attached unless requested."""
+        result = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.8)
+        
+        assert result is not None
+        assert result.crop is None
+    
+    def test_return_crop(self):
+        """Test the crop is a PNG of the matched window."""
+        result = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.8, return_crop=True)
+        
+        assert result is not None
+        assert result.crop.startswith(b"\x89PNG")
+        assert rustmatch.get_size_bytes(result.crop) == rustmatch.get_size(TEMPLATE_IMAGE)
+        assert rustmatch.compare_images(result.crop, TEMPLATE_IMAGE, method="ncc") > 0.99
+    
+    def test_return_crop_all_and_raw(self):
+        """Test crops from find_all and the raw-pixel API."""
+        results = rustmatch.find_all(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.8, return_crop=True)
+        assert len(results) > 0
+        assert all(r.crop is not None for r in results)
+        
+        source = blocky_pixels(60, 40)
+        template = [source[(10 + y) * 60 + 20 + x] for y in range(12) for x in range(18)]
+        result = rustmatch.find_raw(source, 60, 40, template, 18, 12, threshold=0.9, return_crop=True)
+        assert result is not None
+        assert rustmatch.get_size_bytes(result.crop) == (18, 12)
+
+
+class TestCropImage:
+    """Tests for the crop_image utility."""
+    
+    def test_crop_bytes(self):
+        """Test cropping returns a PNG of the requested size."""
+        png = rustmatch.crop_image(SOURCE_IMAGE, 10, 20, 30, 40)
+        
+        assert png.startswith(b"\x89PNG")
+        assert rustmatch.get_size_bytes(png) == (30, 40)
+    
+    def test_crop_matches_template(self):
+        """Test cropping the box of a match recovers the template."""
+        result = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.8)
+        w, h = rustmatch.get_size(TEMPLATE_IMAGE)
+        
+        with open(SOURCE_IMAGE, "rb") as f:
+            png = rustmatch.crop_image(f.read(), *result.bbox(w, h))
+        assert rustmatch.compare_images(png, TEMPLATE_IMAGE, method="ncc") > 0.99
+    
+    def test_crop_to_file(self, tmp_path):
+        """Test writing the crop to a file."""
+        out = str(tmp_path / "crop.png")
+        
+        assert rustmatch.crop_image(SOURCE_IMAGE, 0, 0, 16, 8, out_path=out) is None
+        assert rustmatch.get_size(out) == (16, 8)
+    
+    def test_crop_out_of_bounds(self):
+        """Test rectangles outside the image are rejected."""
+        w, h = rustmatch.get_size(SOURCE_IMAGE)
+        
+        with pytest.raises(ValueError):
+            rustmatch.crop_image(SOURCE_IMAGE, w - 5, 0, 10, 10)
+        with pytest.raises(ValueError):
+            rustmatch.crop_image(SOURCE_IMAGE, 0, 0, 0, 10)
+
+
+class TestResizeImage:
+    """Tests for the resize_image utility."""
+    
+    def test_resize_size(self):
+        """Test every filter produces the requested size."""
+        for name in ["nearest", "bilinear", "bicubic", "gaussian", "lanczos"]:
+            png = rustmatch.resize_image(TEMPLATE_IMAGE, 30, 20, filter=name)
+            assert rustmatch.get_size_bytes(png) == (30, 20)
+    
+    def test_resize_roundtrip(self):
+        """Test enlarging and shrinking back keeps the template matchable."""
+        w, h = rustmatch.get_size(TEMPLATE_IMAGE)
+        big = rustmatch.resize_image(TEMPLATE_IMAGE, w * 2, h * 2)
+        back = rustmatch.resize_image(big, w, h)
+        
+        assert rustmatch.compare_images(back, TEMPLATE_IMAGE, method="ncc") > 0.95
+    
+    def test_resize_invalid(self):
+        """Test unknown filters and empty sizes are rejected."""
+        with pytest.raises(ValueError):
+            rustmatch.resize_image(TEMPLATE_IMAGE, 10, 10, filter="sinc")
+        with pytest.raises(ValueError):
+            rustmatch.resize_image(TEMPLATE_IMAGE, 0, 10)
+
+
+class TestToGrayscale:
+    """Tests for the to_grayscale utility."""
+    
+    def test_grayscale_shape(self):
+        """Test one byte per pixel at the image size."""
+        pixels, w, h = rustmatch.to_grayscale(SOURCE_IMAGE)
+        
+        assert isinstance(pixels, bytes)
+        assert (w, h) == rustmatch.get_size(SOURCE_IMAGE)
+        assert len(pixels) == w * h
+    
+    def test_grayscale_matches_file_api(self):
+        """Test the raw API on converted pixels agrees with the path API."""
+        pixels, w, h = rustmatch.to_grayscale(SOURCE_IMAGE)
+        tpl, tw, th = rustmatch.to_grayscale(TEMPLATE_IMAGE)
+        
+        raw = rustmatch.find_raw(pixels, w, h, tpl, tw, th, threshold=0.8)
+        path = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.8)
+        assert raw.to_tuple() == path.to_tuple()
+
+
+class TestImageInfo:
+    """Tests for get_image_info."""
+    
+    def test_info_png(self):
+        """Test format, size and colour fields of a fixture."""
+        info = rustmatch.get_image_info(SOURCE_IMAGE)
+        
+        assert info["format"] == "png"
+        assert (info["width"], info["height"]) == rustmatch.get_size(SOURCE_IMAGE)
+        assert info["bit_depth"] == 8
+        assert info["channels"] in (1, 2, 3, 4)
+        assert info["has_alpha"] == (info["channels"] in (2, 4))
+        assert info["orientation"] is None
+    
+    def test_info_gray_bytes(self):
+        """Test a grayscale image given as bytes."""
+        info = rustmatch.get_image_info(make_png(5, 3, [0] * 15))
+        
+        assert info["color_type"] == "gray"
+        assert info["channels"] == 1
+        assert not info["has_alpha"]
+    
+    def test_info_orientation(self):
+        """Test the EXIF orientation tag is reported."""
+        # Big-endian TIFF header with one IFD entry: Orientation (0x0112) = 6
+        exif = b"MM\x00*" + struct.pack(">IHHHIHHI", 8, 1, 0x0112, 3, 1, 6, 0, 0)
+        info = rustmatch.get_image_info(make_png(5, 3, [0] * 15, exif=exif))
+        
+        assert info["orientation"] == 6
+    
+    def test_info_invalid(self):
+        """Test undecodable input is rejected."""
+        with pytest.raises(ValueError):
+            rustmatch.get_image_info(b"not an image")
+
+
+class TestBenchmark:
+    """Tests for the benchmark function."""
+    
+    STAGES = {"decode", "grayscale", "preprocess", "integral", "coarse", "refine", "match", "total"}
+    
+    def test_benchmark_report(self):
+        """Test the report covers every stage and the match result."""
+        report = rustmatch.benchmark(SOURCE_IMAGE, TEMPLATE_IMAGE, repeats=3)
+        
+        assert report["repeats"] == 3
+        assert set(report["stages"]) == self.STAGES
+        for timing in report["stages"].values():
+            assert 0.0 <= timing["min_ms"] <= timing["median_ms"]
+        
+        expected = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE)
+        assert report["result"].to_tuple() == expected.to_tuple()
+    
+    def test_benchmark_total(self):
+        """Test the total covers the match stage."""
+        report = rustmatch.benchmark(SOURCE_IMAGE, TEMPLATE_IMAGE, repeats=1, denoise="median3")
+        stages = report["stages"]
+        
+        assert stages["total"]["min_ms"] >= stages["match"]["min_ms"]
+        assert stages["preprocess"]["min_ms"] > 0.0
+    
+    def test_benchmark_invalid(self):
+        """Test repeats must be positive and options are validated."""
+        with pytest.raises(ValueError):
+            rustmatch.benchmark(SOURCE_IMAGE, TEMPLATE_IMAGE, repeats=0)
+        with pytest.raises(TypeError):
+            rustmatch.benchmark(SOURCE_IMAGE, TEMPLATE_IMAGE, bogus=True)
+
+
+class TestLogging:
+    """Tests for debug events on the rustmatch logger."""
+    
+    def test_final_score_logged(self, caplog):
+        """Test the best score is reported against the threshold."""
+        caplog.set_level(logging.DEBUG, logger="rustmatch")
+        rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.8)
+        
+        messages = [r.getMessage() for r in caplog.records if r.name == "rustmatch"]
+        assert any("best score" in m and "threshold 0.800" in m for m in messages)
+    
+    def test_template_too_large_logged(self, caplog):
+        """Test a silent None explains an oversized template."""
+        caplog.set_level(logging.DEBUG, logger="rustmatch")
+        stream = rustmatch.MatcherStream([SOURCE_IMAGE])
+        with open(TEMPLATE_IMAGE, "rb") as f:
+            results = stream.push(f.read())
+        
+        assert results == [None]
+        assert any("larger than source" in r.getMessage() for r in caplog.records)
+    
+    def test_silent_by_default(self, caplog):
+        """Test nothing is emitted below the configured level."""
+        caplog.set_level(logging.WARNING, logger="rustmatch")
+        rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE)
+        
+        assert not [r for r in caplog.records if r.name == "rustmatch"]
+
+
+class TestExceptions:
+    """Tests for the rustmatch exception hierarchy."""
+    
+    def test_hierarchy(self):
+        """Test every error is a RustMatchError and still a ValueError."""
+        for exc in (
+            rustmatch.DecodeError, rustmatch.TemplateTooLargeError,
+            rustmatch.InvalidDimensionsError, rustmatch.TemplateQualityError,
+        ):
+            assert issubclass(exc, rustmatch.RustMatchError)
+        assert issubclass(rustmatch.RustMatchError, ValueError)
+    
+    def test_decode_error(self, tmp_path):
+        """Test corrupt data raises DecodeError for bytes and files."""
+        with pytest.raises(rustmatch.DecodeError):
+            rustmatch.find_bytes(b"not a png", b"not a png")
+        
+        corrupt = tmp_path / "corrupt.png"
+        corrupt.write_bytes(b"\x89PNG\r\n\x1a\n" + b"\x00" * 32)
+        with pytest.raises(rustmatch.DecodeError):
+            rustmatch.find(str(corrupt), TEMPLATE_IMAGE)
+    
+    def test_missing_file_still_oserror(self):
+        """Test filesystem errors are not reported as decode errors."""
+        with pytest.raises(OSError):
+            rustmatch.find("nonexistent.png", TEMPLATE_IMAGE)
+    
+    def test_template_too_large(self):
+        """Test swapping source and template is reported."""
+        with pytest.raises(rustmatch.TemplateTooLargeError):
+            rustmatch.find(TEMPLATE_IMAGE, SOURCE_IMAGE)
+        with pytest.raises(rustmatch.TemplateTooLargeError):
+            rustmatch.find_all(TEMPLATE_IMAGE, SOURCE_IMAGE)
+    
+    def test_invalid_dimensions(self):
+        """Test inconsistent sizes raise InvalidDimensionsError."""
+        with pytest.raises(rustmatch.InvalidDimensionsError):
+            rustmatch.find_raw([0] * 10, 4, 4, [0] * 4, 2, 2)
+        with pytest.raises(rustmatch.InvalidDimensionsError):
+            rustmatch.find_tiled(SOURCE_IMAGE, TEMPLATE_IMAGE, tile_size=8)
+        with pytest.raises(rustmatch.InvalidDimensionsError):
+            rustmatch.compare_images(SOURCE_IMAGE, TEMPLATE_IMAGE)
+
+
+class TestValidateTemplate:
+    """Tests for template quality validation."""
+    
+    def test_valid_template(self):
+        """Test a glyph template passes."""
+        report = rustmatch.validate_template(TEMPLATE_IMAGE)
+        
+        assert report["valid"]
+        assert report["issues"] == []
+        assert report["std"] > 5.0
+        assert report["entropy"] > 0.2
+    
+    def test_flat_template(self):
+        """Test a nearly uniform template is flagged."""
+        flat = make_png(20, 20, [128 + i % 3 for i in range(400)])
+        report = rustmatch.validate_template(flat)
+        
+        assert not report["valid"]
+        assert any("standard deviation" in issue for issue in report["issues"])
+    
+    def test_custom_limits(self):
+        """Test the limits are configurable."""
+        flat = make_png(20, 20, [128 + i % 3 for i in range(400)])
+        
+        assert rustmatch.validate_template(flat, min_std=0.5)["valid"]
+    
+    def test_strict_option(self):
+        """Test strict mode rejects flat templates before matching."""
+        flat = make_png(20, 20, [128 + i % 3 for i in range(400)])
+        with open(SOURCE_IMAGE, "rb") as f:
+            source = f.read()
+        
+        with pytest.raises(rustmatch.TemplateQualityError):
+            rustmatch.find_bytes(source, flat, strict=True)
+        assert rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, strict=True) is not None
+
+
+class TestCalibrateThreshold:
+    """Tests for threshold calibration from negative samples."""
+    
+    @staticmethod
+    def negatives():
+        return [make_png(80, 60, blocky_pixels(80, 60, cell=cell)) for cell in (3, 5)]
+    
+    def test_calibration_report(self):
+        """Test the threshold respects the requested false-positive rate."""
+        cal = rustmatch.calibrate_threshold(TEMPLATE_IMAGE, self.negatives(), false_positive_rate=0.01)
+        
+        assert cal["windows"] == 2 * (80 - 15 + 1) * (60 - 16 + 1)
+        assert cal["false_positive_rate"] <= 0.01
+        assert -1.0 <= cal["threshold"] <= cal["max_score"] + 1e-4
+    
+    def test_zero_rate_above_max(self):
+        """Test a zero rate puts the threshold above every negative score."""
+        cal = rustmatch.calibrate_threshold(TEMPLATE_IMAGE, self.negatives(), false_positive_rate=0.0)
+        
+        assert cal["threshold"] > cal["max_score"]
+        assert cal["false_positive_rate"] == 0.0
+    
+    def test_stricter_rate_raises_threshold(self):
+        """Test lower rates give higher thresholds."""
+        loose = rustmatch.calibrate_threshold(TEMPLATE_IMAGE, self.negatives(), false_positive_rate=0.1)
+        tight = rustmatch.calibrate_threshold(TEMPLATE_IMAGE, self.negatives(), false_positive_rate=0.001)
+        
+        assert tight["threshold"] >= loose["threshold"]
+    
+    def test_invalid_arguments(self):
+        """Test invalid rates and empty negative sets are rejected."""
+        with pytest.raises(ValueError):
+            rustmatch.calibrate_threshold(TEMPLATE_IMAGE, self.negatives(), false_positive_rate=1.5)
+        with pytest.raises(ValueError):
+            rustmatch.calibrate_threshold(TEMPLATE_IMAGE, [])
+        with pytest.raises(rustmatch.TemplateTooLargeError):
+            rustmatch.calibrate_threshold(SOURCE_IMAGE, [TEMPLATE_IMAGE])
+
+
+class TestScoreSweep:
+    """Tests for the threshold sweep."""
+    
+    def test_sweep_default_thresholds(self):
+        """Test the default thresholds are reported in order."""
+        sweep = rustmatch.score_sweep(SOURCE_IMAGE, TEMPLATE_IMAGE)
+        
+        assert [t for t, _ in sweep] == pytest.approx([0.5 + 0.05 * i for i in range(10)])
+    
+    def test_sweep_monotonic(self):
+        """Test higher thresholds never yield more matches."""
+        sweep = rustmatch.score_sweep(SOURCE_IMAGE, TEMPLATE_IMAGE, thresholds=[0.6, 0.8, 0.95, 0.7])
+        counts = dict(sweep)
+        
+        assert counts[0.6] >= counts[0.7] >= counts[0.8] >= counts[0.95] >= 1
+    
+    def test_sweep_covers_find_all(self):
+        """Test the dense scan finds at least the matches of find_all."""
+        sweep = rustmatch.score_sweep(SOURCE_IMAGE, TEMPLATE_IMAGE, thresholds=[0.8])
+        results = rustmatch.find_all(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.8, max_count=1000)
+        
+        assert sweep[0][1] >= len(results) > 0
+    
+    def test_sweep_invalid(self):
+        """Test empty thresholds and oversized templates are rejected."""
+        with pytest.raises(ValueError):
+            rustmatch.score_sweep(SOURCE_IMAGE, TEMPLATE_IMAGE, thresholds=[])
+        with pytest.raises(rustmatch.TemplateTooLargeError):
+            rustmatch.score_sweep(TEMPLATE_IMAGE, SOURCE_IMAGE)
+
+
+class TestFindIter:
+    """Tests for lazy match iteration."""
+    
+    def test_iter_matches_find_all(self):
+        """Test the first results equal find_all with the same cap."""
+        lazy = [r.to_tuple() for r in itertools.islice(rustmatch.find_iter(SOURCE_IMAGE, TEMPLATE_IMAGE), 5)]
+        eager = [r.to_tuple() for r in rustmatch.find_all(SOURCE_IMAGE, TEMPLATE_IMAGE, max_count=5)]
+        
+        assert lazy == eager
+    
+    def test_iter_descending_without_cap(self):
+        """Test results are sorted, non-overlapping and not capped at 10."""
+        results = list(rustmatch.find_iter(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.5))
+        
+        assert len(results) > 10
+        assert all(a.confidence >= b.confidence for a, b in zip(results, results[1:]))
+        for i, a in enumerate(results):
+            for b in results[i + 1:]:
+                assert abs(a.x - b.x) >= 7 or abs(a.y - b.y) >= 8
+    
+    def test_iter_exhausts(self):
+        """Test the iterator stops and stays exhausted."""
+        it = rustmatch.find_iter(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.8)
+        results = list(it)
+        
+        assert len(results) >= 1
+        assert next(it, None) is None
+    
+    def test_iter_options(self):
+        """Test matching options apply to the iterator."""
+        with open(SOURCE_IMAGE, "rb") as f:
+            r = next(rustmatch.find_iter(f.read(), TEMPLATE_IMAGE, return_crop=True))
+        
+        assert r.crop is not None
+
+
+class TestFindNext:
+    """Tests for searching outside regions already handled."""
+    
+    def test_next_follows_iter(self):
+        """Test appending each result walks the find_iter sequence."""
+        handled = []
+        for _ in range(5):
+            handled.append(rustmatch.find_next(SOURCE_IMAGE, TEMPLATE_IMAGE, handled, threshold=0.7))
+        
+        expected = itertools.islice(rustmatch.find_iter(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.7), 5)
+        assert [r.to_tuple() for r in handled] == [r.to_tuple() for r in expected]
+    
+    def test_exclude_boxes(self):
+        """Test boxes exclude matches centred inside them, and nothing is left at the end."""
+        best = rustmatch.find_next(SOURCE_IMAGE, TEMPLATE_IMAGE, [])
+        other = rustmatch.find_next(SOURCE_IMAGE, TEMPLATE_IMAGE, [(best.x - 5, best.y - 5, 30, 30)])
+        
+        assert (other.x, other.y) != (best.x, best.y)
+        assert rustmatch.find_next(SOURCE_IMAGE, TEMPLATE_IMAGE, [(0, 0, 1602, 364)]) is None
+        with pytest.raises(TypeError):
+            rustmatch.find_next(SOURCE_IMAGE, TEMPLATE_IMAGE, ["(1, 2)"])
+    
+    def test_oversized_boxes(self):
+        """Test boxes reaching past the integer range exclude without overflowing."""
+        assert rustmatch.find_next(SOURCE_IMAGE, TEMPLATE_IMAGE, [(0, 0, 2**32 - 1, 2**32 - 1)]) is None
+        assert rustmatch.find_next(SOURCE_IMAGE, TEMPLATE_IMAGE, [(10, 10, 2**31 - 1, 5)]) is not None
+        with pytest.raises(TypeError):
+            rustmatch.find_next(SOURCE_IMAGE, TEMPLATE_IMAGE, [(0, 0, 2**32, 5)])
+
+
+class TestMatchResultPickle:
+    """Tests for pickling and copying match results."""
+    
+    def test_constructor(self):
+        """Test results can be built directly."""
+        r = rustmatch.MatchResult(3, 4, 0.5)
+        
+        assert r.to_tuple() == (3, 4, 0.5)
+        assert r.crop is None
+    
+    def test_pickle_roundtrip(self):
+        """Test a found result survives pickling, including its crop."""
+        result = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, return_crop=True)
+        restored = pickle.loads(pickle.dumps(result))
+        
+        assert isinstance(restored, rustmatch.MatchResult)
+        assert restored.to_tuple() == result.to_tuple()
+        assert restored.crop == result.crop
+    
+    def test_copy(self):
+        """Test copy and deepcopy produce equal values."""
+        result = rustmatch.MatchResult(1, 2, 0.9, b"png")
+        
+        for clone in (copy.copy(result), copy.deepcopy(result)):
+            assert clone.to_tuple() == (1, 2, 0.9)
+            assert clone.crop == b"png"
+
+
+class TestMatchResultComparison:
+    """Tests for equality, hashing and ordering of match results."""
+    
+    def test_equality(self):
+        """Test results compare by position and confidence."""
+        a = rustmatch.MatchResult(3, 4, 0.5)
+        
+        assert a == rustmatch.MatchResult(3, 4, 0.5)
+        assert a == rustmatch.MatchResult(3, 4, 0.5, b"png")
+        assert a != rustmatch.MatchResult(3, 5, 0.5)
+        assert a != (3, 4, 0.5)
+    
+    def test_hash(self):
+        """Test equal results collapse in sets."""
+        found = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE)
+        again = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE)
+        
+        assert hash(found) == hash(again)
+        assert len({found, again, rustmatch.MatchResult(0, 0, 0.1)}) == 2
+    
+    def test_ordering(self):
+        """Test ordering compares confidence."""
+        low = rustmatch.MatchResult(0, 0, 0.2)
+        high = rustmatch.MatchResult(9, 9, 0.9)
+        
+        assert low < high and high > low
+        assert max([low, high]) is high
+        assert sorted([high, low]) == [low, high]
+    
+    def test_is_close(self):
+        """Test tolerant comparison."""
+        a = rustmatch.MatchResult(10, 10, 0.90)
+        
+        assert a.is_close(rustmatch.MatchResult(11, 9, 0.905))
+        assert not a.is_close(rustmatch.MatchResult(12, 10, 0.90))
+        assert not a.is_close(rustmatch.MatchResult(10, 10, 0.80))
+        assert a.is_close(rustmatch.MatchResult(12, 10, 0.80), pixel_tolerance=2, confidence_tolerance=0.1)
+
+
+class TestSerialization:
+    """Tests for dict and JSON serialization of results."""
+    
+    def test_to_dict(self):
+        """Test dict fields, with the crop only when set."""
+        assert rustmatch.MatchResult(1, 2, 0.5).to_dict() == {"x": 1, "y": 2, "confidence": 0.5}
+        assert rustmatch.MatchResult(1, 2, 0.5, b"png").to_dict()["crop"] == b"png"
+    
+    def test_to_json(self):
+        """Test JSON output round-trips through the json module."""
+        result = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, return_crop=True)
+        
+        assert json.loads(result.to_json()) == {"x": result.x, "y": result.y, "confidence": result.confidence}
+    
+    def test_non_finite_confidence(self):
+        """Test non-finite scores become null."""
+        assert json.loads(rustmatch.MatchResult(0, 0, float("nan")).to_json())["confidence"] is None
+    
+    def test_results_to_json(self):
+        """Test list serialization."""
+        results = rustmatch.find_all(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.8)
+        decoded = json.loads(rustmatch.results_to_json(results))
+        
+        assert [(d["x"], d["y"]) for d in decoded] == [(r.x, r.y) for r in results]
+        assert rustmatch.results_to_json([]) == "[]"
+    
+    def test_matches_to_svg(self):
+        """Test the SVG layer parses, with one box and label per match."""
+        import xml.etree.ElementTree as ET
+        results = rustmatch.find_all(SOURCE_IMAGE, TEMPLATE_IMAGE, max_count=5)
+        results.append(MatchResult(4, 2, 0.5, scale=2.0))
+        
+        root = ET.fromstring(rustmatch.matches_to_svg(results, (15, 16), (1602, 364)))
+        ns = {"svg": "http://www.w3.org/2000/svg"}
+        rects = root.findall(".//svg:rect", ns)
+        labels = root.findall(".//svg:text", ns)
+        
+        assert (root.get("width"), root.get("viewBox")) == ("1602", "0 0 1602 364")
+        assert [(int(r.get("x")), int(r.get("y"))) for r in rects] == [(m.x, m.y) for m in results]
+        assert float(rects[0].get("data-confidence")) == results[0].confidence
+        assert (rects[-1].get("width"), rects[-1].get("height")) == ("30", "32")
+        assert labels[-1].text == "0.500" and int(labels[-1].get("y")) > 2
+        assert not ET.fromstring(rustmatch.matches_to_svg([], (15, 16), (10, 10))).findall(".//svg:rect", ns)
+
+
+class TestPyramidFindAll:
+    """Tests for multi-match through the coarse pyramid (templates of 48px and up)."""
+    
+    WIDTH, HEIGHT = 320, 200
+    SPOTS = [(10, 12), (150, 30), (241, 120), (40, 131)]
+    
+    @classmethod
+    def scene(cls):
+        """Flat background with the same 64x64 textured patch pasted at every spot."""
+        template = blocky_pixels(64, 64)
+        pixels = [100] * (cls.WIDTH * cls.HEIGHT)
+        for sx, sy in cls.SPOTS:
+            for y in range(64):
+                pixels[(sy + y) * cls.WIDTH + sx:(sy + y) * cls.WIDTH + sx + 64] = template[y * 64:(y + 1) * 64]
+        return pixels, template
+    
+    @pytest.mark.parametrize("name", ["area", "gaussian"])
+    def test_finds_every_copy(self, name):
+        """Test each pasted copy is found exactly once at its full-resolution position."""
+        source, template = self.scene()
+        results = rustmatch.find_all_raw(
+            source, self.WIDTH, self.HEIGHT, template, 64, 64, threshold=0.9, pyramid_filter=name
+        )
+        
+        assert sorted((r.x, r.y) for r in results) == sorted(self.SPOTS)
+        assert all(r.confidence > 0.99 for r in results)
+    
+    def test_find_iter_matches_find_all(self):
+        """Test the lazy iterator shares the pyramid candidate search."""
+        source, template = self.scene()
+        png, tpl = make_png(self.WIDTH, self.HEIGHT, source), make_png(64, 64, template)
+        
+        assert [r.to_tuple() for r in rustmatch.find_iter(png, tpl, threshold=0.9)] == [
+            r.to_tuple() for r in rustmatch.find_all_bytes(png, tpl, threshold=0.9)
+        ]
+
+
+class TestCoarseCalibration:
+    """Tests for the coarse cutoff scaled to the template's own coarse scores."""
+    
+    WIDTH, HEIGHT = 320, 240
+    
+    @classmethod
+    def noise(cls):
+        """Deterministic white noise, whose coarse scores drop most off the coarse grid."""
+        seed, pixels = 11, []
+        for _ in range(cls.WIDTH * cls.HEIGHT):
+            seed = (seed * 6364136223846793005 + 1442695040888963407) % 2 ** 64
+            pixels.append((seed >> 33) % 256)
+        return pixels
+    
+    @pytest.mark.parametrize("size", [64, 128])
+    @pytest.mark.parametrize("position", [(22, 21), (150, 90)])
+    def test_fine_texture_off_grid(self, size, position):
+        """Test fine textures half a coarse cell off the grid pass at every pyramid scale."""
+        source = self.noise()
+        x0, y0 = position
+        template = [source[(y0 + y) * self.WIDTH + x0 + x] for y in range(size) for x in range(size)]
+        
+        result = rustmatch.find_raw(source, self.WIDTH, self.HEIGHT, template, size, size, threshold=0.95)
+        
+        assert result is not None
+        assert (result.x, result.y) == position
+        assert result.confidence > 0.99
+    
+    def test_find_all_off_grid(self):
+        """Test find_all keeps a fine-textured target between coarse cells."""
+        source = self.noise()
+        template = [source[(90 + y) * self.WIDTH + 150 + x] for y in range(64) for x in range(64)]
+        
+        results = rustmatch.find_all_raw(source, self.WIDTH, self.HEIGHT, template, 64, 64, threshold=0.9)
+        
+        assert [(r.x, r.y) for r in results] == [(150, 90)]
+    
+    def test_smooth_template_unchanged(self):
+        """Test smooth templates still match through the pyramid."""
+        w, h = 240, 160
+        pixels = blocky_pixels(w, h)
+        template = [pixels[(50 + y) * w + 102 + x] for y in range(64) for x in range(64)]
+        
+        result = rustmatch.find_raw(pixels, w, h, template, 64, 64, threshold=0.95)
+        
+        assert result is not None
+        assert (result.x, result.y) == (102, 50)
+
+
+class TestCoarseCandidates:
+    """Tests for refining several coarse pyramid hits."""
+    
+    WIDTH, HEIGHT = 256, 128
+    
+    @classmethod
+    def scene(cls):
+        """The target, plus a 4x4 block-averaged copy that wins at the coarse level only."""
+        template = blocky_pixels(64, 64, cell=5)
+        decoy = list(template)
+        for by in range(0, 64, 4):
+            for bx in range(0, 64, 4):
+                cell = [(by + y) * 64 + bx + x for y in range(4) for x in range(4)]
+                mean = sum(template[i] for i in cell) // 16
+                for i in cell:
+                    decoy[i] = mean
+        pixels = [100] * (cls.WIDTH * cls.HEIGHT)
+        for (sx, sy), patch in (((16, 16), decoy), ((150, 30), template)):
+            for y in range(64):
+                pixels[(sy + y) * cls.WIDTH + sx:(sy + y) * cls.WIDTH + sx + 64] = patch[y * 64:(y + 1) * 64]
+        return pixels, template
+    
+    def test_distractor_hides_target(self):
+        """Test the single coarse hit lands on the decoy."""
+        source, template = self.scene()
+        
+        assert rustmatch.find_raw(source, self.WIDTH, self.HEIGHT, template, 64, 64, threshold=0.9) is None
+    
+    def test_top_n_recovers_target(self):
+        """Test refining two coarse hits finds the target."""
+        source, template = self.scene()
+        result = rustmatch.find_raw(
+            source, self.WIDTH, self.HEIGHT, template, 64, 64, threshold=0.9, coarse_candidates=2
+        )
+        
+        assert result is not None
+        assert (result.x, result.y) == (150, 30)
+        assert result.confidence > 0.99
+    
+    def test_invalid(self):
+        """Test zero candidates are rejected."""
+        with pytest.raises(ValueError):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, coarse_candidates=0)
+
+
+class TestExact:
+    """Tests for the exhaustive search mode."""
+    
+    def test_same_best_on_fixture(self):
+        """Test exact search agrees with the pyramid where both find the target."""
+        fast = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE)
+        exact = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, exact=True)
+        
+        assert exact.to_tuple() == fast.to_tuple()
+    
+    def test_bypasses_coarse_distractor(self):
+        """Test exact search finds a target the single coarse hit misses."""
+        source, template = TestCoarseCandidates.scene()
+        result = rustmatch.find_raw(source, 256, 128, template, 64, 64, threshold=0.9, exact=True)
+        
+        assert result is not None
+        assert (result.x, result.y) == (150, 30)
+    
+    def test_find_all_matches_sweep(self):
+        """Test exact find_all reports every peak counted by score_sweep."""
+        (_, count), = rustmatch.score_sweep(SOURCE_IMAGE, TEMPLATE_IMAGE, thresholds=[0.9])
+        exact = rustmatch.find_all(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.9, max_count=1000, exact=True)
+        fast = rustmatch.find_all(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.9, max_count=1000)
+        
+        assert len(exact) == count >= len(fast)
+    
+    def test_robust(self):
+        """Test exact mode combines with block scoring."""
+        result = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, exact=True, robust=True, robust_grid=2)
+        
+        assert result is not None
+        assert result.confidence > 0.99
+
+
+class TestMode:
+    """Tests for the speed/accuracy presets."""
+    
+    @pytest.mark.parametrize("mode", ["fast", "balanced", "accurate"])
+    def test_modes_find_fixture(self, mode):
+        """Test every preset finds the fixture template."""
+        result = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, mode=mode)
+        
+        assert result is not None
+        assert result.confidence > 0.99
+    
+    def test_presets_differ(self):
+        """Test the coarse distractor is only overcome by the stronger presets."""
+        source, template = TestCoarseCandidates.scene()
+        found = {
+            mode: rustmatch.find_raw(source, 256, 128, template, 64, 64, threshold=0.9, mode=mode)
+            for mode in ("fast", "balanced", "accurate")
+        }
+        
+        assert found["fast"] is None
+        assert (found["balanced"].x, found["balanced"].y) == (150, 30)
+        assert (found["accurate"].x, found["accurate"].y) == (150, 30)
+    
+    def test_explicit_option_overrides(self):
+        """Test explicit options win over the preset."""
+        source, template = TestCoarseCandidates.scene()
+        
+        assert rustmatch.find_raw(
+            source, 256, 128, template, 64, 64, threshold=0.9, coarse_candidates=1, mode="balanced",
+            pyramid_filter="area",
+        ) is None
+    
+    def test_unknown_mode(self):
+        """Test unknown presets are rejected."""
+        with pytest.raises(ValueError):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, mode="turbo")
+
+
+class TestExhaustiveFallback:
+    """Tests for the exhaustive retry after a pyramid miss."""
+    
+    def test_recovers_coarse_miss(self):
+        """Test the fallback finds the noise target the bilinear pyramid misses."""
+        source = TestPyramidFilter.noise()
+        template = [source[(21 + y) * 160 + 22 + x] for y in range(64) for x in range(64)]
+        options = {"threshold": 0.9, "pyramid_filter": "bilinear"}
+        
+        assert rustmatch.find_raw(source, 160, 120, template, 64, 64, **options) is None
+        result = rustmatch.find_raw(source, 160, 120, template, 64, 64, exhaustive_fallback=True, **options)
+        assert result is not None
+        assert (result.x, result.y) == (22, 21)
+    
+    def test_absent_target_still_none(self):
+        """Test the fallback doesn't invent matches."""
+        source = TestPyramidFilter.noise()
+        template = blocky_pixels(64, 64)
+        
+        assert rustmatch.find_raw(source, 160, 120, template, 64, 64, threshold=0.9, exhaustive_fallback=True) is None
+    
+    def test_logs_fallback(self, caplog):
+        """Test the retry is reported at DEBUG level."""
+        source = TestPyramidFilter.noise()
+        template = [source[(21 + y) * 160 + 22 + x] for y in range(64) for x in range(64)]
+        caplog.set_level(logging.DEBUG, logger="rustmatch")
+        rustmatch.find_raw(
+            source, 160, 120, template, 64, 64, threshold=0.9, pyramid_filter="bilinear", exhaustive_fallback=True
+        )
+        
+        messages = [r.getMessage() for r in caplog.records if r.name == "rustmatch"]
+        assert any("falling back" in m for m in messages)
+
+
+class TestPartialBorder:
+    """Tests for matches that extend past the image border."""
+    
+    WIDTH, HEIGHT = 200, 150
+    
+    @classmethod
+    def scene(cls, sx, sy):
+        """Flat background with a 40x40 textured patch whose top-left is at (sx, sy), clipped."""
+        template = blocky_pixels(40, 40, cell=3)
+        pixels = [100] * (cls.WIDTH * cls.HEIGHT)
+        for y in range(40):
+            for x in range(40):
+                if 0 <= sx + x < cls.WIDTH and 0 <= sy + y < cls.HEIGHT:
+                    pixels[(sy + y) * cls.WIDTH + sx + x] = template[y * 40 + x]
+        return pixels, template
+    
+    @pytest.mark.parametrize("spot", [(-15, 20), (30, -12), (170, 125)])
+    def test_finds_cut_off_target(self, spot):
+        """Test targets cut off at any edge are found at their true position."""
+        source, template = self.scene(*spot)
+        
+        assert rustmatch.find_raw(source, self.WIDTH, self.HEIGHT, template, 40, 40, threshold=0.9) is None
+        result = rustmatch.find_raw(
+            source, self.WIDTH, self.HEIGHT, template, 40, 40, threshold=0.9, partial_border=True
+        )
+        assert (result.x, result.y) == spot
+        assert result.confidence > 0.99
+    
+    def test_find_all_includes_border(self):
+        """Test multi-match reports border matches next to regular ones."""
+        source, template = self.scene(-15, 20)
+        for y in range(40):
+            source[(60 + y) * self.WIDTH + 80:(60 + y) * self.WIDTH + 120] = template[y * 40:(y + 1) * 40]
+        results = rustmatch.find_all_raw(
+            source, self.WIDTH, self.HEIGHT, template, 40, 40, threshold=0.9, partial_border=True
+        )
+        
+        assert sorted((r.x, r.y) for r in results) == [(-15, 20), (80, 60)]
+    
+    def test_mostly_hidden_target_ignored(self):
+        """Test windows with less than half the template visible are not scored."""
+        source, template = self.scene(-25, 20)
+        
+        assert rustmatch.find_raw(
+            source, self.WIDTH, self.HEIGHT, template, 40, 40, threshold=0.9, partial_border=True
+        ) is None
+    
+    def test_crop_is_visible_part(self):
+        """Test return_crop yields the visible part of a cut-off match."""
+        source, template = self.scene(-15, 20)
+        png, tpl = make_png(self.WIDTH, self.HEIGHT, source), make_png(40, 40, template)
+        result = rustmatch.find_bytes(png, tpl, threshold=0.9, partial_border=True, return_crop=True)
+        
+        assert rustmatch.get_size_bytes(result.crop) == (25, 40)
+    
+    def test_unsupported_combinations(self):
+        """Test tiled search and block/weighted scoring are rejected."""
+        with pytest.raises(ValueError):
+            rustmatch.find_tiled(SOURCE_IMAGE, TEMPLATE_IMAGE, partial_border=True)
+        with pytest.raises(ValueError):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, partial_border=True, robust=True)
+
+
+class TestBorderMargin:
+    """Tests for dropping matches near the image edges."""
+    
+    WIDTH, HEIGHT = 200, 150
+    
+    @classmethod
+    def scene(cls):
+        """A 40x40 patch touching the left edge and a slightly noisy copy inside."""
+        template = blocky_pixels(40, 40, cell=3)
+        pixels = [100] * (cls.WIDTH * cls.HEIGHT)
+        for y in range(40):
+            pixels[(20 + y) * cls.WIDTH:(20 + y) * cls.WIDTH + 40] = template[y * 40:(y + 1) * 40]
+            pixels[(90 + y) * cls.WIDTH + 120:(90 + y) * cls.WIDTH + 160] = [
+                v + (9 if (x * 7 + y * 3) % 5 == 0 else 0) for x, v in enumerate(template[y * 40:(y + 1) * 40])
+            ]
+        return pixels, template
+    
+    def test_edge_match_dropped(self):
+        """Test the edge match loses to the inner one, also for a single result slot."""
+        source, template = self.scene()
+        args = (source, self.WIDTH, self.HEIGHT, template, 40, 40)
+        
+        assert (rustmatch.find_raw(*args, threshold=0.9).x, rustmatch.find_raw(*args, threshold=0.9).y) == (0, 20)
+        result = rustmatch.find_raw(*args, threshold=0.9, border_margin=1)
+        assert (result.x, result.y) == (120, 90)
+        results = rustmatch.find_all_raw(*args, threshold=0.9, max_count=1, border_margin=1)
+        assert [(r.x, r.y) for r in results] == [(120, 90)]
+        assert rustmatch.find_all_raw(*args, threshold=0.9, border_margin=40) == []
+        assert rustmatch.find_raw(*args, threshold=0.9, border_margin=2**63) is None
+    
+    def test_unsupported_combinations(self):
+        """Test tiled search and edge-dependent options are rejected."""
+        with pytest.raises(ValueError):
+            rustmatch.find_tiled(SOURCE_IMAGE, TEMPLATE_IMAGE, border_margin=2)
+        with pytest.raises(ValueError):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, border_margin=2, hint=(0, 0))
+
+
+class TestPath:
+    """Tests for searching along a polyline corridor."""
+    
+    WIDTH, HEIGHT = 200, 150
+    TRACK = [(10, 40), (170, 40), (170, 140)]
+    
+    @classmethod
+    def scene(cls):
+        """24x24 thumbs: a noisy copy centred on the top leg of TRACK, an exact copy
+        centred on its right leg and another off the track."""
+        template = blocky_pixels(24, 24, cell=3)
+        noisy = [v + (40 if (i * 7 + i // 24 * 3) % 5 == 0 else 0) for i, v in enumerate(template)]
+        pixels = [100] * (cls.WIDTH * cls.HEIGHT)
+        for (sx, sy), patch in (((48, 28), noisy), ((158, 98), template), ((48, 98), template)):
+            for y in range(24):
+                pixels[(sy + y) * cls.WIDTH + sx:(sy + y) * cls.WIDTH + sx + 24] = patch[y * 24:(y + 1) * 24]
+        return (pixels, cls.WIDTH, cls.HEIGHT, template, 24, 24)
+    
+    def test_only_corridor_searched(self):
+        """Test the exact copies off the corridor lose to the noisy one on it."""
+        args = self.scene()
+        
+        assert rustmatch.find_raw(*args, threshold=0.9).confidence > 0.99
+        result = rustmatch.find_raw(*args, threshold=0.9, path=self.TRACK[:2])
+        assert (result.x, result.y) == (48, 28)
+        assert result.confidence < 0.99
+    
+    def test_find_all_along_polyline(self):
+        """Test find_all reports the matches on both legs and none off the track."""
+        args = self.scene()
+        results = rustmatch.find_all_raw(*args, threshold=0.9, path=self.TRACK)
+        
+        assert sorted((r.x, r.y) for r in results) == [(48, 28), (158, 98)]
+    
+    def test_tolerance(self):
+        """Test centres farther from the path than path_tolerance are not searched."""
+        args = self.scene()
+        track = [(10, 45), (120, 45)]
+        
+        assert rustmatch.find_raw(*args, threshold=0.9, path=track) is None
+        result = rustmatch.find_raw(*args, threshold=0.9, path=track, path_tolerance=5)
+        assert (result.x, result.y) == (48, 28)
+    
+    def test_invalid(self):
+        """Test malformed paths and unsupported combinations are rejected."""
+        for options in (
+            {"path": []}, {"path": [(0, 0)], "path_tolerance": -1}, {"path": [(0, 0)], "robust": True},
+            {"path": [(0, 0)], "hint": (0, 0)},
+        ):
+            with pytest.raises(ValueError):
+                rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, **options)
+        with pytest.raises(ValueError):
+            rustmatch.find_tiled(SOURCE_IMAGE, TEMPLATE_IMAGE, path=[(0, 0)])
+
+
+class TestWrap:
+    """Tests for wrap-around matching on tileable sources."""
+    
+    WIDTH, HEIGHT = 200, 150
+    
+    @classmethod
+    def wrapped_template(cls, source, sx, sy, size):
+        """Template cut from the infinitely tiled source at (sx, sy)."""
+        return [
+            source[((sy + y) % cls.HEIGHT) * cls.WIDTH + (sx + x) % cls.WIDTH]
+            for y in range(size) for x in range(size)
+        ]
+    
+    @pytest.mark.parametrize("spot,size", [((186, 140), 40), ((190, 20), 64), ((10, 120), 64)])
+    def test_finds_match_across_seam(self, spot, size):
+        """Test matches crossing either seam are found at their source position."""
+        source = blocky_pixels(self.WIDTH, self.HEIGHT, cell=4)
+        template = self.wrapped_template(source, *spot, size)
+        
+        result = rustmatch.find_raw(source, self.WIDTH, self.HEIGHT, template, size, size, threshold=0.9, wrap=True)
+        results = rustmatch.find_all_raw(
+            source, self.WIDTH, self.HEIGHT, template, size, size, threshold=0.9, wrap=True
+        )
+        
+        assert (result.x, result.y) == spot
+        assert [(r.x, r.y) for r in results] == [spot]
+    
+    def test_crop_stitched(self):
+        """Test the crop of a seam-crossing match is stitched from both sides."""
+        source = blocky_pixels(self.WIDTH, self.HEIGHT, cell=4)
+        template = self.wrapped_template(source, 186, 140, 40)
+        png, tpl = make_png(self.WIDTH, self.HEIGHT, source), make_png(40, 40, template)
+        result = rustmatch.find_bytes(png, tpl, threshold=0.9, wrap=True, return_crop=True)
+        
+        pixels, width, height = rustmatch.to_grayscale(result.crop)
+        assert (width, height) == (40, 40)
+        assert list(pixels) == template
+    
+    def test_unsupported_combinations(self):
+        """Test combinations that need real image edges are rejected."""
+        with pytest.raises(ValueError):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, wrap=True, partial_border=True)
+        with pytest.raises(ValueError):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, wrap=True, autocrop=True)
+        with pytest.raises(ValueError):
+            rustmatch.find_all_tiled(SOURCE_IMAGE, TEMPLATE_IMAGE, wrap=True)
+
+
+class TestBinary:
+    """Tests for IoU scoring of binary masks."""
+    
+    WIDTH, HEIGHT = 100, 80
+    
+    @classmethod
+    def masks(cls, value=255):
+        """A 20x20 square and a 20x10 bar on black, plus a 30x30 template holding the square."""
+        source = [0] * (cls.WIDTH * cls.HEIGHT)
+        for y, x in [(y, x) for y in range(30, 50) for x in range(40, 60)] + [(y, x) for y in range(5, 15) for x in range(5, 25)]:
+            source[y * cls.WIDTH + x] = value
+        template = [255 if 5 <= x < 25 and 5 <= y < 25 else 0 for y in range(30) for x in range(30)]
+        return source, template
+    
+    def test_iou_scores(self):
+        """Test the exact shape scores 1 and the half-covered bar scores its IoU of 0.5."""
+        source, template = self.masks()
+        results = rustmatch.find_all_raw(source, self.WIDTH, self.HEIGHT, template, 30, 30, threshold=0.45, binary=True)
+        
+        assert [(r.x, r.y) for r in results][0] == (35, 25)
+        assert [r.confidence for r in results] == pytest.approx([1.0, 0.5])
+    
+    def test_binary_threshold(self):
+        """Test the foreground level applies to source and template alike."""
+        source, template = self.masks(value=100)
+        
+        assert rustmatch.find_raw(source, self.WIDTH, self.HEIGHT, template, 30, 30, binary=True) is None
+        result = rustmatch.find_raw(
+            source, self.WIDTH, self.HEIGHT, template, 30, 30, binary=True, binary_threshold=50
+        )
+        assert result.to_tuple() == (35, 25, 1.0)
+    
+    def test_unsupported_combinations(self):
+        """Test scorings that can't apply to masks are rejected."""
+        with pytest.raises(ValueError):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, binary=True, robust=True)
+
+
+class TestTemplateBank:
+    """Tests for precomputed scale/rotation template banks."""
+    
+    WIDTH, HEIGHT = 200, 150
+    
+    @staticmethod
+    def template():
+        """40x30 template with no rotational symmetry."""
+        return [240 if (x // 5 + y // 8) % 3 == 0 else 10 + 3 * x for y in range(30) for x in range(40)]
+    
+    @classmethod
+    def rotated_scene(cls):
+        """Source holding the template rotated 90 degrees clockwise at (100, 40)."""
+        template = cls.template()
+        source = blocky_pixels(cls.WIDTH, cls.HEIGHT, cell=9)
+        for y in range(40):
+            for x in range(30):
+                source[(40 + y) * cls.WIDTH + 100 + x] = template[(29 - x) * 40 + y]
+        return make_png(cls.WIDTH, cls.HEIGHT, source), make_png(40, 30, template)
+    
+    def test_variants(self):
+        """Test every scale/angle combination is prepared with its size."""
+        _, template = self.rotated_scene()
+        
+        bank = rustmatch.TemplateBank(template, scales=[1.0, 2.0], angles=[0, 90])
+        
+        assert len(bank) == 4
+        assert sorted(bank.variants) == [(1.0, 0.0, 40, 30), (1.0, 90.0, 30, 40), (2.0, 0.0, 80, 60), (2.0, 90.0, 60, 80)]
+    
+    def test_rotated_variant(self):
+        """Test the rotated target is found and tagged with its angle."""
+        source, template = self.rotated_scene()
+        bank = rustmatch.TemplateBank(template, angles=[0, 45, 90])
+        
+        assert rustmatch.find_bytes(source, template, threshold=0.9) is None
+        result = rustmatch.find_bytes(source, bank, threshold=0.9)
+        
+        assert (result.x, result.y, result.angle, result.scale) == (100, 40, 90.0, 1.0)
+        assert result.confidence > 0.99
+    
+    def test_scaled_variant(self):
+        """Test the enlarged target is found by the 2x variant."""
+        source, template = TestScaleEstimation.scene()
+        bank = rustmatch.TemplateBank(template, scales=[1.0, 2.0])
+        
+        results = rustmatch.find_all_bytes(source, bank, threshold=0.9)
+        
+        assert [(r.x, r.y, r.scale) for r in results] == [(100, 60, 2.0)]
+    
+    def test_serialization(self, tmp_path):
+        """Test bytes, file and pickle round trips keep the variants."""
+        source, template = self.rotated_scene()
+        bank = rustmatch.TemplateBank(template, angles=[0, 90])
+        path = str(tmp_path / "template.bank")
+        bank.save(path)
+        
+        for copy_ in (rustmatch.TemplateBank.from_bytes(bank.to_bytes()), rustmatch.TemplateBank.load(path), pickle.loads(pickle.dumps(bank))):
+            assert copy_.variants == bank.variants
+            assert copy_.find(source, threshold=0.9) == bank.find(source, threshold=0.9)
+    
+    def test_corrupt_data(self):
+        """Test truncated or foreign data raises DecodeError."""
+        _, template = self.rotated_scene()
+        data = rustmatch.TemplateBank(template).to_bytes()
+        
+        with pytest.raises(rustmatch.DecodeError):
+            rustmatch.TemplateBank.from_bytes(data[:-1])
+        with pytest.raises(rustmatch.DecodeError):
+            rustmatch.TemplateBank.from_bytes(b"not a bank")
+    
+    def test_invalid_arguments(self):
+        """Test scale/angle validation and rejected matching options."""
+        _, template = self.rotated_scene()
+        with pytest.raises(ValueError):
+            rustmatch.TemplateBank(template, scales=[0.0])
+        with pytest.raises(ValueError):
+            rustmatch.TemplateBank(template, angles=[])
+        with pytest.raises(TypeError):
+            rustmatch.find(SOURCE_IMAGE, rustmatch.TemplateBank(TEMPLATE_IMAGE), exact=True)
+    
+    def test_result_fields(self):
+        """Test scale and angle appear in the serialized result only when set."""
+        plain = MatchResult(1, 2, 0.9)
+        tagged = MatchResult(1, 2, 0.9, scale=2.0, angle=90.0)
+        
+        assert plain.scale is None and "scale" not in plain.to_dict()
+        assert json.loads(tagged.to_json()) == {"x": 1, "y": 2, "confidence": 0.9, "scale": 2.0, "angle": 90.0}
+        assert pickle.loads(pickle.dumps(tagged)).angle == 90.0
+
+
+class TestImageCache:
+    """Tests for the decoded image cache."""
+    
+    @pytest.fixture(autouse=True)
+    def fresh_cache(self):
+        rustmatch.clear_image_cache()
+        yield
+        rustmatch.set_image_cache_size(8)
+        rustmatch.clear_image_cache()
+    
+    def test_repeated_calls_hit(self):
+        """Test a second search on the same files skips decoding both."""
+        first = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE)
+        assert rustmatch.image_cache_info() == {"hits": 0, "misses": 2, "size": 2, "capacity": 8}
+        
+        assert rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE) == first
+        assert rustmatch.image_cache_info()["hits"] == 2
+    
+    def test_rewritten_file(self, tmp_path):
+        """Test a file changed on disk is decoded again."""
+        template = blocky_pixels(20, 20, cell=4)
+        template_path = tmp_path / "template.png"
+        template_path.write_bytes(make_png(20, 20, template))
+        source_path = tmp_path / "source.png"
+        for x0 in (10, 50):
+            source = [0] * (100 * 60)
+            for y in range(20):
+                source[(20 + y) * 100 + x0:(20 + y) * 100 + x0 + 20] = template[y * 20:(y + 1) * 20]
+            source_path.write_bytes(make_png(100, 60, source))
+            os.utime(source_path, (1_000_000 + x0, 1_000_000 + x0))
+            
+            assert rustmatch.find(str(source_path), str(template_path)).x == x0
+    
+    def test_lru_eviction(self, tmp_path):
+        """Test the capacity bounds the cache and 0 disables it."""
+        rustmatch.set_image_cache_size(1)
+        rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE)
+        assert rustmatch.image_cache_info()["size"] == 1
+        
+        rustmatch.set_image_cache_size(0)
+        rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE)
+        assert rustmatch.image_cache_info()["size"] == 0
+        assert rustmatch.image_cache_info()["hits"] == 0
+
+
+class TestStreamedDecoding:
+    """Tests for the incremental PNG/TIFF decoding of file paths."""
+    
+    WIDTH, HEIGHT = 150, 100
+    
+    @classmethod
+    def pixels(cls):
+        return blocky_pixels(cls.WIDTH, cls.HEIGHT, cell=7)
+    
+    @pytest.mark.parametrize("layout", [{"tile": 32}, {"rows_per_strip": 7}, {}])
+    def test_tiff_layouts(self, tmp_path, layout):
+        """Test tiled and stripped TIFFs decode to the exact pixels and can be searched."""
+        pixels = self.pixels()
+        path = tmp_path / "scan.tiff"
+        path.write_bytes(make_tiff(self.WIDTH, self.HEIGHT, pixels, **layout))
+        template = [pixels[(40 + y) * self.WIDTH + 60 + x] for y in range(24) for x in range(24)]
+        template_path = tmp_path / "template.png"
+        template_path.write_bytes(make_png(24, 24, template))
+        
+        assert rustmatch.to_grayscale(str(path)) == (bytes(pixels), self.WIDTH, self.HEIGHT)
+        assert rustmatch.find(str(path), str(template_path)).to_tuple()[:2] == (60, 40)
+        assert rustmatch.find_tiled(str(path), str(template_path), tile_size=64).to_tuple()[:2] == (60, 40)
+    
+    @pytest.mark.parametrize("layout", [{"tile": 32}, {"rows_per_strip": 7}])
+    def test_tiled_crops_across_bands(self, tmp_path, layout):
+        """Test tiled search reads the rows in bands and crops the exact pixels."""
+        pixels = self.pixels()
+        path = tmp_path / "scan.tiff"
+        path.write_bytes(make_tiff(self.WIDTH, self.HEIGHT, pixels, **layout))
+        template = [pixels[(70 + y) * self.WIDTH + 60 + x] for y in range(24) for x in range(24)]
+        template_path = tmp_path / "template.png"
+        template_path.write_bytes(make_png(24, 24, template))
+        
+        result = rustmatch.find_tiled(str(path), str(template_path), tile_size=30, return_crop=True)
+        results = rustmatch.find_all_tiled(str(path), str(template_path), tile_size=30, return_crop=True)
+        
+        assert result.to_tuple()[:2] == (60, 70)
+        assert rustmatch.to_grayscale(result.crop) == (bytes(template), 24, 24)
+        assert (results[0].x, results[0].y, results[0].crop) == (60, 70, result.crop)
+    
+    def test_png_matches_full_decode(self):
+        """Test the streamed PNG path gives the same pixels as decoding the bytes."""
+        with open(SOURCE_IMAGE, "rb") as f:
+            data = f.read()
+        
+        assert rustmatch.to_grayscale(SOURCE_IMAGE) == rustmatch.to_grayscale(data)
+    
+    def test_truncated_tiff(self, tmp_path):
+        """Test a truncated TIFF falls back to the full decoder and fails there."""
+        path = tmp_path / "broken.tiff"
+        path.write_bytes(make_tiff(self.WIDTH, self.HEIGHT, self.pixels())[:200])
+        
+        with pytest.raises((OSError, rustmatch.DecodeError)):
+            rustmatch.to_grayscale(str(path))
+
+
+class TestFindPages:
+    """Tests for multi-page TIFF matching."""
+    
+    WIDTH, HEIGHT = 150, 100
+    
+    def test_pages(self, tmp_path):
+        """Test the target is reported on its page and thumbnail pages are skipped."""
+        target_page = blocky_pixels(self.WIDTH, self.HEIGHT, cell=7)
+        template = [target_page[(40 + y) * self.WIDTH + 60 + x] for y in range(24) for x in range(24)]
+        blank = [128] * (self.WIDTH * self.HEIGHT)
+        path = tmp_path / "scan.tiff"
+        path.write_bytes(make_tiff(
+            self.WIDTH, self.HEIGHT, blank, tile=32,
+            extra_pages=[(16, 16, [0] * 256), (self.WIDTH, self.HEIGHT, target_page)],
+        ))
+        
+        results = rustmatch.find_pages(str(path), make_png(24, 24, template))
+        
+        assert [(page, r.x, r.y) for page, r in results] == [(2, 60, 40)]
+        assert results[0][1].confidence > 0.99
+    
+    def test_single_page_formats(self):
+        """Test non-TIFF files are searched as page 0."""
+        results = rustmatch.find_pages(SOURCE_IMAGE, TEMPLATE_IMAGE)
+        
+        assert [(page, r.x, r.y) for page, r in results] == [(0, 847, 297)]
+
+
+class TestFindInAnimation:
+    """Tests for animated image matching."""
+    
+    WIDTH, HEIGHT = 120, 80
+    
+    def test_gif_frames(self):
+        """Test only the frame showing the target is reported, with its delay."""
+        scene = blocky_pixels(self.WIDTH, self.HEIGHT, cell=7)
+        template = [scene[(30 + y) * self.WIDTH + 50 + x] for y in range(24) for x in range(24)]
+        blank = [128] * (self.WIDTH * self.HEIGHT)
+        gif = make_gif(self.WIDTH, self.HEIGHT, [blank, scene, blank], delay_cs=12)
+        
+        results = rustmatch.find_in_animation(gif, make_png(24, 24, template))
+        
+        assert [(index, delay, r.x, r.y) for index, delay, r in results] == [(1, 120, 50, 30)]
+    
+    def test_still_image(self):
+        """Test a still image is a single frame with delay 0."""
+        results = rustmatch.find_in_animation(SOURCE_IMAGE, TEMPLATE_IMAGE)
+        
+        assert [(index, delay, r.x, r.y) for index, delay, r in results] == [(0, 0, 847, 297)]
+    
+    def test_corrupt_animation(self):
+        """Test a truncated GIF raises DecodeError."""
+        gif = make_gif(self.WIDTH, self.HEIGHT, [[0] * (self.WIDTH * self.HEIGHT)] * 2)
+        
+        with pytest.raises(rustmatch.DecodeError):
+            rustmatch.find_in_animation(gif[:400], TEMPLATE_IMAGE)
+
+
+class TestIsoImageFormats:
+    """Tests for AVIF/HEIF detection."""
+    
+    @pytest.mark.parametrize("brand", [b"avif", b"heic", b"mif1"])
+    def test_undecodable_container(self, tmp_path, brand):
+        """Test AVIF/HEIF data raises DecodeError (unsupported build or invalid body)."""
+        data = struct.pack(">I", 24) + b"ftyp" + brand + b"\x00" * 12
+        path = tmp_path / "photo.heic"
+        path.write_bytes(data)
+        
+        with pytest.raises(rustmatch.DecodeError):
+            rustmatch.get_size_bytes(data)
+        with pytest.raises(rustmatch.DecodeError):
+            rustmatch.find(str(path), TEMPLATE_IMAGE)
+
+
+class TestFindYuv:
+    """Tests for planar YUV frame input."""
+    
+    WIDTH, HEIGHT, STRIDE = 120, 80, 128
+    
+    @classmethod
+    def nv12(cls):
+        """NV12 frame with padded rows: limited-range luma, then an interleaved chroma plane."""
+        luma = [16 + v * 219 // 255 for v in blocky_pixels(cls.WIDTH, cls.HEIGHT, cell=7)]
+        rows = b"".join(bytes(luma[y * cls.WIDTH:(y + 1) * cls.WIDTH]) + b"\xff" * (cls.STRIDE - cls.WIDTH) for y in range(cls.HEIGHT))
+        chroma = bytes([128, 200]) * (cls.STRIDE * cls.HEIGHT // 4)
+        template = [blocky_pixels(cls.WIDTH, cls.HEIGHT, cell=7)[(30 + y) * cls.WIDTH + 50 + x] for y in range(24) for x in range(24)]
+        return rows + chroma, make_png(24, 24, template)
+    
+    def test_find_with_stride(self):
+        """Test the target is found through the row padding and chroma plane."""
+        frame, template = self.nv12()
+        
+        result = rustmatch.find_yuv(frame, self.WIDTH, self.HEIGHT, template, stride=self.STRIDE)
+        
+        assert (result.x, result.y) == (50, 30)
+        assert result.confidence > 0.99
+        assert [(r.x, r.y) for r in rustmatch.find_all_yuv(frame, self.WIDTH, self.HEIGHT, template, threshold=0.95, stride=self.STRIDE)] == [(50, 30)]
+    
+    def test_buffer_too_small(self):
+        """Test frames shorter than the Y plane are rejected."""
+        frame, template = self.nv12()
+        
+        with pytest.raises(rustmatch.InvalidDimensionsError):
+            rustmatch.find_yuv(frame[:self.STRIDE * 10], self.WIDTH, self.HEIGHT, template, stride=self.STRIDE)
+        with pytest.raises(rustmatch.InvalidDimensionsError):
+            rustmatch.find_yuv(frame, self.WIDTH, self.HEIGHT, template, stride=self.WIDTH - 1)
+
+
+class TestPixelRatio:
+    """Tests for HiDPI logical coordinates."""
+    
+    def test_logical_coordinates(self):
+        """Test physical coordinates are unchanged and logical ones are divided by the ratio."""
+        plain = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE)
+        result = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, pixel_ratio=2.0)
+        
+        assert result == plain
+        assert (result.pixel_ratio, result.logical_x, result.logical_y) == (2.0, 423.5, 148.5)
+        assert result.logical_bbox(15, 16) == (423.5, 148.5, 7.5, 8.0)
+        assert (plain.logical_x, plain.logical_y) == (847.0, 297.0)
+    
+    def test_serialization(self):
+        """Test the ratio survives pickle and appears in dict/JSON only when not 1."""
+        result = MatchResult(10, 20, 0.9, pixel_ratio=2.0)
+        
+        assert pickle.loads(pickle.dumps(result)).pixel_ratio == 2.0
+        assert result.to_dict()["logical_x"] == 5.0
+        assert json.loads(result.to_json())["logical_y"] == 10.0
+        assert "pixel_ratio" not in MatchResult(10, 20, 0.9).to_dict()
+    
+    def test_invalid_ratio(self):
+        """Test non-positive ratios are rejected."""
+        with pytest.raises(ValueError):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, pixel_ratio=0)
+
+
+class TestGradientMatching:
+    """Tests for gradient-magnitude (edge) matching."""
+    
+    WIDTH, HEIGHT = 120, 90
+    
+    @classmethod
+    def scene(cls):
+        """Blocky source; template cut at (40, 30) with a horizontal shading ramp added."""
+        source = blocky_pixels(cls.WIDTH, cls.HEIGHT)
+        template = [
+            min(255, source[(30 + y) * cls.WIDTH + 40 + x] // 2 + 5 * x) for y in range(24) for x in range(24)
+        ]
+        return source, template
+    
+    def test_gradient_finds_reshaded_template(self):
+        """Test edge matching is found where intensity matching is thrown off by shading."""
+        source, template = self.scene()
+        
+        assert rustmatch.find_raw(source, self.WIDTH, self.HEIGHT, template, 24, 24, threshold=0.7) is None
+        result = rustmatch.find_raw(source, self.WIDTH, self.HEIGHT, template, 24, 24, threshold=0.7, gradient=True)
+        
+        assert result is not None
+        assert (result.x, result.y) == (40, 30)
+    
+    def test_inverted_colors(self):
+        """Test gradient magnitude ignores the polarity of edges."""
+        source = blocky_pixels(self.WIDTH, self.HEIGHT)
+        template = [255 - source[(30 + y) * self.WIDTH + 40 + x] for y in range(24) for x in range(24)]
+        
+        result = rustmatch.find_raw(source, self.WIDTH, self.HEIGHT, template, 24, 24, threshold=0.9, gradient=True)
+        
+        assert result is not None
+        assert (result.x, result.y) == (40, 30)
+    
+    def test_gradient_tiled(self):
+        """Test tiled and full search agree on edge maps."""
+        result = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.5, gradient=True)
+        tiled = rustmatch.find_tiled(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.5, tile_size=128, gradient=True)
+        
+        assert result is not None and tiled is not None
+        assert abs(tiled.confidence - result.confidence) < 0.01
+
+
+class TestCensusMatching:
+    """Tests for census-transform matching."""
+    
+    WIDTH, HEIGHT = 120, 90
+    
+    @classmethod
+    def scene(cls):
+        """Per-pixel noise texture; template cut at (40, 30) with its halves lit differently."""
+        source = [(x * 7919 + y * 104729 + x * y * 31) % 251 for y in range(cls.HEIGHT) for x in range(cls.WIDTH)]
+        template = [
+            source[(30 + y) * cls.WIDTH + 40 + x] // 3 if x < 12 else source[(30 + y) * cls.WIDTH + 40 + x] // 4 + 150
+            for y in range(24) for x in range(24)
+        ]
+        return source, template
+    
+    def test_local_lighting_change(self):
+        """Test census matching survives a lighting change that varies across the template."""
+        source, template = self.scene()
+        
+        assert rustmatch.find_raw(source, self.WIDTH, self.HEIGHT, template, 24, 24, threshold=0.8) is None
+        result = rustmatch.find_raw(source, self.WIDTH, self.HEIGHT, template, 24, 24, threshold=0.8, census=True)
+        
+        assert result is not None
+        assert (result.x, result.y) == (40, 30)
+        assert result.confidence > 0.95
+    
+    def test_find_all(self):
+        """Test the texture has a single census match above the threshold."""
+        source, template = self.scene()
+        
+        results = rustmatch.find_all_raw(
+            source, self.WIDTH, self.HEIGHT, template, 24, 24, threshold=0.8, census=True
+        )
+        
+        assert [(r.x, r.y) for r in results] == [(40, 30)]
+    
+    def test_invalid_combination(self):
+        """Test census can't be combined with other scoring modes."""
+        with pytest.raises(ValueError):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, census=True, binary=True)
+
+
+class TestMutualInformation:
+    """Tests for mutual-information scoring."""
+    
+    @staticmethod
+    def remap(value):
+        """Nonlinear, non-monotonic grey-level mapping standing in for another sensor."""
+        return (value // 16 * 7 % 16) * 16 + 8
+    
+    def scene(self, width, height, x, y, size, cell):
+        source = blocky_pixels(width, height, cell)
+        template = [self.remap(source[(y + ty) * width + x + tx]) for ty in range(size) for tx in range(size)]
+        return source, template
+    
+    def test_nonlinear_mapping(self):
+        """Test a remapped template is found where NCC can't find it."""
+        source, template = self.scene(120, 90, 40, 30, 24, 6)
+        
+        assert rustmatch.find_raw(source, 120, 90, template, 24, 24, threshold=0.8) is None
+        result = rustmatch.find_raw(source, 120, 90, template, 24, 24, threshold=0.8, mutual_information=True)
+        
+        assert result is not None
+        assert (result.x, result.y) == (40, 30)
+        assert result.confidence == pytest.approx(1.0)
+        results = rustmatch.find_all_raw(source, 120, 90, template, 24, 24, threshold=0.95, mutual_information=True)
+        assert [(r.x, r.y) for r in results] == [(40, 30)]
+    
+    def test_coarse_level(self):
+        """Test the pyramid prunes candidates for a template large enough to downsample."""
+        source, template = self.scene(320, 240, 100, 70, 64, 16)
+        
+        result = rustmatch.find_raw(source, 320, 240, template, 64, 64, threshold=0.9, mutual_information=True)
+        
+        assert result is not None
+        assert (result.x, result.y) == (100, 70)
+    
+    def test_invalid_combination(self):
+        """Test mutual information can't be combined with other scoring modes."""
+        with pytest.raises(ValueError):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, mutual_information=True, robust=True)
+
+
+class TestFindRotated:
+    """Tests for rotation search with a ring projection prescreen."""
+    
+    @staticmethod
+    def scene():
+        """Blocky 200x150 source with a 32x32 template pasted at (120, 60) rotated 90 degrees clockwise."""
+        source = blocky_pixels(200, 150, 5)
+        template = blocky_pixels(32, 32, 4)
+        for y in range(32):
+            for x in range(32):
+                source[(60 + y) * 200 + 120 + x] = template[(31 - x) * 32 + y]
+        return make_png(200, 150, source), make_png(32, 32, template)
+    
+    def test_finds_rotation(self):
+        """Test the rotated copy is found with its angle."""
+        source, template = self.scene()
+        
+        result = rustmatch.find_rotated(source, template, threshold=0.9)
+        
+        assert result is not None
+        assert (result.x, result.y, result.angle) == (120, 60, 90.0)
+        assert result.confidence == pytest.approx(1.0)
+    
+    def test_angles_exclude_rotation(self):
+        """Test nothing is found when the true angle isn't tried."""
+        source, template = self.scene()
+        
+        assert rustmatch.find_rotated(source, template, threshold=0.9, angles=[0, 180]) is None
+    
+    def test_invalid_arguments(self):
+        """Test empty angle lists, zero candidates and tiny templates are rejected."""
+        source, template = self.scene()
+        
+        with pytest.raises(ValueError):
+            rustmatch.find_rotated(source, template, angles=[])
+        with pytest.raises(ValueError):
+            rustmatch.find_rotated(source, template, candidates=0)
+        with pytest.raises(rustmatch.InvalidDimensionsError):
+            rustmatch.find_rotated(source, make_png(8, 8, [0, 255] * 32))
+
+
+class TestSampledNcc:
+    """Tests for sampled (approximate) NCC scanning."""
+    
+    WIDTH, HEIGHT = 300, 220
+    
+    @classmethod
+    def scene(cls):
+        """Blocky source and a 96x96 template cut at (131, 57)."""
+        source = blocky_pixels(cls.WIDTH, cls.HEIGHT, 7)
+        template = [source[(57 + y) * cls.WIDTH + 131 + x] for y in range(96) for x in range(96)]
+        return source, template
+    
+    def test_matches_exact_search(self):
+        """Test sampled scanning reports the exact position and exact confidence."""
+        source, template = self.scene()
+        
+        plain = rustmatch.find_raw(source, self.WIDTH, self.HEIGHT, template, 96, 96)
+        for fraction in (0.05, 0.2, 1.0):
+            result = rustmatch.find_raw(source, self.WIDTH, self.HEIGHT, template, 96, 96, sample_fraction=fraction)
+            assert result == plain
+            assert (result.x, result.y) == (131, 57)
+            assert result.confidence == pytest.approx(plain.confidence)
+    
+    def test_find_all(self):
+        """Test sampled find_all confirms candidates with exact scores."""
+        source, template = self.scene()
+        
+        results = rustmatch.find_all_raw(
+            source, self.WIDTH, self.HEIGHT, template, 96, 96, threshold=0.9, sample_fraction=0.2
+        )
+        
+        assert [(r.x, r.y) for r in results] == [(131, 57)]
+        assert results[0].confidence == pytest.approx(1.0)
+    
+    def test_invalid_fraction(self):
+        """Test fractions outside (0, 1] and non-NCC modes are rejected."""
+        with pytest.raises(ValueError):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, sample_fraction=0)
+        with pytest.raises(ValueError):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, sample_fraction=1.5)
+        with pytest.raises(ValueError):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, sample_fraction=0.5, robust=True)
+
+
+class TestFindClusters:
+    """Tests for clustering nearby detections."""
+    
+    def test_best_members_match_find_all(self):
+        """Test one cluster per find_all result, with consistent statistics."""
+        clusters = rustmatch.find_clusters(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.5, max_count=100)
+        results = rustmatch.find_all(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.5, max_count=100)
+        
+        assert {c.best.to_tuple() for c in clusters} == {r.to_tuple() for r in results}
+        assert [c.confidence for c in clusters] == sorted((c.confidence for c in clusters), reverse=True)
+        assert any(c.count > 1 for c in clusters)
+        for c in clusters:
+            assert c.confidence == c.best.confidence >= c.mean_confidence
+            assert c.score_std >= 0
+            assert abs(c.x - c.best.x) < 8 and abs(c.y - c.best.y) < 8
+    
+    def test_single_detection(self):
+        """Test an isolated match forms a cluster of one at its own position."""
+        source = blocky_pixels(120, 90)
+        template = [source[(30 + y) * 120 + 40 + x] for y in range(24) for x in range(24)]
+        
+        clusters = rustmatch.find_clusters(make_png(120, 90, source), make_png(24, 24, template), threshold=0.95)
+        
+        assert len(clusters) == 1
+        c = clusters[0]
+        assert (c.x, c.y, c.count, c.score_std) == (40.0, 30.0, 1, 0.0)
+        assert c.to_dict()["best"]["x"] == 40
+
+
+class TestFindRepeats:
+    """Tests for self-matching repeated regions."""
+    
+    WIDTH, HEIGHT = 160, 120
+    
+    @staticmethod
+    def noise(seed, count):
+        values = []
+        for _ in range(count):
+            seed = (seed * 1103515245 + 12345) % 2 ** 31
+            values.append(seed >> 16 & 255)
+        return values
+    
+    def source_with_copies(self, corners):
+        source = self.noise(1, self.WIDTH * self.HEIGHT)
+        block = self.noise(2, 24 * 24)
+        for bx, by in corners:
+            for y in range(24):
+                source[(by + y) * self.WIDTH + bx:(by + y) * self.WIDTH + bx + 24] = block[y * 24:(y + 1) * 24]
+        return make_png(self.WIDTH, self.HEIGHT, source)
+    
+    def test_pasted_copies_form_one_group(self):
+        """Test three copies of a block are reported once, at the same offset in each copy."""
+        corners = [(10, 10), (120, 30), (60, 90)]
+        groups = rustmatch.find_repeats(self.source_with_copies(corners), patch_size=16, stride=8)
+        
+        assert len(groups) == 1
+        group = groups[0]
+        assert len(group) == 3
+        dx, dy = group[0].x - corners[0][0], group[0].y - corners[0][1]
+        assert sorted((r.x - dx, r.y - dy) for r in group) == sorted(corners)
+        assert all(r.confidence > 0.99 for r in group)
+    
+    def test_no_repeats(self):
+        """Test noise without copies and flat images give no groups."""
+        assert rustmatch.find_repeats(self.source_with_copies([]), patch_size=16) == []
+        flat = make_png(64, 64, [128] * 64 * 64)
+        assert rustmatch.find_repeats(flat, patch_size=16) == []
+    
+    def test_invalid_arguments(self):
+        """Test patch size and stride validation."""
+        source = self.source_with_copies([])
+        with pytest.raises(rustmatch.InvalidDimensionsError):
+            rustmatch.find_repeats(source, patch_size=4)
+        with pytest.raises(ValueError):
+            rustmatch.find_repeats(source, patch_size=16, stride=0)
+        with pytest.raises(rustmatch.TemplateTooLargeError):
+            rustmatch.find_repeats(source, patch_size=200)
+
+
+class TestRegisterRotscale:
+    """Tests for Fourier-Mellin rotation and scale registration."""
+    
+    SIZE = 160
+    
+    def rotated_scaled(self, angle, scale):
+        """Reference texture and the same texture rotated and zoomed about the centre."""
+        n, c = self.SIZE, self.SIZE / 2
+        source = blocky_pixels(n, n)
+        sin, cos = math.sin(math.radians(angle)), math.cos(math.radians(angle))
+        warped = []
+        for y in range(n):
+            for x in range(n):
+                qx, qy = x + 0.5 - c, y + 0.5 - c
+                px, py = (cos * qx + sin * qy) / scale + c, (-sin * qx + cos * qy) / scale + c
+                warped.append(source[int(py) * n + int(px)] if 0 <= px < n and 0 <= py < n else 97)
+        return source, warped
+    
+    def test_rotation_and_scale(self):
+        """Test a rotated, zoomed copy is registered and the template found at the estimate."""
+        n = self.SIZE
+        source, warped = self.rotated_scaled(20, 1.25)
+        template = [source[(60 + y) * n + 60 + x] for y in range(40) for x in range(40)]
+        
+        reg = rustmatch.register_rotscale(
+            make_png(n, n, source), make_png(n, n, warped), template=make_png(40, 40, template), threshold=0.7,
+        )
+        
+        assert abs(reg.angle - 20) < 1
+        assert abs(reg.scale - 1.25) < 0.02
+        assert abs(reg.dx) < 1 and abs(reg.dy) < 1
+        assert reg.confidence > 0.9
+        m = reg.template_match
+        assert m is not None and m.confidence > 0.9
+        assert (m.angle, m.scale) == (reg.angle, reg.scale)
+        # The template was centred on the image, so its bounding box stays centred
+        side = 40 * reg.scale * (math.cos(math.radians(20)) + math.sin(math.radians(20)))
+        assert abs(m.x + side / 2 - n / 2) <= 1.5 and abs(m.y + side / 2 - n / 2) <= 1.5
+        assert reg.to_dict()["template_match"]["x"] == m.x
+    
+    def test_identical_images(self):
+        """Test an image registers with itself as the identity."""
+        source = make_png(self.SIZE, self.SIZE, blocky_pixels(self.SIZE, self.SIZE))
+        
+        reg = rustmatch.register_rotscale(source, source)
+        
+        assert reg.angle == pytest.approx(0, abs=1e-6) and reg.scale == pytest.approx(1)
+        assert reg.dx == pytest.approx(0, abs=1e-6) and reg.dy == pytest.approx(0, abs=1e-6)
+        assert reg.confidence > 0.99
+        assert reg.template_match is None
+    
+    def test_too_small(self):
+        """Test images under 16x16 are rejected."""
+        small = make_png(8, 8, blocky_pixels(8, 8))
+        with pytest.raises(rustmatch.InvalidDimensionsError):
+            rustmatch.register_rotscale(small, TEMPLATE_IMAGE)
+
+
+class TestRefineAlignment:
+    """Tests for ECC sub-pixel alignment refinement."""
+    
+    WIDTH, HEIGHT = 160, 120
+    
+    def sampled_template(self, warp, width=40, height=32):
+        """Template read from the blocky source through a 2x3 warp, with bilinear interpolation."""
+        source = blocky_pixels(self.WIDTH, self.HEIGHT)
+        
+        def at(x, y):
+            x0, y0 = int(x), int(y)
+            tx, ty = x - x0, y - y0
+            p = lambda px, py: source[py * self.WIDTH + px]
+            top = p(x0, y0) * (1 - tx) + p(x0 + 1, y0) * tx
+            bottom = p(x0, y0 + 1) * (1 - tx) + p(x0 + 1, y0 + 1) * tx
+            return round(top * (1 - ty) + bottom * ty)
+        
+        (a, b, c), (d, e, f) = warp
+        pixels = [at(a * u + b * v + c, d * u + e * v + f) for v in range(height) for u in range(width)]
+        return make_png(self.WIDTH, self.HEIGHT, source), make_png(width, height, pixels)
+    
+    def test_subpixel_translation(self):
+        """Test a template read at a fractional offset is located to a small fraction of a pixel."""
+        source, template = self.sampled_template([[1, 0, 50.3], [0, 1, 40.6]])
+        r = rustmatch.find_bytes(source, template, threshold=0.8)
+        assert r is not None
+        
+        a = rustmatch.refine_alignment(source, template, r)
+        
+        assert a.motion == "translation" and a.converged
+        assert abs(a.x - 50.3) < 0.05 and abs(a.y - 40.6) < 0.05
+        assert a.warp[0][:2] == [1.0, 0.0] and a.warp[1][:2] == [0.0, 1.0]
+        assert a.correlation > r.confidence
+        assert a.transform(10, 5) == pytest.approx((a.x + 10, a.y + 5))
+    
+    def test_affine(self):
+        """Test an affine warp is recovered from the integer match."""
+        warp = [[1.03, 0.02, 50.5], [-0.015, 0.98, 40.3]]
+        source, template = self.sampled_template(warp)
+        r = rustmatch.find_bytes(source, template, threshold=0.5)
+        assert r is not None
+        
+        a = rustmatch.refine_alignment(source, template, r, motion="affine")
+        
+        for row, expected in zip(a.warp, warp):
+            assert row == pytest.approx(expected, abs=0.05)
+        assert a.correlation > 0.98
+        assert a.to_dict()["motion"] == "affine"
+    
+    def test_invalid_motion(self):
+        """Test unknown motion models are rejected."""
+        r = rustmatch.MatchResult(0, 0, 1.0)
+        with pytest.raises(ValueError):
+            rustmatch.refine_alignment(SOURCE_IMAGE, TEMPLATE_IMAGE, r, motion="homography")
+
+
+class TestAssertTemplateFound:
+    """Tests for the test-suite assertion helper."""
+    
+    def test_found(self):
+        """Test the match is returned when found."""
+        r = rustmatch.assert_template_found(SOURCE_IMAGE, TEMPLATE_IMAGE)
+        
+        assert (r.x, r.y) == (847, 297)
+    
+    def test_not_found_message(self):
+        """Test the message reports the best score and where it was reached."""
+        best = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.0)
+        
+        with pytest.raises(AssertionError) as info:
+            rustmatch.assert_template_found(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=1.01)
+        
+        message = str(info.value)
+        assert f"best score {best.confidence:.4f} at ({best.x}, {best.y})" in message
+        assert "threshold 1.01" in message
+        assert "report" not in message
+    
+    def test_artifact(self, tmp_path):
+        """Test failure reports are saved without overwriting earlier ones."""
+        for _ in range(2):
+            with pytest.raises(AssertionError) as info:
+                rustmatch.assert_template_found(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=1.01, artifact_dir=tmp_path / "out")
+        
+        saved = sorted(p.name for p in (tmp_path / "out").iterdir())
+        assert saved == ["a3-not-found-1.png", "a3-not-found.png"]
+        assert str(tmp_path / "out" / "a3-not-found-1.png") in str(info.value)
+        with open(tmp_path / "out" / "a3-not-found.png", "rb") as f:
+            assert f.read(8) == b"\x89PNG\r\n\x1a\n"
+
+
+class TestRawBuffers:
+    """Raw API with buffer-protocol and shared-memory pixels."""
+    
+    W, H = 60, 48
+    
+    def _frame(self):
+        source = bytes(blocky_pixels(self.W, self.H, cell=5))
+        template = bytes(
+            source[(y + 20) * self.W + 25 + x] for y in range(16) for x in range(16)
+        )
+        return source, template
+    
+    def test_buffer_types_match_bytes(self):
+        """bytearray, memoryview and lists give the same result as bytes."""
+        source, template = self._frame()
+        expected = rustmatch.find_raw(source, self.W, self.H, template, 16, 16, threshold=0.9)
+        assert (expected.x, expected.y) == (25, 20)
+        for wrap in (bytearray, memoryview, list):
+            result = rustmatch.find_raw(wrap(source), self.W, self.H, wrap(template), 16, 16, threshold=0.9)
+            assert (result.x, result.y, result.confidence) == (expected.x, expected.y, expected.confidence)
+    
+    def test_source_offset(self):
+        """source_offset picks one frame out of a larger buffer."""
+        source, template = self._frame()
+        ring = bytearray(self.W * self.H * 2 + 100)
+        ring[self.W * self.H + 7:self.W * self.H * 2 + 7] = source
+        result = rustmatch.find_raw(
+            ring, self.W, self.H, template, 16, 16, threshold=0.9, source_offset=self.W * self.H + 7
+        )
+        assert (result.x, result.y) == (25, 20)
+        # The stride-2 scan of find_all would skip the odd x of this target
+        results = rustmatch.find_all_raw(
+            memoryview(ring), self.W, self.H, template, 16, 16, threshold=0.9, exact=True,
+            source_offset=self.W * self.H + 7,
+        )
+        expected = rustmatch.find_all_raw(source, self.W, self.H, template, 16, 16, threshold=0.9, exact=True)
+        assert [(r.x, r.y) for r in expected][:1] == [(25, 20)]
+        assert [(r.x, r.y, r.confidence) for r in results] == [(r.x, r.y, r.confidence) for r in expected]
+    
+    def test_offset_past_end(self):
+        """A frame running past the end of the buffer is rejected."""
+        source, template = self._frame()
+        with pytest.raises(rustmatch.InvalidDimensionsError):
+            rustmatch.find_raw(source, self.W, self.H, template, 16, 16, source_offset=1)
+        with pytest.raises(rustmatch.InvalidDimensionsError):
+            rustmatch.find_raw(source + b"\0", self.W, self.H, template, 16, 16)
+    
+    def test_shared_memory(self):
+        """A SharedMemory block, or its name, is matched in place."""
+        shared_memory = pytest.importorskip("multiprocessing.shared_memory")
+        source, template = self._frame()
+        shm = shared_memory.SharedMemory(create=True, size=len(source) * 2)
+        try:
+            shm.buf[len(source):len(source) * 2] = source
+            by_block = rustmatch.find_raw(
+                shm, self.W, self.H, template, 16, 16, threshold=0.9, source_offset=len(source)
+            )
+            by_name = rustmatch.find_raw(
+                shm.name, self.W, self.H, template, 16, 16, threshold=0.9, source_offset=len(source)
+            )
+            assert (by_block.x, by_block.y) == (by_name.x, by_name.y) == (25, 20)
+        finally:
+            shm.close()
+            shm.unlink()
+
+
+class TestFindTensor:
+    """Tensor inputs through __array_interface__ and DLPack."""
+    
+    W, H = 60, 48
+    
+    def _frame(self):
+        source = bytes(blocky_pixels(self.W, self.H, cell=5))
+        template = bytes(
+            source[(y + 20) * self.W + 25 + x] for y in range(16) for x in range(16)
+        )
+        return source, template
+    
+    class _Interface:
+        """Minimal __array_interface__ exporter over a ctypes buffer."""
+        
+        def __init__(self, data, shape, typestr="|u1", strides=None):
+            import ctypes
+            self._buf = (ctypes.c_char * len(data)).from_buffer_copy(data)
+            self.__array_interface__ = {
+                "version": 3, "shape": shape, "typestr": typestr, "strides": strides,
+                "data": (ctypes.addressof(self._buf), False),
+            }
+    
+    def test_array_interface(self):
+        """Objects exposing __array_interface__ are matched in place."""
+        source, template = self._frame()
+        result = rustmatch.find_tensor(
+            self._Interface(source, (self.H, self.W)), self._Interface(template, (16, 16)), threshold=0.9
+        )
+        assert (result.x, result.y) == (25, 20)
+        results = rustmatch.find_all_tensor(self._Interface(source, (self.H, self.W)), make_png(16, 16, template))
+        assert (results[0].x, results[0].y) == (25, 20)
+    
+    def test_strided_float_channels(self):
+        """Planar float data viewed channels-last through strides."""
+        source, template = self._frame()
+        planes = b"".join(struct.pack("<%df" % len(source), *(v / 255 for v in source)) for _ in range(3))
+        plane = self.W * self.H * 4
+        view = self._Interface(planes, (self.H, self.W, 3), "<f4", (self.W * 4, 4, plane))
+        result = rustmatch.find_tensor(view, self._Interface(template, (16, 16)), threshold=0.9, return_crop=True)
+        assert (result.x, result.y) == (25, 20)
+        assert result.crop is not None
+    
+    def test_bad_tensors(self):
+        """Unsupported dtypes and shapes are rejected."""
+        source, template = self._frame()
+        with pytest.raises(TypeError):
+            rustmatch.find_tensor(self._Interface(source, (self.H, self.W // 4), "<i4"), template)
+        with pytest.raises(rustmatch.InvalidDimensionsError):
+            rustmatch.find_tensor(self._Interface(source, (self.H, self.W // 2, 2)), self._Interface(template, (16, 16)))
+    
+    def test_numpy(self):
+        """numpy arrays, including transposed views."""
+        np = pytest.importorskip("numpy")
+        source, template = self._frame()
+        array = np.frombuffer(source, dtype=np.uint8).reshape(self.H, self.W)
+        tpl = np.frombuffer(template, dtype=np.uint8).reshape(16, 16)
+        assert rustmatch.find_tensor(array, tpl, threshold=0.9).x == 25
+        result = rustmatch.find_tensor(array.T, tpl.T, threshold=0.9)
+        assert (result.x, result.y) == (20, 25)
+
+
+class TestIntegralPrecision:
+    """Window statistics stay exact where whole-image sums pass 2^53."""
+    
+    W, H = 2048, 1536
+    
+    @staticmethod
+    def ncc(source, width, template, tw, th, x, y):
+        """Direct NCC of one window in exact integer arithmetic."""
+        s = [source[(y + j) * width + x + i] for j in range(th) for i in range(tw)]
+        n = len(s)
+        cross = n * sum(a * b for a, b in zip(s, template)) - sum(s) * sum(template)
+        var_s = n * sum(a * a for a in s) - sum(s) ** 2
+        var_t = n * sum(b * b for b in template) - sum(template) ** 2
+        return cross / math.sqrt(var_s * var_t)
+    
+    def test_bottom_right_parity(self):
+        """Test a deep 16-bit plane scores like a direct computation at its far corner."""
+        from array import array
+        
+        # Bright, low-contrast noise: the squared total is about 1.1e16
+        def noise(x, y):
+            h = (x * 374761393 + y * 668265263) & 0xFFFFFFFF
+            return ((h ^ h >> 13) * 1274126177 >> 29) & 7
+        
+        source = array("H", (60000 + noise(x, y) for y in range(self.H) for x in range(self.W)))
+        x0, y0 = self.W - 40, self.H - 30
+        template = [source[(y0 + j) * self.W + x0 + i] + (3 if (i * j) % 11 == 0 else 0) for j in range(24) for i in range(24)]
+        
+        result = rustmatch.find_tensor(uint16_view(self.W, self.H, source), uint16_view(24, 24, template), threshold=0.5)
+        
+        assert (result.x, result.y) == (x0, y0)
+        assert result.confidence == pytest.approx(self.ncc(source, self.W, template, 24, 24, x0, y0), abs=1e-6)
+
+
+class TestFindNp:
+    """numpy arrays through find_np / find_all_np."""
+    
+    W, H = 60, 48
+    _Interface = TestFindTensor._Interface
+    _frame = TestFindTensor._frame
+    
+    def test_gray_and_color(self):
+        """2D uint8 and 3D float channels-last arrays match alike."""
+        np = pytest.importorskip("numpy")
+        source, template = self._frame()
+        gray = np.frombuffer(source, dtype=np.uint8).reshape(self.H, self.W)
+        tpl = np.ascontiguousarray(gray[20:36, 25:41])
+        result = rustmatch.find_np(gray, tpl, threshold=0.9)
+        assert (result.x, result.y) == (25, 20)
+        rgb = np.repeat(gray[:, :, None] / 255.0, 3, axis=2).astype(np.float32)
+        results = rustmatch.find_all_np(rgb, tpl, threshold=0.9)
+        assert (results[0].x, results[0].y) == (25, 20)
+    
+    def test_float_array(self):
+        """Float data is multiplied by float_scale, 255 by default, to give 8-bit levels."""
+        source, template = self._frame()
+        floats = struct.pack("<%dd" % len(source), *(v / 255 for v in source))
+        result = rustmatch.find_np(
+            self._Interface(floats, (self.H, self.W, 1), "<f8"), self._Interface(template, (16, 16)), threshold=0.9
+        )
+        assert (result.x, result.y) == (25, 20)
+        levels = struct.pack("<%dd" % len(source), *source)
+        result = rustmatch.find_np(
+            self._Interface(levels, (self.H, self.W, 1), "<f8"), self._Interface(template, (16, 16)),
+            threshold=0.9, float_scale=1.0, return_crop=True,
+        )
+        assert (result.x, result.y) == (25, 20)
+        assert rustmatch.find_bytes(result.crop, make_png(16, 16, template), threshold=0.99) is not None
+        with pytest.raises(ValueError):
+            rustmatch.find_np(self._Interface(levels, (self.H, self.W, 1), "<f8"), self._Interface(template, (16, 16)), float_scale=0)
+    
+    def test_rejected_arrays(self):
+        """Non-arrays, strided views, bad dtypes and shapes raise before matching."""
+        source, template = self._frame()
+        tpl = self._Interface(template, (16, 16))
+        with pytest.raises(TypeError, match="numpy array"):
+            rustmatch.find_np(list(source), tpl)
+        with pytest.raises(TypeError, match="numpy array"):
+            rustmatch.find_np(source, tpl)
+        with pytest.raises(ValueError, match="contiguous"):
+            rustmatch.find_np(self._Interface(source, (self.H // 2, self.W), strides=(self.W * 2, 1)), tpl)
+        with pytest.raises(TypeError):
+            rustmatch.find_np(self._Interface(source, (self.H, self.W // 4), "<i4"), tpl)
+        with pytest.raises(rustmatch.InvalidDimensionsError):
+            rustmatch.find_all_np(self._Interface(source, (self.H, self.W // 2, 2)), tpl)
+    
+    def test_torch_dlpack(self):
+        """CPU torch tensors through DLPack."""
+        torch = pytest.importorskip("torch")
+        source, template = self._frame()
+        frame = torch.tensor(list(source), dtype=torch.float32).reshape(self.H, self.W) / 255
+        tpl = torch.tensor(list(template), dtype=torch.uint8).reshape(16, 16)
+        result = rustmatch.find_tensor(frame, tpl, threshold=0.9)
+        assert (result.x, result.y) == (25, 20)
+
+
+class TestMatchArray:
+    """Structured array output for large result sets."""
+    
+    def test_packed_records(self):
+        """Packed records match the MatchResult list, without numpy."""
+        from rustmatch import _core
+        results = rustmatch.find_all(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.7, max_count=50)
+        packed = _core.find_all_templates_packed(SOURCE_IMAGE, TEMPLATE_IMAGE, 0.7, 50)
+        assert len(packed) == 16 * len(results)
+        records = [struct.unpack_from("<iid", packed, i * 16) for i in range(len(results))]
+        assert records == [(r.x, r.y, r.confidence) for r in results]
+    
+    def test_as_array(self):
+        """as_array=True returns a structured array in the same order."""
+        np = pytest.importorskip("numpy")
+        results = rustmatch.find_all(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.7, max_count=50)
+        array = rustmatch.find_all(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.7, max_count=50, as_array=True)
+        assert array.dtype == np.dtype(rustmatch.MATCH_DTYPE)
+        assert array["x"].tolist() == [r.x for r in results]
+        assert array["y"].tolist() == [r.y for r in results]
+        assert array["confidence"].tolist() == [r.confidence for r in results]
+        with open(SOURCE_IMAGE, "rb") as s, open(TEMPLATE_IMAGE, "rb") as t:
+            from_bytes = rustmatch.find_all_bytes(s.read(), t.read(), threshold=0.7, max_count=50, as_array=True)
+        assert from_bytes.tolist() == array.tolist()
+    
+    def test_as_array_empty_and_crop(self):
+        """No matches give an empty array; return_crop is rejected."""
+        pytest.importorskip("numpy")
+        empty = rustmatch.find_all(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=1.01, as_array=True)
+        assert empty.shape == (0,)
+        with pytest.raises(ValueError):
+            rustmatch.find_all(SOURCE_IMAGE, TEMPLATE_IMAGE, as_array=True, return_crop=True)
+
+
+class TestScoreGrid:
+    """Grid-pooled score maxima."""
+    
+    def test_grid_shape_and_peak(self):
+        """The best cell holds the best match score."""
+        best = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.5, exact=True)
+        grid = rustmatch.score_grid(SOURCE_IMAGE, TEMPLATE_IMAGE, cell=32)
+        cols, rows = 1602 - 15 + 1, 364 - 16 + 1
+        assert len(grid) == (rows + 31) // 32
+        assert all(len(row) == (cols + 31) // 32 for row in grid)
+        assert grid[best.y // 32][best.x // 32] == pytest.approx(best.confidence)
+        assert max(max(row) for row in grid) == pytest.approx(best.confidence)
+    
+    def test_cell_one_and_errors(self):
+        """cell=1 is the full score map; invalid input is rejected."""
+        pixels = blocky_pixels(40, 30, cell=5)
+        template = [pixels[(y + 7) * 40 + 11 + x] for y in range(10) for x in range(10)]
+        grid = rustmatch.score_grid(make_png(40, 30, pixels), make_png(10, 10, template), cell=1)
+        assert (len(grid), len(grid[0])) == (21, 31)
+        assert grid[7][11] == pytest.approx(1.0)
+        with pytest.raises(ValueError):
+            rustmatch.score_grid(SOURCE_IMAGE, TEMPLATE_IMAGE, cell=0)
+        with pytest.raises(rustmatch.TemplateTooLargeError):
+            rustmatch.score_grid(TEMPLATE_IMAGE, SOURCE_IMAGE)
+
+
+class TestTemplateLibrary:
+    """Named template sets and zip template packs."""
+    
+    W, H = 60, 48
+    
+    def _images(self):
+        pixels = blocky_pixels(self.W, self.H, cell=5)
+        crop = lambda x, y: make_png(16, 16, [pixels[(y + j) * self.W + x + i] for j in range(16) for i in range(16)])
+        return make_png(self.W, self.H, pixels), {"left": crop(8, 28), "right": crop(25, 20)}
+    
+    def _zip(self, entries, compression=None):
+        import io
+        import zipfile
+        buf = io.BytesIO()
+        with zipfile.ZipFile(buf, "w", compression or zipfile.ZIP_DEFLATED) as archive:
+            for name, data in entries.items():
+                archive.writestr(name, data)
+        return buf.getvalue()
+    
+    def test_from_dict(self):
+        """Templates given as a dict keep their names and order."""
+        source, templates = self._images()
+        library = rustmatch.TemplateLibrary(templates, threshold=0.9)
+        assert library.names == ["left", "right"] and len(library) == 2 and "left" in library
+        assert (library.find(source, "right").x, library.find(source, "right").y) == (25, 20)
+        with pytest.raises(KeyError):
+            library.find(source, "missing")
+    
+    def test_from_zip(self, tmp_path):
+        """Zip entries become templates named by path without extension."""
+        import zipfile
+        source, templates = self._images()
+        entries = {
+            "icons/left.png": templates["left"], "icons/sub/right.png": templates["right"],
+            "README.txt": b"not an image", "__MACOSX/icons/._left.png": b"junk",
+        }
+        for compression in (zipfile.ZIP_DEFLATED, zipfile.ZIP_STORED):
+            archive = self._zip(entries, compression)
+            library = rustmatch.TemplateLibrary.from_zip(archive, threshold=0.9)
+            assert library.names == ["icons/left", "icons/sub/right"]
+            found = library.find_each(source)
+            assert {name: (r.x, r.y) for name, r in found.items()} == {"icons/left": (8, 28), "icons/sub/right": (25, 20)}
+        path = tmp_path / "pack.zip"
+        path.write_bytes(archive)
+        assert rustmatch.TemplateLibrary.from_zip(str(path)).names == library.names
+    
+    def test_find_each_matches_single_finds(self):
+        """Coarse levels shared across templates give the same results as one-by-one finds."""
+        w, h = 200, 150
+        pixels = blocky_pixels(w, h, cell=5)
+        crop = lambda x, y, cw, ch: make_png(cw, ch, [pixels[(y + j) * w + x + i] for j in range(ch) for i in range(cw)])
+        templates = {
+            "small": crop(8, 28, 16, 16), "wide": crop(40, 30, 96, 64),
+            "square": crop(100, 70, 64, 64), "large": crop(20, 10, 128, 128),
+        }
+        source = make_png(w, h, pixels)
+        library = rustmatch.TemplateLibrary(templates, threshold=0.5)
+        found = library.find_each(source)
+        for name in templates:
+            single = library.find(source, name)
+            assert (found[name].x, found[name].y, found[name].confidence) == (single.x, single.y, single.confidence)
+        assert (found["square"].x, found["square"].y) == (100, 70)
+    
+    def test_zip_errors(self):
+        """Broken archives and entries are reported."""
+        _, templates = self._images()
+        with pytest.raises(rustmatch.DecodeError):
+            rustmatch.TemplateLibrary.from_zip(b"not a zip archive at all")
+        with pytest.raises(rustmatch.DecodeError, match="broken"):
+            rustmatch.TemplateLibrary.from_zip(self._zip({"broken.png": b"\x89PNG garbage"}))
+        with pytest.raises(ValueError):
+            rustmatch.TemplateLibrary.from_zip(self._zip({"ok.png": templates["left"], "ok.bmp": templates["left"]}))
+
+
+class TestFileLikeInput:
+    """Binary file-like objects in the bytes entry points."""
+    
+    def test_file_objects(self):
+        """Open files and BytesIO give the same result as bytes."""
+        import io
+        with open(SOURCE_IMAGE, "rb") as s, open(TEMPLATE_IMAGE, "rb") as t:
+            source, template = s.read(), t.read()
+        expected = rustmatch.find_bytes(source, template)
+        with open(SOURCE_IMAGE, "rb") as s, open(TEMPLATE_IMAGE, "rb") as t:
+            result = rustmatch.find_bytes(s, t)
+        assert (result.x, result.y, result.confidence) == (expected.x, expected.y, expected.confidence)
+        results = rustmatch.find_all_bytes(io.BytesIO(source), io.BytesIO(template), threshold=0.7)
+        assert [(r.x, r.y) for r in results] == [(r.x, r.y) for r in rustmatch.find_all_bytes(source, template, threshold=0.7)]
+    
+    def test_chunked_reader(self):
+        """Readers returning short chunks are read to the end."""
+        with open(SOURCE_IMAGE, "rb") as s, open(TEMPLATE_IMAGE, "rb") as t:
+            source, template = s.read(), t.read()
+        
+        class Trickle:
+            def __init__(self, data):
+                self.data, self.calls = data, 0
+            
+            def read(self, size=-1):
+                self.calls += 1
+                chunk, self.data = self.data[:1000], self.data[1000:]
+                return chunk
+        
+        reader = Trickle(source)
+        result = rustmatch.find_bytes(reader, template)
+        assert (result.x, result.y) == (847, 297)
+        assert reader.calls > len(source) // 1000
+    
+    def test_sqlite_blob(self):
+        """sqlite3 BLOBs are read incrementally."""
+        import sqlite3
+        if not hasattr(sqlite3.Connection, "blobopen"):
+            pytest.skip("sqlite3.Blob needs Python 3.11")
+        with open(SOURCE_IMAGE, "rb") as s:
+            source = s.read()
+        db = sqlite3.connect(":memory:")
+        db.execute("CREATE TABLE shots (png BLOB)")
+        db.execute("INSERT INTO shots VALUES (?)", (source,))
+        with db.blobopen("shots", "png", 1, readonly=True) as blob, open(TEMPLATE_IMAGE, "rb") as template:
+            result = rustmatch.find_bytes(blob, template)
+        assert (result.x, result.y) == (847, 297)
+    
+    def test_text_mode_rejected(self):
+        """Text-mode files and other objects raise TypeError."""
+        import io
+        with pytest.raises(TypeError):
+            rustmatch.find_bytes(io.StringIO("not binary"), b"")
+        with pytest.raises(TypeError):
+            rustmatch.find_bytes(12345, b"")
+
+
+class TestColorSpace:
+    """Tests for the color_space / channels matching options"""
+    
+    GREY = (60, 60, 60)
+    RED, GREEN = (240, 40, 40), (20, 200, 20)
+    
+    @classmethod
+    def led_panel(cls):
+        """Grey panel with a red LED at (10, 10) and a green one at (50, 10)."""
+        def pixel(x, y):
+            if 10 <= y < 18 and 10 <= x < 18:
+                return cls.RED
+            if 10 <= y < 18 and 50 <= x < 58:
+                return cls.GREEN
+            return cls.GREY
+        return make_rgb_png(80, 30, [pixel(x, y) for y in range(30) for x in range(80)])
+    
+    @classmethod
+    def led(cls, colour):
+        """16x16 template: an 8x8 LED centred on the panel grey."""
+        return make_rgb_png(16, 16, [
+            colour if 4 <= x < 12 and 4 <= y < 12 else cls.GREY for y in range(16) for x in range(16)
+        ])
+    
+    def test_grayscale_cannot_tell_red_from_green(self):
+        results = rustmatch.find_all_bytes(self.led_panel(), self.led(self.GREEN), threshold=0.9)
+        assert sorted((r.x, r.y) for r in results) == [(6, 6), (46, 6)]
+    
+    def test_hue_separates_green_from_red(self):
+        results = rustmatch.find_all_bytes(self.led_panel(), self.led(self.GREEN), threshold=0.9, color_space="hsv", channels="h")
+        assert [(r.x, r.y) for r in results] == [(46, 6)]
+        assert results[0].confidence > 0.99
+    
+    @pytest.mark.parametrize("channels", ["a", {"a": 1, "b": 0.25}])
+    def test_lab_channels_pick_the_right_led(self, channels):
+        for colour, x in [(self.GREEN, 46), (self.RED, 6)]:
+            results = rustmatch.find_all_bytes(
+                self.led_panel(), self.led(colour), threshold=0.9, color_space="lab", channels=channels
+            )
+            assert [(r.x, r.y) for r in results] == [(x, 6)]
+            assert results[0].confidence > 0.99
+    
+    def test_paths_and_matcher(self, tmp_path):
+        panel, led = tmp_path / "panel.png", tmp_path / "led.png"
+        panel.write_bytes(self.led_panel())
+        led.write_bytes(self.led(self.RED))
+        result = rustmatch.find(str(panel), str(led), color_space="lab", channels="a")
+        assert (result.x, result.y) == (6, 6)
+        matcher = rustmatch.Matcher(str(led), threshold=0.9, color_space="lab", channels="a")
+        assert [(r.x, r.y) for r in matcher.find_all(self.led_panel())] == [(6, 6)]
+    
+    def test_crop_is_cut_from_the_colour_source(self):
+        result = rustmatch.find_bytes(self.led_panel(), self.led(self.GREEN), color_space="hsv", channels="h", return_crop=True)
+        assert (result.x, result.y) == (46, 6)
+        assert result.crop[:8] == b"\x89PNG\r\n\x1a\n"
+    
+    @pytest.mark.parametrize("options, message", [
+        ({"color_space": "rgb"}, "Unknown color_space"),
+        ({"channels": "h"}, "channels requires color_space"),
+        ({"color_space": "hsv", "channels": "a"}, "Unknown channel 'a'"),
+        ({"color_space": "lab", "channels": {"a": -1}}, "non-negative"),
+        ({"color_space": "lab", "channels": {"a": 0}}, "positive weight"),
+    ])
+    def test_invalid_options(self, options, message):
+        with pytest.raises(ValueError, match=message):
+            rustmatch.find_bytes(self.led_panel(), self.led(self.RED), **options)
+    
+    def test_luma_only_sources_reject_color_space(self):
+        with pytest.raises(ValueError, match="not supported by find_raw"):
+            rustmatch.find_raw([0] * 64, 8, 8, [0] * 4, 2, 2, color_space="hsv")
+
+
+class TestColorPrefilter:
+    """Tests for the color_prefilter matching option"""
+    
+    def test_skips_regions_without_the_template_colours(self):
+        panel = TestColorSpace.led_panel()
+        for colour, x in [(TestColorSpace.GREEN, 46), (TestColorSpace.RED, 6)]:
+            led = TestColorSpace.led(colour)
+            results = rustmatch.find_all_bytes(panel, led, threshold=0.9, color_prefilter=True)
+            assert [(r.x, r.y) for r in results] == [(x, 6)]
+            assert rustmatch.find_bytes(panel, led, color_prefilter=0.0).x == x
+    
+    def test_same_result_when_colours_match(self):
+        plain = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE)
+        filtered = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, color_prefilter=True)
+        assert (filtered.x, filtered.y) == (plain.x, plain.y) == (847, 297)
+        assert filtered.confidence == pytest.approx(plain.confidence)
+        matcher = rustmatch.Matcher(TEMPLATE_IMAGE, color_prefilter=True)
+        assert (matcher.find(SOURCE_IMAGE).x, matcher.find(SOURCE_IMAGE).y) == (847, 297)
+    
+    def test_find_iter_and_matcher(self):
+        panel, led = TestColorSpace.led_panel(), TestColorSpace.led(TestColorSpace.GREEN)
+        assert [(r.x, r.y) for r in rustmatch.find_iter(panel, led, threshold=0.9, color_prefilter=True)] == [(46, 6)]
+        matcher = rustmatch.Matcher(led, threshold=0.9, color_prefilter=True)
+        assert [(r.x, r.y) for r in matcher.find_all(panel)] == [(46, 6)]
+    
+    @pytest.mark.parametrize("options, message", [
+        ({"color_prefilter": 1.0}, r"\[0, 1\)"),
+        ({"color_prefilter": -0.1}, r"\[0, 1\)"),
+        ({"color_prefilter": True, "wrap": True}, "can't be combined"),
+        ({"color_prefilter": True, "auto_scale": True}, "can't be combined"),
+    ])
+    def test_invalid_options(self, options, message):
+        with pytest.raises(ValueError, match=message):
+            rustmatch.find_bytes(TestColorSpace.led_panel(), TestColorSpace.led(TestColorSpace.RED), **options)
+    
+    def test_luma_only_sources_reject_it(self):
+        with pytest.raises(ValueError, match="color_prefilter is not supported by find_raw"):
+            rustmatch.find_raw([0] * 64, 8, 8, [0] * 4, 2, 2, color_prefilter=True)
+
+
+class TestMinVarianceRatio:
+    """Tests for the min_variance_ratio matching option"""
+    
+    @staticmethod
+    def two_copies():
+        """120x48 source: a 60x48 texture, then the same texture at a quarter of the contrast."""
+        patch = blocky_pixels(60, 48, cell=5)
+        pixels = [
+            patch[y * 60 + x] if x < 60 else patch[y * 60 + x - 60] // 4 + 96
+            for y in range(48) for x in range(120)
+        ]
+        template = [patch[y * 60 + x] for y in range(20, 40) for x in range(25, 49)]
+        return make_png(120, 48, pixels), make_png(24, 20, template)
+    
+    def test_same_result_on_real_screenshot(self):
+        plain = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE)
+        fast = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, min_variance_ratio=0.05)
+        assert (fast.x, fast.y, fast.confidence) == (plain.x, plain.y, plain.confidence)
+        plain_all = rustmatch.find_all(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.7)
+        fast_all = rustmatch.find_all(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.7, min_variance_ratio=0.05)
+        assert [(r.x, r.y) for r in fast_all] == [(r.x, r.y) for r in plain_all]
+    
+    def test_low_contrast_windows_are_skipped(self):
+        source, template = self.two_copies()
+        both = rustmatch.find_all_bytes(source, template, threshold=0.9, exact=True)
+        assert sorted((r.x, r.y) for r in both) == [(25, 20), (85, 20)]
+        strong = rustmatch.find_all_bytes(source, template, threshold=0.9, exact=True, min_variance_ratio=0.2)
+        assert [(r.x, r.y) for r in strong] == [(25, 20)]
+    
+    def test_skipped_windows_logged(self, caplog):
+        caplog.set_level(logging.DEBUG, logger="rustmatch")
+        rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, min_variance_ratio=0.05)
+        messages = [r.getMessage() for r in caplog.records if r.name == "rustmatch"]
+        assert any(m.startswith("min_variance_ratio:") and "skip the cross term" in m for m in messages)
+    
+    @pytest.mark.parametrize("options, message", [
+        ({"min_variance_ratio": 1.5}, r"\[0, 1\]"),
+        ({"min_variance_ratio": -0.1}, r"\[0, 1\]"),
+        ({"min_variance_ratio": 0.1, "robust": True}, "plain NCC"),
+        ({"min_variance_ratio": 0.1, "sample_fraction": 0.5}, "plain NCC"),
+    ])
+    def test_invalid_options(self, options, message):
+        with pytest.raises(ValueError, match=message):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, **options)
+
+
+class TestBoundPruning:
+    """Pruned single-match scans must report exactly what a full scan finds"""
+    
+    def test_exact_search_matches_unpruned_score_map(self):
+        # score_grid scores every window without pruning
+        template = make_png(24, 20, [
+            v + (x * 7 + y * 3) % 11 * 3 for i, v in enumerate(blocky_pixels(60, 48, cell=5))
+            for y, x in [divmod(i, 60)] if 20 <= y < 40 and 25 <= x < 49
+        ])
+        source = make_png(60, 48, blocky_pixels(60, 48, cell=5))
+        unpruned = max(max(row) for row in rustmatch.score_grid(source, template, cell=8))
+        result = rustmatch.find_bytes(source, template, threshold=-1.0, exact=True)
+        assert result.confidence == pytest.approx(unpruned, abs=1e-12)
+        assert (result.x, result.y) == (25, 20)
+    
+    def test_pyramid_and_exact_agree_on_screenshot(self):
+        fast = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE)
+        exact = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, exact=True)
+        assert (fast.x, fast.y) == (exact.x, exact.y) == (847, 297)
+        assert fast.confidence == exact.confidence
+
+
+class TestHint:
+    """Tests for the hint locality prior"""
+    
+    def test_nearby_copy_wins(self):
+        # A.png holds the target twice; the hint picks the copy it points at
+        best = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE)
+        assert (best.x, best.y) == (847, 297)
+        near = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, hint=(850, 160))
+        assert (near.x, near.y) == (847, 162)
+        near = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, hint=(840, 290), hint_margin=8)
+        assert (near.x, near.y) == (847, 297)
+    
+    def test_falls_back_to_full_search(self):
+        plain = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE)
+        for hint in [(0, 0), (1600, 360), (10_000, 10_000)]:
+            found = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, hint=hint)
+            assert (found.x, found.y, found.confidence) == (plain.x, plain.y, plain.confidence)
+        assert rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=1.01, hint=(847, 297)) is None
+    
+    def test_other_entry_points(self):
+        pixels = blocky_pixels(60, 48, cell=5)
+        template = [pixels[y * 60 + x] for y in range(20, 32) for x in range(25, 37)]
+        found = rustmatch.find_raw(pixels, 60, 48, template, 12, 12, hint=(20, 20), hint_margin=4)
+        assert (found.x, found.y) == (25, 20)
+        found = rustmatch.find_bytes(make_png(60, 48, pixels), make_png(12, 12, template), hint=(20, 20), hint_margin=4)
+        assert (found.x, found.y) == (25, 20)
+        matcher = rustmatch.Matcher(make_png(12, 12, template), hint=(27, 18), hint_margin=4)
+        found = matcher.find(make_png(60, 48, pixels))
+        assert (found.x, found.y) == (25, 20)
+    
+    def test_hint_logged(self, caplog):
+        caplog.set_level(logging.DEBUG, logger="rustmatch")
+        rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, hint=(847, 297))
+        assert "hint: found within" in caplog.text
+    
+    def test_invalid(self):
+        with pytest.raises(ValueError, match="hint_margin"):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, hint=(0, 0), hint_margin=0)
+        with pytest.raises(ValueError, match="hint can't be combined"):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, hint=(0, 0), wrap=True)
+        with pytest.raises(ValueError, match="tiled"):
+            rustmatch.find_tiled(SOURCE_IMAGE, TEMPLATE_IMAGE, hint=(0, 0))
+
+
+class TestResultCache:
+    """Tests for the opt-in result cache."""
+    
+    @pytest.fixture(autouse=True)
+    def fresh_cache(self):
+        rustmatch.clear_result_cache()
+        yield
+        rustmatch.set_result_cache(0)
+        rustmatch.clear_result_cache()
+    
+    def test_disabled_by_default(self):
+        rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE)
+        assert rustmatch.result_cache_info() == {"hits": 0, "misses": 0, "size": 0, "capacity": 0, "ttl": None}
+    
+    def test_repeated_calls_hit(self):
+        rustmatch.set_result_cache(8)
+        first = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE)
+        assert rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE) == first
+        info = rustmatch.result_cache_info()
+        assert (info["hits"], info["misses"], info["size"]) == (1, 1, 1)
+        
+        # Different parameters, options or functions are separate entries
+        rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.9)
+        rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, exact=True)
+        all_results = rustmatch.find_all(SOURCE_IMAGE, TEMPLATE_IMAGE, max_count=3)
+        assert rustmatch.find_all(SOURCE_IMAGE, TEMPLATE_IMAGE, max_count=3) == all_results
+        info = rustmatch.result_cache_info()
+        assert (info["hits"], info["misses"], info["size"]) == (2, 4, 4)
+    
+    def test_bytes_keyed_on_content(self):
+        rustmatch.set_result_cache(8)
+        pixels = blocky_pixels(60, 48, cell=5)
+        template = make_png(12, 12, [pixels[y * 60 + x] for y in range(20, 32) for x in range(25, 37)])
+        found = rustmatch.find_bytes(make_png(60, 48, pixels), template)
+        assert rustmatch.find_bytes(make_png(60, 48, pixels), template) == found
+        assert rustmatch.find_bytes(make_png(60, 48, pixels[::-1]), template) != found
+        info = rustmatch.result_cache_info()
+        assert (info["hits"], info["misses"]) == (1, 2)
+    
+    def test_rewritten_file_misses(self, tmp_path):
+        rustmatch.set_result_cache(8)
+        pixels = blocky_pixels(60, 48, cell=5)
+        template = make_png(12, 12, [pixels[y * 60 + x] for y in range(20, 32) for x in range(25, 37)])
+        source_path, template_path = tmp_path / "source.png", tmp_path / "template.png"
+        template_path.write_bytes(template)
+        source_path.write_bytes(make_png(60, 48, pixels))
+        assert rustmatch.find(str(source_path), str(template_path)) is not None
+        source_path.write_bytes(make_png(60, 48, [0] * (60 * 48)))
+        os.utime(source_path, (1_000_000, 1_000_000))
+        assert rustmatch.find(str(source_path), str(template_path)) is None
+        assert rustmatch.result_cache_info()["hits"] == 0
+    
+    def test_ttl_and_size(self):
+        rustmatch.set_result_cache(1, ttl=0)
+        rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE)
+        time.sleep(0.01)
+        rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE)
+        assert rustmatch.result_cache_info()["hits"] == 0
+        
+        rustmatch.set_result_cache(1, ttl=60)
+        assert rustmatch.result_cache_info()["ttl"] == 60
+        rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE)
+        rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.5)
+        rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE)
+        info = rustmatch.result_cache_info()
+        assert (info["hits"], info["size"]) == (0, 1)
+        with pytest.raises(ValueError, match="ttl"):
+            rustmatch.set_result_cache(1, ttl=-1)
+
+
+class TestExplainMatch:
+    """Tests for per-block NCC contributions."""
+    
+    def test_cells_sum_to_score(self):
+        best = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.5)
+        rows = rustmatch.explain_match(SOURCE_IMAGE, TEMPLATE_IMAGE, best.x, best.y)
+        assert (len(rows), len(rows[0])) == (16, 15)
+        assert sum(map(sum, rows)) == pytest.approx(best.confidence, abs=1e-6)
+        
+        coarse = rustmatch.explain_match(SOURCE_IMAGE, TEMPLATE_IMAGE, best.x, best.y, block=4)
+        assert (len(coarse), len(coarse[0])) == (4, 4)
+        assert sum(map(sum, coarse)) == pytest.approx(best.confidence, abs=1e-6)
+    
+    def test_disagreeing_block_is_negative(self):
+        pixels = blocky_pixels(60, 48, cell=5)
+        template = [pixels[y * 60 + x] for y in range(20, 40) for x in range(20, 40)]
+        # Invert the top-left quarter of the source window
+        for y in range(20, 30):
+            for x in range(20, 30):
+                pixels[y * 60 + x] = 255 - pixels[y * 60 + x]
+        rows = rustmatch.explain_match(make_png(60, 48, pixels), make_png(20, 20, template), 20, 20, block=10)
+        assert rows[0][0] < 0
+        assert min(rows[0][1], rows[1][0], rows[1][1]) > 0
+    
+    def test_heatmap(self):
+        rows, png = rustmatch.explain_match(SOURCE_IMAGE, TEMPLATE_IMAGE, 847, 297, block=4, return_image=True)
+        assert png.startswith(b"\x89PNG")
+        assert rustmatch.get_size_bytes(png) == (15, 16)
+    
+    def test_invalid(self):
+        with pytest.raises(ValueError, match="block"):
+            rustmatch.explain_match(SOURCE_IMAGE, TEMPLATE_IMAGE, 0, 0, block=0)
+        with pytest.raises(ValueError, match="doesn't fit"):
+            rustmatch.explain_match(SOURCE_IMAGE, TEMPLATE_IMAGE, 1600, 0)
+        with pytest.raises(rustmatch.TemplateTooLargeError):
+            rustmatch.explain_match(TEMPLATE_IMAGE, SOURCE_IMAGE, 0, 0)
+
+
+class TestCompositeTemplate:
+    """Tests for multi-part composite templates."""
+    
+    W, H = 200, 150
+    
+    @pytest.fixture
+    def scene(self):
+        pixels = blocky_pixels(self.W, self.H, cell=5)
+        
+        def crop(x0, y0, w, h):
+            return make_png(w, h, [pixels[y * self.W + x] for y in range(y0, y0 + h) for x in range(x0, x0 + w)])
+        
+        return make_png(self.W, self.H, pixels), crop(20, 10, 40, 12), crop(20, 60, 40, 12)
+    
+    def test_parts_at_offsets(self, scene):
+        source, header, footer = scene
+        composite = rustmatch.CompositeTemplate([(header, 0, 0), (footer, 0, 50)])
+        assert len(composite) == 2 and composite.size == (40, 62)
+        
+        m = composite.find(source)
+        assert (m.x, m.y, m.matched) == (20, 10, 2)
+        assert m.confidence == pytest.approx(1.0)
+        assert [(p.x, p.y) for p in m.parts] == [(20, 10), (20, 60)]
+        assert m.to_dict()["parts"][1]["y"] == 60
+        assert len(composite.find_all(source)) == 1
+    
+    def test_tolerance(self, scene):
+        source, header, footer = scene
+        assert rustmatch.CompositeTemplate([(header, 0, 0), (footer, 0, 47)]).find(source) is None
+        m = rustmatch.CompositeTemplate([(header, 0, 0), (footer, 0, 47)], tolerance=4).find(source)
+        assert (m.x, m.y) == (20, 10)
+        assert (m.parts[1].x, m.parts[1].y) == (20, 60)
+    
+    def test_k_of_n(self, scene):
+        source, header, footer = scene
+        absent = make_png(20, 20, [(i * 7919 + 13) % 251 for i in range(400)])
+        parts = [(header, 0, 0), (footer, 0, 50), (absent, 10, 20, 0.5)]
+        assert rustmatch.CompositeTemplate(parts).find(source) is None
+        
+        m = rustmatch.CompositeTemplate(parts, min_parts=2).find(source)
+        assert (m.x, m.y, m.matched) == (20, 10, 2)
+        assert m.confidence == pytest.approx((2 + 0.5 * max(m.parts[2].confidence, 0)) / 2.5)
+        assert m.confidence < 1
+    
+    def test_invalid(self, scene):
+        _, header, _ = scene
+        with pytest.raises(ValueError):
+            rustmatch.CompositeTemplate([])
+        with pytest.raises(ValueError, match="min_parts"):
+            rustmatch.CompositeTemplate([(header, 0, 0)], min_parts=2)
+        with pytest.raises(ValueError, match="weights"):
+            rustmatch.CompositeTemplate([(header, 0, 0, 0.0)])
+        with pytest.raises(TypeError):
+            rustmatch.CompositeTemplate([header])
+
+
+class TestFlip:
+    """Tests for the flip option (mirrored template search)."""
+    
+    W, H = 60, 48
+    
+    @pytest.fixture
+    def images(self):
+        pixels = blocky_pixels(self.W, self.H, cell=5)
+        rows = [[pixels[y * self.W + x] for x in range(25, 37)] for y in range(20, 32)]
+        mirrored = make_png(12, 12, [v for row in rows for v in reversed(row)])
+        upside_down = make_png(12, 12, [v for row in reversed(rows) for v in row])
+        return make_png(self.W, self.H, pixels), mirrored, upside_down
+    
+    def test_horizontal(self, images):
+        source, mirrored, _ = images
+        assert rustmatch.find_bytes(source, mirrored, threshold=0.9, exact=True) is None
+        r = rustmatch.find_bytes(source, mirrored, threshold=0.9, exact=True, flip="horizontal")
+        assert (r.x, r.y, r.flip) == (25, 20, "horizontal")
+        assert r.confidence == pytest.approx(1.0)
+        assert rustmatch.find_bytes(source, mirrored, threshold=0.9, exact=True, flip="vertical") is None
+    
+    def test_both(self, images):
+        source, mirrored, upside_down = images
+        r = rustmatch.find_bytes(source, upside_down, threshold=0.9, exact=True, flip="both")
+        assert (r.x, r.y, r.flip) == (25, 20, "vertical")
+        matches = rustmatch.find_all_bytes(source, mirrored, threshold=0.99, exact=True, flip="both")
+        assert [(m.x, m.y, m.flip) for m in matches] == [(25, 20, "horizontal")]
+    
+    def test_unflipped_preferred(self):
+        r = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.8, flip="both")
+        assert r.flip is None
+        assert (r.x, r.y) == (847, 297)
+    
+    def test_result_round_trip(self, images):
+        source, mirrored, _ = images
+        r = rustmatch.find_bytes(source, mirrored, threshold=0.9, exact=True, flip="horizontal")
+        assert "flip='horizontal'" in repr(r)
+        assert r.to_dict()["flip"] == "horizontal"
+        assert pickle.loads(pickle.dumps(r)).flip == "horizontal"
+        assert "flip" not in rustmatch.MatchResult(1, 2, 0.9).to_dict()
+    
+    def test_invalid(self, images, tmp_path):
+        source, mirrored, _ = images
+        with pytest.raises(ValueError, match="Unknown flip"):
+            rustmatch.find_bytes(source, mirrored, flip="diagonal")
+        with pytest.raises(ValueError, match="weights"):
+            rustmatch.find_bytes(source, mirrored, flip="both", weights=[1.0] * 144)
+        (tmp_path / "s.png").write_bytes(source)
+        (tmp_path / "t.png").write_bytes(mirrored)
+        with pytest.raises(ValueError, match="flip"):
+            rustmatch.find_tiled(str(tmp_path / "s.png"), str(tmp_path / "t.png"), tile_size=32, flip="both")
+
+
+class TestInvert:
+    """Tests for the invert option (inverted-polarity matching)."""
+    
+    W, H = 60, 48
+    
+    @pytest.fixture
+    def images(self):
+        pixels = blocky_pixels(self.W, self.H, cell=5)
+        rows = [[255 - pixels[y * self.W + x] for x in range(25, 37)] for y in range(20, 32)]
+        inverted = make_png(12, 12, [v for row in rows for v in row])
+        inverted_mirror = make_png(12, 12, [v for row in rows for v in reversed(row)])
+        return make_png(self.W, self.H, pixels), inverted, inverted_mirror
+    
+    def test_inverted_match(self, images):
+        source, inverted, _ = images
+        assert rustmatch.find_bytes(source, inverted, threshold=0.9, exact=True) is None
+        r = rustmatch.find_bytes(source, inverted, threshold=0.9, exact=True, invert=True)
+        assert (r.x, r.y, r.inverted, r.flip) == (25, 20, True, None)
+        assert r.confidence == pytest.approx(1.0)
+        matches = rustmatch.find_all_bytes(source, inverted, threshold=0.99, exact=True, invert=True)
+        assert [(m.x, m.y, m.inverted) for m in matches] == [(25, 20, True)]
+    
+    def test_with_flip(self, images):
+        source, _, inverted_mirror = images
+        r = rustmatch.find_bytes(source, inverted_mirror, threshold=0.9, exact=True, invert=True, flip="horizontal")
+        assert (r.x, r.y, r.inverted, r.flip) == (25, 20, True, "horizontal")
+    
+    def test_original_preferred(self):
+        r = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.8, invert=True)
+        assert (r.x, r.y, r.inverted) == (847, 297, False)
+    
+    def test_result_round_trip(self, images):
+        source, inverted, _ = images
+        r = rustmatch.find_bytes(source, inverted, threshold=0.9, exact=True, invert=True)
+        assert "inverted=True" in repr(r)
+        assert r.to_dict()["inverted"] is True
+        assert json.loads(r.to_json())["inverted"] is True
+        assert pickle.loads(pickle.dumps(r)).inverted
+        assert "inverted" not in rustmatch.MatchResult(1, 2, 0.9).to_dict()
+    
+    def test_tiled_rejected(self, images, tmp_path):
+        source, inverted, _ = images
+        (tmp_path / "s.png").write_bytes(source)
+        (tmp_path / "t.png").write_bytes(inverted)
+        with pytest.raises(ValueError, match="invert"):
+            rustmatch.find_tiled(str(tmp_path / "s.png"), str(tmp_path / "t.png"), tile_size=32, invert=True)
+
+
+class TestFindPerspective:
+    """Tests for homography-based perspective matching."""
+    
+    TW, TH = 96, 72
+    W, H = 240, 180
+    
+    @staticmethod
+    def invert(m):
+        (a, b, c), (d, e, f), (g, h, i) = m
+        det = a * (e * i - f * h) - b * (d * i - f * g) + c * (d * h - e * g)
+        return [
+            [(e * i - f * h) / det, (c * h - b * i) / det, (b * f - c * e) / det],
+            [(f * g - d * i) / det, (a * i - c * g) / det, (c * d - a * f) / det],
+            [(d * h - e * g) / det, (b * g - a * h) / det, (a * e - b * d) / det],
+        ]
+    
+    @staticmethod
+    def project(m, u, v):
+        s = m[2][0] * u + m[2][1] * v + m[2][2]
+        return ((m[0][0] * u + m[0][1] * v + m[0][2]) / s, (m[1][0] * u + m[1][1] * v + m[1][2]) / s)
+    
+    def render(self, homography):
+        """Template and a source showing it through ``homography`` on a flat background."""
+        texture = blocky_pixels(self.TW, self.TH, cell=8)
+        inverse = self.invert(homography)
+        pixels = []
+        for y in range(self.H):
+            for x in range(self.W):
+                u, v = (round(c) for c in self.project(inverse, x, y))
+                inside = 0 <= u < self.TW and 0 <= v < self.TH
+                pixels.append(texture[v * self.TW + u] if inside else 100)
+        return make_png(self.W, self.H, pixels), make_png(self.TW, self.TH, texture)
+    
+    def test_shifted(self):
+        source, template = self.render([[1, 0, 50], [0, 1, 40], [0, 0, 1]])
+        m = rustmatch.find_perspective(source, template)
+        expected = [(50, 40), (146, 40), (146, 112), (50, 112)]
+        assert all(abs(x - ex) < 1 and abs(y - ey) < 1 for (x, y), (ex, ey) in zip(m.corners, expected))
+        assert m.score > 0.99
+        assert m.inliers >= 8 and 0 < m.confidence <= 1
+        x, y, w, h = m.bbox()
+        assert abs(x - 50) <= 1 and abs(y - 40) <= 1 and abs(w - 96) <= 2 and abs(h - 72) <= 2
+    
+    def test_perspective(self):
+        homography = self.invert([[1.1, 0.1, -60], [0.05, 1.05, -50], [0.001, 0.0005, 1]])
+        source, template = self.render(homography)
+        assert rustmatch.find_bytes(source, template, threshold=0.8) is None
+        
+        m = rustmatch.find_perspective(source, template)
+        for (u, v), (x, y) in zip([(0, 0), (96, 0), (96, 72), (0, 72)], m.corners):
+            ex, ey = self.project(homography, u, v)
+            assert abs(x - ex) < 2.5 and abs(y - ey) < 2.5
+        assert m.score > 0.9
+        cx, cy = m.transform(48, 36)
+        ex, ey = self.project(homography, 48, 36)
+        assert abs(cx - ex) < 2 and abs(cy - ey) < 2
+        d = m.to_dict()
+        assert d["inliers"] == m.inliers and len(d["homography"]) == 3
+    
+    def test_not_found(self):
+        _, template = self.render([[1, 0, 50], [0, 1, 40], [0, 0, 1]])
+        flat = make_png(self.W, self.H, [100] * (self.W * self.H))
+        assert rustmatch.find_perspective(flat, template) is None
+    
+    def test_invalid(self):
+        source, template = self.render([[1, 0, 50], [0, 1, 40], [0, 0, 1]])
+        with pytest.raises(ValueError, match="min_inliers"):
+            rustmatch.find_perspective(source, template, min_inliers=3)
+        with pytest.raises(rustmatch.InvalidDimensionsError):
+            rustmatch.find_perspective(source, make_png(10, 10, [0] * 100))
+
+
+class TestTemplateFromRegion:
+    """Tests for Template.from_region."""
+    
+    def test_crop(self, tmp_path):
+        path = str(tmp_path / "a3.png")
+        template = rustmatch.Template.from_region(SOURCE_IMAGE, 847, 297, 15, 16, grayscale=True, save_path=path)
+        assert (template.width, template.height) == (15, 16)
+        assert template.region == (847, 297, 15, 16)
+        assert bytes(template) == template.data
+        assert rustmatch.get_image_info(template.data)["channels"] == 1
+        assert rustmatch.get_size(path) == (15, 16)
+        assert repr(template) == "Template(width=15, height=16, region=(847, 297, 15, 16))"
+        
+        with open(SOURCE_IMAGE, "rb") as f:
+            result = rustmatch.find_bytes(f.read(), template.data, threshold=0.99)
+        assert (result.x, result.y) == (847, 297)
+    
+    def test_autocrop(self):
+        template = rustmatch.Template.from_region(SOURCE_IMAGE, 840, 290, 30, 30, autocrop=True)
+        assert template.region == (847, 298, 23, 13)
+        assert (template.width, template.height) == (23, 13)
+    
+    def test_save(self, tmp_path):
+        template = rustmatch.Template.from_region(make_png(8, 6, list(range(48))), 2, 1, 4, 3)
+        template.save(str(tmp_path / "t.png"))
+        assert rustmatch.get_size(str(tmp_path / "t.png")) == (4, 3)
+    
+    def test_invalid(self):
+        with pytest.raises(rustmatch.InvalidDimensionsError):
+            rustmatch.Template.from_region(SOURCE_IMAGE, 1600, 0, 10, 10)
+        with pytest.raises(rustmatch.InvalidDimensionsError):
+            rustmatch.Template.from_region(SOURCE_IMAGE, 0, 0, 0, 10)
+        with pytest.raises(ValueError, match="autocrop_tolerance"):
+            rustmatch.Template.from_region(SOURCE_IMAGE, 0, 0, 10, 10, autocrop=True, autocrop_tolerance=-1)
+
+
+class TestTemplateQuality:
+    """Tests for template_quality."""
+    
+    W, H = 60, 48
+    
+    @pytest.fixture
+    def noise(self):
+        pixels = [hash_noise(i) for i in range(self.W * self.H)]
+        crop = [pixels[y * self.W + x] for y in range(20, 32) for x in range(25, 37)]
+        return make_png(self.W, self.H, pixels), make_png(12, 12, crop)
+    
+    def test_distinct(self, noise):
+        source, template = noise
+        report = rustmatch.template_quality(template, [source])
+        assert report["distinct"] and report["issues"] == []
+        assert report["max_spurious_score"] < 0.5
+        background = report["backgrounds"][0]
+        assert background["contains_template"]
+        assert (background["x"], background["y"]) != (25, 20)
+    
+    def test_duplicate_flagged(self):
+        report = rustmatch.template_quality(TEMPLATE_IMAGE, [SOURCE_IMAGE])
+        assert not report["distinct"]
+        assert report["max_spurious_score"] > 0.99
+        assert any("spurious" in issue for issue in report["issues"])
+    
+    def test_contrast_only(self):
+        report = rustmatch.template_quality(make_png(8, 8, [50] * 63 + [51]))
+        assert report["max_spurious_score"] is None and report["backgrounds"] == []
+        assert not report["distinct"]
+        assert report["std"] < 5
+    
+    def test_invalid(self, noise):
+        source, template = noise
+        with pytest.raises(rustmatch.TemplateTooLargeError):
+            rustmatch.template_quality(source, [template])
+        with pytest.raises(ValueError, match="margin"):
+            rustmatch.template_quality(template, [source], margin=-0.1)
+
+
+class TestSuggestTemplateRegion:
+    """Tests for suggest_template_region."""
+    
+    W, H = 120, 80
+    
+    @pytest.fixture
+    def screen(self):
+        """Periodic 16x16 texture with one unique noise patch at (70, 30)-(90, 50)."""
+        return make_png(self.W, self.H, [
+            hash_noise(y * self.W + x) if 70 <= x < 90 and 30 <= y < 50 else hash_noise((y % 16) * 16 + x % 16)
+            for y in range(self.H) for x in range(self.W)
+        ])
+    
+    def test_picks_unique_content(self, screen):
+        region = rustmatch.suggest_template_region(screen, 75, 35, size_range=(16, 40))
+        x, y, w, h = region["x"], region["y"], region["width"], region["height"]
+        assert x <= 75 < x + w and y <= 35 < y + h
+        assert 16 <= w <= 40 and 16 <= h <= 40
+        overlap = (min(x + w, 90) - max(x, 70)) * (min(y + h, 50) - max(y, 30))
+        assert overlap >= w * h / 2
+        assert region["self_similarity"] < 0.5
+        assert region["candidates"] > 1
+        
+        periodic = rustmatch.suggest_template_region(screen, 20, 20, size_range=(16, 40))
+        assert periodic["self_similarity"] > region["self_similarity"]
+    
+    def test_flat_image(self):
+        assert rustmatch.suggest_template_region(make_png(64, 64, [90] * 4096), 30, 30) is None
+    
+    def test_invalid(self, screen):
+        with pytest.raises(rustmatch.InvalidDimensionsError):
+            rustmatch.suggest_template_region(screen, 120, 10)
+        with pytest.raises(ValueError, match="size_range"):
+            rustmatch.suggest_template_region(screen, 10, 10, size_range=(40, 16))
+
+
+class TestCompareToGolden:
+    """Tests for golden-image regression comparison."""
+    
+    WIDTH, HEIGHT = 120, 90
+    
+    def pair(self, edits, dx=3, dy=2):
+        """Golden texture and a copy moved by (dx, dy) with golden boxes `edits` (x, y, w, h) altered."""
+        golden = blocky_pixels(self.WIDTH, self.HEIGHT)
+        current = [0] * (self.WIDTH * self.HEIGHT)
+        for y in range(self.HEIGHT):
+            for x in range(self.WIDTH):
+                gx, gy = x - dx, y - dy
+                if 0 <= gx < self.WIDTH and 0 <= gy < self.HEIGHT:
+                    v = golden[gy * self.WIDTH + gx]
+                    if any(x0 <= gx < x0 + w and y0 <= gy < y0 + h for x0, y0, w, h in edits):
+                        v = (v + 100) % 256
+                    current[y * self.WIDTH + x] = v
+        return make_png(self.WIDTH, self.HEIGHT, golden), make_png(self.WIDTH, self.HEIGHT, current)
+    
+    def test_shift_is_aligned(self):
+        """Test a shifted but otherwise identical screenshot passes."""
+        golden, current = self.pair([])
+        
+        report = rustmatch.compare_to_golden(golden, current)
+        
+        assert report["passed"]
+        assert report["shift"] == (3, 2)
+        assert report["alignment_score"] == pytest.approx(1.0)
+        assert report["regions"] == []
+    
+    def test_offending_regions(self):
+        """Test changed boxes are reported in golden coordinates with scores."""
+        golden, current = self.pair([(60, 40, 10, 8)])
+        
+        report = rustmatch.compare_to_golden(golden, current)
+        
+        assert not report["passed"]
+        assert report["shift"] == (3, 2)
+        [region] = report["regions"]
+        assert (region["x"], region["y"], region["width"], region["height"]) == (60, 40, 10, 8)
+        assert region["changed_pixels"] == 80
+        assert region["max_diff"] >= 100
+        assert region["score"] < 0.9
+    
+    def test_ignore_regions(self):
+        """Test changes inside ignore regions do not fail the comparison."""
+        golden, current = self.pair([(60, 40, 10, 8), (5, 5, 20, 10)])
+        
+        report = rustmatch.compare_to_golden(golden, current, ignore_regions=[(0, 0, 30, 20), (55, 35, 200, 20)])
+        
+        assert report["passed"]
+        assert len(rustmatch.compare_to_golden(golden, current, max_shift=4)["regions"]) == 2
+    
+    def test_without_alignment(self):
+        """Test max_shift=0 diffs the images as they are."""
+        golden, current = self.pair([])
+        
+        report = rustmatch.compare_to_golden(golden, current, max_shift=0)
+        
+        assert report["shift"] == (0, 0)
+        assert not report["passed"]
+    
+    def test_invalid_arguments(self):
+        """Test size mismatches and bad options are rejected."""
+        with pytest.raises(rustmatch.InvalidDimensionsError):
+            rustmatch.compare_to_golden(SOURCE_IMAGE, TEMPLATE_IMAGE)
+        with pytest.raises(ValueError):
+            rustmatch.compare_to_golden(TEMPLATE_IMAGE, TEMPLATE_IMAGE, tolerance=-1)
+        with pytest.raises(ValueError):
+            rustmatch.compare_to_golden(TEMPLATE_IMAGE, TEMPLATE_IMAGE, max_shift=15)
+
+
+class TestFindInDirectory:
+    """Tests for directory batch matching."""
+    
+    @pytest.fixture
+    def directory(self, tmp_path):
+        """Two copies of the source (one nested), a blank image, a tiny image and a text file."""
+        with open(SOURCE_IMAGE, "rb") as f:
+            source = f.read()
+        (tmp_path / "sub").mkdir()
+        (tmp_path / "a.png").write_bytes(source)
+        (tmp_path / "sub" / "b.png").write_bytes(source)
+        (tmp_path / "blank.png").write_bytes(make_png(64, 64, [128] * 64 * 64))
+        (tmp_path / "tiny.png").write_bytes(make_png(8, 8, [0] * 64))
+        (tmp_path / "notes.txt").write_text("not an image")
+        return tmp_path
+    
+    def test_matches_every_file(self, directory):
+        """Test each matching file maps to its results, in sorted order."""
+        results = rustmatch.find_in_directory(str(directory), TEMPLATE_IMAGE)
+        
+        assert list(results) == ["a.png", "blank.png", "tiny.png"]
+        assert [(r.x, r.y) for r in results["a.png"]] == [(847, 297)]
+        assert results["blank.png"] == []
+        assert results["tiny.png"] == []
+    
+    def test_recursive_and_pattern(self, directory):
+        """Test subdirectories are walked on request and the pattern filters names."""
+        results = rustmatch.find_in_directory(str(directory), TEMPLATE_IMAGE, pattern="?.png", recursive=True)
+        
+        assert list(results) == ["a.png", "sub/b.png"]
+        assert [(r.x, r.y) for r in results["sub/b.png"]] == [(847, 297)]
+    
+    def test_max_count(self, directory):
+        """Test max_count returns several matches per file."""
+        results = rustmatch.find_in_directory(str(directory), TEMPLATE_IMAGE, pattern="a.png", max_count=5)
+        
+        expected = [(r.x, r.y) for r in rustmatch.find_all(SOURCE_IMAGE, TEMPLATE_IMAGE, max_count=5)]
+        assert [(r.x, r.y) for r in results["a.png"]] == expected
+    
+    @pytest.mark.parametrize("max_workers", [0, 1, 3])
+    def test_max_workers(self, directory, max_workers):
+        """Test every worker count gives the results of the global pool, in order."""
+        for i in range(6):
+            (directory / f"copy{i}.png").write_bytes((directory / "a.png").read_bytes())
+        
+        def summary(results):
+            return {name: [(r.x, r.y, r.confidence) for r in found] for name, found in results.items()}
+        
+        expected = summary(rustmatch.find_in_directory(str(directory), TEMPLATE_IMAGE, recursive=True))
+        results = rustmatch.find_in_directory(str(directory), TEMPLATE_IMAGE, recursive=True, max_workers=max_workers)
+        
+        assert list(results) == sorted(expected)
+        assert summary(results) == expected
+    
+    def test_errors(self, directory):
+        """Test a missing directory and undecodable files are reported."""
+        with pytest.raises(IOError):
+            rustmatch.find_in_directory(str(directory / "missing"), TEMPLATE_IMAGE)
+        with pytest.raises(rustmatch.DecodeError):
+            rustmatch.find_in_directory(str(directory), TEMPLATE_IMAGE, pattern="*.txt")
+        with pytest.raises(rustmatch.DecodeError):
+            rustmatch.find_in_directory(str(directory), TEMPLATE_IMAGE, pattern="*", max_workers=2)
+
+
+class TestExportResults:
+    """Tests for JSON/CSV report export."""
+    
+    def test_json_schema(self, tmp_path):
+        """Test the JSON report has one object per match and one per file without a match."""
+        best = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE)
+        results = {"a.png": [best], "b.png": [], "c.png": None}
+        path = tmp_path / "report.json"
+        
+        rows = rustmatch.export_results(results, str(path), template=TEMPLATE_IMAGE, timings={"a.png": 12.5})
+        
+        report = json.loads(path.read_text())
+        assert rows == 3
+        assert report["version"] == 1
+        assert report["results"][0] == {
+            "file": "a.png", "template": TEMPLATE_IMAGE, "x": 847, "y": 297,
+            "confidence": pytest.approx(best.confidence), "width": 15, "height": 16, "time_ms": 12.5,
+        }
+        assert report["results"][1] == {
+            "file": "b.png", "template": TEMPLATE_IMAGE, "x": None, "y": None,
+            "confidence": None, "width": None, "height": None, "time_ms": None,
+        }
+        assert [list(row) for row in report["results"]] == [
+            ["file", "template", "x", "y", "confidence", "width", "height", "time_ms"]
+        ] * 3
+    
+    def test_csv(self, tmp_path):
+        """Test the CSV report has a header, quoted fields and empty unknowns."""
+        results = {"with,comma.png": rustmatch.MatchResult(1, 2, 0.5), "empty.png": []}
+        path = tmp_path / "report.csv"
+        
+        rustmatch.export_results(results, str(path), format="csv")
+        
+        with open(path, newline="") as f:
+            rows = list(csv.reader(f))
+        assert rows == [
+            ["file", "template", "x", "y", "confidence", "width", "height", "time_ms"],
+            ["with,comma.png", "", "1", "2", "0.5", "", "", ""],
+            ["empty.png", "", "", "", "", "", "", ""],
+        ]
+    
+    def test_list_and_directory_timings(self, tmp_path):
+        """Test plain lists export without a file and directory timings fill time_ms."""
+        (tmp_path / "shots").mkdir()
+        with open(SOURCE_IMAGE, "rb") as f:
+            (tmp_path / "shots" / "a.png").write_bytes(f.read())
+        results, timings = rustmatch.find_in_directory(str(tmp_path / "shots"), TEMPLATE_IMAGE, return_timings=True)
+        
+        assert list(timings) == ["a.png"] and timings["a.png"] > 0
+        rustmatch.export_results(results, str(tmp_path / "dir.json"), timings=timings)
+        rustmatch.export_results(rustmatch.find_all(SOURCE_IMAGE, TEMPLATE_IMAGE, max_count=2), str(tmp_path / "list.json"))
+        
+        assert json.loads((tmp_path / "dir.json").read_text())["results"][0]["time_ms"] == pytest.approx(timings["a.png"])
+        assert [row["file"] for row in json.loads((tmp_path / "list.json").read_text())["results"]] == [None, None]
+    
+    def test_unknown_format(self, tmp_path):
+        """Test unknown formats are rejected."""
+        with pytest.raises(ValueError):
+            rustmatch.export_results([], str(tmp_path / "report.xml"), format="xml")
+
+
+
+class TestMatchSheet:
+    """Tests for save_match_sheet."""
+    
+    def test_sheet_layout(self, tmp_path):
+        """Test the sheet holds every crop and wraps after `columns` cells."""
+        matches = rustmatch.find_all(SOURCE_IMAGE, TEMPLATE_IMAGE, max_count=8)
+        size = rustmatch.get_size(TEMPLATE_IMAGE)
+        wide, narrow = str(tmp_path / "wide.png"), str(tmp_path / "narrow.png")
+        
+        assert rustmatch.save_match_sheet(SOURCE_IMAGE, matches, size, wide) is None
+        rustmatch.save_match_sheet(SOURCE_IMAGE, matches, size, narrow, columns=3)
+        
+        (ww, wh), (nw, nh) = rustmatch.get_size(wide), rustmatch.get_size(narrow)
+        assert nw < ww and nh > wh
+        found = rustmatch.find_all(narrow, TEMPLATE_IMAGE, threshold=0.95, max_count=20, exact=True)
+        assert len(found) >= len([m for m in matches if m.confidence >= 0.99])
+    
+    def test_scaled_and_clipped(self, tmp_path):
+        """Test scaled matches and crops past the border still fill their cell."""
+        matches = [MatchResult(0, 0, 0.9, scale=2.0), MatchResult(-5, -5, -0.5)]
+        out = str(tmp_path / "sheet.jpg")
+        
+        rustmatch.save_match_sheet(SOURCE_IMAGE, matches, (15, 16), out)
+        
+        assert os.path.getsize(out) > 0
+    
+    def test_invalid_arguments(self, tmp_path):
+        """Test empty match lists and bad sizes are rejected."""
+        out = str(tmp_path / "sheet.png")
+        match = MatchResult(0, 0, 1.0)
+        with pytest.raises(ValueError):
+            rustmatch.save_match_sheet(SOURCE_IMAGE, [], (15, 16), out)
+        with pytest.raises(ValueError):
+            rustmatch.save_match_sheet(SOURCE_IMAGE, [match], (15, 16), out, columns=0)
+        with pytest.raises(rustmatch.InvalidDimensionsError):
+            rustmatch.save_match_sheet(SOURCE_IMAGE, [match], (0, 16), out)
+
+
+class TestParallelThreshold:
+    """Tests for the small-search parallelism cut-off."""
+    
+    def teardown_method(self):
+        rustmatch.set_parallel_threshold()
+    
+    @pytest.mark.parametrize("min_work", [0, 1, 1 << 40])
+    def test_results_unchanged(self, min_work):
+        """Test results don't depend on whether the search fans out."""
+        expected = [(r.x, r.y, r.confidence) for r in rustmatch.find_all(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.7)]
+        best = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE)
+        
+        rustmatch.set_parallel_threshold(min_work)
+        
+        assert [(r.x, r.y, r.confidence) for r in rustmatch.find_all(SOURCE_IMAGE, TEMPLATE_IMAGE, threshold=0.7)] == expected
+        assert rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE).confidence == best.confidence
+    
+    def test_small_crops_from_threads(self):
+        """Test concurrent small searches from Python threads all find their target."""
+        from concurrent.futures import ThreadPoolExecutor
+        
+        scene = blocky_pixels(200, 150, cell=5)
+        template = make_png(20, 20, [scene[(60 + y) * 200 + 90 + x] for y in range(20) for x in range(20)])
+        source = make_png(200, 150, scene)
+        with ThreadPoolExecutor(8) as pool:
+            found = list(pool.map(lambda _: rustmatch.find_bytes(source, template), range(32)))
+        
+        assert {(r.x, r.y) for r in found} == {(90, 60)}
+
+
+class TestTimeout:
+    """Tests for the timeout_ms option."""
+    
+    def test_timeout_raises(self):
+        """Test an overlong search raises TimeoutError."""
+        with pytest.raises(TimeoutError):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, exact=True, timeout_ms=0.001)
+        with pytest.raises(TimeoutError):
+            rustmatch.find_all(SOURCE_IMAGE, TEMPLATE_IMAGE, exact=True, timeout_ms=0.001)
+        with pytest.raises(TimeoutError):
+            rustmatch.find_all(SOURCE_IMAGE, TEMPLATE_IMAGE, sample_fraction=0.5, timeout_ms=0.001)
+    
+    def test_partial_result(self):
+        """Test on_timeout="partial" returns what was found instead of raising."""
+        result = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, exact=True, timeout_ms=0.001, on_timeout="partial")
+        results = rustmatch.find_all(SOURCE_IMAGE, TEMPLATE_IMAGE, exact=True, timeout_ms=0.001, on_timeout="partial")
+        
+        assert result is None or result.confidence >= 0.8
+        assert isinstance(results, list)
+    
+    def test_generous_timeout(self):
+        """Test a search finishing in time is unaffected."""
+        result = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, timeout_ms=60000)
+        
+        assert (result.x, result.y) == (847, 297)
+        assert rustmatch.Matcher(TEMPLATE_IMAGE, timeout_ms=60000).find(SOURCE_IMAGE).x == 847
+    
+    def test_invalid_options(self):
+        """Test bad timeouts and unsupported functions are rejected."""
+        with pytest.raises(ValueError):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, timeout_ms=0)
+        with pytest.raises(ValueError):
+            rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, timeout_ms=100, on_timeout="ignore")
+        with pytest.raises(ValueError):
+            rustmatch.find_tiled(SOURCE_IMAGE, TEMPLATE_IMAGE, timeout_ms=100)
+
+
+class TestDiagnostics:
+    """Tests for return_diagnostics."""
+    
+    def test_find_diagnostics(self):
+        """Test the coarse peak, windows and timings of a pyramid search."""
+        w, h = 240, 160
+        pixels = blocky_pixels(w, h)
+        template = [pixels[(50 + y) * w + 100 + x] for y in range(64) for x in range(64)]
+        
+        result, diag = rustmatch.find_bytes(make_png(w, h, pixels), make_png(64, 64, template), return_diagnostics=True)
+        
+        assert (result.x, result.y) == (100, 50)
+        assert isinstance(diag, rustmatch.MatchDiagnostics)
+        assert diag.pyramid_scale >= 2
+        assert 0.5 < diag.coarse_score <= 1.0
+        cx, cy = diag.coarse_position
+        assert abs(cx - 100) <= diag.pyramid_scale and abs(cy - 50) <= diag.pyramid_scale
+        assert any(x1 <= 100 <= x2 and y1 <= 50 <= y2 for x1, y1, x2, y2 in diag.refinement_windows)
+        assert 0 <= diag.windows_pruned <= diag.windows_evaluated
+        assert {"decode", "preprocess", "pyramid", "coarse", "refine", "search", "total"} <= set(diag.timings)
+        assert diag.timings["total"] >= diag.timings["search"] > 0
+        assert diag.to_dict()["windows_evaluated"] == diag.windows_evaluated
+        assert "coarse_score=" in repr(diag)
+    
+    def test_exact_search(self):
+        """Test an exhaustive search reports no pyramid level."""
+        result, diag = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, exact=True, return_diagnostics=True)
+        
+        assert (result.x, result.y) == (847, 297)
+        assert diag.pyramid_scale is None and diag.coarse_score is None
+        assert diag.windows_evaluated > 0
+    
+    def test_other_entry_points(self):
+        """Test find_all and the bytes functions return diagnostics too."""
+        with open(SOURCE_IMAGE, "rb") as f:
+            source = f.read()
+        with open(TEMPLATE_IMAGE, "rb") as f:
+            template = f.read()
+        
+        results, diag = rustmatch.find_all(SOURCE_IMAGE, TEMPLATE_IMAGE, return_diagnostics=True)
+        assert results and diag.windows_evaluated > 0
+        found, diag = rustmatch.find_bytes(source, template, return_diagnostics=True)
+        assert found.x == 847 and "decode" in diag.timings
+        results, diag = rustmatch.find_all_bytes(source, template, return_diagnostics=True)
+        assert results and diag.timings["search"] > 0
+    
+    @pytest.mark.parametrize("options", [{}, {"sample_fraction": 0.5}])
+    def test_find_all_counts_refinement(self, options):
+        """Test find_all counts the windows refined around each coarse peak."""
+        w, h = 400, 300
+        pixels = blocky_pixels(w, h)
+        template = [pixels[(50 + y) * w + 100 + x] for y in range(64) for x in range(64)]
+        
+        _, diag = rustmatch.find_all_bytes(
+            make_png(w, h, pixels), make_png(64, 64, template), return_diagnostics=True, **options
+        )
+        
+        scale = diag.pyramid_scale
+        coarse = (w // scale - 64 // scale + 1) * (h // scale - 64 // scale + 1)
+        assert diag.refinement_windows
+        assert diag.windows_evaluated > coarse
+    
+    def test_default_unchanged(self):
+        """Test the plain return value without the flag, cached or not."""
+        rustmatch.set_result_cache(16)
+        try:
+            first = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE)
+            _, diag = rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, return_diagnostics=True)
+            assert isinstance(first, MatchResult)
+            assert diag.windows_evaluated > 0
+        finally:
+            rustmatch.set_result_cache(0)
+
+
+
+class TestDebugDir:
+    """Tests for the debug_dir option."""
+    
+    def images(self):
+        w, h = 240, 160
+        pixels = blocky_pixels(w, h)
+        template = [pixels[(50 + y) * w + 100 + x] for y in range(64) for x in range(64)]
+        return make_png(w, h, pixels), make_png(64, 64, template)
+    
+    def test_pyramid_images(self, tmp_path):
+        """Test the coarse images, score map and refinement overlay are written."""
+        source, template = self.images()
+        result = rustmatch.find_bytes(source, template, debug_dir=str(tmp_path / "dump"))
+        
+        assert (result.x, result.y) == (100, 50)
+        dump = tmp_path / "dump"
+        assert rustmatch.get_size(str(dump / "coarse_source.png")) == (60, 40)
+        assert rustmatch.get_size(str(dump / "coarse_template.png")) == (16, 16)
+        assert rustmatch.get_size(str(dump / "coarse_scores.png")) == (45, 25)
+        assert rustmatch.get_size(str(dump / "refinement.png")) == (240, 160)
+    
+    def test_find_all_and_diagnostics(self, tmp_path):
+        """Test find_all dumps too and diagnostics are unaffected by the dump."""
+        source, template = self.images()
+        results, diag = rustmatch.find_all_bytes(source, template, debug_dir=tmp_path, return_diagnostics=True)
+        _, plain = rustmatch.find_all_bytes(source, template, return_diagnostics=True)
+        
+        assert (results[0].x, results[0].y) == (100, 50)
+        assert (tmp_path / "refinement.png").exists()
+        assert diag.windows_evaluated == plain.windows_evaluated
+    
+    def test_exact_search(self, tmp_path):
+        """Test a search without a coarse stage writes only the overlay."""
+        rustmatch.find(SOURCE_IMAGE, TEMPLATE_IMAGE, exact=True, debug_dir=str(tmp_path))
+        
+        assert sorted(p.name for p in tmp_path.iterdir()) == ["refinement.png"]
+    
+    def test_tiled_rejected(self, tmp_path):
+        """Test the tiled functions reject debug_dir."""
+        with pytest.raises(ValueError):
+            rustmatch.find_tiled(SOURCE_IMAGE, TEMPLATE_IMAGE, debug_dir=str(tmp_path))
+
+
+class TestFindInVideo:
+    """Tests for video file matching."""
+    
+    def test_invalid_frame_step(self):
+        """Test frame_step is validated before decoding."""
+        with pytest.raises(ValueError):
+            rustmatch.find_in_video("missing.mp4", TEMPLATE_IMAGE, frame_step=0)
+    
+    def test_missing_video(self):
+        """Test missing files fail (or report the feature is unavailable)."""
+        with pytest.raises((OSError, RuntimeError)):
+            rustmatch.find_in_video("missing.mp4", TEMPLATE_IMAGE)
+
+
+class TestFindUrl:
+    """Tests for URL sources."""
+    
+    def test_invalid_timeout(self):
+        """Test the timeout is validated before any request."""
+        with pytest.raises(ValueError):
+            rustmatch.find_url("http://127.0.0.1:9/a.png", TEMPLATE_IMAGE, timeout=0)
+    
+    def test_local_server(self):
+        """Test a source served over HTTP (or that the feature is unavailable)."""
+        import functools
+        import http.server
+        import threading
+        handler = functools.partial(http.server.SimpleHTTPRequestHandler, directory=os.path.dirname(SOURCE_IMAGE) or ".")
+        server = http.server.ThreadingHTTPServer(("127.0.0.1", 0), handler)
+        threading.Thread(target=server.serve_forever, daemon=True).start()
+        url = f"http://127.0.0.1:{server.server_address[1]}/{os.path.basename(SOURCE_IMAGE)}"
+        try:
+            try:
+                result = rustmatch.find_url(url, TEMPLATE_IMAGE, timeout=10)
+            except RuntimeError:
+                pytest.skip("built without the http feature")
+            assert (result.x, result.y) == (847, 297)
+            with pytest.raises(OSError):
+                rustmatch.find_url(url + ".missing", TEMPLATE_IMAGE, timeout=10)
+        finally:
+            server.shutdown()
+
+
+class TestWatchCamera:
+    """Tests for live camera matching."""
+    
+    def test_invalid_frame_step(self):
+        """Test frame_step is validated before opening the camera."""
+        with pytest.raises(ValueError):
+            rustmatch.watch_camera(0, TEMPLATE_IMAGE, lambda i, r: True, frame_step=0)
+    
+    def test_missing_device(self):
+        """Test an absent camera fails (or reports the feature is unavailable)."""
+        with pytest.raises((OSError, RuntimeError)):
+            rustmatch.watch_camera(9999, TEMPLATE_IMAGE, lambda i, r: True, max_frames=1)
+
+
+class TestUtilities:
+    """Tests for utility functions."""
+    
+    def test_get_size(self):
+        """Test getting image dimensions."""
+        width, height = rustmatch.get_size(SOURCE_IMAGE)
+        
+        assert width == 1602
+        assert height == 364
+    
+    def test_get_size_bytes(self):
+        """Test getting dimensions from bytes."""
+        with open(SOURCE_IMAGE, "rb") as f:
+            data = f.read()
+        
+        width, height = rustmatch.get_size_bytes(data)
+        
+        assert width == 1602
+        assert height == 364
+    
+    def test_version(self):
+        """Test version function."""
+        ver = rustmatch.version()
+        
+        assert isinstance(ver, str)
+        assert "." in ver  # Should be semver format
+    
+    def test_set_threads(self):
+        """Test setting thread count."""
+        # Note: set_threads can only be called once before any matching
+        # After that, the thread pool is already initialized
+        # This test just verifies the function exists and is callable
+        try:
+            rustmatch.set_threads(4)
+        except ValueError as e:
+            # Expected if thread pool already initialized
+            assert "already been initialized" in str(e)
+
+
+class TestCompareImages:
+    """Tests for whole-image similarity."""
+    
+    def test_identical_images(self):
+        """Test identical images score as identical."""
+        assert rustmatch.compare_images(SOURCE_IMAGE, SOURCE_IMAGE) == pytest.approx(1.0)
+        assert rustmatch.compare_images(SOURCE_IMAGE, SOURCE_IMAGE, method="ncc") == pytest.approx(1.0)
+        assert rustmatch.compare_images(SOURCE_IMAGE, SOURCE_IMAGE, method="psnr") == float("inf")
+    
+    def test_different_images(self):
+        """Test different images score lower."""
+        a = make_png(32, 32, [(x * 7) % 256 for x in range(32 * 32)])
+        b = make_png(32, 32, [(x * 13) % 256 for x in range(32 * 32)])
+        
+        assert rustmatch.compare_images(a, b) < 0.9
+        assert rustmatch.compare_images(a, b, method="psnr") < 40.0
+    
+    def test_size_mismatch(self):
+        """Test images of different sizes are rejected."""
+        with pytest.raises(ValueError):
+            rustmatch.compare_images(SOURCE_IMAGE, TEMPLATE_IMAGE)
+    
+    def test_unknown_method(self):
+        """Test unknown methods are rejected."""
+        with pytest.raises(ValueError):
+            rustmatch.compare_images(SOURCE_IMAGE, SOURCE_IMAGE, method="mse")
+
+
+class TestDiffImages:
+    """Tests for changed-region detection between screenshots."""
+    
+    WIDTH, HEIGHT = 120, 90
+    
+    def pair(self, edits):
+        """Baseline texture and a copy with faint noise everywhere plus `edits` (x, y, w, h) brightened."""
+        baseline = blocky_pixels(self.WIDTH, self.HEIGHT)
+        current = [min(v + i % 5, 255) for i, v in enumerate(baseline)]
+        for x0, y0, w, h in edits:
+            for y in range(y0, y0 + h):
+                for x in range(x0, x0 + w):
+                    current[y * self.WIDTH + x] = (baseline[y * self.WIDTH + x] + 100) % 256
+        return make_png(self.WIDTH, self.HEIGHT, baseline), make_png(self.WIDTH, self.HEIGHT, current)
+    
+    def test_changed_regions(self):
+        """Test each edit is boxed exactly and sub-tolerance noise is ignored."""
+        baseline, current = self.pair([(70, 50, 12, 8), (10, 5, 20, 10)])
+        
+        assert rustmatch.diff_images(baseline, current) == [(10, 5, 20, 10), (70, 50, 12, 8)]
+        assert rustmatch.diff_images(baseline, baseline) == []
+    
+    def test_merge_distance_and_min_area(self):
+        """Test nearby edits share a box and tiny ones can be dropped."""
+        baseline, current = self.pair([(20, 20, 5, 5), (28, 20, 5, 5), (100, 80, 1, 1)])
+        
+        assert rustmatch.diff_images(baseline, current) == [(20, 20, 13, 5), (100, 80, 1, 1)]
+        assert rustmatch.diff_images(baseline, current, merge_distance=2, min_area=2) == [(20, 20, 5, 5), (28, 20, 5, 5)]
+    
+    def test_return_image(self):
+        """Test the visualization is a PNG of the same size."""
+        baseline, current = self.pair([(10, 5, 20, 10)])
+        
+        boxes, png = rustmatch.diff_images(baseline, current, return_image=True)
+        
+        assert boxes == [(10, 5, 20, 10)]
+        assert png[:8] == b"\x89PNG\r\n\x1a\n"
+        assert struct.unpack(">II", png[16:24]) == (self.WIDTH, self.HEIGHT)
+    
+    def test_size_mismatch(self):
+        """Test images of different sizes are rejected."""
+        with pytest.raises(rustmatch.InvalidDimensionsError):
+            rustmatch.diff_images(SOURCE_IMAGE, TEMPLATE_IMAGE)
+
+
+class TestPerceptualHash:
+    """Tests for perceptual hashing."""
+    
+    def test_identical_images(self):
+        """Test identical images hash identically."""
+        with open(SOURCE_IMAGE, "rb") as f:
+            data = f.read()
+        
+        assert rustmatch.phash(SOURCE_IMAGE) == rustmatch.phash(data)
+        assert rustmatch.dhash(SOURCE_IMAGE) == rustmatch.dhash(data)
+    
+    def test_different_images(self):
+        """Test unrelated images are far apart."""
+        a = rustmatch.phash(SOURCE_IMAGE)
+        b = rustmatch.phash(make_png(64, 64, [(x * 37) % 256 for x in range(64 * 64)]))
+        
+        assert 0 <= a < 2 ** 64
+        assert rustmatch.hamming_distance(a, b) > 10
+    
+    def test_hamming_distance(self):
+        """Test bit counting."""
+        assert rustmatch.hamming_distance(0, 0) == 0
+        assert rustmatch.hamming_distance(0b1011, 0b0001) == 2
+        assert rustmatch.hamming_distance(0, 2 ** 64 - 1) == 64
 
 
 class TestConsistency: